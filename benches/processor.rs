@@ -0,0 +1,37 @@
+//! Simple benchmark without external harness, run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use cute_ledger::{
+    command::TransactionKind,
+    processor::{TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor},
+};
+use rust_decimal::Decimal;
+
+const CLIENTS: u32 = u16::MAX as u32;
+const TXS: u32 = 5_000_000;
+
+fn run(mut processor: InMemoryTransactionProcessor) -> Duration {
+    let started = Instant::now();
+    for tx in 0..TXS {
+        processor
+            .process_transaction(
+                tx,
+                (tx % CLIENTS) as u16,
+                Some(Decimal::ONE),
+                TransactionKind::Deposit,
+            )
+            .unwrap();
+    }
+    started.elapsed()
+}
+
+fn main() {
+    let default = run(InMemoryTransactionProcessor::default());
+    let presized = run(InMemoryTransactionProcessor::with_capacity(
+        CLIENTS as usize,
+        TXS as usize,
+    ));
+    println!("{TXS} deposits, default:       {default:?}");
+    println!("{TXS} deposits, with_capacity: {presized:?}");
+}
@@ -0,0 +1,141 @@
+//! HTTP service embedding the ledger in an axum app.
+//! Processors are single-threaded, so the ledger lives on its own thread
+//! and request handlers talk to it over a channel.
+//!
+//! Run with `cargo run --example axum_service --features axum-example`, then:
+//! ```text
+//! curl -X POST localhost:3000/transactions -H 'content-type: application/json' \
+//!     -d '{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}'
+//! curl localhost:3000/accounts/1
+//! curl localhost:3000/accounts/1/transactions
+//! ```
+
+use std::thread;
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+};
+use cute_ledger::{
+    Ledger,
+    account::TransactionId,
+    bin_utils::{csv_parser::Transaction, csv_printer::Account},
+    processor::{
+        ClientId, TransactionOutcome, TransactionProcessor,
+        in_memory_processor::InMemoryTransactionProcessor,
+    },
+};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+enum Request {
+    Submit(
+        Transaction,
+        oneshot::Sender<Result<TransactionOutcome, Rejection>>,
+    ),
+    Account(ClientId, oneshot::Sender<Option<Account>>),
+    Transactions(ClientId, oneshot::Sender<Vec<TransactionId>>),
+}
+
+#[derive(Serialize)]
+struct Rejection {
+    code: &'static str,
+    message: String,
+}
+
+type Requests = mpsc::Sender<Request>;
+
+/// Owns the ledger and serves requests one by one, in order of arrival
+fn run_ledger(mut requests: mpsc::Receiver<Request>) {
+    let mut ledger =
+        Ledger::with_processor(InMemoryTransactionProcessor::default().with_client_index());
+    while let Some(request) = requests.blocking_recv() {
+        match request {
+            Request::Submit(tx, reply) => {
+                let result = ledger.submit(tx).map_err(|err| Rejection {
+                    code: err.code(),
+                    message: err.to_string(),
+                });
+                let _ = reply.send(result);
+            }
+            Request::Account(client_id, reply) => {
+                let account = ledger.processor().account(client_id);
+                let _ = reply.send(account.map(|acc| Account::of(client_id, acc)));
+            }
+            Request::Transactions(client_id, reply) => {
+                let processor = ledger.processor();
+                let tx_ids = processor.client_transactions(client_id).unwrap_or_default();
+                let _ = reply.send(tx_ids.to_vec());
+            }
+        }
+    }
+}
+
+/// Sends request built around the reply channel, and waits for the reply
+async fn ask<T>(
+    requests: &Requests,
+    request: impl FnOnce(oneshot::Sender<T>) -> Request,
+) -> Result<T, StatusCode> {
+    let (reply, replied) = oneshot::channel();
+    // ledger thread is gone
+    let unavailable = StatusCode::SERVICE_UNAVAILABLE;
+    requests
+        .send(request(reply))
+        .await
+        .map_err(|_| unavailable)?;
+    replied.await.map_err(|_| unavailable)
+}
+
+async fn submit(
+    State(requests): State<Requests>,
+    Json(tx): Json<Transaction>,
+) -> Result<StatusCode, (StatusCode, Json<Rejection>)> {
+    let result = ask(&requests, |reply| Request::Submit(tx, reply))
+        .await
+        .map_err(|status| {
+            let rejection = Rejection {
+                code: "unavailable",
+                message: "ledger is not running".to_string(),
+            };
+            (status, Json(rejection))
+        })?;
+    match result {
+        Ok(outcome) if outcome.is_applied() => Ok(StatusCode::CREATED),
+        // parked or held for review
+        Ok(_) => Ok(StatusCode::ACCEPTED),
+        Err(rejection) => Err((StatusCode::UNPROCESSABLE_ENTITY, Json(rejection))),
+    }
+}
+
+async fn account(
+    State(requests): State<Requests>,
+    Path(client_id): Path<ClientId>,
+) -> Result<Json<Account>, StatusCode> {
+    let account = ask(&requests, |reply| Request::Account(client_id, reply)).await?;
+    account.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn transactions(
+    State(requests): State<Requests>,
+    Path(client_id): Path<ClientId>,
+) -> Result<Json<Vec<TransactionId>>, StatusCode> {
+    let tx_ids = ask(&requests, |reply| Request::Transactions(client_id, reply)).await?;
+    Ok(Json(tx_ids))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (requests, received) = mpsc::channel(1024);
+    thread::spawn(move || run_ledger(received));
+    let app = Router::new()
+        .route("/transactions", post(submit))
+        .route("/accounts/{client}", get(account))
+        .route("/accounts/{client}/transactions", get(transactions))
+        .with_state(requests);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
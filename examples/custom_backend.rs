@@ -0,0 +1,83 @@
+//! Custom processor backend: accounts and created transactions kept in
+//! ordered maps, checked against the conformance corpus.
+//! Run with `cargo run --example custom_backend`.
+
+use std::collections::BTreeMap;
+
+use cute_ledger::{
+    Ledger,
+    account::{Account, TransactionId},
+    bin_utils::OutputFormat,
+    command::{AccountCommand, CreateTransactionCommand, TransactionKind, ZeroAmountPolicy},
+    conformance,
+    processor::{ClientId, TransactionOutcome, TransactionProcessError, TransactionProcessor},
+};
+use rust_decimal::Decimal;
+
+/// Stand-in for a real backend (a key-value store, a remote service):
+/// everything an alternative processor has to do is parsing the command,
+/// letting the account handle it, and storing the outcome.
+#[derive(Default)]
+struct OrderedProcessor {
+    accounts: BTreeMap<ClientId, Account>,
+    created: BTreeMap<TransactionId, CreateTransactionCommand>,
+}
+
+impl TransactionProcessor for OrderedProcessor {
+    fn process_transaction(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let cmd = AccountCommand::parse_command(
+            tx_id,
+            self.created.get(&tx_id),
+            &kind,
+            amount,
+            ZeroAmountPolicy::default(),
+        )?;
+        let acc = self.accounts.entry(client_id).or_default();
+        let event = match &cmd {
+            AccountCommand::CreateTx(command) => acc.handle_create_transaction(command.clone())?,
+            AccountCommand::ModifyTx(command) => acc.handle_modify_transaction(command.clone())?,
+        };
+        let was_locked = acc.locked();
+        acc.try_apply(&event)?;
+        let outcome = TransactionOutcome::applied(event.kind(), acc, was_locked);
+        // store only when the account accepted the transaction
+        if let AccountCommand::CreateTx(command) = cmd {
+            self.created.insert(tx_id, command);
+        }
+        Ok(outcome)
+    }
+
+    fn accounts(&self) -> impl Iterator<Item = (ClientId, &Account)> {
+        self.accounts
+            .iter()
+            .map(|(client_id, acc)| (*client_id, acc))
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let failures = conformance::verify(OrderedProcessor::default)?;
+    for failure in &failures {
+        eprintln!(
+            "case `{}` differs\nexpected:\n{}actual:\n{}",
+            failure.name, failure.expected, failure.actual
+        );
+    }
+    anyhow::ensure!(failures.is_empty(), "{} cases differ", failures.len());
+
+    let mut ledger = Ledger::with_processor(OrderedProcessor::default());
+    let rejected = ledger.ingest_csv(
+        "type,client,tx,amount\ndeposit,2,1,10\ndeposit,1,2,5\ndispute,2,1,\n".as_bytes(),
+    )?;
+    assert!(rejected.is_empty());
+    ledger.report(&mut std::io::stdout(), OutputFormat::Csv)
+}
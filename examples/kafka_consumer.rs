@@ -0,0 +1,52 @@
+//! Kafka consumer applying JSON transactions from a topic, e.g.
+//! `{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}`.
+//! Offsets are committed only after the transaction was applied or
+//! rejected, so a restarted consumer doesn't skip any.
+//!
+//! Run with `cargo run --example kafka_consumer --features kafka-example -- localhost:9092 transactions`.
+
+use std::time::Duration;
+
+use cute_ledger::{Ledger, bin_utils::csv_parser::Transaction};
+use rdkafka::{
+    ClientConfig, Message,
+    consumer::{BaseConsumer, CommitMode, Consumer},
+};
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let brokers = args.next().unwrap_or_else(|| "localhost:9092".to_string());
+    let topic = args.next().unwrap_or_else(|| "transactions".to_string());
+
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &brokers)
+        .set("group.id", "cute-ledger")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[&topic])?;
+    // single partition assignment per processor keeps transactions of a client ordered
+    let mut ledger = Ledger::new().with_source(format!("kafka:{topic}"));
+
+    loop {
+        let Some(message) = consumer.poll(Duration::from_secs(1)) else {
+            continue;
+        };
+        let message = message?;
+        let payload = message.payload().unwrap_or_default();
+        match serde_json::from_slice::<Transaction>(payload) {
+            Ok(tx) => {
+                let tx_id = tx.tx;
+                if let Err(err) = ledger.submit(tx) {
+                    eprintln!("tx {tx_id} rejected: {} ({err})", err.code());
+                }
+            }
+            Err(err) => eprintln!(
+                "offset {} of partition {} is not a transaction: {err}",
+                message.offset(),
+                message.partition()
+            ),
+        }
+        consumer.commit_message(&message, CommitMode::Async)?;
+    }
+}
@@ -1,31 +1,194 @@
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    str::FromStr,
+};
 
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
-use crate::command::{
-    CreateTransactionAction, CreateTransactionCommand, ModifyTransactionAction,
-    ModifyTransactionCommand,
+use crate::{
+    command::{
+        CreateTransactionAction, CreateTransactionCommand, ModifyTransactionAction,
+        ModifyTransactionCommand,
+    },
+    money::Money,
 };
 
 pub type TransactionId = u32;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AccountEventKind {
     Deposited,
     Withdrawn,
     Disputed,
     Resolved,
     Chargedback,
+    DepositPending,
+    Settled,
+    Authorized,
+    Captured,
+    Voided,
+    /// Available funds carried over from compacted history
+    OpeningBalance,
+    /// Account was frozen in compacted history
+    Locked,
+    /// Funds of charged back transaction were restored
+    Reinstated,
+    /// Disputed transaction was contested by the merchant
+    Represented,
+    /// Contested dispute was escalated by the cardholder
+    PreArbitrated,
+    /// Frozen account was unlocked by an operator
+    Unlocked,
+    /// Available funds were corrected by an operator, by signed amount
+    Adjusted,
+    /// Personal data of the client was erased by an operator, balances are kept
+    Erased,
 }
 
-#[derive(Debug)]
+impl AccountEventKind {
+    pub const ALL: [AccountEventKind; 18] = [
+        AccountEventKind::Deposited,
+        AccountEventKind::Withdrawn,
+        AccountEventKind::Disputed,
+        AccountEventKind::Resolved,
+        AccountEventKind::Chargedback,
+        AccountEventKind::DepositPending,
+        AccountEventKind::Settled,
+        AccountEventKind::Authorized,
+        AccountEventKind::Captured,
+        AccountEventKind::Voided,
+        AccountEventKind::OpeningBalance,
+        AccountEventKind::Locked,
+        AccountEventKind::Reinstated,
+        AccountEventKind::Represented,
+        AccountEventKind::PreArbitrated,
+        AccountEventKind::Unlocked,
+        AccountEventKind::Adjusted,
+        AccountEventKind::Erased,
+    ];
+
+    /// Stable name, used by storage backends
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountEventKind::Deposited => "deposited",
+            AccountEventKind::Withdrawn => "withdrawn",
+            AccountEventKind::Disputed => "disputed",
+            AccountEventKind::Resolved => "resolved",
+            AccountEventKind::Chargedback => "chargedback",
+            AccountEventKind::DepositPending => "deposit_pending",
+            AccountEventKind::Settled => "settled",
+            AccountEventKind::Authorized => "authorized",
+            AccountEventKind::Captured => "captured",
+            AccountEventKind::Voided => "voided",
+            AccountEventKind::OpeningBalance => "opening_balance",
+            AccountEventKind::Locked => "locked",
+            AccountEventKind::Reinstated => "reinstated",
+            AccountEventKind::Represented => "represented",
+            AccountEventKind::PreArbitrated => "pre_arbitrated",
+            AccountEventKind::Unlocked => "unlocked",
+            AccountEventKind::Adjusted => "adjusted",
+            AccountEventKind::Erased => "erased",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AccountEvent {
     transaction_id: TransactionId,
     amount: Decimal,
     kind: AccountEventKind,
 }
 
+impl AccountEvent {
+    /// Restores event, that was previously persisted by a storage backend
+    pub fn new(transaction_id: TransactionId, amount: Decimal, kind: AccountEventKind) -> Self {
+        Self {
+            transaction_id,
+            amount,
+            kind,
+        }
+    }
+
+    /// Event of a transaction (deposit, withdrawal, dispute and others),
+    /// whose amount can never be negative
+    pub fn of_transaction(
+        transaction_id: TransactionId,
+        amount: Money,
+        kind: AccountEventKind,
+    ) -> Self {
+        Self {
+            transaction_id,
+            amount: amount.amount(),
+            kind,
+        }
+    }
+
+    /// Available funds carried over when history is compacted, not tied to any transaction
+    pub fn opening_balance(amount: Decimal) -> Self {
+        Self {
+            transaction_id: 0,
+            amount,
+            kind: AccountEventKind::OpeningBalance,
+        }
+    }
+
+    pub fn locked() -> Self {
+        Self {
+            transaction_id: 0,
+            amount: Decimal::ZERO,
+            kind: AccountEventKind::Locked,
+        }
+    }
+
+    /// Operator unlocked the account, see [`crate::processor::admin::AdminOps::unlock`]
+    pub fn unlocked() -> Self {
+        Self {
+            transaction_id: 0,
+            amount: Decimal::ZERO,
+            kind: AccountEventKind::Unlocked,
+        }
+    }
+
+    /// Operator corrected available funds by signed `amount`,
+    /// see [`crate::processor::admin::AdminOps::adjust`]
+    pub fn adjusted(amount: Decimal) -> Self {
+        Self {
+            transaction_id: 0,
+            amount,
+            kind: AccountEventKind::Adjusted,
+        }
+    }
+
+    /// Operator erased personal data of the client,
+    /// see [`crate::processor::admin::AdminOps::erase_client`]
+    pub fn erased() -> Self {
+        Self {
+            transaction_id: 0,
+            amount: Decimal::ZERO,
+            kind: AccountEventKind::Erased,
+        }
+    }
+
+    pub fn transaction_id(&self) -> TransactionId {
+        self.transaction_id
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    pub fn kind(&self) -> AccountEventKind {
+        self.kind
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum AccountError {
     #[error("Account is frozen, no further operations are allowed")]
@@ -39,14 +202,204 @@ pub enum AccountError {
     },
     #[error("Dispute operation is not supported for parent transaction")]
     DisputeNotSupported,
+    #[error("{action:?} cannot be initiated, because the transaction is not settled yet")]
+    TransactionNotSettled { action: ModifyTransactionAction },
+    #[error("Only pending deposit can be settled")]
+    TransactionNotPending,
+    #[error("{action:?} requires an open authorization")]
+    AuthorizationNotOpen { action: ModifyTransactionAction },
+    #[error("Account version is {actual}, but {expected} was expected")]
+    VersionMismatch { expected: u64, actual: u64 },
+    #[error("Balance would exceed the cap of {cap}")]
+    BalanceCapExceeded { cap: Decimal },
+    #[error("Only charged back transaction can be reinstated")]
+    TransactionNotChargedBack,
+    #[error("Withdrawal is below the minimum of {minimum}")]
+    WithdrawalBelowMinimum { minimum: Decimal },
+    #[error("Withdrawal must be a multiple of {denomination}")]
+    WithdrawalDenominationMismatch { denomination: Decimal },
 }
 
-#[derive(Debug, Default)]
+impl AccountError {
+    /// Stable identifier of the error, that doesn't change with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountError::AccountFrozen => "account_frozen",
+            AccountError::InsufficientFunds => "insufficient_funds",
+            AccountError::TransactionDisputeStateMismatch { .. } => "dispute_state_mismatch",
+            AccountError::DisputeNotSupported => "dispute_not_supported",
+            AccountError::TransactionNotSettled { .. } => "transaction_not_settled",
+            AccountError::TransactionNotPending => "transaction_not_pending",
+            AccountError::AuthorizationNotOpen { .. } => "authorization_not_open",
+            AccountError::VersionMismatch { .. } => "version_mismatch",
+            AccountError::BalanceCapExceeded { .. } => "balance_cap_exceeded",
+            AccountError::TransactionNotChargedBack => "transaction_not_chargedback",
+            AccountError::WithdrawalBelowMinimum { .. } => "withdrawal_below_minimum",
+            AccountError::WithdrawalDenominationMismatch { .. } => {
+                "withdrawal_denomination_mismatch"
+            }
+        }
+    }
+}
+
+/// Event is inconsistent with the account state, applying it would
+/// corrupt balances
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+    #[error("Balance overflow")]
+    Overflow,
+    #[error("{balance} balance would become negative")]
+    NegativeBalance { balance: &'static str },
+    #[error("Transaction {tx_id} is not in a state, where it can be {kind:?}")]
+    TransactionStateMismatch {
+        kind: AccountEventKind,
+        tx_id: TransactionId,
+    },
+}
+
+impl ApplyError {
+    /// Stable identifier of the error, that doesn't change with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApplyError::Overflow => "balance_overflow",
+            ApplyError::NegativeBalance { .. } => "negative_balance",
+            ApplyError::TransactionStateMismatch { .. } => "transaction_state_mismatch",
+        }
+    }
+}
+
+/// Stage of an open dispute past its opening
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisputeStage {
+    Representment,
+    PreArbitration,
+}
+
+/// Handling status of a dispute case, see [`DisputeCase`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaseStatus {
+    /// Nobody looked at the dispute yet
+    #[default]
+    New,
+    Investigating,
+    /// Waiting for evidence from the merchant or the cardholder
+    AwaitingEvidence,
+}
+
+impl FromStr for CaseStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new" => Ok(Self::New),
+            "investigating" => Ok(Self::Investigating),
+            "awaiting-evidence" => Ok(Self::AwaitingEvidence),
+            other => Err(format!("unknown case status `{other}`")),
+        }
+    }
+}
+
+/// Handling of an open dispute by the team, so small teams can track it
+/// inside the ledger instead of a spreadsheet. Closed together with the
+/// dispute, when it is resolved or charged back.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DisputeCase {
+    pub status: CaseStatus,
+    pub assignee: Option<String>,
+    /// Oldest first
+    pub notes: Vec<String>,
+}
+
+/// Dispute state machine of the deployment. Disputes are always opened with
+/// `dispute`, and may be contested with `representment` and escalated with
+/// `pre_arbitration` before they are resolved or charged back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeFlow {
+    /// Dispute can be resolved or charged back at any stage
+    #[default]
+    Simple,
+    /// Chargeback requires representment first, as some card schemes do
+    Strict,
+}
+
+impl FromStr for DisputeFlow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simple" => Ok(Self::Simple),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!("unknown dispute flow `{other}`")),
+        }
+    }
+}
+
+/// Plain, comparable copy of account state, so tests can compare accounts
+/// as a whole, see also [`assert_account!`](crate::assert_account)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AccountSnapshot {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub pending: Decimal,
+    pub locked: bool,
+    /// Transactions under dispute, that are neither resolved nor charged back
+    pub open_disputes: BTreeSet<TransactionId>,
+    /// Custom fields, see [`Account::extensions`]
+    pub extensions: BTreeMap<String, Value>,
+}
+
+/// Asserts selected fields of [`AccountSnapshot`] of the account,
+/// values are converted into field type, e.g. integers into [`Decimal`]:
+/// ```
+/// # use cute_ledger::{account::Account, assert_account};
+/// let account = Account::default();
+/// assert_account!(account, available: 0, locked: false, open_disputes: []);
+/// ```
+#[macro_export]
+macro_rules! assert_account {
+    ($account:expr, $($field:ident: $value:expr),+ $(,)?) => {{
+        let snapshot = $crate::account::Account::snapshot(&$account);
+        $(
+            let expected = $crate::account::AccountSnapshot::expected(&snapshot.$field, $value);
+            assert_eq!(
+                snapshot.$field,
+                expected,
+                "account `{}` differs",
+                stringify!($field)
+            );
+        )+
+    }};
+}
+
+impl AccountSnapshot {
+    #[doc(hidden)]
+    pub fn expected<T>(_field: &T, value: impl Into<T>) -> T {
+        value.into()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Account {
     available: Decimal,
     held: Decimal,
+    /// Deposited, but not yet settled funds, not included in total
+    pending: Decimal,
     locked: bool,
     txs_under_dispute: HashSet<TransactionId>,
+    pending_txs: HashSet<TransactionId>,
+    open_authorizations: HashSet<TransactionId>,
+    /// Charged back transactions, that can still be reinstated
+    chargedback_txs: HashSet<TransactionId>,
+    /// Disputes past their opening
+    dispute_stages: HashMap<TransactionId, DisputeStage>,
+    /// Cases of open disputes, created once they are first updated
+    dispute_cases: HashMap<TransactionId, DisputeCase>,
+    /// Custom fields of the deployment, not derived from events
+    extensions: HashMap<String, Value>,
+    /// Number of applied events
+    version: u64,
 }
 
 impl Account {
@@ -61,11 +414,375 @@ impl Account {
         self.held
     }
 
+    pub fn pending(&self) -> Decimal {
+        self.pending
+    }
+
     pub fn locked(&self) -> bool {
         self.locked
     }
 
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Custom fields set by middleware or [`crate::projection::AccountExtension`]s,
+    /// e.g. risk score or tier, so deployments don't have to fork the account
+    pub fn extensions(&self) -> &HashMap<String, Value> {
+        &self.extensions
+    }
+
+    pub fn extension(&self, key: &str) -> Option<&Value> {
+        self.extensions.get(key)
+    }
+
+    /// Sets custom field, returning its previous value
+    pub fn set_extension(&mut self, key: impl Into<String>, value: Value) -> Option<Value> {
+        self.extensions.insert(key.into(), value)
+    }
+
+    pub fn remove_extension(&mut self, key: &str) -> Option<Value> {
+        self.extensions.remove(key)
+    }
+
+    /// Removes all custom fields, e.g. on erasure of the client
+    pub fn clear_extensions(&mut self) {
+        self.extensions.clear();
+    }
+
+    /// Stage of open dispute of the transaction, `None` if the dispute was
+    /// only opened, or the transaction is not under dispute
+    pub fn dispute_stage(&self, tx_id: TransactionId) -> Option<DisputeStage> {
+        self.dispute_stages.get(&tx_id).copied()
+    }
+
+    /// Case of open dispute of the transaction, `None` if it was never
+    /// updated, or the transaction is not under dispute
+    pub fn dispute_case(&self, tx_id: TransactionId) -> Option<&DisputeCase> {
+        self.dispute_cases.get(&tx_id)
+    }
+
+    pub fn is_under_dispute(&self, tx_id: TransactionId) -> bool {
+        self.txs_under_dispute.contains(&tx_id)
+    }
+
+    /// Case of open dispute of the transaction, created when missing,
+    /// `None` if the transaction is not under dispute
+    pub fn dispute_case_mut(&mut self, tx_id: TransactionId) -> Option<&mut DisputeCase> {
+        self.is_under_dispute(tx_id)
+            .then(|| self.dispute_cases.entry(tx_id).or_default())
+    }
+
+    /// Transactions under dispute in ascending order, with their stage and case
+    pub fn open_disputes(
+        &self,
+    ) -> impl Iterator<Item = (TransactionId, Option<DisputeStage>, Option<&DisputeCase>)> {
+        let mut tx_ids: Vec<_> = self.txs_under_dispute.iter().copied().collect();
+        tx_ids.sort_unstable();
+        tx_ids
+            .into_iter()
+            .map(|tx_id| (tx_id, self.dispute_stage(tx_id), self.dispute_case(tx_id)))
+    }
+
+    pub fn snapshot(&self) -> AccountSnapshot {
+        AccountSnapshot {
+            available: self.available,
+            held: self.held,
+            pending: self.pending,
+            locked: self.locked,
+            open_disputes: self.txs_under_dispute.iter().copied().collect(),
+            extensions: self
+                .extensions
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Compact binary encoding of the whole state, for accounts spilled to
+    /// the cold tier, see [`crate::processor::account_tiers`]
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64);
+        for amount in [self.available, self.held, self.pending] {
+            bytes.extend_from_slice(&amount.serialize());
+        }
+        bytes.push(u8::from(self.locked));
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        for txs in [
+            &self.txs_under_dispute,
+            &self.pending_txs,
+            &self.open_authorizations,
+            &self.chargedback_txs,
+        ] {
+            bytes.extend_from_slice(&(txs.len() as u32).to_le_bytes());
+            for tx_id in txs {
+                bytes.extend_from_slice(&tx_id.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&(self.dispute_stages.len() as u32).to_le_bytes());
+        for (tx_id, stage) in &self.dispute_stages {
+            bytes.extend_from_slice(&tx_id.to_le_bytes());
+            bytes.push(match stage {
+                DisputeStage::Representment => 0,
+                DisputeStage::PreArbitration => 1,
+            });
+        }
+        fn put_str(bytes: &mut Vec<u8>, s: &str) {
+            bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        bytes.extend_from_slice(&(self.dispute_cases.len() as u32).to_le_bytes());
+        for (tx_id, case) in &self.dispute_cases {
+            bytes.extend_from_slice(&tx_id.to_le_bytes());
+            bytes.push(match case.status {
+                CaseStatus::New => 0,
+                CaseStatus::Investigating => 1,
+                CaseStatus::AwaitingEvidence => 2,
+            });
+            bytes.push(u8::from(case.assignee.is_some()));
+            if let Some(assignee) = &case.assignee {
+                put_str(&mut bytes, assignee);
+            }
+            bytes.extend_from_slice(&(case.notes.len() as u32).to_le_bytes());
+            for note in &case.notes {
+                put_str(&mut bytes, note);
+            }
+        }
+        // extensions are rare, and arbitrary JSON anyway
+        if !self.extensions.is_empty() {
+            serde_json::to_writer(&mut bytes, &self.extensions)
+                .expect("JSON values are serializable");
+        }
+        bytes
+    }
+
+    /// Decodes [`Self::encode`]d state, `None` if `bytes` are truncated or invalid
+    pub(crate) fn decode(mut bytes: &[u8]) -> Option<Self> {
+        fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+            let (head, rest) = bytes.split_first_chunk::<N>()?;
+            *bytes = rest;
+            Some(*head)
+        }
+        let mut acc = Account {
+            available: Decimal::deserialize(take(&mut bytes)?),
+            held: Decimal::deserialize(take(&mut bytes)?),
+            pending: Decimal::deserialize(take(&mut bytes)?),
+            locked: take::<1>(&mut bytes)?[0] != 0,
+            version: u64::from_le_bytes(take(&mut bytes)?),
+            ..Default::default()
+        };
+        for txs in [
+            &mut acc.txs_under_dispute,
+            &mut acc.pending_txs,
+            &mut acc.open_authorizations,
+            &mut acc.chargedback_txs,
+        ] {
+            let len = u32::from_le_bytes(take(&mut bytes)?);
+            for _ in 0..len {
+                txs.insert(TransactionId::from_le_bytes(take(&mut bytes)?));
+            }
+        }
+        let len = u32::from_le_bytes(take(&mut bytes)?);
+        for _ in 0..len {
+            let tx_id = TransactionId::from_le_bytes(take(&mut bytes)?);
+            let stage = match take::<1>(&mut bytes)?[0] {
+                0 => DisputeStage::Representment,
+                1 => DisputeStage::PreArbitration,
+                _ => return None,
+            };
+            acc.dispute_stages.insert(tx_id, stage);
+        }
+        fn take_str(bytes: &mut &[u8]) -> Option<String> {
+            let len = u32::from_le_bytes(take(bytes)?) as usize;
+            let s = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            String::from_utf8(s.to_vec()).ok()
+        }
+        let len = u32::from_le_bytes(take(&mut bytes)?);
+        for _ in 0..len {
+            let tx_id = TransactionId::from_le_bytes(take(&mut bytes)?);
+            let status = match take::<1>(&mut bytes)?[0] {
+                0 => CaseStatus::New,
+                1 => CaseStatus::Investigating,
+                2 => CaseStatus::AwaitingEvidence,
+                _ => return None,
+            };
+            let assignee = match take::<1>(&mut bytes)?[0] {
+                0 => None,
+                _ => Some(take_str(&mut bytes)?),
+            };
+            let notes = (0..u32::from_le_bytes(take(&mut bytes)?))
+                .map(|_| take_str(&mut bytes))
+                .collect::<Option<_>>()?;
+            acc.dispute_cases.insert(
+                tx_id,
+                DisputeCase {
+                    status,
+                    assignee,
+                    notes,
+                },
+            );
+        }
+        if !bytes.is_empty() {
+            acc.extensions = serde_json::from_slice(bytes).ok()?;
+        }
+        Some(acc)
+    }
+
+    /// Applies event only if nobody else applied events since `expected_version`
+    /// was read, so storage backends can detect concurrent writers and retry.
+    pub fn apply_if_version(
+        &mut self,
+        event: &AccountEvent,
+        expected_version: u64,
+    ) -> Result<(), AccountError> {
+        if self.version != expected_version {
+            return Err(AccountError::VersionMismatch {
+                expected: expected_version,
+                actual: self.version,
+            });
+        }
+        self.apply(event);
+        Ok(())
+    }
+
+    /// Fails if deposit `event` would raise balance, including pending
+    /// deposits, above the `cap`
+    pub fn check_balance_cap(
+        &self,
+        event: &AccountEvent,
+        cap: Decimal,
+    ) -> Result<(), AccountError> {
+        let deposit = matches!(
+            event.kind,
+            AccountEventKind::Deposited | AccountEventKind::DepositPending
+        );
+        let balance = self
+            .total_amount()
+            .checked_add(self.pending)
+            .and_then(|balance| balance.checked_add(event.amount));
+        if deposit && balance.is_none_or(|balance| balance > cap) {
+            return Err(AccountError::BalanceCapExceeded { cap });
+        }
+        Ok(())
+    }
+
+    /// Checks, that `event` can be applied without overflow, negative held
+    /// or pending balances, or modifying transaction in the wrong state.
+    /// Available balance may become negative, e.g. dispute after withdrawal.
+    pub fn check_apply(&self, event: &AccountEvent) -> Result<(), ApplyError> {
+        let amount = event.amount;
+        let tx_id = event.transaction_id;
+        let add = |a: Decimal, b: Decimal| a.checked_add(b).ok_or(ApplyError::Overflow);
+        let sub = |a: Decimal, b: Decimal| a.checked_sub(b).ok_or(ApplyError::Overflow);
+        let (available, held, pending, tx_state) = match event.kind {
+            AccountEventKind::Deposited | AccountEventKind::OpeningBalance => {
+                (add(self.available, amount)?, self.held, self.pending, true)
+            }
+            AccountEventKind::Withdrawn => {
+                (sub(self.available, amount)?, self.held, self.pending, true)
+            }
+            AccountEventKind::Disputed => (
+                sub(self.available, amount)?,
+                add(self.held, amount)?,
+                self.pending,
+                !self.txs_under_dispute.contains(&tx_id),
+            ),
+            AccountEventKind::Resolved => (
+                add(self.available, amount)?,
+                sub(self.held, amount)?,
+                self.pending,
+                self.txs_under_dispute.contains(&tx_id),
+            ),
+            AccountEventKind::Chargedback => (
+                self.available,
+                sub(self.held, amount)?,
+                self.pending,
+                self.txs_under_dispute.contains(&tx_id),
+            ),
+            AccountEventKind::DepositPending => (
+                self.available,
+                self.held,
+                add(self.pending, amount)?,
+                !self.pending_txs.contains(&tx_id),
+            ),
+            AccountEventKind::Settled => (
+                add(self.available, amount)?,
+                self.held,
+                sub(self.pending, amount)?,
+                self.pending_txs.contains(&tx_id),
+            ),
+            AccountEventKind::Authorized => (
+                sub(self.available, amount)?,
+                add(self.held, amount)?,
+                self.pending,
+                !self.open_authorizations.contains(&tx_id),
+            ),
+            AccountEventKind::Captured => (
+                self.available,
+                sub(self.held, amount)?,
+                self.pending,
+                self.open_authorizations.contains(&tx_id),
+            ),
+            AccountEventKind::Voided => (
+                add(self.available, amount)?,
+                sub(self.held, amount)?,
+                self.pending,
+                self.open_authorizations.contains(&tx_id),
+            ),
+            AccountEventKind::Locked => (self.available, self.held, self.pending, true),
+            AccountEventKind::Reinstated => (
+                add(self.available, amount)?,
+                self.held,
+                self.pending,
+                self.chargedback_txs.contains(&tx_id),
+            ),
+            AccountEventKind::Represented => (
+                self.available,
+                self.held,
+                self.pending,
+                self.txs_under_dispute.contains(&tx_id) && self.dispute_stage(tx_id).is_none(),
+            ),
+            AccountEventKind::PreArbitrated => (
+                self.available,
+                self.held,
+                self.pending,
+                self.dispute_stage(tx_id) == Some(DisputeStage::Representment),
+            ),
+            AccountEventKind::Unlocked => (self.available, self.held, self.pending, self.locked),
+            AccountEventKind::Erased => (self.available, self.held, self.pending, true),
+            AccountEventKind::Adjusted => {
+                (add(self.available, amount)?, self.held, self.pending, true)
+            }
+        };
+        if !tx_state {
+            return Err(ApplyError::TransactionStateMismatch {
+                kind: event.kind,
+                tx_id,
+            });
+        }
+        if held < Decimal::ZERO {
+            return Err(ApplyError::NegativeBalance { balance: "held" });
+        }
+        if pending < Decimal::ZERO {
+            return Err(ApplyError::NegativeBalance { balance: "pending" });
+        }
+        // total must be representable too
+        add(available, held)?;
+        Ok(())
+    }
+
+    /// Applies event after [`Account::check_apply`], so untrusted events
+    /// can't corrupt the account or panic. Use [`Account::apply`] only
+    /// for replaying events, that were already validated.
+    pub fn try_apply(&mut self, event: &AccountEvent) -> Result<(), ApplyError> {
+        self.check_apply(event)?;
+        self.apply(event);
+        Ok(())
+    }
+
     pub fn apply(&mut self, event: &AccountEvent) {
+        self.version += 1;
         match event.kind {
             AccountEventKind::Deposited => {
                 self.available += event.amount;
@@ -82,11 +799,72 @@ impl Account {
                 self.available += event.amount;
                 self.held -= event.amount;
                 self.txs_under_dispute.remove(&event.transaction_id);
+                self.dispute_stages.remove(&event.transaction_id);
+                self.dispute_cases.remove(&event.transaction_id);
             }
             AccountEventKind::Chargedback => {
                 self.held -= event.amount;
                 self.locked = true;
                 self.txs_under_dispute.remove(&event.transaction_id);
+                self.dispute_stages.remove(&event.transaction_id);
+                self.dispute_cases.remove(&event.transaction_id);
+                self.chargedback_txs.insert(event.transaction_id);
+            }
+            AccountEventKind::Reinstated => {
+                self.available += event.amount;
+                self.chargedback_txs.remove(&event.transaction_id);
+                // account stays frozen, while other chargebacks are not remediated
+                if self.chargedback_txs.is_empty() {
+                    self.locked = false;
+                }
+            }
+            AccountEventKind::DepositPending => {
+                self.pending += event.amount;
+                self.pending_txs.insert(event.transaction_id);
+            }
+            AccountEventKind::Settled => {
+                self.pending -= event.amount;
+                self.available += event.amount;
+                self.pending_txs.remove(&event.transaction_id);
+            }
+            AccountEventKind::Authorized => {
+                self.available -= event.amount;
+                self.held += event.amount;
+                self.open_authorizations.insert(event.transaction_id);
+            }
+            AccountEventKind::Captured => {
+                self.held -= event.amount;
+                self.open_authorizations.remove(&event.transaction_id);
+            }
+            AccountEventKind::Voided => {
+                self.held -= event.amount;
+                self.available += event.amount;
+                self.open_authorizations.remove(&event.transaction_id);
+            }
+            AccountEventKind::OpeningBalance => {
+                self.available += event.amount;
+            }
+            AccountEventKind::Locked => {
+                self.locked = true;
+            }
+            AccountEventKind::Represented => {
+                self.dispute_stages
+                    .insert(event.transaction_id, DisputeStage::Representment);
+            }
+            AccountEventKind::PreArbitrated => {
+                self.dispute_stages
+                    .insert(event.transaction_id, DisputeStage::PreArbitration);
+            }
+            // charged back transactions can still be reinstated
+            AccountEventKind::Unlocked => {
+                self.locked = false;
+            }
+            AccountEventKind::Adjusted => {
+                self.available += event.amount;
+            }
+            // custom fields are free-form, so they may hold personal data
+            AccountEventKind::Erased => {
+                self.clear_extensions();
             }
         }
     }
@@ -98,20 +876,44 @@ impl Account {
         if self.locked {
             return Err(AccountError::AccountFrozen);
         }
+        self.handle_create_transaction_ignoring_lock(command)
+    }
 
+    /// Same as [`Account::handle_create_transaction`], but locked account is
+    /// handled as unlocked one, for replays of historical data
+    pub fn handle_create_transaction_ignoring_lock(
+        &self,
+        command: CreateTransactionCommand,
+    ) -> Result<AccountEvent, AccountError> {
         match command.action {
-            CreateTransactionAction::Deposit => Ok(AccountEvent {
-                transaction_id: command.tx_id,
-                amount: command.amount,
-                kind: AccountEventKind::Deposited,
-            }),
+            CreateTransactionAction::Deposit => Ok(AccountEvent::of_transaction(
+                command.tx_id,
+                command.amount,
+                AccountEventKind::Deposited,
+            )),
             CreateTransactionAction::Withdraw => {
-                if self.available >= command.amount {
-                    Ok(AccountEvent {
-                        transaction_id: command.tx_id,
-                        amount: command.amount,
-                        kind: AccountEventKind::Withdrawn,
-                    })
+                if self.available >= command.amount.amount() {
+                    Ok(AccountEvent::of_transaction(
+                        command.tx_id,
+                        command.amount,
+                        AccountEventKind::Withdrawn,
+                    ))
+                } else {
+                    Err(AccountError::InsufficientFunds)
+                }
+            }
+            CreateTransactionAction::PendingDeposit => Ok(AccountEvent::of_transaction(
+                command.tx_id,
+                command.amount,
+                AccountEventKind::DepositPending,
+            )),
+            CreateTransactionAction::Authorize => {
+                if self.available >= command.amount.amount() {
+                    Ok(AccountEvent::of_transaction(
+                        command.tx_id,
+                        command.amount,
+                        AccountEventKind::Authorized,
+                    ))
                 } else {
                     Err(AccountError::InsufficientFunds)
                 }
@@ -123,38 +925,145 @@ impl Account {
         &self,
         command: ModifyTransactionCommand,
     ) -> Result<AccountEvent, AccountError> {
-        if self.locked {
+        self.handle_modify_transaction_in(command, DisputeFlow::Simple)
+    }
+
+    /// Same as [`Account::handle_modify_transaction`], with disputes
+    /// following the `flow` of the deployment
+    pub fn handle_modify_transaction_in(
+        &self,
+        command: ModifyTransactionCommand,
+        flow: DisputeFlow,
+    ) -> Result<AccountEvent, AccountError> {
+        // reinstatement is the way to unfreeze the account
+        if self.locked && !matches!(command.action, ModifyTransactionAction::Reinstate) {
             return Err(AccountError::AccountFrozen);
         }
+        if flow == DisputeFlow::Strict
+            && command.action == ModifyTransactionAction::Chargeback
+            && self.txs_under_dispute.contains(&command.tx_id)
+            && self.dispute_stage(command.tx_id).is_none()
+        {
+            return Err(AccountError::TransactionDisputeStateMismatch {
+                action: command.action,
+                dispute_state_str: "not represented yet".to_string(),
+            });
+        }
+        self.handle_modify_transaction_ignoring_lock(command)
+    }
+
+    /// Same as [`Account::handle_modify_transaction`], but locked account is
+    /// handled as unlocked one, for replays of historical data
+    pub fn handle_modify_transaction_ignoring_lock(
+        &self,
+        command: ModifyTransactionCommand,
+    ) -> Result<AccountEvent, AccountError> {
         let amount = command.amount;
         let transaction_id = command.tx_id;
 
+        let pending = self.pending_txs.contains(&command.tx_id);
+        if let ModifyTransactionAction::Settle = command.action {
+            return if pending {
+                Ok(AccountEvent::of_transaction(
+                    transaction_id,
+                    amount,
+                    AccountEventKind::Settled,
+                ))
+            } else {
+                Err(AccountError::TransactionNotPending)
+            };
+        }
+        if let ModifyTransactionAction::Capture | ModifyTransactionAction::Void = command.action {
+            if !self.open_authorizations.contains(&transaction_id) {
+                return Err(AccountError::AuthorizationNotOpen {
+                    action: command.action,
+                });
+            }
+            let kind = if let ModifyTransactionAction::Capture = command.action {
+                AccountEventKind::Captured
+            } else {
+                AccountEventKind::Voided
+            };
+            return Ok(AccountEvent::of_transaction(transaction_id, amount, kind));
+        }
+        if pending {
+            return Err(AccountError::TransactionNotSettled {
+                action: command.action,
+            });
+        }
+
+        if let ModifyTransactionAction::Reinstate = command.action {
+            return if self.chargedback_txs.contains(&transaction_id) {
+                Ok(AccountEvent::of_transaction(
+                    transaction_id,
+                    amount,
+                    AccountEventKind::Reinstated,
+                ))
+            } else {
+                Err(AccountError::TransactionNotChargedBack)
+            };
+        }
+
         let under_dispute = self.txs_under_dispute.contains(&command.tx_id);
 
         match (command.action, under_dispute) {
             (ModifyTransactionAction::Dispute, false) => {
                 match command.create_action {
-                    CreateTransactionAction::Deposit => {
+                    CreateTransactionAction::Deposit | CreateTransactionAction::PendingDeposit => {
                         // Question: maybe it makes sense to check available balance?
-                        Ok(AccountEvent {
+                        Ok(AccountEvent::of_transaction(
                             transaction_id,
                             amount,
-                            kind: AccountEventKind::Disputed,
-                        })
+                            AccountEventKind::Disputed,
+                        ))
+                    }
+                    CreateTransactionAction::Withdraw | CreateTransactionAction::Authorize => {
+                        Err(AccountError::DisputeNotSupported)
                     }
-                    CreateTransactionAction::Withdraw => Err(AccountError::DisputeNotSupported),
                 }
             }
-            (ModifyTransactionAction::Resolve, true) => Ok(AccountEvent {
+            (ModifyTransactionAction::Resolve, true) => Ok(AccountEvent::of_transaction(
                 transaction_id,
                 amount,
-                kind: AccountEventKind::Resolved,
-            }),
-            (ModifyTransactionAction::Chargeback, true) => Ok(AccountEvent {
+                AccountEventKind::Resolved,
+            )),
+            (ModifyTransactionAction::Chargeback, true) => Ok(AccountEvent::of_transaction(
                 transaction_id,
                 amount,
-                kind: AccountEventKind::Chargedback,
-            }),
+                AccountEventKind::Chargedback,
+            )),
+            (ModifyTransactionAction::Representment, true) => {
+                match self.dispute_stage(transaction_id) {
+                    None => Ok(AccountEvent::of_transaction(
+                        transaction_id,
+                        amount,
+                        AccountEventKind::Represented,
+                    )),
+                    Some(_) => Err(AccountError::TransactionDisputeStateMismatch {
+                        action: command.action,
+                        dispute_state_str: "already represented".to_string(),
+                    }),
+                }
+            }
+            (ModifyTransactionAction::PreArbitration, true) => {
+                match self.dispute_stage(transaction_id) {
+                    Some(DisputeStage::Representment) => Ok(AccountEvent::of_transaction(
+                        transaction_id,
+                        amount,
+                        AccountEventKind::PreArbitrated,
+                    )),
+                    Some(DisputeStage::PreArbitration) => {
+                        Err(AccountError::TransactionDisputeStateMismatch {
+                            action: command.action,
+                            dispute_state_str: "already in pre-arbitration".to_string(),
+                        })
+                    }
+                    None => Err(AccountError::TransactionDisputeStateMismatch {
+                        action: command.action,
+                        dispute_state_str: "not represented yet".to_string(),
+                    }),
+                }
+            }
             _ => Err(AccountError::TransactionDisputeStateMismatch {
                 action: command.action,
                 dispute_state_str: if under_dispute {
@@ -171,6 +1080,8 @@ impl Account {
 mod tests {
     use rust_decimal::prelude::{FromPrimitive, Zero};
 
+    use crate::money::Money;
+
     use super::*;
 
     #[test]
@@ -181,35 +1092,26 @@ mod tests {
             amount: Decimal::from_u32(10).unwrap(),
             kind: AccountEventKind::Deposited,
         });
-        assert_eq!(acc.available, Decimal::from_u32(10).unwrap());
-        assert_eq!(acc.held, Decimal::zero());
-        assert!(acc.txs_under_dispute.is_empty());
+        assert_account!(acc, available: 10, held: 0, open_disputes: []);
         acc.apply(&AccountEvent {
             transaction_id: 1,
             amount: Decimal::from_u32(3).unwrap(),
             kind: AccountEventKind::Withdrawn,
         });
-        assert_eq!(acc.available, Decimal::from_u32(7).unwrap());
-        assert_eq!(acc.held, Decimal::zero());
-        assert!(acc.txs_under_dispute.is_empty());
+        assert_account!(acc, available: 7, held: 0, open_disputes: []);
         // event is the source of truth, there's no more validation happening
         acc.apply(&AccountEvent {
             transaction_id: 3,
             amount: Decimal::from_u32(5).unwrap(),
             kind: AccountEventKind::Disputed,
         });
-        assert_eq!(acc.available, Decimal::from_u32(2).unwrap());
-        assert_eq!(acc.held, Decimal::from_u32(5).unwrap());
-        assert_eq!(acc.txs_under_dispute.len(), 1);
+        assert_account!(acc, available: 2, held: 5, open_disputes: [3]);
         acc.apply(&AccountEvent {
             transaction_id: 3,
             amount: Decimal::from_u32(5).unwrap(),
             kind: AccountEventKind::Resolved,
         });
-        assert_eq!(acc.available, Decimal::from_u32(7).unwrap());
-        assert_eq!(acc.held, Decimal::from_u32(0).unwrap());
-        assert!(acc.txs_under_dispute.is_empty());
-        assert!(!acc.locked);
+        assert_account!(acc, available: 7, held: 0, open_disputes: [], locked: false);
 
         acc.apply(&AccountEvent {
             transaction_id: 5,
@@ -221,10 +1123,14 @@ mod tests {
             amount: Decimal::from_u32(5).unwrap(),
             kind: AccountEventKind::Chargedback,
         });
-        assert_eq!(acc.available, Decimal::from_u32(2).unwrap());
-        assert_eq!(acc.held, Decimal::from_u32(0).unwrap());
-        assert!(acc.txs_under_dispute.is_empty());
-        assert!(acc.locked)
+        assert_eq!(
+            acc.snapshot(),
+            AccountSnapshot {
+                available: Decimal::from_u32(2).unwrap(),
+                locked: true,
+                ..Default::default()
+            }
+        );
     }
 
     #[test]
@@ -246,7 +1152,7 @@ mod tests {
             .handle_create_transaction(CreateTransactionCommand {
                 tx_id: 0,
                 action: CreateTransactionAction::Deposit,
-                amount: Decimal::from_u32(13).unwrap(),
+                amount: Money::from(13),
             })
             .unwrap();
         assert_eq!(deposit_evt.amount, Decimal::from_u32(13).unwrap());
@@ -256,7 +1162,7 @@ mod tests {
         let withdrawal_cmd = CreateTransactionCommand {
             tx_id: 0,
             action: CreateTransactionAction::Withdraw,
-            amount: Decimal::from_u32(5).unwrap(),
+            amount: Money::from(5),
         };
         let err = acc
             .handle_create_transaction(withdrawal_cmd.clone())
@@ -291,7 +1197,7 @@ mod tests {
         let dispute_cmd = ModifyTransactionCommand {
             tx_id: 1,
             action: ModifyTransactionAction::Dispute,
-            amount: Decimal::from_u32(13).unwrap(),
+            amount: Money::from(13),
             create_action: CreateTransactionAction::Deposit,
         };
         let dispute_evt = acc.handle_modify_transaction(dispute_cmd.clone()).unwrap();
@@ -328,7 +1234,7 @@ mod tests {
         let resolve_cmd = ModifyTransactionCommand {
             tx_id: 1,
             action: ModifyTransactionAction::Resolve,
-            amount: Decimal::from_u32(13).unwrap(),
+            amount: Money::from(13),
             create_action: CreateTransactionAction::Deposit,
         };
         let resolve_evt = acc.handle_modify_transaction(resolve_cmd.clone()).unwrap();
@@ -355,7 +1261,7 @@ mod tests {
         let chargeback_cmd = ModifyTransactionCommand {
             tx_id: 1,
             action: ModifyTransactionAction::Chargeback,
-            amount: Decimal::from_u32(13).unwrap(),
+            amount: Money::from(13),
             create_action: CreateTransactionAction::Deposit,
         };
         let chargeback_evt = acc
@@ -371,4 +1277,291 @@ mod tests {
             .unwrap_err();
         assert!(matches!(err, AccountError::AccountFrozen));
     }
+
+    #[test]
+    fn pending_deposit_settlement() {
+        let mut acc = Account::default();
+        let pending_evt = acc
+            .handle_create_transaction(CreateTransactionCommand {
+                tx_id: 1,
+                action: CreateTransactionAction::PendingDeposit,
+                amount: Money::from(10),
+            })
+            .unwrap();
+        assert_eq!(pending_evt.kind, AccountEventKind::DepositPending);
+        acc.apply(&pending_evt);
+        assert_eq!(acc.pending(), Decimal::from_u32(10).unwrap());
+        assert_eq!(acc.available(), Decimal::zero());
+        assert_eq!(acc.total_amount(), Decimal::zero());
+
+        let modify_cmd = |action| ModifyTransactionCommand {
+            tx_id: 1,
+            action,
+            amount: Money::from(10),
+            create_action: CreateTransactionAction::PendingDeposit,
+        };
+        // cannot dispute until settled
+        let err = acc
+            .handle_modify_transaction(modify_cmd(ModifyTransactionAction::Dispute))
+            .unwrap_err();
+        assert!(matches!(err, AccountError::TransactionNotSettled { .. }));
+
+        let settled_evt = acc
+            .handle_modify_transaction(modify_cmd(ModifyTransactionAction::Settle))
+            .unwrap();
+        acc.apply(&settled_evt);
+        assert_eq!(acc.pending(), Decimal::zero());
+        assert_eq!(acc.available(), Decimal::from_u32(10).unwrap());
+
+        // settled only once, after that it behaves as regular deposit
+        let err = acc
+            .handle_modify_transaction(modify_cmd(ModifyTransactionAction::Settle))
+            .unwrap_err();
+        assert!(matches!(err, AccountError::TransactionNotPending));
+        let dispute_evt = acc
+            .handle_modify_transaction(modify_cmd(ModifyTransactionAction::Dispute))
+            .unwrap();
+        assert_eq!(dispute_evt.kind, AccountEventKind::Disputed);
+    }
+
+    #[test]
+    fn authorization_capture_and_void() {
+        let mut acc = Account {
+            available: Decimal::from_u32(10).unwrap(),
+            ..Default::default()
+        };
+        let authorize = |tx_id, amount| CreateTransactionCommand {
+            tx_id,
+            action: CreateTransactionAction::Authorize,
+            amount: Money::from(amount),
+        };
+        let modify = |tx_id, action, amount| ModifyTransactionCommand {
+            tx_id,
+            action,
+            amount: Money::from(amount),
+            create_action: CreateTransactionAction::Authorize,
+        };
+
+        let err = acc.handle_create_transaction(authorize(1, 11)).unwrap_err();
+        assert!(matches!(err, AccountError::InsufficientFunds));
+
+        for evt in [
+            acc.handle_create_transaction(authorize(1, 4)).unwrap(),
+            acc.handle_create_transaction(authorize(2, 3)).unwrap(),
+        ] {
+            acc.apply(&evt);
+        }
+        assert_eq!(acc.available(), Decimal::from_u32(3).unwrap());
+        assert_eq!(acc.held(), Decimal::from_u32(7).unwrap());
+
+        // capture turns hold into withdrawal
+        let evt = acc
+            .handle_modify_transaction(modify(1, ModifyTransactionAction::Capture, 4))
+            .unwrap();
+        assert_eq!(evt.kind, AccountEventKind::Captured);
+        acc.apply(&evt);
+        assert_eq!(acc.available(), Decimal::from_u32(3).unwrap());
+        assert_eq!(acc.held(), Decimal::from_u32(3).unwrap());
+
+        // void releases the hold
+        let evt = acc
+            .handle_modify_transaction(modify(2, ModifyTransactionAction::Void, 3))
+            .unwrap();
+        assert_eq!(evt.kind, AccountEventKind::Voided);
+        acc.apply(&evt);
+        assert_eq!(acc.available(), Decimal::from_u32(6).unwrap());
+        assert_eq!(acc.held(), Decimal::zero());
+
+        // authorization can only be closed once
+        let err = acc
+            .handle_modify_transaction(modify(2, ModifyTransactionAction::Capture, 3))
+            .unwrap_err();
+        assert!(matches!(err, AccountError::AuthorizationNotOpen { .. }));
+        let err = acc
+            .handle_modify_transaction(modify(1, ModifyTransactionAction::Dispute, 4))
+            .unwrap_err();
+        assert!(matches!(err, AccountError::DisputeNotSupported));
+    }
+    #[test]
+    fn apply_if_version() {
+        let mut acc = Account::default();
+        let deposit = |amount| AccountEvent {
+            transaction_id: 1,
+            amount,
+            kind: AccountEventKind::Deposited,
+        };
+        assert_eq!(acc.version(), 0);
+        acc.apply_if_version(&deposit(Decimal::ONE), 0).unwrap();
+        assert_eq!(acc.version(), 1);
+
+        // another writer read the same version, but was late
+        let err = acc.apply_if_version(&deposit(Decimal::TWO), 0).unwrap_err();
+        assert!(matches!(
+            err,
+            AccountError::VersionMismatch {
+                expected: 0,
+                actual: 1
+            }
+        ));
+        assert_eq!(acc.available(), Decimal::ONE);
+    }
+
+    #[test]
+    fn balance_cap() {
+        let mut acc = Account::default();
+        let event = |amount, kind| AccountEvent::new(1, Decimal::from(amount), kind);
+        acc.apply(&event(60, AccountEventKind::Deposited));
+        acc.apply(&event(30, AccountEventKind::DepositPending));
+        let cap = Decimal::from(100);
+        acc.check_balance_cap(&event(10, AccountEventKind::Deposited), cap)
+            .unwrap();
+        let err = acc
+            .check_balance_cap(&event(11, AccountEventKind::DepositPending), cap)
+            .unwrap_err();
+        assert!(matches!(err, AccountError::BalanceCapExceeded { .. }));
+        // withdrawals are not limited
+        acc.check_balance_cap(&event(11, AccountEventKind::Withdrawn), cap)
+            .unwrap();
+    }
+
+    #[test]
+    fn try_apply_rejects_inconsistent_events() {
+        let mut acc = Account::default();
+        let event = |tx, amount, kind| AccountEvent::new(tx, amount, kind);
+        acc.try_apply(&event(1, Decimal::MAX, AccountEventKind::Deposited))
+            .unwrap();
+        let err = acc
+            .try_apply(&event(2, Decimal::ONE, AccountEventKind::Deposited))
+            .unwrap_err();
+        assert_eq!(err, ApplyError::Overflow);
+
+        let mut acc = Account::default();
+        acc.try_apply(&event(1, Decimal::TEN, AccountEventKind::Deposited))
+            .unwrap();
+        // resolving transaction, that is not disputed
+        let err = acc
+            .try_apply(&event(1, Decimal::ONE, AccountEventKind::Resolved))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ApplyError::TransactionStateMismatch {
+                kind: AccountEventKind::Resolved,
+                tx_id: 1
+            }
+        );
+        acc.try_apply(&event(1, Decimal::ONE, AccountEventKind::Disputed))
+            .unwrap();
+        let err = acc
+            .try_apply(&event(1, Decimal::TWO, AccountEventKind::Chargedback))
+            .unwrap_err();
+        assert_eq!(err, ApplyError::NegativeBalance { balance: "held" });
+        // nothing was applied by failed attempts
+        assert_eq!(acc.held(), Decimal::ONE);
+        assert_eq!(acc.version(), 2);
+    }
+
+    #[test]
+    fn reinstate_chargedback_transaction() {
+        let mut acc = Account::default();
+        let modify = |tx_id, action| ModifyTransactionCommand {
+            tx_id,
+            action,
+            amount: Money::from(5),
+            create_action: CreateTransactionAction::Deposit,
+        };
+        for tx_id in [1, 2] {
+            acc.apply(&AccountEvent::new(
+                tx_id,
+                Decimal::from(5),
+                AccountEventKind::Deposited,
+            ));
+        }
+        // only charged back transaction can be reinstated
+        let err = acc
+            .handle_modify_transaction(modify(1, ModifyTransactionAction::Reinstate))
+            .unwrap_err();
+        assert!(matches!(err, AccountError::TransactionNotChargedBack));
+
+        for tx_id in [1, 2] {
+            for action in [
+                ModifyTransactionAction::Dispute,
+                ModifyTransactionAction::Chargeback,
+            ] {
+                let event = acc
+                    .handle_modify_transaction_ignoring_lock(modify(tx_id, action))
+                    .unwrap();
+                acc.apply(&event);
+            }
+        }
+        assert_account!(acc, available: 0, held: 0, locked: true);
+
+        // frozen account accepts reinstatement, but stays frozen until
+        // all chargebacks are reinstated
+        let event = acc
+            .handle_modify_transaction(modify(1, ModifyTransactionAction::Reinstate))
+            .unwrap();
+        acc.try_apply(&event).unwrap();
+        assert_account!(acc, available: 5, locked: true);
+        let event = acc
+            .handle_modify_transaction(modify(2, ModifyTransactionAction::Reinstate))
+            .unwrap();
+        acc.try_apply(&event).unwrap();
+        assert_account!(acc, available: 10, locked: false);
+
+        let err = acc
+            .handle_modify_transaction(modify(2, ModifyTransactionAction::Reinstate))
+            .unwrap_err();
+        assert!(matches!(err, AccountError::TransactionNotChargedBack));
+    }
+
+    #[test]
+    fn strict_dispute_flow_requires_representment() {
+        let mut acc = Account::default();
+        let modify = |tx_id, action| ModifyTransactionCommand {
+            tx_id,
+            action,
+            amount: Money::from(5),
+            create_action: CreateTransactionAction::Deposit,
+        };
+        let strict = |acc: &mut Account, tx_id, action| {
+            let event =
+                acc.handle_modify_transaction_in(modify(tx_id, action), DisputeFlow::Strict)?;
+            acc.try_apply(&event).unwrap();
+            Ok::<_, AccountError>(())
+        };
+        for tx_id in [1, 2] {
+            acc.apply(&AccountEvent::new(
+                tx_id,
+                Decimal::from(5),
+                AccountEventKind::Deposited,
+            ));
+            strict(&mut acc, tx_id, ModifyTransactionAction::Dispute).unwrap();
+        }
+
+        let err = strict(&mut acc, 1, ModifyTransactionAction::Chargeback).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Chargeback cannot be initiated, because the transaction is not represented yet"
+        );
+        // pre-arbitration follows representment
+        let err = strict(&mut acc, 1, ModifyTransactionAction::PreArbitration).unwrap_err();
+        assert_eq!(err.code(), "dispute_state_mismatch");
+        // simple flow allows chargeback straight away
+        let event = acc
+            .handle_modify_transaction(modify(2, ModifyTransactionAction::Chargeback))
+            .unwrap();
+        assert_eq!(event.kind(), AccountEventKind::Chargedback);
+
+        strict(&mut acc, 1, ModifyTransactionAction::Representment).unwrap();
+        assert_eq!(acc.dispute_stage(1), Some(DisputeStage::Representment));
+        strict(&mut acc, 1, ModifyTransactionAction::Representment).unwrap_err();
+        strict(&mut acc, 1, ModifyTransactionAction::PreArbitration).unwrap();
+        assert_eq!(acc.dispute_stage(1), Some(DisputeStage::PreArbitration));
+        // funds stay held until the dispute is decided
+        assert_account!(acc, available: 0, held: 10, open_disputes: [1, 2]);
+
+        strict(&mut acc, 1, ModifyTransactionAction::Chargeback).unwrap();
+        assert_eq!(acc.dispute_stage(1), None);
+        assert_account!(acc, held: 5, locked: true, open_disputes: [2]);
+    }
 }
@@ -1,27 +1,1113 @@
-use std::fs::File;
+use std::{fs::File, io::BufWriter, panic, process::ExitCode, time::Duration};
 
 use anyhow::{Context, Result};
-use cute_ledger::bin_utils::Service;
-
-fn main() -> Result<()> {
-    let filename = std::env::args()
-        .nth(1)
-        .context("Expected a file name as the first argument")?;
-    let file = File::open(&filename).with_context(|| format!("Failed to open `{filename}`"))?;
-
-    let service = Service {
-        input: file,
-        output: &mut std::io::stdout(),
-        error_printer: Box::new(|line, err| {
-            match err {
-                cute_ledger::processor::TransactionProcessError::CommandErr(err) => {
-                    eprintln!("Error at line {line}: {err}")
-                }
-                cute_ledger::processor::TransactionProcessError::AccountErr(_) => {
-                    // these are not technical errors, so we don't need to print them
+use clap::{ArgGroup, Args, Parser, Subcommand};
+#[cfg(feature = "aws")]
+use cute_ledger::processor::dynamodb_store::DynamoDbStateStore;
+#[cfg(feature = "zstd")]
+use cute_ledger::processor::file_store::Compression;
+#[cfg(feature = "redis")]
+use cute_ledger::processor::redis_store::RedisStateStore;
+#[cfg(feature = "sqlite")]
+use cute_ledger::processor::sqlite_processor::{GroupCommit, SqliteTransactionProcessor};
+use cute_ledger::{
+    account::{AccountEventKind, DisputeFlow, TransactionId},
+    bin_utils::{
+        AccountsOutput, InputFormat, OutputFormat, Service, UnknownKindPolicy,
+        account_clients::AccountClients,
+        cdc::ChangeFeed,
+        circuit_breaker::{BreakerAction, CircuitBreaker},
+        config_reload::ConfigReload,
+        csv_parser::{ColumnMapping, InvalidRow},
+        csv_printer,
+        export::{self, ExportFormat},
+        fixed_width::FixedWidthLayout,
+        integrity,
+        manifest::{HashingReader, HashingWriter, Manifest, ReportSigner},
+        normalize::KindMappings,
+        number_format::NumberFormat,
+        planner::InputProfile,
+        progress::ProgressReporter,
+        reject_log::RejectLog,
+        run_report::{ExitStatus, StrictCategory},
+        signature::SignatureVerifier,
+        simulation::{self, SimulatedBackend, WorkloadProfile},
+        standing_orders,
+        statement_printer::{self, StatementFormat},
+        watermark::LatePolicy,
+    },
+    command::{AccountCommandError, ZeroAmountPolicy},
+    history::{EventFilter, EventHistory, EventSeq, TxStatus},
+    id_allocator::{DEFAULT_FIRST_TX_ID, HashedAllocator, IdAllocator, RangeAllocator},
+    processor::{
+        ClientId, TransactionProcessError, TransactionProcessor,
+        account_tiers::TieringPolicy,
+        balance_cap::{BalanceCaps, OverCapPolicy},
+        event_schema::EventV2,
+        file_store::{FileStateStore, SegmentPolicy},
+        in_memory_processor::InMemoryTransactionProcessor,
+        kyc::{KycLimits, KycRules, KycStatus},
+        store_processor::StoreTransactionProcessor,
+        tx_id_order::TxIdOrder,
+        withdrawal_rules::WithdrawalRules,
+    },
+    projection::{DisputeCount, FraudHeuristics},
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static ALLOCATOR: cute_ledger::bin_utils::resource_usage::TrackingAllocator =
+    cute_ledger::bin_utils::resource_usage::TrackingAllocator;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print statement of a single client: opening balance, each event
+    /// with running balance, and closing balance
+    Statement(StatementArgs),
+    /// Print events recorded in a store matching all given filters as CSV
+    Query(QueryArgs),
+    /// Export every event of a single client in chronological order,
+    /// for customer support and GDPR data requests
+    Export(ExportArgs),
+    /// Profile the input and estimate memory, disk and time needed by
+    /// each backend, before choosing one for a large run
+    Plan(PlanArgs),
+    /// Drive each backend with generated activity of a number of days and
+    /// report sustained throughput and latency percentiles, for capacity tests
+    Simulate(SimulateArgs),
+    /// Browse accounts, their events and open disputes of stored state
+    /// in a terminal UI
+    #[cfg(feature = "tui")]
+    Inspect(InspectArgs),
+    /// Validate invariants of stored state, such as held funds matching
+    /// open disputes, and print violations; exits with 4 if any are found
+    Check(CheckArgs),
+}
+
+#[derive(Args, Serialize)]
+struct RunArgs {
+    /// CSV file with transactions
+    #[arg(required = true)]
+    filename: Option<String>,
+    /// Format of the accounts report: csv or xlsx
+    #[arg(long, default_value = "csv")]
+    output_format: OutputFormat,
+    /// Accounts to print: all, changed (created or changed during this run,
+    /// when warm-starting from previous state) or changed-with-tombstones
+    /// (also an empty row for every untouched account)
+    #[arg(long, default_value = "all")]
+    accounts_output: AccountsOutput,
+    /// Add `extensions` column with custom fields of accounts as a JSON
+    /// object, such as `disputes` count (CSV output only)
+    #[arg(long)]
+    extended_report: bool,
+    /// Exit with code 4 if any rows of these categories occur: rejects,
+    /// invalid, skipped; all of them when no value is given (`--strict=rejects`)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 0..,
+        require_equals = true,
+        default_missing_values = ["rejects", "invalid", "skipped"]
+    )]
+    strict: Vec<StrictCategory>,
+    /// Print run summary and per-stage processing timings to stderr
+    #[arg(long)]
+    stats: bool,
+    /// Report peak memory and map sizes with run summary, and allocation
+    /// stats when built with `alloc-stats` feature
+    #[arg(long)]
+    resource_usage: bool,
+    /// Expected number of distinct clients, to pre-size accounts map
+    #[arg(long, default_value_t = 0, conflicts_with = "backend")]
+    expect_clients: usize,
+    /// Expected number of deposits and withdrawals, to pre-size transactions map
+    #[arg(long, default_value_t = 0, conflicts_with = "backend")]
+    expect_txs: usize,
+    /// CSV file with standing orders, expanded and processed after the input
+    #[arg(long)]
+    standing_orders: Option<String>,
+    /// First tx id assigned to transactions expanded from standing orders
+    #[arg(long, default_value_t = DEFAULT_FIRST_TX_ID)]
+    standing_orders_first_tx: TransactionId,
+    /// Derive ids of synthetic transactions from hashes of this namespace
+    /// (e.g. instance id), spread from the first tx id up, instead of
+    /// assigning them sequentially
+    #[arg(long)]
+    synthetic_id_namespace: Option<String>,
+    /// Drop records of resolved, charged back, captured and voided transactions
+    /// to save memory; resolved transactions cannot be disputed again
+    #[arg(long, conflicts_with = "backend")]
+    gc_settled_txs: bool,
+    /// Keep only ids of withdrawals, which can't be disputed, in a compressed
+    /// bitmap instead of full records, to save memory
+    #[arg(long, conflicts_with = "backend")]
+    compact_txs: bool,
+    /// Replay of historical data: accounts locked by chargebacks still
+    /// accept later transactions, locks are only reported in the output
+    #[arg(long, conflicts_with = "backend")]
+    backfill: bool,
+    /// Park dispute, resolve and chargeback rows referencing unknown transactions,
+    /// until the transaction arrives, and report rows that were never matched
+    #[arg(long, conflicts_with = "backend")]
+    suspense: bool,
+    /// Maximum balance of every account, including pending deposits
+    #[arg(long, conflicts_with = "backend")]
+    balance_cap: Option<Decimal>,
+    /// CSV file with `client,cap` columns, caps of individual accounts
+    #[arg(long, conflicts_with = "backend")]
+    client_caps: Option<String>,
+    /// What to do with deposits exceeding the cap: reject, or hold in suspense
+    #[arg(long, default_value = "reject", conflicts_with = "backend")]
+    over_cap: OverCapPolicy,
+    /// Queue transactions of these clients for review instead of applying them,
+    /// e.g. `--watch 7,42`; queued transactions are reported at the end
+    #[arg(long, value_delimiter = ',', conflicts_with = "backend")]
+    watch: Vec<ClientId>,
+    /// Reject withdrawals below this amount
+    #[arg(long, conflicts_with = "backend")]
+    min_withdrawal: Option<Decimal>,
+    /// Reject withdrawals, that are not multiples of this amount, e.g. 0.25
+    #[arg(long, conflicts_with = "backend")]
+    withdrawal_denomination: Option<Decimal>,
+    /// CSV file with `client,status` columns, status is verified or unverified.
+    /// Transactions of unverified clients are limited by the `--unverified-*` options
+    #[arg(long, conflicts_with = "backend")]
+    kyc_status: Option<String>,
+    /// Treat clients missing from `--kyc-status` file as unverified
+    #[arg(long, conflicts_with = "backend")]
+    unverified_by_default: bool,
+    /// Largest single deposit of unverified client
+    #[arg(long, conflicts_with = "backend")]
+    unverified_deposit_ceiling: Option<Decimal>,
+    /// Largest single withdrawal of unverified client
+    #[arg(long, conflicts_with = "backend")]
+    unverified_withdrawal_ceiling: Option<Decimal>,
+    /// Largest sum of deposits of unverified client
+    #[arg(long, conflicts_with = "backend")]
+    unverified_deposit_limit: Option<Decimal>,
+    /// Largest sum of withdrawals of unverified client
+    #[arg(long, conflicts_with = "backend")]
+    unverified_withdrawal_limit: Option<Decimal>,
+    /// Number of rows held back and reordered by `timestamp` column, so
+    /// disputes arriving slightly before their deposit are not rejected
+    #[arg(long, default_value_t = 0)]
+    reorder_buffer: usize,
+    /// Track event-time watermark trailing the highest `timestamp` by this
+    /// many units; rows behind it are late
+    #[arg(long)]
+    allowed_lateness: Option<u64>,
+    /// What to do with late rows: apply, divert to the late queue, or reject
+    #[arg(long, default_value = "apply", requires = "allowed_lateness")]
+    late_events: LatePolicy,
+    /// Write diverted late rows to this CSV file, in input format
+    #[arg(long, requires = "allowed_lateness")]
+    late_queue: Option<String>,
+    /// CSV file with `client,public_key` columns; rows of these clients must
+    /// carry a valid ed25519 `signature`
+    #[arg(long)]
+    client_keys: Option<String>,
+    /// Trim fields, fix case and synonyms of types, and decimal commas
+    /// before parsing, and report changed rows
+    #[arg(long)]
+    normalize: bool,
+    /// JSON file mapping partner-specific types and signed amounts onto
+    /// canonical types, by source of rows; implies normalization
+    #[arg(long)]
+    kind_mappings: Option<String>,
+    /// How amounts are written: plain (1234.56), point (1,234.56) or comma (1.234,56)
+    #[arg(long, default_value = "plain")]
+    number_format: NumberFormat,
+    /// Names of input columns holding expected ones, e.g.
+    /// `txn_type=type,customer_id=client,txn_id=tx,value=amount`
+    #[arg(long)]
+    columns: Option<ColumnMapping>,
+    /// Input is fixed-width records with given fields, each as `name:offset:length`,
+    /// e.g. `type:0:10,client:10:5,tx:15:10,amount:25:15`
+    #[arg(long)]
+    fixed_width: Option<FixedWidthLayout>,
+    /// Input is ISO 20022 camt message, accounts of its entries are mapped
+    /// to clients by this CSV file with `account,client` columns
+    #[cfg(feature = "iso20022")]
+    #[arg(long)]
+    iso20022_accounts: Option<String>,
+    /// Input is SWIFT MT940 statement, its accounts are mapped to clients
+    /// by this CSV file with `account,client` columns
+    #[arg(long)]
+    mt940_accounts: Option<String>,
+    /// Input has no header row, its columns in order are given instead,
+    /// e.g. `type,client,tx,amount`
+    #[arg(long, value_delimiter = ',')]
+    headerless: Option<Vec<String>>,
+    /// What to do with rows of unknown type: reject, or skip with a warning
+    #[arg(long, default_value = "reject")]
+    unknown_kinds: UnknownKindPolicy,
+    /// What to do with zero-amount deposits and withdrawals: accept,
+    /// skip with a warning, or reject
+    #[arg(long, default_value = "accept", conflicts_with = "backend")]
+    zero_amounts: ZeroAmountPolicy,
+    /// Dispute state machine: `simple`, or `strict` requiring `representment`
+    /// of a dispute before its chargeback
+    #[arg(long, default_value = "simple", conflicts_with = "backend")]
+    dispute_flow: DisputeFlow,
+    /// For upstreams with increasing tx ids: `global` or `per-client`
+    /// rejects deposits and withdrawals with ids lower than accepted ones
+    #[arg(long, default_value = "any", conflicts_with = "backend")]
+    tx_id_order: TxIdOrder,
+    /// Skip with a warning modify rows repeating the last action applied
+    /// to the transaction, e.g. retried resolves, instead of rejecting them
+    #[arg(long, conflicts_with = "backend")]
+    idempotent_modifies: bool,
+    /// Spill accounts of the in-memory backend idle for this many
+    /// transactions to a compact cold tier, for long-tail client populations
+    #[arg(long, conflicts_with = "backend")]
+    cold_after: Option<u64>,
+    /// Append every applied event of the in-memory backend to this file as
+    /// JSON lines with log sequence numbers, continuing after its last one
+    #[arg(long, value_name = "FILE", conflicts_with = "backend")]
+    cdc: Option<String>,
+    /// Print accounts ordered by client id, with nothing depending on time,
+    /// so outputs of the same input are byte identical. Events appended to
+    /// `--event-store` have no time of the append, and repeated options are
+    /// ordered in the manifest.
+    #[arg(long)]
+    deterministic: bool,
+    /// Origin of transactions recorded for audit (API key id, partner id),
+    /// unless rows have `source` column. Defaults to input file name
+    #[arg(long)]
+    source: Option<String>,
+    /// Write JSON manifest with input and output hashes, row counts,
+    /// engine version and these options to this file
+    #[arg(long)]
+    manifest: Option<String>,
+    /// Sign the accounts report with ed25519 key from this file (hex encoded
+    /// 32-byte secret key), key id is embedded in the manifest
+    #[arg(long, requires = "signature")]
+    signing_key: Option<String>,
+    /// Write hex encoded detached signature of the accounts report to this file
+    #[arg(long, requires = "signing_key")]
+    signature: Option<String>,
+    /// Emit single-line JSON progress events (rows, errors, throughput) to stderr
+    #[arg(long)]
+    machine_progress: bool,
+    /// Write every rejected row, with error code and message, to this file as JSON lines
+    #[arg(long)]
+    rejects: Option<String>,
+    /// Interval between progress events, in milliseconds
+    #[arg(long, default_value_t = 1000)]
+    progress_interval_ms: u64,
+    /// Stop processing rows of a client whose row crashed the processor or
+    /// broke an invariant of its account, report it and continue with other clients
+    #[arg(long)]
+    isolate_clients: bool,
+    /// Reject disputes and other rows referencing a rejected deposit or
+    /// withdrawal with the line where it was rejected
+    #[arg(long)]
+    track_rejected_txs: bool,
+    /// Trip the circuit breaker when more than `--breaker-threshold` of this
+    /// many last rows are rejected, as that usually means a malformed file
+    #[arg(long)]
+    breaker_window: Option<usize>,
+    /// Share of rejected rows in the window, above which the breaker trips
+    #[arg(long, default_value_t = 0.9, requires = "breaker_window")]
+    breaker_threshold: f64,
+    /// What a tripped breaker does: halt, quarantine-client or quarantine-source
+    #[arg(long, default_value = "halt", requires = "breaker_window")]
+    breaker_action: BreakerAction,
+    /// Apply sections of rows from this source tentatively, rolling a section
+    /// back when more than `--max-error-rate` of its rows are rejected
+    #[arg(long)]
+    tentative_source: Vec<String>,
+    /// Share of rejected rows, above which a tentative section is rolled back
+    #[arg(long, default_value_t = 0.1)]
+    max_error_rate: f64,
+    /// JSON file with fraud thresholds, balance cap, limits of unverified
+    /// clients, dispute flow and breaker threshold, overriding the
+    /// corresponding options; reloaded when it changes or on SIGHUP
+    #[arg(long, conflicts_with = "backend")]
+    risk_config: Option<String>,
+    /// Interval between checks of `--risk-config` for changes, in milliseconds
+    #[arg(long, default_value_t = 1000, requires = "risk_config")]
+    risk_config_interval_ms: u64,
+    /// Write average held balance of every client and day to this CSV file,
+    /// using `timestamp` column as seconds since unix epoch
+    #[arg(long)]
+    held_accrual: Option<String>,
+    /// Write disputes open at the end of the run, with their stage and case
+    /// status, assignee and notes, to this CSV file
+    #[arg(long)]
+    open_disputes: Option<String>,
+    /// Run fraud heuristics and write flagged clients to this CSV file
+    #[arg(long, conflicts_with = "backend")]
+    fraud_flags: Option<String>,
+    /// SQLite database file keeping state between runs, created when missing
+    #[cfg(feature = "sqlite")]
+    #[arg(long, group = "backend")]
+    sqlite: Option<String>,
+    /// Keep a bloom filter of stored transaction ids sized for this many ids,
+    /// so rows referencing unknown transactions don't query the database
+    #[cfg(feature = "sqlite")]
+    #[arg(long, requires = "sqlite")]
+    tx_filter: Option<usize>,
+    /// Commit this many transactions to the database at once
+    #[cfg(feature = "sqlite")]
+    #[arg(long, requires = "sqlite")]
+    commit_batch: Option<usize>,
+    /// Commit a batch once its oldest transaction waits this many milliseconds
+    #[cfg(feature = "sqlite")]
+    #[arg(long, default_value_t = 100, requires = "commit_batch")]
+    commit_delay_ms: u64,
+    /// Directory of event segment files keeping state between runs
+    #[arg(long, group = "backend")]
+    event_store: Option<String>,
+    /// Seal the active event segment once it grows to this many bytes
+    #[arg(long, default_value_t = SegmentPolicy::default().max_bytes)]
+    segment_bytes: u64,
+    /// Seal the active event segment once it's open for this many seconds
+    #[arg(long)]
+    segment_max_age: Option<u64>,
+    /// Compress sealed event segments with zstd of this level
+    #[cfg(feature = "zstd")]
+    #[arg(long)]
+    compress_segments: Option<i32>,
+    /// Redis URL of the state shared with other instances, e.g. redis://127.0.0.1/
+    #[cfg(feature = "redis")]
+    #[arg(long, group = "backend")]
+    redis: Option<String>,
+    /// DynamoDB table of the state shared with other instances,
+    /// credentials and region are read from the environment
+    #[cfg(feature = "aws")]
+    #[arg(long, group = "backend")]
+    dynamodb_table: Option<String>,
+}
+
+#[derive(Args)]
+struct StatementArgs {
+    /// CSV file with transactions
+    filename: String,
+    #[arg(long)]
+    client: ClientId,
+    /// First event sequence number included in the statement
+    #[arg(long, default_value_t = 0)]
+    from: EventSeq,
+    /// Sequence number of the first event after the statement
+    #[arg(long, default_value_t = EventSeq::MAX)]
+    to: EventSeq,
+    /// Format of the statement: text or csv
+    #[arg(long, default_value = "text")]
+    format: StatementFormat,
+}
+
+/// Store with events recorded by runs, see `--event-store` and `--sqlite`
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct RecordedArgs {
+    /// Directory of event segment files, see `--event-store`
+    #[arg(long)]
+    event_store: Option<String>,
+    /// SQLite database, see `--sqlite`
+    #[cfg(feature = "sqlite")]
+    #[arg(long)]
+    sqlite: Option<String>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    recorded: RecordedArgs,
+    #[arg(long)]
+    client: ClientId,
+    /// Format of the export: csv or json
+    #[arg(long, default_value = "csv")]
+    format: ExportFormat,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct InspectArgs {
+    /// Directory of event segment files, see `--event-store`
+    #[arg(long)]
+    event_store: Option<String>,
+    /// SQLite database, see `--sqlite`
+    #[cfg(feature = "sqlite")]
+    #[arg(long)]
+    sqlite: Option<String>,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("store").required(true).multiple(false)))]
+struct CheckArgs {
+    /// Directory of event segment files, see `--event-store`
+    #[arg(long, group = "store")]
+    event_store: Option<String>,
+    /// SQLite database, see `--sqlite`; balances of its accounts table are
+    /// compared with events
+    #[cfg(feature = "sqlite")]
+    #[arg(long, group = "store")]
+    sqlite: Option<String>,
+    /// Accounts report in CSV format, whose balances are compared with
+    /// events instead, e.g. the output of the run that wrote them
+    #[arg(long)]
+    accounts: Option<String>,
+}
+
+#[derive(Args)]
+struct PlanArgs {
+    /// CSV file with transactions
+    filename: String,
+    /// Read only this many rows, and extrapolate the rest from the file size
+    #[arg(long)]
+    sample_rows: Option<u64>,
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    #[arg(long, default_value_t = 1000)]
+    clients: ClientId,
+    /// Simulated transactions per second of activity
+    #[arg(long, default_value_t = 10)]
+    tx_per_sec: u64,
+    /// Chance of a transaction disputing the latest deposit of its client
+    #[arg(long, default_value_t = 0.01)]
+    dispute_probability: f64,
+    /// Simulated days of activity
+    #[arg(long, default_value_t = 1)]
+    days: u64,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Comma separated backends: in-memory, event-store, sqlite
+    #[arg(long, value_delimiter = ',', default_value = "in-memory,event-store")]
+    backends: Vec<SimulatedBackend>,
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    #[command(flatten)]
+    recorded: RecordedArgs,
+    #[arg(long)]
+    client: Option<ClientId>,
+    #[arg(long)]
+    tx: Option<TransactionId>,
+    /// Event kind, e.g. deposited, withdrawn, disputed, chargedback
+    #[arg(long, value_parser = parse_event_kind)]
+    kind: Option<AccountEventKind>,
+    #[arg(long)]
+    min_amount: Option<Decimal>,
+    /// Only events of transactions in this status: disputed or chargedback
+    #[arg(long)]
+    status: Option<TxStatus>,
+}
+
+fn parse_event_kind(name: &str) -> Result<AccountEventKind, String> {
+    AccountEventKind::from_name(name).ok_or_else(|| format!("unknown event kind `{name}`"))
+}
+
+/// Exit codes: 0 clean run, 2 some rows rejected by business rules,
+/// 3 some rows malformed or input unreadable, 4 failure, see [`ExitStatus`]
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            // help and version are printed the same way
+            if !err.use_stderr() {
+                return ExitCode::SUCCESS;
+            }
+            return ExitCode::from(ExitStatus::Fatal.code());
+        }
+    };
+    let result = match cli.command {
+        Some(Command::Statement(args)) => statement(args).map(|()| ExitStatus::Clean),
+        Some(Command::Query(args)) => query(args).map(|()| ExitStatus::Clean),
+        Some(Command::Export(args)) => export(args).map(|()| ExitStatus::Clean),
+        Some(Command::Plan(args)) => plan(args).map(|()| ExitStatus::Clean),
+        Some(Command::Simulate(args)) => simulate(args).map(|()| ExitStatus::Clean),
+        #[cfg(feature = "tui")]
+        Some(Command::Inspect(args)) => inspect(args).map(|()| ExitStatus::Clean),
+        Some(Command::Check(args)) => check(args),
+        // a panic is a bug rather than bad input, so it fails the run
+        None => panic::catch_unwind(|| run(cli.run)).unwrap_or(Ok(ExitStatus::Fatal)),
+    };
+    match result {
+        Ok(status) => ExitCode::from(status.code()),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            if is_invalid_input(&err) {
+                ExitCode::from(ExitStatus::InvalidInput.code())
+            } else {
+                ExitCode::from(ExitStatus::Fatal.code())
+            }
+        }
+    }
+}
+
+/// Input, that couldn't be parsed, stops the run
+fn is_invalid_input(err: &anyhow::Error) -> bool {
+    #[cfg(feature = "iso20022")]
+    if err.is::<cute_ledger::bin_utils::iso20022::Iso20022Error>() {
+        return true;
+    }
+    err.is::<InvalidRow>() || err.is::<cute_ledger::bin_utils::mt940::Mt940Error>()
+}
+
+fn open(filename: &str) -> Result<File> {
+    File::open(filename).with_context(|| format!("Failed to open `{filename}`"))
+}
+
+fn print_error(line: u64, err: TransactionProcessError) {
+    match err {
+        TransactionProcessError::CommandErr(err) => {
+            eprintln!("Error at line {line}: {err}")
+        }
+        TransactionProcessError::AccountErr(_) | TransactionProcessError::KycErr(_) => {
+            // these are not technical errors, so we don't need to print them
+        }
+        TransactionProcessError::StorageErr(err) => {
+            eprintln!("Storage error at line {line}: {err}")
+        }
+        TransactionProcessError::SignatureErr(_) | TransactionProcessError::ApplyErr(_) => {
+            eprintln!("Error at line {line}: {err}")
+        }
+    }
+}
+
+fn run(mut args: RunArgs) -> Result<ExitStatus> {
+    if args.deterministic {
+        // order of repeated options only shows in the manifest
+        args.strict.sort_unstable();
+        args.strict.dedup();
+        args.watch.sort_unstable();
+        args.watch.dedup();
+        args.tentative_source.sort_unstable();
+        args.tentative_source.dedup();
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        let mut processor = SqliteTransactionProcessor::open(path)
+            .with_context(|| format!("Failed to open database `{path}`"))?;
+        if let Some(capacity) = args.tx_filter {
+            processor = processor
+                .with_tx_filter(capacity)
+                .context("Failed to load transaction ids")?;
+        }
+        if let Some(max_batch) = args.commit_batch {
+            processor = processor.with_group_commit(GroupCommit {
+                max_batch,
+                max_delay: Duration::from_millis(args.commit_delay_ms),
+            });
+        }
+        return run_with(args, processor);
+    }
+    if let Some(dir) = &args.event_store {
+        #[cfg_attr(not(feature = "zstd"), allow(unused_mut))]
+        let mut policy = SegmentPolicy {
+            max_bytes: args.segment_bytes,
+            max_age: args.segment_max_age.map(Duration::from_secs),
+            ..Default::default()
+        };
+        #[cfg(feature = "zstd")]
+        if let Some(level) = args.compress_segments {
+            policy.compression = Compression::Zstd { level };
+        }
+        let mut store = FileStateStore::open(dir, policy)
+            .with_context(|| format!("Failed to open event store `{dir}`"))?;
+        if args.deterministic {
+            store = store.without_timestamps();
+        }
+        return run_with(args, StoreTransactionProcessor::new(store));
+    }
+    #[cfg(feature = "redis")]
+    if let Some(url) = &args.redis {
+        let store = RedisStateStore::connect(url, "cute-ledger")
+            .with_context(|| format!("Failed to connect to `{url}`"))?;
+        return run_with(args, StoreTransactionProcessor::new(store));
+    }
+    #[cfg(feature = "aws")]
+    if let Some(table) = &args.dynamodb_table {
+        let store = DynamoDbStateStore::connect(table)
+            .context("Failed to start DynamoDB client runtime")?;
+        return run_with(args, StoreTransactionProcessor::new(store));
+    }
+
+    let mut processor =
+        InMemoryTransactionProcessor::with_capacity(args.expect_clients, args.expect_txs);
+    if args.suspense || args.over_cap == OverCapPolicy::Suspend {
+        processor = processor.with_suspense();
+    }
+    let mut caps = BalanceCaps {
+        global: args.balance_cap,
+        policy: args.over_cap,
+        ..Default::default()
+    };
+    if let Some(filename) = &args.client_caps {
+        caps.parse_client_caps(open(filename)?)?;
+    }
+    processor = processor.with_balance_caps(caps);
+    processor = processor.with_withdrawal_rules(WithdrawalRules {
+        minimum: args.min_withdrawal,
+        denomination: args.withdrawal_denomination,
+    });
+    if let Some(filename) = &args.kyc_status {
+        let limits = KycLimits {
+            deposit_ceiling: args.unverified_deposit_ceiling,
+            withdrawal_ceiling: args.unverified_withdrawal_ceiling,
+            deposit_limit: args.unverified_deposit_limit,
+            withdrawal_limit: args.unverified_withdrawal_limit,
+        };
+        let default_status = if args.unverified_by_default {
+            KycStatus::Unverified
+        } else {
+            KycStatus::Verified
+        };
+        let mut rules = KycRules::new(limits, default_status);
+        rules.parse_statuses(open(filename)?)?;
+        processor = processor.with_kyc(rules);
+    }
+    if args.gc_settled_txs {
+        processor = processor.with_settled_tx_gc();
+    }
+    if args.compact_txs {
+        processor = processor.with_compact_txs();
+    }
+    if args.backfill {
+        processor = processor.with_deferred_locks();
+    }
+    processor = processor.with_zero_amounts(args.zero_amounts);
+    processor = processor.with_dispute_flow(args.dispute_flow);
+    processor = processor.with_tx_id_order(args.tx_id_order);
+    if args.idempotent_modifies {
+        processor = processor.with_idempotent_modifies();
+    }
+    if let Some(idle_after) = args.cold_after {
+        processor = processor.with_tiering(TieringPolicy { idle_after });
+    }
+    if let Some(filename) = &args.cdc {
+        let feed = ChangeFeed::append(filename)
+            .with_context(|| format!("Failed to open change feed `{filename}`"))?;
+        processor = processor.with_subscriber(feed);
+    }
+    if !args.watch.is_empty() {
+        processor = processor.with_watchlist(args.watch.iter().copied());
+    }
+    if args.fraud_flags.is_some() {
+        processor = processor.with_projection(FraudHeuristics::default());
+    }
+    if args.extended_report {
+        processor = processor.with_extension(DisputeCount);
+    }
+    run_with(args, processor)
+}
+
+fn run_with<P: TransactionProcessor>(args: RunArgs, processor: P) -> Result<ExitStatus> {
+    let file = open(args.filename.as_deref().unwrap_or_default())?;
+
+    let extra_rows = match &args.standing_orders {
+        Some(filename) => {
+            let orders = standing_orders::parse_standing_orders(open(filename)?)?;
+            let first = args.standing_orders_first_tx;
+            let mut ids: Box<dyn IdAllocator> = match &args.synthetic_id_namespace {
+                Some(namespace) => {
+                    Box::new(HashedAllocator::new(namespace.as_str(), first..=u32::MAX))
                 }
+                None => Box::new(RangeAllocator::starting_at(first)),
+            };
+            standing_orders::expand(&orders, ids.as_mut())?
+        }
+        None => Vec::new(),
+    };
+    let verifier = match &args.client_keys {
+        Some(filename) => Some(SignatureVerifier::parse_keys(open(filename)?)?),
+        None => None,
+    };
+
+    let mut input = HashingReader::new(file);
+    let mut output = HashingWriter::new(std::io::stdout());
+    let unknown_kinds = args.unknown_kinds;
+    let late_events = args.late_events;
+    let input_format = match (&args.fixed_width, &args.mt940_accounts) {
+        (Some(layout), _) => InputFormat::FixedWidth(layout.clone()),
+        (None, Some(filename)) => InputFormat::Mt940(AccountClients::parse(open(filename)?)?),
+        (None, None) => InputFormat::Csv,
+    };
+    #[cfg(feature = "iso20022")]
+    let input_format = match &args.iso20022_accounts {
+        Some(filename) => InputFormat::Iso20022(AccountClients::parse(open(filename)?)?),
+        None => input_format,
+    };
+    let mut columns = args.columns.clone().unwrap_or_default();
+    if let Some(schema) = &args.headerless {
+        columns = columns.headerless(schema.clone());
+    }
+    let rejects = match &args.rejects {
+        Some(filename) => Some(RejectLog::json_lines(BufWriter::new(
+            File::create(filename).with_context(|| format!("Failed to create `{filename}`"))?,
+        ))),
+        None => None,
+    };
+    let signer = match &args.signing_key {
+        Some(filename) => Some(
+            ReportSigner::from_hex(
+                &std::fs::read_to_string(filename)
+                    .with_context(|| format!("Failed to read `{filename}`"))?,
+            )
+            .with_context(|| format!("Invalid signing key in `{filename}`"))?,
+        ),
+        None => None,
+    };
+    let mut service = Service::builder()
+        .input(&mut input)
+        .output(&mut output)
+        .processor(processor)
+        .input_format(input_format)
+        .output_format(args.output_format)
+        .accounts_output(args.accounts_output)
+        .extended_report(args.extended_report)
+        .extra_rows(extra_rows)
+        .reorder_buffer(args.reorder_buffer)
+        .normalize(args.normalize)
+        .number_format(args.number_format)
+        .columns(columns)
+        .unknown_kinds(args.unknown_kinds)
+        .deterministic(args.deterministic)
+        .isolate_clients(args.isolate_clients)
+        .track_rejected_txs(args.track_rejected_txs)
+        .tentative_sources(args.tentative_source.clone(), args.max_error_rate)
+        .held_accrual(args.held_accrual.is_some())
+        .open_disputes(args.open_disputes.is_some())
+        .resource_usage(args.resource_usage)
+        .on_error(move |line, err| match err {
+            TransactionProcessError::CommandErr(AccountCommandError::UnknownKind { .. })
+                if unknown_kinds == UnknownKindPolicy::Skip =>
+            {
+                eprintln!("Warning at line {line}: {err}, row skipped")
+            }
+            TransactionProcessError::CommandErr(
+                AccountCommandError::ZeroAmountSkipped { .. }
+                | AccountCommandError::RepeatedModifySkipped { .. },
+            ) => eprintln!("Warning at line {line}: {err}, row skipped"),
+            TransactionProcessError::CommandErr(AccountCommandError::LateEvent { .. })
+                if late_events == LatePolicy::Divert =>
+            {
+                eprintln!("Warning at line {line}: {err}, row diverted to late queue")
+            }
+            err => print_error(line, err),
+        });
+    if let Some(filename) = &args.kind_mappings {
+        let mappings = KindMappings::from_json(open(filename)?)
+            .with_context(|| format!("Invalid kind mappings `{filename}`"))?;
+        service = service.kind_mappings(mappings);
+    }
+    if let Some(window) = args.breaker_window {
+        service = service.circuit_breaker(CircuitBreaker::new(
+            window,
+            args.breaker_threshold,
+            args.breaker_action,
+        ));
+    }
+    if let Some(allowed_lateness) = args.allowed_lateness {
+        service = service.late_events(allowed_lateness, args.late_events);
+    }
+    if let Some(verifier) = verifier {
+        service = service.verifier(verifier);
+    }
+    if let Some(source) = args.source.clone().or_else(|| args.filename.clone()) {
+        service = service.source(source);
+    }
+    if let Some(filename) = &args.risk_config {
+        let interval = Duration::from_millis(args.risk_config_interval_ms);
+        let reload = ConfigReload::new(filename, interval)
+            .with_context(|| format!("Failed to watch risk config `{filename}`"))?;
+        service = service.risk_config(reload);
+    }
+    if args.machine_progress {
+        let interval = Duration::from_millis(args.progress_interval_ms);
+        service = service.progress(ProgressReporter::json_lines(interval, std::io::stderr()));
+    }
+    if let Some(rejects) = rejects {
+        service = service.rejects(rejects);
+    }
+    let report = service.build().run()?;
+    if let Some(filename) = &args.manifest {
+        let file =
+            File::create(filename).with_context(|| format!("Failed to create `{filename}`"))?;
+        let mut manifest = Manifest::new(&report, input.hash(), output.hash(), &args);
+        if let Some(signer) = &signer {
+            manifest = manifest.with_signing_key_id(signer.key_id());
+        }
+        manifest.write_json(file)?;
+    }
+    if let (Some(signer), Some(filename)) = (&signer, &args.signature) {
+        std::fs::write(filename, signer.sign(&output.digest()))
+            .with_context(|| format!("Failed to write `{filename}`"))?;
+    }
+    if let Some(filename) = &args.held_accrual {
+        let mut file =
+            File::create(filename).with_context(|| format!("Failed to create `{filename}`"))?;
+        csv_printer::print_held_accrual(&mut file, &report.held_accrual)?;
+    }
+    if let Some(filename) = &args.late_queue {
+        let mut file =
+            File::create(filename).with_context(|| format!("Failed to create `{filename}`"))?;
+        csv_printer::print_late_rows(&mut file, &report.late)?;
+    }
+    if let Some(filename) = &args.open_disputes {
+        let mut file =
+            File::create(filename).with_context(|| format!("Failed to create `{filename}`"))?;
+        csv_printer::print_open_disputes(&mut file, &report.open_disputes)?;
+    }
+    if let Some(filename) = &args.fraud_flags {
+        let mut file =
+            File::create(filename).with_context(|| format!("Failed to create `{filename}`"))?;
+        csv_printer::print_flags(&mut file, &report.flags)?;
+    }
+    if args.stats {
+        eprint!("{report}");
+    } else {
+        if let Some(suspense) = &report.suspense {
+            eprint!("{suspense}");
+        }
+        if let Some(compliance) = &report.compliance {
+            eprint!("{compliance}");
+        }
+        if let Some(reviews) = &report.reviews {
+            eprint!("{reviews}");
+        }
+        if !report.normalized.is_empty() {
+            eprintln!("rows normalized: {}", report.normalized.len());
+        }
+        if report.rows_late > 0 {
+            eprintln!("rows late: {}", report.rows_late);
+        }
+        for batch in &report.rolled_back {
+            eprintln!(
+                "Rows of `{}` at lines {}-{} rolled back: {} of {} rejected",
+                batch.source, batch.first_line, batch.last_line, batch.rejected, batch.rows
+            );
+        }
+        for reload in &report.config_reloads {
+            match &reload.error {
+                Some(err) => eprintln!(
+                    "Risk config reload after {} rows failed, previous one kept: {err}",
+                    reload.after_rows
+                ),
+                None => eprintln!("Risk config reloaded after {} rows", reload.after_rows),
             }
-        }),
+        }
+        for source in &report.quarantined_sources {
+            eprintln!(
+                "Source `{}` quarantined at line {}: {}",
+                source.source, source.line, source.reason
+            );
+        }
+        if let Some(trip) = &report.halted {
+            eprintln!(
+                "Processing halted at line {}: {:.0}% of rows rejected",
+                trip.line,
+                trip.reject_rate * 100.0
+            );
+        }
+        for client in &report.quarantined {
+            eprintln!(
+                "Client {} quarantined at line {}: {}",
+                client.client, client.line, client.reason
+            );
+        }
+        if let Some(resources) = &report.resources {
+            eprint!("{resources}");
+        }
+    }
+    Ok(report.exit_status(&args.strict))
+}
+
+fn statement(args: StatementArgs) -> Result<()> {
+    let processor = Service::builder()
+        .input(open(&args.filename)?)
+        .processor(InMemoryTransactionProcessor::default().with_history())
+        .source(args.filename.clone())
+        .on_error(print_error)
+        .build()
+        .run_into()?;
+    let statement = processor
+        .statement(args.client, args.from..args.to)
+        .context("Event history is not recorded")?;
+    statement_printer::print_statement(&mut std::io::stdout(), &statement, args.format)
+}
+
+fn query(args: QueryArgs) -> Result<()> {
+    let history = recorded_history(&args.recorded)?;
+    let filter = EventFilter {
+        client: args.client,
+        tx: args.tx,
+        kind: args.kind,
+        min_amount: args.min_amount,
+        status: args.status,
     };
-    service.run()
+    csv_printer::print_events(&mut std::io::stdout(), history.query(&filter).into_iter())
+}
+
+fn plan(args: PlanArgs) -> Result<()> {
+    let file = open(&args.filename)?;
+    let total_bytes = file.metadata().ok().map(|metadata| metadata.len());
+    let profile = InputProfile::sample(file, total_bytes, args.sample_rows)?;
+    print!("{profile}");
+    Ok(())
+}
+
+fn simulate(args: SimulateArgs) -> Result<()> {
+    let profile = WorkloadProfile {
+        clients: args.clients,
+        tx_per_sec: args.tx_per_sec,
+        dispute_probability: args.dispute_probability,
+        days: args.days,
+        seed: args.seed,
+    };
+    println!(
+        "simulating {} transactions of {} clients over {} days",
+        profile.transactions(),
+        profile.clients,
+        profile.days
+    );
+    for backend in args.backends {
+        let dir = std::env::temp_dir().join(format!(
+            "cute-ledger-simulate-{}-{}",
+            std::process::id(),
+            backend.name()
+        ));
+        let report = match backend {
+            SimulatedBackend::InMemory => simulation::simulate(
+                &profile,
+                backend.name(),
+                &mut InMemoryTransactionProcessor::default(),
+            ),
+            SimulatedBackend::EventStore => {
+                let store = FileStateStore::open(dir.clone(), SegmentPolicy::default())
+                    .with_context(|| format!("Failed to open event store `{}`", dir.display()))?;
+                let mut processor = StoreTransactionProcessor::new(store);
+                simulation::simulate(&profile, backend.name(), &mut processor)
+            }
+            #[cfg(feature = "sqlite")]
+            SimulatedBackend::Sqlite => {
+                std::fs::create_dir_all(&dir)?;
+                let path = dir.join("ledger.db");
+                let mut processor = SqliteTransactionProcessor::open(&path)
+                    .with_context(|| format!("Failed to open database `{}`", path.display()))?;
+                simulation::simulate(&profile, backend.name(), &mut processor)
+            }
+            #[cfg(not(feature = "sqlite"))]
+            SimulatedBackend::Sqlite => {
+                anyhow::bail!("sqlite backend requires the `sqlite` feature")
+            }
+        };
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        print!("{report}");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+fn inspect(args: InspectArgs) -> Result<()> {
+    use cute_ledger::bin_utils::inspector::{Inspector, LedgerSnapshot};
+
+    let mut events = Vec::new();
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        let processor = SqliteTransactionProcessor::open(path)
+            .with_context(|| format!("Failed to open database `{path}`"))?;
+        for (client, _) in processor.accounts() {
+            events.push((client, processor.client_events(client)?));
+        }
+    }
+    if let Some(dir) = &args.event_store {
+        let store = FileStateStore::open(dir, SegmentPolicy::default())
+            .with_context(|| format!("Failed to open event store `{dir}`"))?;
+        for client in store.clients() {
+            let stored = store.load_range(client, 0..u64::MAX)?;
+            events.push((client, stored.iter().map(EventV2::event).collect()));
+        }
+    }
+    Inspector::new(LedgerSnapshot::from_events(events)).run()?;
+    Ok(())
+}
+
+fn check(args: CheckArgs) -> Result<ExitStatus> {
+    let mut events = Vec::new();
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_mut))]
+    let mut stored = match &args.accounts {
+        Some(filename) => Some(
+            integrity::read_balances(open(filename)?)
+                .with_context(|| format!("Invalid accounts report `{filename}`"))?,
+        ),
+        None => None,
+    };
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        let processor = SqliteTransactionProcessor::open(path)
+            .with_context(|| format!("Failed to open database `{path}`"))?;
+        for (client, _) in processor.accounts() {
+            events.push((client, processor.client_events(client)?));
+        }
+        if stored.is_none() {
+            stored = Some(processor.stored_balances()?.into_iter().collect());
+        }
+    }
+    if let Some(dir) = &args.event_store {
+        let store = FileStateStore::open(dir, SegmentPolicy::default())
+            .with_context(|| format!("Failed to open event store `{dir}`"))?;
+        for client in store.clients() {
+            let stored = store.load_range(client, 0..u64::MAX)?;
+            events.push((client, stored.iter().map(EventV2::event).collect()));
+        }
+    }
+    let accounts = events.len();
+    let violations = integrity::check(events, stored.as_ref());
+    for violation in &violations {
+        println!("{violation}");
+    }
+    eprintln!(
+        "Checked {accounts} accounts, found {} violations",
+        violations.len()
+    );
+    Ok(if violations.is_empty() {
+        ExitStatus::Clean
+    } else {
+        ExitStatus::Fatal
+    })
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let history = recorded_history(&args.recorded)?;
+    export::export_client(&mut std::io::stdout(), &history, args.client, args.format)
+}
+
+/// Events of all clients in the order they were applied, as recorded in the store
+fn recorded_history(args: &RecordedArgs) -> Result<EventHistory> {
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        let processor = SqliteTransactionProcessor::open(path)
+            .with_context(|| format!("Failed to open database `{path}`"))?;
+        return Ok(processor.history()?);
+    }
+    let dir = args.event_store.as_deref().unwrap_or_default();
+    let store = FileStateStore::open(dir, SegmentPolicy::default())
+        .with_context(|| format!("Failed to open event store `{dir}`"))?;
+    Ok(store.history()?)
 }
@@ -0,0 +1,65 @@
+//! Bank statements identify accounts (IBAN or other account id) rather
+//! than clients, so accounts are mapped to clients owning them.
+
+use std::{collections::HashMap, io::Read};
+
+use csv::Trim;
+use serde::Deserialize;
+
+use crate::{
+    account::AccountEventKind,
+    event_bus::{AppliedEvent, EventSubscriber},
+    processor::ClientId,
+};
+
+#[derive(Deserialize)]
+struct AccountClientRow {
+    account: String,
+    client: ClientId,
+}
+
+/// Clients owning accounts of the statements
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountClients {
+    clients: HashMap<String, ClientId>,
+}
+
+impl AccountClients {
+    /// Parses CSV with `account,client` columns
+    pub fn parse(source: impl Read) -> Result<Self, csv::Error> {
+        let mut accounts = Self::default();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(source);
+        for row in reader.deserialize() {
+            let AccountClientRow { account, client } = row?;
+            accounts.insert(account, client);
+        }
+        Ok(accounts)
+    }
+
+    pub fn insert(&mut self, account: String, client: ClientId) {
+        self.clients.insert(account, client);
+    }
+
+    /// Removes all account identifiers mapped to the `client`, returns how many
+    pub fn erase_client(&mut self, client: ClientId) -> usize {
+        let before = self.clients.len();
+        self.clients.retain(|_, owner| *owner != client);
+        before - self.clients.len()
+    }
+
+    pub fn client(&self, account: &str) -> Option<ClientId> {
+        self.clients.get(account).copied()
+    }
+}
+
+/// Subscribed to the processor, mappings of the client are removed by
+/// [`crate::processor::admin::AdminOps::erase_client`]
+impl EventSubscriber for AccountClients {
+    fn on_event(&mut self, event: &AppliedEvent) {
+        if event.event.kind() == AccountEventKind::Erased {
+            self.erase_client(event.client_id);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Async counterpart of [`super::Service`] input side, for tokio/futures
+//! pipelines: transactions come as a [`Stream`] and are processed one by one.
+//! Every record is applied synchronously between awaits, and processing
+//! yields to the executor every [`RECORDS_PER_POLL`] records, also when the
+//! stream is always ready, so dropping the processing future (e.g. losing
+//! `tokio::select!` to a shutdown signal) cancels it at a record boundary,
+//! and [`AsyncProcessor::last_line`] tells where to resume from.
+
+use std::{
+    future::poll_fn,
+    pin::{Pin, pin},
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::processor::TransactionProcessor;
+
+use super::{
+    TransactionSource,
+    csv_parser::{InvalidRow, Transaction},
+    error_sink::ErrorSink,
+};
+
+/// Records processed in a single poll, before other tasks get their turn
+pub const RECORDS_PER_POLL: u64 = 64;
+
+/// Transaction with line number of the record it was parsed from
+#[derive(Debug)]
+pub struct SourcedTransaction {
+    pub line: u64,
+    pub transaction: Transaction,
+}
+
+impl From<(u64, Transaction)> for SourcedTransaction {
+    fn from((line, transaction): (u64, Transaction)) -> Self {
+        Self { line, transaction }
+    }
+}
+
+/// Stream of rows of any parser, see [`source_stream`]
+pub struct SourceStream<S> {
+    source: S,
+}
+
+/// Adapts parser of the input, e.g. [`super::csv_parser::CsvTransactionParser`],
+/// into a stream. Parsing is blocking, so large inputs are better parsed
+/// on a blocking thread and sent over a channel.
+pub fn source_stream<S: TransactionSource>(source: S) -> SourceStream<S> {
+    SourceStream { source }
+}
+
+impl<S: TransactionSource> SourceStream<S> {
+    /// Row, at which the stream ended, as it couldn't be parsed
+    pub fn error(&self) -> Option<&InvalidRow> {
+        self.source.error()
+    }
+}
+
+impl<S: TransactionSource + Unpin> Stream for SourceStream<S> {
+    type Item = SourcedTransaction;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.source.next().map(SourcedTransaction::from))
+    }
+}
+
+/// Processes streams of transactions into the wrapped processor
+pub struct AsyncProcessor<P> {
+    processor: P,
+    last_line: Option<u64>,
+}
+
+impl<P: TransactionProcessor> AsyncProcessor<P> {
+    pub fn new(processor: P) -> Self {
+        Self {
+            processor,
+            last_line: None,
+        }
+    }
+
+    /// Line of the last processed record, also when processing was cancelled
+    pub fn last_line(&self) -> Option<u64> {
+        self.last_line
+    }
+
+    /// Processes records until the stream ends, errors of rejected ones
+    /// are sent to `errors`. Returns number of processed records.
+    pub async fn process_stream<S>(&mut self, stream: S, mut errors: impl ErrorSink) -> u64
+    where
+        S: Stream<Item = SourcedTransaction>,
+    {
+        let mut stream = pin!(stream);
+        let mut processed = 0;
+        while let Some(SourcedTransaction { line, transaction }) =
+            poll_fn(|cx| stream.as_mut().poll_next(cx)).await
+        {
+            let trace_id = transaction.trace_id.as_deref();
+            if let Err(err) = self.processor.process_transaction_traced(
+                transaction.tx,
+                transaction.client,
+                transaction.amount,
+                transaction.kind,
+                transaction.source.as_deref(),
+                trace_id,
+            ) {
+                errors.report_traced(line, trace_id, err);
+            }
+            self.last_line = Some(line);
+            processed += 1;
+            if processed % RECORDS_PER_POLL == 0 {
+                yield_now().await;
+            }
+        }
+        processed
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    pub fn into_inner(self) -> P {
+        self.processor
+    }
+}
+
+/// Pending once, with the task woken right away, so the executor polls
+/// other tasks and `select!` branches before it continues
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Waker;
+
+    use rust_decimal::Decimal;
+
+    use crate::{
+        bin_utils::{csv_parser::CsvTransactionParser, error_sink::SilentSink},
+        processor::in_memory_processor::InMemoryTransactionProcessor,
+    };
+
+    use super::*;
+
+    /// Yields a row, then stays pending forever, like a feed waiting for data
+    struct StalledFeed(Option<SourcedTransaction>);
+
+    impl Stream for StalledFeed {
+        type Item = SourcedTransaction;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            match self.0.take() {
+                Some(row) => Poll::Ready(Some(row)),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        pin!(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn processes_and_cancels_at_record_boundary() {
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,5.0\n";
+        let mut processor = AsyncProcessor::new(InMemoryTransactionProcessor::default());
+        let mut errors = Vec::new();
+        let stream = source_stream(CsvTransactionParser::new(input.as_bytes()));
+        // fewer records than processed in a single poll
+        assert_eq!(
+            poll_once(processor.process_stream(stream, &mut errors)),
+            Poll::Ready(2)
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(processor.last_line(), Some(3));
+
+        let row = CsvTransactionParser::new("type,client,tx,amount\ndeposit,1,3,1.0\n".as_bytes())
+            .next()
+            .unwrap();
+        let feed = StalledFeed(Some(row.into()));
+        // future is dropped while waiting for the next record
+        assert!(poll_once(processor.process_stream(feed, &mut errors)).is_pending());
+        assert_eq!(processor.last_line(), Some(2));
+        assert_eq!(
+            processor.processor().account(1).unwrap().available(),
+            Decimal::from(3)
+        );
+    }
+
+    #[test]
+    fn ready_stream_is_cancelled_between_records() {
+        let mut input = "type,client,tx,amount\n".to_string();
+        for tx in 1..=2 * RECORDS_PER_POLL + 1 {
+            input.push_str(&format!("deposit,1,{tx},1.0\n"));
+        }
+        let mut processor = AsyncProcessor::new(InMemoryTransactionProcessor::default());
+        let stream = source_stream(CsvTransactionParser::new(input.as_bytes()));
+        // shutdown won, while the stream still had records ready
+        assert!(poll_once(processor.process_stream(stream, SilentSink)).is_pending());
+        // header is the first line
+        assert_eq!(processor.last_line(), Some(RECORDS_PER_POLL + 1));
+        assert_eq!(
+            processor.processor().account(1).unwrap().available(),
+            Decimal::from(RECORDS_PER_POLL)
+        );
+    }
+}
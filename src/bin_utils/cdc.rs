@@ -0,0 +1,214 @@
+//! Change data capture: every applied event as a JSON line with a log
+//! sequence number (LSN), global across clients and increasing by one from 1,
+//! so warehouses can load changes incrementally, remembering the last LSN
+//! they loaded, and deduplicate records on it.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::TransactionId,
+    event_bus::{AppliedEvent, EventSubscriber},
+    processor::ClientId,
+};
+
+/// Log sequence number of a change, `0` is before the first one
+pub type Lsn = u64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    pub lsn: Lsn,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub event: String,
+    pub amount: Decimal,
+    /// Account state after the event
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+/// Subscriber writing [`ChangeRecord`]s as JSON lines. The first write error
+/// is kept, and nothing is written after it, so the feed has no gaps.
+pub struct ChangeFeed {
+    output: Box<dyn Write>,
+    next_lsn: Lsn,
+    error: Option<io::Error>,
+}
+
+impl ChangeFeed {
+    /// Feed, that continues after `last_lsn` written by a previous run
+    pub fn json_lines(output: impl Write + 'static, last_lsn: Lsn) -> Self {
+        Self {
+            output: Box::new(output),
+            next_lsn: last_lsn + 1,
+            error: None,
+        }
+    }
+
+    /// Appends to the feed file, continuing after its last record.
+    /// Fails if the file ends with a partially written record.
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let last_lsn = last_lsn(&mut file)?;
+        Ok(Self::json_lines(BufWriter::new(file), last_lsn))
+    }
+
+    /// LSN of the next applied event
+    pub fn next_lsn(&self) -> Lsn {
+        self.next_lsn
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl EventSubscriber for ChangeFeed {
+    fn on_event(&mut self, event: &AppliedEvent) {
+        if self.error.is_some() {
+            return;
+        }
+        let record = ChangeRecord {
+            lsn: self.next_lsn,
+            client: event.client_id,
+            tx: event.event.transaction_id(),
+            event: event.event.kind().name().to_string(),
+            amount: event.event.amount(),
+            available: event.account.available(),
+            held: event.account.held(),
+            locked: event.account.locked(),
+            source: event.source.map(str::to_string),
+            trace_id: event.trace_id.map(str::to_string),
+        };
+        let result = serde_json::to_writer(&mut self.output, &record)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.output));
+        match result {
+            Ok(()) => self.next_lsn += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.error {
+            Some(err) => Err(io::Error::new(err.kind(), err.to_string())),
+            None => self.output.flush(),
+        }
+    }
+}
+
+/// Records of the feed after `lsn`, e.g. the last one loaded into
+/// the warehouse; `0` reads all of them
+pub fn read_since(input: impl Read, lsn: Lsn) -> impl Iterator<Item = io::Result<ChangeRecord>> {
+    BufReader::new(input)
+        .lines()
+        .map(|line| Ok(serde_json::from_str::<ChangeRecord>(&line?)?))
+        .filter(move |record| !matches!(record, Ok(record) if record.lsn <= lsn))
+}
+
+/// Reads the feed backwards, until its last record
+fn last_lsn(file: &mut File) -> io::Result<Lsn> {
+    const CHUNK: u64 = 4096;
+    let len = file.seek(SeekFrom::End(0))?;
+    if len == 0 {
+        return Ok(0);
+    }
+    let mut tail = Vec::new();
+    let mut start = len;
+    while start > 0 && !tail[..tail.len().saturating_sub(1)].contains(&b'\n') {
+        let chunk = start.min(CHUNK);
+        start -= chunk;
+        file.seek(SeekFrom::Start(start))?;
+        let mut buf = vec![0; chunk as usize];
+        file.read_exact(&mut buf)?;
+        buf.append(&mut tail);
+        tail = buf;
+    }
+    let Some(body) = tail.strip_suffix(b"\n") else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "change feed ends with a partial record",
+        ));
+    };
+    let line = match body.iter().rposition(|b| *b == b'\n') {
+        Some(pos) => &body[pos + 1..],
+        None => body,
+    };
+    let record: ChangeRecord = serde_json::from_slice(line)?;
+    Ok(record.lsn)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::TransactionKind,
+        processor::{TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn feed_continues_after_last_lsn() {
+        let path = std::env::temp_dir().join(format!("cute-ledger-cdc-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_subscriber(ChangeFeed::append(&path).unwrap());
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor.flush_subscribers().unwrap();
+        drop(processor);
+        // the next run continues numbering
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_subscriber(ChangeFeed::append(&path).unwrap());
+        processor
+            .process_transaction(2, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert_eq!(processor.subscriber::<ChangeFeed>().unwrap().next_lsn(), 4);
+        processor.flush_subscribers().unwrap();
+        drop(processor);
+
+        let records: Vec<_> = read_since(File::open(&path).unwrap(), 0)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let lsns: Vec<_> = records.iter().map(|record| record.lsn).collect();
+        assert_eq!(lsns, [1, 2, 3]);
+        assert_eq!(records[2].event, "deposited");
+        assert_eq!(
+            (records[1].available, records[1].held),
+            (0.into(), 10.into())
+        );
+
+        let since: Vec<_> = read_since(File::open(&path).unwrap(), 2)
+            .map(|record| record.unwrap().lsn)
+            .collect();
+        assert_eq!(since, [3]);
+
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"lsn\":")
+            .unwrap();
+        assert!(ChangeFeed::append(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
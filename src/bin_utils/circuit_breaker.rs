@@ -0,0 +1,165 @@
+//! Circuit breaker over the rolling reject rate. A 90% reject rate nearly
+//! always means a malformed file rather than real business errors, so once
+//! the rate over the last rows exceeds the threshold, processing is halted,
+//! or the client or source with the most rejected rows is quarantined.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+use crate::processor::ClientId;
+
+/// What happens when the breaker trips
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BreakerAction {
+    /// Stop processing, remaining rows are not read
+    #[default]
+    Halt,
+    /// Reject further rows of the client with the most rejected rows
+    QuarantineClient,
+    /// Reject further rows of the source with the most rejected rows,
+    /// halts when rejected rows have no source
+    QuarantineSource,
+}
+
+impl FromStr for BreakerAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "halt" => Ok(Self::Halt),
+            "quarantine-client" => Ok(Self::QuarantineClient),
+            "quarantine-source" => Ok(Self::QuarantineSource),
+            other => Err(format!("unknown breaker action `{other}`")),
+        }
+    }
+}
+
+/// Reject rate exceeded the threshold at `line`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakerTrip {
+    pub line: u64,
+    pub reject_rate: f64,
+    /// Client with the most rejected rows in the window
+    pub client: ClientId,
+    /// Source with the most rejected rows in the window, if rows have one
+    pub source: Option<String>,
+}
+
+struct Outcome {
+    client: ClientId,
+    source: Option<String>,
+    rejected: bool,
+}
+
+pub struct CircuitBreaker {
+    window: usize,
+    threshold: f64,
+    action: BreakerAction,
+    recent: VecDeque<Outcome>,
+    rejected: usize,
+}
+
+impl CircuitBreaker {
+    /// Trips when more than `threshold` share of the last `window` rows are rejected
+    pub fn new(window: usize, threshold: f64, action: BreakerAction) -> Self {
+        Self {
+            window: window.max(1),
+            threshold,
+            action,
+            recent: VecDeque::with_capacity(window),
+            rejected: 0,
+        }
+    }
+
+    pub fn action(&self) -> BreakerAction {
+        self.action
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Takes effect from the next recorded row, rows in the window are kept
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold;
+    }
+
+    /// Records outcome of a row. Trips only once the window is full, and
+    /// starts over with an empty window afterwards.
+    pub fn record(
+        &mut self,
+        line: u64,
+        client: ClientId,
+        source: Option<&str>,
+        rejected: bool,
+    ) -> Option<BreakerTrip> {
+        if self.recent.len() == self.window
+            && let Some(oldest) = self.recent.pop_front()
+            && oldest.rejected
+        {
+            self.rejected -= 1;
+        }
+        self.recent.push_back(Outcome {
+            client,
+            source: source.map(str::to_string),
+            rejected,
+        });
+        self.rejected += usize::from(rejected);
+        let reject_rate = self.rejected as f64 / self.window as f64;
+        if self.recent.len() < self.window || reject_rate <= self.threshold {
+            return None;
+        }
+        let trip = BreakerTrip {
+            line,
+            reject_rate,
+            client: self.most_rejected(|outcome| Some(outcome.client))?,
+            source: self.most_rejected(|outcome| outcome.source.clone()),
+        };
+        self.recent.clear();
+        self.rejected = 0;
+        Some(trip)
+    }
+
+    /// Key with the most rejected rows in the window, the lowest one of ties
+    fn most_rejected<K: Ord>(&self, key: impl Fn(&Outcome) -> Option<K>) -> Option<K> {
+        let mut counts = BTreeMap::new();
+        for outcome in self.recent.iter().filter(|outcome| outcome.rejected) {
+            if let Some(key) = key(outcome) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+        let most = counts.values().copied().max()?;
+        counts
+            .into_iter()
+            .find(|(_, count)| *count == most)
+            .map(|(key, _)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trips_when_rolling_reject_rate_exceeds_threshold() {
+        let mut breaker = CircuitBreaker::new(4, 0.5, BreakerAction::Halt);
+        // window is not full yet
+        assert_eq!(breaker.record(1, 1, None, false), None);
+        assert_eq!(breaker.record(2, 2, Some("a"), true), None);
+        assert_eq!(breaker.record(3, 1, None, true), None);
+        // exactly at the threshold
+        assert_eq!(breaker.record(4, 1, None, false), None);
+        let trip = breaker.record(5, 2, Some("b"), true).unwrap();
+        assert_eq!(trip.line, 5);
+        assert_eq!(trip.reject_rate, 0.75);
+        assert_eq!(trip.client, 2);
+        assert_eq!(trip.source.as_deref(), Some("a"));
+        // window starts over
+        assert_eq!(breaker.record(6, 2, None, true), None);
+    }
+}
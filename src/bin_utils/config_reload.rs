@@ -0,0 +1,163 @@
+//! Reloading of [`RiskConfig`] while rows are processed, so thresholds,
+//! limits and dispute policy of a long-running run, e.g. one reading a pipe
+//! that never ends, can be changed without restarting it and losing its
+//! in-memory state. The file is reloaded when it changes, which is checked
+//! between rows at most once per interval, or when the process receives
+//! SIGHUP. A reload that fails keeps the previous config.
+
+#[cfg(unix)]
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use thiserror::Error;
+
+use crate::processor::{
+    TransactionProcessor,
+    risk_config::{DisabledSections, RiskConfig},
+};
+
+use super::circuit_breaker::CircuitBreaker;
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error("Failed to read risk config: {0}")]
+    Read(#[from] io::Error),
+    #[error("Invalid risk config: {0}")]
+    Invalid(#[from] serde_json::Error),
+    #[error(transparent)]
+    Disabled(#[from] DisabledSections),
+}
+
+/// Modification time and length of the file, as the former alone may not
+/// change between quick writes on file systems with coarse timestamps
+type Version = (Option<SystemTime>, u64);
+
+/// Watches a risk config file, see the [module docs](self)
+pub struct ConfigReload {
+    path: PathBuf,
+    interval: Duration,
+    checked: Instant,
+    version: Option<Version>,
+    #[cfg(unix)]
+    hangup: Arc<AtomicBool>,
+    #[cfg(unix)]
+    signal: signal_hook::SigId,
+}
+
+impl ConfigReload {
+    /// Starts handling SIGHUP, which no longer terminates the process
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> io::Result<Self> {
+        #[cfg(unix)]
+        let hangup = Arc::new(AtomicBool::new(false));
+        Ok(Self {
+            path: path.into(),
+            interval,
+            checked: Instant::now(),
+            version: None,
+            #[cfg(unix)]
+            signal: signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&hangup))?,
+            #[cfg(unix)]
+            hangup,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the config as it is now, changes after it are reported by [`Self::poll`]
+    pub fn load(&mut self) -> Result<RiskConfig, ReloadError> {
+        self.checked = Instant::now();
+        self.version = self.version().ok();
+        Ok(RiskConfig::from_json(File::open(&self.path)?)?)
+    }
+
+    /// Config, when its file changed or SIGHUP was received since it was last loaded
+    pub fn poll(&mut self) -> Option<Result<RiskConfig, ReloadError>> {
+        #[cfg(unix)]
+        if self.hangup.swap(false, Ordering::Relaxed) {
+            return Some(self.load());
+        }
+        if self.checked.elapsed() < self.interval {
+            return None;
+        }
+        self.checked = Instant::now();
+        match self.version() {
+            Ok(version) if Some(version) == self.version => None,
+            // file being replaced is reported, once it is back
+            Err(_) => None,
+            Ok(_) => Some(self.load()),
+        }
+    }
+
+    fn version(&self) -> io::Result<Version> {
+        let metadata = fs::metadata(&self.path)?;
+        Ok((metadata.modified().ok(), metadata.len()))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ConfigReload {
+    fn drop(&mut self) {
+        signal_hook::low_level::unregister(self.signal);
+    }
+}
+
+/// Applies `config` to the processor and the circuit breaker of the run,
+/// nothing is applied when some section can't be
+pub(super) fn apply<P: TransactionProcessor>(
+    config: &RiskConfig,
+    processor: &mut P,
+    breaker: Option<&mut CircuitBreaker>,
+) -> Result<(), ReloadError> {
+    if config.breaker_threshold.is_some() && breaker.is_none() {
+        return Err(DisabledSections(vec!["circuit breaker threshold"]).into());
+    }
+    processor.reload_config(config)?;
+    if let (Some(threshold), Some(breaker)) = (config.breaker_threshold, breaker) {
+        breaker.set_threshold(threshold);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a single test, as SIGHUP is received by every watcher of the process
+    #[test]
+    fn reloads_changed_file_and_on_hangup() {
+        let path = std::env::temp_dir().join(format!("risk-{}.json", std::process::id()));
+        fs::write(&path, r#"{"balance_cap": "10"}"#).unwrap();
+        let mut reload = ConfigReload::new(&path, Duration::ZERO).unwrap();
+        assert_eq!(reload.load().unwrap().balance_cap, Some(10.into()));
+        assert!(reload.poll().is_none());
+
+        fs::write(&path, r#"{"balance_cap": "100"}"#).unwrap();
+        let config = reload.poll().unwrap().unwrap();
+        assert_eq!(config.balance_cap, Some(100.into()));
+        assert!(reload.poll().is_none());
+
+        #[cfg(unix)]
+        {
+            signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+            assert!(reload.poll().unwrap().is_ok());
+            assert!(reload.poll().is_none());
+        }
+
+        fs::write(&path, r#"{"balance_limit": "1"}"#).unwrap();
+        assert!(matches!(reload.poll(), Some(Err(ReloadError::Invalid(_)))));
+        assert!(reload.poll().is_none());
+
+        fs::remove_file(&path).unwrap();
+        assert!(reload.poll().is_none());
+    }
+}
@@ -1,26 +1,143 @@
-use std::io::Read;
+use std::{io::Read, str::FromStr};
 
 use crate::command::TransactionKind;
-use csv::{DeserializeRecordsIntoIter, Trim};
+use csv::{StringRecord, Trim};
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Deserialize)]
+use super::{TransactionSource, number_format::NumberFormat};
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Transaction {
     #[serde(rename = "type")]
     pub kind: TransactionKind,
     pub client: u16,
     pub tx: u32,
     pub amount: Option<Decimal>,
+    /// Optional ordering key (sequence number or timestamp) set by the feed
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Hex encoded ed25519 signature of the row, see [`super::signature`]
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Who submitted the transaction, see [`super::ServiceBuilder::source`]
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Correlates the payment across systems, passed to applied events,
+    /// errors and rejects
+    #[serde(default)]
+    pub trace_id: Option<String>,
 }
 
-/// Parses transaction list in CSV format
-///
-/// # Panics
-///
-/// If transaction cannot be parsed
+impl Transaction {
+    /// Transaction without ordering key, signature, source and trace id
+    pub fn new(kind: TransactionKind, client: u16, tx: u32, amount: Option<Decimal>) -> Self {
+        Self {
+            kind,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+            signature: None,
+            source: None,
+            trace_id: None,
+        }
+    }
+}
+
+/// Row of the input, that couldn't be parsed. Parsers stop at it,
+/// see [`super::TransactionSource::error`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid row at line {line}: {message}")]
+pub struct InvalidRow {
+    pub line: u64,
+    pub message: String,
+}
+
+impl InvalidRow {
+    pub fn new(line: u64, message: impl ToString) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Renames input columns to the expected ones, for partner files
+/// with headers like `txn_type,customer_id,txn_id,value`,
+/// or names columns of headerless input
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ColumnMapping {
+    /// Input column and the expected column it holds
+    renames: Vec<(String, String)>,
+    /// Columns in order, when input has no header row
+    schema: Option<Vec<String>>,
+}
+
+impl ColumnMapping {
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty() && self.schema.is_none()
+    }
+
+    /// Input has no header row, and its columns are `schema`,
+    /// e.g. `["type", "client", "tx", "amount"]`, so the first row is parsed as data
+    pub fn headerless(mut self, schema: Vec<String>) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Maps `column` of the input to expected `to` column, e.g. `amount`
+    pub fn rename(mut self, column: impl Into<String>, to: impl Into<String>) -> Self {
+        self.renames.push((column.into(), to.into()));
+        self
+    }
+
+    fn apply(&self, headers: &StringRecord) -> StringRecord {
+        headers
+            .iter()
+            .map(|header| {
+                self.renames
+                    .iter()
+                    .find(|(column, _)| column == header)
+                    .map_or(header, |(_, to)| to.as_str())
+            })
+            .collect()
+    }
+}
+
+/// Parses `txn_type=type,customer_id=client` list
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        s.split(',')
+            .map(|pair| match pair.split_once('=') {
+                Some((column, to)) if !column.trim().is_empty() && !to.trim().is_empty() => {
+                    Ok((column.trim().to_string(), to.trim().to_string()))
+                }
+                _ => Err(format!("expected `column=expected_column`, got `{pair}`")),
+            })
+            .collect::<Result<_, _>>()
+            .map(|renames| Self {
+                renames,
+                schema: None,
+            })
+    }
+}
+
+/// Parses transaction list in CSV format, until the first row,
+/// that cannot be parsed
 pub struct CsvTransactionParser<R> {
-    iter: DeserializeRecordsIntoIter<R, Transaction>,
+    reader: csv::Reader<R>,
+    headers: StringRecord,
+    record: StringRecord,
+    amount_idx: Option<usize>,
+    number_format: NumberFormat,
+    error: Option<InvalidRow>,
 }
 
 impl<R> CsvTransactionParser<R>
@@ -28,15 +145,106 @@ where
     R: Read,
 {
     pub fn new(source: R) -> Self {
-        let reader = csv::ReaderBuilder::new()
-            .trim(Trim::All)
+        Self::with_number_format(source, NumberFormat::Plain)
+    }
+
+    pub fn with_number_format(source: R, number_format: NumberFormat) -> Self {
+        Self::build(source, Trim::All, number_format, &ColumnMapping::default())
+    }
+
+    /// Input columns are renamed according to `columns`
+    pub fn with_columns(source: R, number_format: NumberFormat, columns: &ColumnMapping) -> Self {
+        Self::build(source, Trim::All, number_format, columns)
+    }
+
+    /// Fields are trimmed only in the header, so rows can be inspected as is
+    pub(super) fn untrimmed(
+        source: R,
+        number_format: NumberFormat,
+        columns: &ColumnMapping,
+    ) -> Self {
+        Self::build(source, Trim::Headers, number_format, columns)
+    }
+
+    fn build(source: R, trim: Trim, number_format: NumberFormat, columns: &ColumnMapping) -> Self {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(trim)
             .flexible(true)
+            .has_headers(columns.schema.is_none())
             .from_reader(source);
-
+        let headers = match &columns.schema {
+            Some(schema) => StringRecord::from(schema.clone()),
+            None => reader.headers().cloned().unwrap_or_default(),
+        };
+        let headers = columns.apply(&headers);
         Self {
-            iter: reader.into_deserialize(),
+            amount_idx: headers.iter().position(|header| header == "amount"),
+            reader,
+            headers,
+            record: StringRecord::new(),
+            number_format,
+            error: None,
+        }
+    }
+
+    pub(super) fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    /// Reads next record, and returns its line number
+    pub(super) fn read_record(&mut self) -> Option<u64> {
+        if self.error.is_some() {
+            return None;
+        }
+        let line = self.reader.position().line();
+        match self.reader.read_record(&mut self.record) {
+            Ok(read) => read.then_some(line),
+            Err(err) => {
+                self.error = Some(InvalidRow::new(line, err));
+                None
+            }
+        }
+    }
+
+    pub(super) fn record_mut(&mut self) -> &mut StringRecord {
+        &mut self.record
+    }
+
+    /// Parses the last read record, or stops the parser if it's invalid
+    pub(super) fn parse_record(&mut self, line: u64) -> Option<Transaction> {
+        match self.deserialize_record() {
+            Ok(row) => Some(row),
+            Err(message) => {
+                self.error = Some(InvalidRow::new(line, message));
+                None
+            }
         }
     }
+
+    fn deserialize_record(&mut self) -> Result<Transaction, String> {
+        if let Some(idx) = self.amount_idx
+            && let Some(amount) = self.record.get(idx)
+        {
+            let plain = self.number_format.to_plain(amount)?;
+            if plain != amount {
+                let mut fields: Vec<_> = self.record.iter().map(str::to_string).collect();
+                fields[idx] = plain.into_owned();
+                self.record = StringRecord::from(fields);
+            }
+        }
+        self.record
+            .deserialize(Some(&self.headers))
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl<R> TransactionSource for CsvTransactionParser<R>
+where
+    R: Read,
+{
+    fn error(&self) -> Option<&InvalidRow> {
+        self.error.as_ref()
+    }
 }
 
 impl<R> Iterator for CsvTransactionParser<R>
@@ -46,7 +254,45 @@ where
     type Item = (u64, Transaction);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let curr_line = self.iter.reader().position().line();
-        self.iter.next().map(|row| (curr_line, row.unwrap()))
+        let line = self.read_record()?;
+        self.parse_record(line).map(|row| (line, row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_partner_columns() {
+        let columns: ColumnMapping = "txn_type=type, customer_id=client,txn_id=tx,value=amount"
+            .parse()
+            .unwrap();
+        let input = "txn_type,customer_id,txn_id,value\n\
+            deposit,1,7,1.5\n";
+        let rows: Vec<_> =
+            CsvTransactionParser::with_columns(input.as_bytes(), NumberFormat::Plain, &columns)
+                .collect();
+        let (line, row) = &rows[0];
+        assert_eq!(*line, 2);
+        assert_eq!((row.client, row.tx), (1, 7));
+        assert_eq!(row.amount, Some(Decimal::new(15, 1)));
+
+        assert!("txn_type".parse::<ColumnMapping>().is_err());
+    }
+
+    #[test]
+    fn headerless_input_with_schema() {
+        let schema = ["type", "client", "tx", "amount"].map(str::to_string);
+        let columns = ColumnMapping::default().headerless(schema.to_vec());
+        let input = "deposit,1,7,1.5\n\
+            withdrawal,1,8,0.5\n";
+        let rows: Vec<_> =
+            CsvTransactionParser::with_columns(input.as_bytes(), NumberFormat::Plain, &columns)
+                .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 1);
+        assert_eq!(rows[0].1.kind, TransactionKind::Deposit);
+        assert_eq!(rows[1].1.tx, 8);
     }
 }
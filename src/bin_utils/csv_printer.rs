@@ -1,9 +1,17 @@
-use std::io::Write;
+use std::{collections::BTreeMap, io::Write};
 
-use crate::processor::ClientId;
+use crate::{
+    account::{CaseStatus, DisputeStage, TransactionId},
+    history::{EventSeq, HistoryEntry, PeriodId},
+    processor::ClientId,
+    projection::{FraudFlag, FraudReason},
+};
 use csv::Writer;
 use rust_decimal::Decimal;
 use serde::Serialize;
+use serde_json::Value;
+
+use super::{held_accrual::DailyHeld, run_report::OpenDispute, watermark::LateRow};
 
 #[derive(Debug, Serialize)]
 pub struct Account {
@@ -12,6 +20,28 @@ pub struct Account {
     pub held: Decimal,
     pub total: Decimal,
     pub locked: bool,
+    pub pending: Decimal,
+    /// Printed only by [`print_accounts_extended`]
+    #[serde(skip)]
+    pub extensions: BTreeMap<String, Value>,
+}
+
+impl Account {
+    pub fn of(client: ClientId, account: &crate::account::Account) -> Self {
+        Self {
+            client,
+            available: account.available(),
+            held: account.held(),
+            total: account.total_amount(),
+            locked: account.locked(),
+            pending: account.pending(),
+            extensions: account
+                .extensions()
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
 }
 
 pub fn print_accounts<W>(
@@ -33,3 +63,245 @@ where
     }
     Ok(())
 }
+
+/// Account with its custom fields as a JSON object
+#[derive(Debug, Serialize)]
+struct ExtendedRow<'a> {
+    client: ClientId,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    pending: Decimal,
+    extensions: &'a str,
+}
+
+/// Writes accounts like [`print_accounts`], with additional `extensions`
+/// column holding custom fields of each account as a JSON object
+pub fn print_accounts_extended<W>(
+    output: &mut W,
+    accounts: impl Iterator<Item = Account>,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    for acc in accounts {
+        let extensions = serde_json::to_string(&acc.extensions)?;
+        writer.serialize(ExtendedRow {
+            client: acc.client,
+            available: acc.available,
+            held: acc.held,
+            total: acc.total,
+            locked: acc.locked,
+            pending: acc.pending,
+            extensions: &extensions,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Row of the delta report, balances are empty for untouched accounts
+#[derive(Debug, Serialize)]
+struct DeltaRow {
+    client: ClientId,
+    available: Option<Decimal>,
+    held: Option<Decimal>,
+    total: Option<Decimal>,
+    locked: Option<bool>,
+    pending: Option<Decimal>,
+}
+
+/// Writes changed accounts like [`print_accounts`], followed by a tombstone
+/// row with only the client id for each of `untouched` clients
+pub fn print_accounts_delta<W>(
+    output: &mut W,
+    changed: impl Iterator<Item = Account>,
+    untouched: &[ClientId],
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    let changed = changed.map(|acc| DeltaRow {
+        client: acc.client,
+        available: Some(acc.available),
+        held: Some(acc.held),
+        total: Some(acc.total),
+        locked: Some(acc.locked),
+        pending: Some(acc.pending),
+    });
+    let tombstones = untouched.iter().map(|&client| DeltaRow {
+        client,
+        available: None,
+        held: None,
+        total: None,
+        locked: None,
+        pending: None,
+    });
+    for row in changed.chain(tombstones) {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct EventRow<'a> {
+    seq: EventSeq,
+    client: ClientId,
+    tx: TransactionId,
+    kind: &'static str,
+    amount: Decimal,
+    period: PeriodId,
+    source: Option<&'a str>,
+}
+
+impl<'a> EventRow<'a> {
+    pub(super) fn of(entry: &'a HistoryEntry) -> Self {
+        Self {
+            seq: entry.seq,
+            client: entry.client,
+            tx: entry.event.transaction_id(),
+            kind: entry.event.kind().name(),
+            amount: entry.event.amount(),
+            period: entry.period,
+            source: entry.source.as_deref(),
+        }
+    }
+}
+
+/// Writes history entries as `seq,client,tx,kind,amount,period,source` rows
+pub fn print_events<'a, W>(
+    output: &mut W,
+    entries: impl Iterator<Item = &'a HistoryEntry>,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    for entry in entries {
+        writer.serialize(EventRow::of(entry))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct LateRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    client: ClientId,
+    tx: TransactionId,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+}
+
+/// Writes late rows as input rows, `type,client,tx,amount,timestamp`,
+/// so they can be processed with the next period
+pub fn print_late_rows<W>(output: &mut W, rows: &[LateRow]) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    for late in rows {
+        writer.serialize(LateRecord {
+            kind: late.row.kind.name(),
+            client: late.row.client,
+            tx: late.row.tx,
+            amount: late.row.amount,
+            timestamp: late.row.timestamp,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct HeldAccrualRow {
+    client: ClientId,
+    date: String,
+    average_held: Decimal,
+}
+
+/// Writes average daily held balances as `client,date,average_held` rows
+pub fn print_held_accrual<W>(output: &mut W, daily: &[DailyHeld]) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    for held in daily {
+        writer.serialize(HeldAccrualRow {
+            client: held.client,
+            date: held.date(),
+            average_held: held.average_held,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct OpenDisputeRow<'a> {
+    client: ClientId,
+    tx: TransactionId,
+    stage: &'static str,
+    status: CaseStatus,
+    assignee: Option<&'a str>,
+    /// JSON array
+    notes: String,
+}
+
+/// Writes open disputes as `client,tx,stage,status,assignee,notes` rows,
+/// with notes of the case as a JSON array
+pub fn print_open_disputes<W>(output: &mut W, disputes: &[OpenDispute]) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    for dispute in disputes {
+        writer.serialize(OpenDisputeRow {
+            client: dispute.client,
+            tx: dispute.tx,
+            stage: match dispute.stage {
+                None => "disputed",
+                Some(DisputeStage::Representment) => "representment",
+                Some(DisputeStage::PreArbitration) => "pre_arbitration",
+            },
+            status: dispute.case.status,
+            assignee: dispute.case.assignee.as_deref(),
+            notes: serde_json::to_string(&dispute.case.notes)?,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct FlagRow {
+    client: ClientId,
+    reason: &'static str,
+    value: Decimal,
+}
+
+/// Writes fraud flags as `client,reason,value` rows
+pub fn print_flags<W>(output: &mut W, flags: &[FraudFlag]) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut writer = Writer::from_writer(output);
+    for flag in flags {
+        let (reason, value) = match flag.reason {
+            FraudReason::Chargebacks(count) => ("chargebacks", Decimal::from(count)),
+            FraudReason::DisputeRatio(ratio) => ("dispute_ratio", ratio),
+        };
+        writer.serialize(FlagRow {
+            client: flag.client,
+            reason,
+            value,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Destinations of errors of rejected rows, reported with their line numbers.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use serde::Serialize;
+
+use crate::{error_data::ErrorDetails, processor::TransactionProcessError};
+
+/// Receives error of every rejected row, see [`super::ServiceBuilder::on_error`].
+/// Closures taking line number and error are sinks as well.
+pub trait ErrorSink {
+    fn report(&mut self, line: u64, err: TransactionProcessError);
+
+    /// Error of a row with trace id, sinks that don't record it get [`ErrorSink::report`]
+    fn report_traced(&mut self, line: u64, trace_id: Option<&str>, err: TransactionProcessError) {
+        let _ = trace_id;
+        self.report(line, err)
+    }
+
+    /// Called once all rows are processed, e.g. to flush buffered output
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(u64, TransactionProcessError)> ErrorSink for F {
+    fn report(&mut self, line: u64, err: TransactionProcessError) {
+        self(line, err)
+    }
+}
+
+/// Ignores errors, they are still counted in [`super::run_report::RunReport`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentSink;
+
+impl ErrorSink for SilentSink {
+    fn report(&mut self, _line: u64, _err: TransactionProcessError) {}
+}
+
+/// Prints every error to stderr
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl ErrorSink for StderrSink {
+    fn report(&mut self, line: u64, err: TransactionProcessError) {
+        eprintln!("Error at line {line}: {err}")
+    }
+}
+
+/// Collects errors, so they can be asserted after the run
+impl ErrorSink for &mut Vec<(u64, TransactionProcessError)> {
+    fn report(&mut self, line: u64, err: TransactionProcessError) {
+        self.push((line, err));
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorLine<'a> {
+    line: u64,
+    code: &'static str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<&'static str, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<&'a str>,
+}
+
+/// Writes errors as JSON lines with `line`, `code`, `message` and `fields`
+/// of [`crate::error_data::ErrorData`], if the error has any, and `trace_id`
+/// of the row, if it has one,
+/// the first write error is kept and returned when the run completes
+pub struct JsonLinesSink<W> {
+    output: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> ErrorSink for JsonLinesSink<W> {
+    fn report(&mut self, line: u64, err: TransactionProcessError) {
+        self.report_traced(line, None, err)
+    }
+
+    fn report_traced(&mut self, line: u64, trace_id: Option<&str>, err: TransactionProcessError) {
+        if self.error.is_some() {
+            return;
+        }
+        let message = err.to_string();
+        let error_line = ErrorLine {
+            line,
+            code: err.code(),
+            message: &message,
+            fields: err.error_data().fields,
+            trace_id,
+        };
+        let result = serde_json::to_writer(&mut self.output, &error_line)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.output));
+        self.error = result.err();
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{account::AccountError, command::AccountCommandError};
+
+    use super::*;
+
+    #[test]
+    fn writes_json_lines() {
+        let mut output = Vec::new();
+        let mut sink = JsonLinesSink::new(&mut output);
+        sink.report(2, AccountError::InsufficientFunds.into());
+        sink.report(
+            3,
+            AccountCommandError::UnknownKind {
+                kind: "refund".to_string(),
+            }
+            .into(),
+        );
+        sink.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"line\":2,\"code\":\"insufficient_funds\",\"message\":\"Insufficient funds\"}\n\
+            {\"line\":3,\"code\":\"unknown_kind\",\"message\":\"Unknown transaction type `refund`\",\"fields\":{\"kind\":\"refund\"}}\n"
+        );
+    }
+}
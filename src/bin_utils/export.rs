@@ -0,0 +1,94 @@
+//! Everything recorded about a single client, for customer support
+//! responses and GDPR data requests.
+
+use std::{io::Write, str::FromStr};
+
+use crate::{history::EventHistory, processor::ClientId};
+
+use super::csv_printer::{self, EventRow};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown export format `{other}`")),
+        }
+    }
+}
+
+/// Writes every event of the `client` in the order they were applied,
+/// with the transaction it belongs to and where the transaction came from.
+/// CSV has the same columns as [`csv_printer::print_events`], JSON is an array
+/// of objects with the same fields.
+pub fn export_client<W: Write>(
+    output: &mut W,
+    history: &EventHistory,
+    client: ClientId,
+    format: ExportFormat,
+) -> anyhow::Result<()> {
+    let entries = history.for_client(client);
+    match format {
+        ExportFormat::Csv => csv_printer::print_events(output, entries),
+        ExportFormat::Json => {
+            let rows: Vec<_> = entries.map(EventRow::of).collect();
+            serde_json::to_writer_pretty(&mut *output, &rows)?;
+            writeln!(output)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::{
+        command::TransactionKind,
+        processor::{TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn exports_events_of_client() {
+        let mut processor = InMemoryTransactionProcessor::default().with_history();
+        for (tx, client, amount, kind) in [
+            (1, 42, Some(Decimal::TEN), TransactionKind::Deposit),
+            (2, 7, Some(Decimal::ONE), TransactionKind::Deposit),
+            (3, 42, Some(Decimal::TWO), TransactionKind::Withdrawal),
+            (1, 42, None, TransactionKind::Dispute),
+        ] {
+            processor
+                .process_transaction_from(tx, client, amount, kind, Some("feed.csv"))
+                .unwrap();
+        }
+        let history = processor.history().unwrap();
+
+        let mut output = Vec::new();
+        export_client(&mut output, history, 42, ExportFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "seq,client,tx,kind,amount,period,source\n\
+            0,42,1,deposited,10,0,feed.csv\n\
+            2,42,3,withdrawn,2,0,feed.csv\n\
+            3,42,1,disputed,10,0,feed.csv\n"
+        );
+
+        let mut output = Vec::new();
+        export_client(&mut output, history, 7, ExportFormat::Json).unwrap();
+        let rows: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(rows[0]["tx"], 2);
+        assert_eq!(rows[0]["kind"], "deposited");
+        assert_eq!(rows.as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,287 @@
+//! Hand rolled tokenizer for the common `type,client,tx,amount` schema.
+//! Line splitting and field splitting are done using `memchr`, which is
+//! SIMD accelerated, and fields are parsed directly from bytes without
+//! going through serde. Anything unusual (quoted fields, different header)
+//! is delegated to the generic [`CsvTransactionParser`].
+
+use std::{
+    io::{BufRead, BufReader, Chain, Cursor, Read},
+    str::{FromStr, from_utf8},
+};
+
+use csv::{StringRecord, Trim};
+use memchr::{memchr, memchr_iter};
+use rust_decimal::Decimal;
+
+use crate::command::TransactionKind;
+
+use super::{
+    TransactionSource,
+    csv_parser::{CsvTransactionParser, InvalidRow, Transaction},
+};
+
+const SCHEMA: [&[u8]; 4] = [b"type", b"client", b"tx", b"amount"];
+
+/// Parses transaction list in CSV format, as long as it matches the common schema.
+/// Stops at the first row, that cannot be parsed.
+pub struct FastCsvTransactionParser<R> {
+    reader: BufReader<R>,
+    buf: Vec<u8>,
+    line: u64,
+    error: Option<InvalidRow>,
+}
+
+impl<R> FastCsvTransactionParser<R>
+where
+    R: Read,
+{
+    fn parse_line(line: &[u8]) -> Result<Transaction, String> {
+        if memchr(b'"', line).is_some() {
+            // quoting rules are complex, let csv crate deal with it
+            return parse_with_csv(line).map_err(|err| err.to_string());
+        }
+        let mut fields = [&line[..0]; 4];
+        let mut count = 0;
+        let mut start = 0;
+        for end in memchr_iter(b',', line).chain([line.len()]) {
+            if count == fields.len() {
+                return Err("too many fields".to_string());
+            }
+            fields[count] = line[start..end].trim_ascii();
+            count += 1;
+            start = end + 1;
+        }
+        if count < 3 {
+            return Err("expected at least 3 fields".to_string());
+        }
+        Ok(Transaction {
+            kind: TransactionKind::from_name(from_utf8(fields[0]).map_err(|_| "invalid type")?),
+            client: parse_num(fields[1]).ok_or("invalid client")?,
+            tx: parse_num(fields[2]).ok_or("invalid tx")?,
+            amount: if fields[3].is_empty() {
+                None
+            } else {
+                Some(parse_decimal(fields[3]).ok_or("invalid amount")?)
+            },
+            timestamp: None,
+            signature: None,
+            source: None,
+            trace_id: None,
+        })
+    }
+}
+
+impl<R> Iterator for FastCsvTransactionParser<R>
+where
+    R: Read,
+{
+    type Item = (u64, Transaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.error.is_none() {
+            self.buf.clear();
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => {
+                    self.error = Some(InvalidRow::new(self.line + 1, err));
+                    break;
+                }
+            }
+            let line = self.buf.trim_ascii();
+            // same as csv crate, empty lines are skipped
+            if !line.is_empty() {
+                match Self::parse_line(line) {
+                    Ok(row) => return Some((self.line, row)),
+                    Err(message) => self.error = Some(InvalidRow::new(self.line, message)),
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<R> TransactionSource for FastCsvTransactionParser<R>
+where
+    R: Read,
+{
+    fn error(&self) -> Option<&InvalidRow> {
+        self.error.as_ref()
+    }
+}
+
+type Fallback<R> = CsvTransactionParser<Chain<Cursor<Vec<u8>>, BufReader<R>>>;
+
+/// Uses [`FastCsvTransactionParser`] when header matches the common schema,
+/// otherwise falls back to generic [`CsvTransactionParser`].
+pub enum AutoTransactionParser<R> {
+    Fast(FastCsvTransactionParser<R>),
+    Generic(Fallback<R>),
+}
+
+impl<R> AutoTransactionParser<R>
+where
+    R: Read,
+{
+    pub fn new(source: R) -> Self {
+        let mut reader = BufReader::new(source);
+        let mut header = Vec::new();
+        if reader.read_until(b'\n', &mut header).is_ok() && is_common_schema(&header) {
+            Self::Fast(FastCsvTransactionParser {
+                reader,
+                buf: Vec::new(),
+                line: 1,
+                error: None,
+            })
+        } else {
+            Self::Generic(CsvTransactionParser::new(Cursor::new(header).chain(reader)))
+        }
+    }
+}
+
+impl<R> Iterator for AutoTransactionParser<R>
+where
+    R: Read,
+{
+    type Item = (u64, Transaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            AutoTransactionParser::Fast(parser) => parser.next(),
+            AutoTransactionParser::Generic(parser) => parser.next(),
+        }
+    }
+}
+
+impl<R> TransactionSource for AutoTransactionParser<R>
+where
+    R: Read,
+{
+    fn error(&self) -> Option<&InvalidRow> {
+        match self {
+            AutoTransactionParser::Fast(parser) => parser.error(),
+            AutoTransactionParser::Generic(parser) => parser.error(),
+        }
+    }
+}
+
+fn is_common_schema(header: &[u8]) -> bool {
+    let header = header.trim_ascii();
+    let mut names = header.split(|b| *b == b',').map(<[u8]>::trim_ascii);
+    SCHEMA
+        .iter()
+        .all(|expected| names.next() == Some(*expected))
+        && names.next().is_none()
+}
+
+fn parse_num<T: FromStr>(field: &[u8]) -> Option<T> {
+    from_utf8(field).ok()?.parse().ok()
+}
+
+// mirrors how `rust_decimal` is deserialized from csv: csv crate infers
+// numbers, so anything that looks like float goes through `f64` first
+fn parse_decimal(field: &[u8]) -> Option<Decimal> {
+    let field = from_utf8(field).ok()?;
+    if let Ok(value) = field.parse::<u64>() {
+        return Some(Decimal::from(value));
+    }
+    if let Ok(value) = field.parse::<i64>() {
+        return Some(Decimal::from(value));
+    }
+    if let Ok(value) = field.parse::<f64>() {
+        return Decimal::from_str(&value.to_string()).ok();
+    }
+    Decimal::from_str(field)
+        .or_else(|_| Decimal::from_scientific(field))
+        .ok()
+}
+
+fn parse_with_csv(line: &[u8]) -> csv::Result<Transaction> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(line);
+    let headers = StringRecord::from(vec!["type", "client", "tx", "amount"]);
+    let mut record = StringRecord::new();
+    reader.read_record(&mut record)?;
+    record.deserialize(Some(&headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_fast(input: &str) -> Vec<(u64, Transaction)> {
+        let parser = AutoTransactionParser::new(input.as_bytes());
+        assert!(matches!(parser, AutoTransactionParser::Fast(_)));
+        parser.collect()
+    }
+
+    fn parse_generic(input: &str) -> Vec<(u64, Transaction)> {
+        CsvTransactionParser::new(input.as_bytes()).collect()
+    }
+
+    fn assert_same(input: &str, compare_lines: bool) {
+        let fast = parse_fast(input);
+        let generic = parse_generic(input);
+        assert_eq!(fast.len(), generic.len());
+        for ((fast_line, fast), (generic_line, generic)) in fast.iter().zip(&generic) {
+            if compare_lines {
+                assert_eq!(fast_line, generic_line);
+            }
+            assert_eq!(fast.kind, generic.kind);
+            assert_eq!(fast.client, generic.client);
+            assert_eq!(fast.tx, generic.tx);
+            assert_eq!(fast.amount, generic.amount);
+        }
+    }
+
+    #[test]
+    fn conformance_with_csv_parser() {
+        assert_same(include_str!("../../tests/transactions.csv"), true);
+        assert_same(
+            "type,client,tx,amount\n\
+             deposit,1,1,1.2345\n\
+             withdrawal,  1 ,2 , 0.5\n\
+             dispute,1,1,\n\
+             resolve,1,1\n\
+             chargeback,65535,4294967295,\n\
+             deposit,2,3,\"10.5\"\n\
+             deposit,2,4,1e2\n\
+             deposit,2,5,-0\n\
+             pending_deposit,3,6,5\n\
+             settle,3,6\n\
+             authorize,3,7,1\n\
+             capture,3,7\n\
+             void,3,7,",
+            true,
+        );
+        // csv crate reports position of the previous record end as a line,
+        // which is off by one for CRLF, so only the data is compared
+        assert_same(
+            "  type , client,tx ,amount  \r\ndeposit,1,1,1.0\r\n\r\ndeposit,2,2,2.0\r\n",
+            false,
+        );
+    }
+
+    #[test]
+    fn falls_back_on_different_schema() {
+        let input = "client,type,tx,amount\n1,deposit,1,1.0\n";
+        let parser = AutoTransactionParser::new(input.as_bytes());
+        assert!(matches!(parser, AutoTransactionParser::Generic(_)));
+        let rows: Vec<_> = parser.collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].1.kind, TransactionKind::Deposit);
+        assert_eq!(rows[0].1.client, 1);
+    }
+
+    #[test]
+    fn stops_at_invalid_row() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,x,2,1.0\ndeposit,1,3,1.0\n";
+        let mut parser = AutoTransactionParser::new(input.as_bytes());
+        assert_eq!(parser.by_ref().count(), 1);
+        assert_eq!(parser.error(), Some(&InvalidRow::new(3, "invalid client")));
+        assert!(parser.next().is_none());
+    }
+}
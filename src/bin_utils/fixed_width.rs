@@ -0,0 +1,159 @@
+//! Fixed-width (mainframe, COBOL-style) records, where every field
+//! occupies the same columns in each line.
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    ops::Range,
+    str::FromStr,
+};
+
+use csv::StringRecord;
+use serde::Serialize;
+
+use super::{
+    TransactionSource,
+    csv_parser::{InvalidRow, Transaction},
+};
+
+/// Positions of fields in a record, as byte offsets
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FixedWidthLayout {
+    fields: Vec<(String, Range<usize>)>,
+}
+
+impl FixedWidthLayout {
+    pub fn new(fields: Vec<(String, Range<usize>)>) -> Self {
+        Self { fields }
+    }
+}
+
+/// Parses `name:offset:length` list, e.g. `type:0:10,client:10:5,tx:15:10,amount:25:15`
+impl FromStr for FixedWidthLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field = |spec: &str| {
+            let mut parts = spec.trim().split(':');
+            let (Some(name), Some(offset), Some(length), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                return Err(format!("expected `name:offset:length`, got `{spec}`"));
+            };
+            let number = |value: &str| {
+                value
+                    .parse::<usize>()
+                    .map_err(|err| format!("invalid `{value}` in `{spec}`: {err}"))
+            };
+            let offset = number(offset)?;
+            Ok((name.to_string(), offset..offset + number(length)?))
+        };
+        s.split(',')
+            .map(field)
+            .collect::<Result<_, _>>()
+            .map(Self::new)
+    }
+}
+
+/// Parses fixed-width records, blank lines are skipped.
+/// Fields are trimmed, and shorter lines leave trailing fields empty.
+/// Stops at the first record, that cannot be parsed.
+pub struct FixedWidthParser<R> {
+    lines: std::io::Lines<BufReader<R>>,
+    line: u64,
+    headers: StringRecord,
+    layout: FixedWidthLayout,
+    error: Option<InvalidRow>,
+}
+
+impl<R: Read> FixedWidthParser<R> {
+    pub fn new(source: R, layout: FixedWidthLayout) -> Self {
+        Self {
+            lines: BufReader::new(source).lines(),
+            line: 0,
+            headers: layout
+                .fields
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect(),
+            layout,
+            error: None,
+        }
+    }
+
+    fn parse_line(&self, text: &str) -> Result<Transaction, String> {
+        let record = self
+            .layout
+            .fields
+            .iter()
+            .map(|(_, range)| {
+                let end = range.end.min(text.len());
+                text.get(range.start.min(end)..end)
+                    .map(str::trim)
+                    .ok_or("field splits a character")
+            })
+            .collect::<Result<StringRecord, _>>()?;
+        record
+            .deserialize(Some(&self.headers))
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl<R: Read> Iterator for FixedWidthParser<R> {
+    type Item = (u64, Transaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.error.is_none() {
+            self.line += 1;
+            let line = self.line;
+            let text = match self.lines.next()? {
+                Ok(text) if text.trim().is_empty() => continue,
+                Ok(text) => text,
+                Err(err) => {
+                    self.error = Some(InvalidRow::new(line, err));
+                    break;
+                }
+            };
+            match self.parse_line(&text) {
+                Ok(row) => return Some((line, row)),
+                Err(message) => self.error = Some(InvalidRow::new(line, message)),
+            }
+        }
+        None
+    }
+}
+
+impl<R: Read> TransactionSource for FixedWidthParser<R> {
+    fn error(&self) -> Option<&InvalidRow> {
+        self.error.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::command::TransactionKind;
+
+    use super::*;
+
+    #[test]
+    fn parse_fixed_width_records() {
+        let layout: FixedWidthLayout = "type:0:10,client:10:5,tx:15:10,amount:25:12"
+            .parse()
+            .unwrap();
+        let input = "deposit       1         1     100.5000\n\
+            \n\
+            dispute       1         1\n";
+        let rows: Vec<_> = FixedWidthParser::new(input.as_bytes(), layout).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 1);
+        assert_eq!(rows[0].1.kind, TransactionKind::Deposit);
+        assert_eq!(rows[0].1.amount, Some(Decimal::new(1005, 1)));
+        assert_eq!(rows[1].0, 3);
+        assert_eq!(rows[1].1.kind, TransactionKind::Dispute);
+        assert_eq!(rows[1].1.amount, None);
+
+        assert!("type:0".parse::<FixedWidthLayout>().is_err());
+        assert!("type:0:x".parse::<FixedWidthLayout>().is_err());
+    }
+}
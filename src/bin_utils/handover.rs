@@ -0,0 +1,504 @@
+//! Zero-downtime handover of a running daemon to a new binary version.
+//! The successor connects to the socket of the running process, which, between
+//! records, sends its accounts and created transactions, and then forwards
+//! rows it keeps reading instead of processing them, until it stops reading
+//! and tells the successor the line to continue from. Nothing is lost or
+//! applied twice: forwarded rows are processed by the successor, in order.
+//!
+//! Accounts, created transactions, the client index and tx id watermarks are
+//! handed over. Processors with rows parked in suspense, held over the cap or
+//! queued for review refuse to hand over until they are drained. History and
+//! other subscribers are not handed over, and options are those the successor
+//! was built with.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    account::{Account, TransactionId},
+    command::{CreateTransactionAction, CreateTransactionCommand, TransactionKind},
+    money::Money,
+    processor::{
+        ClientId, TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor,
+    },
+};
+
+use super::csv_parser::Transaction;
+
+/// Version of the frames, both processes must speak the same one
+pub const PROTOCOL: u32 = 2;
+
+#[derive(Debug, Error)]
+pub enum HandoverError {
+    #[error("Handover connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid handover frame: {0}")]
+    InvalidFrame(#[from] serde_json::Error),
+    #[error("Handover protocol {0} is not supported, expected {PROTOCOL}")]
+    UnsupportedProtocol(u32),
+    #[error("Invalid state of {0} in handover")]
+    InvalidState(String),
+    #[error("Handover ended before the predecessor finished it")]
+    Truncated,
+    #[error("Processor has {0}, that can't be handed over")]
+    PendingRows(&'static str),
+}
+
+/// JSON line of the handover stream
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "frame", rename_all = "snake_case")]
+enum Frame {
+    Hello {
+        protocol: u32,
+    },
+    Account {
+        client: ClientId,
+        /// Hex encoded compact account state
+        state: String,
+    },
+    /// Created transaction, without action and amount when retired
+    Transaction {
+        tx: TransactionId,
+        action: Option<String>,
+        amount: Option<Decimal>,
+    },
+    /// Created transactions of a client, oldest first
+    ClientIndex {
+        client: ClientId,
+        txs: Vec<TransactionId>,
+    },
+    /// Highest accepted tx id of a client, or the global one
+    Watermark {
+        client: Option<ClientId>,
+        tx: TransactionId,
+    },
+    /// End of state, with counts of the frames sent, `indexed_clients`
+    /// is `None` when the predecessor keeps no client index
+    State {
+        accounts: usize,
+        transactions: usize,
+        indexed_clients: Option<usize>,
+        watermarks: usize,
+    },
+    /// Row read by the predecessor after the state was sent
+    Row {
+        line: u64,
+        kind: String,
+        client: ClientId,
+        tx: TransactionId,
+        amount: Option<Decimal>,
+        timestamp: Option<u64>,
+        signature: Option<String>,
+        source: Option<String>,
+        trace_id: Option<String>,
+    },
+    Done {
+        next_line: u64,
+    },
+}
+
+/// Predecessor side of the handover
+pub struct HandoverSender<W: Write> {
+    output: W,
+}
+
+impl<W: Write> HandoverSender<W> {
+    /// Sends state of `processor`, which must be taken between records.
+    /// Fails before sending anything, when rows are waiting in its queues.
+    pub fn send_state(
+        processor: &InMemoryTransactionProcessor,
+        output: W,
+    ) -> Result<Self, HandoverError> {
+        if let Some(pending) = processor.pending_rows() {
+            return Err(HandoverError::PendingRows(pending));
+        }
+        let mut sender = Self { output };
+        sender.write(&Frame::Hello { protocol: PROTOCOL })?;
+        let mut accounts = 0;
+        for (client, acc) in processor.accounts() {
+            sender.write(&Frame::Account {
+                client,
+                state: hex::encode(acc.encode()),
+            })?;
+            accounts += 1;
+        }
+        let mut transactions = 0;
+        for (tx, command) in processor.created_transactions() {
+            sender.write(&Frame::Transaction {
+                tx,
+                action: command
+                    .as_ref()
+                    .map(|command| command.action.name().to_string()),
+                amount: command.as_ref().map(|command| command.amount.amount()),
+            })?;
+            transactions += 1;
+        }
+        let mut indexed_clients = None;
+        if let Some(index) = processor.indexed_clients() {
+            let mut clients = 0;
+            for (client, txs) in index {
+                sender.write(&Frame::ClientIndex {
+                    client,
+                    txs: txs.to_vec(),
+                })?;
+                clients += 1;
+            }
+            indexed_clients = Some(clients);
+        }
+        let mut watermarks = 0;
+        for (client, tx) in processor.tx_id_watermarks() {
+            sender.write(&Frame::Watermark { client, tx })?;
+            watermarks += 1;
+        }
+        sender.write(&Frame::State {
+            accounts,
+            transactions,
+            indexed_clients,
+            watermarks,
+        })?;
+        sender.output.flush()?;
+        Ok(sender)
+    }
+
+    /// Forwards a row read after the state was sent, instead of processing it
+    pub fn forward(&mut self, line: u64, row: &Transaction) -> Result<(), HandoverError> {
+        self.write(&Frame::Row {
+            line,
+            kind: row.kind.name().to_string(),
+            client: row.client,
+            tx: row.tx,
+            amount: row.amount,
+            timestamp: row.timestamp,
+            signature: row.signature.clone(),
+            source: row.source.clone(),
+            trace_id: row.trace_id.clone(),
+        })
+    }
+
+    /// Ends the handover, once the predecessor stopped reading input;
+    /// the successor continues reading it at `next_line`
+    pub fn finish(mut self, next_line: u64) -> Result<(), HandoverError> {
+        self.write(&Frame::Done { next_line })?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, frame: &Frame) -> Result<(), HandoverError> {
+        serde_json::to_writer(&mut self.output, frame)?;
+        self.output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// What the successor took over, see [`receive_state`]
+#[derive(Debug)]
+pub struct Handover {
+    pub accounts: usize,
+    pub transactions: usize,
+    /// Rows forwarded by the predecessor, to be processed before any other
+    pub rows: Vec<(u64, Transaction)>,
+    /// Line of the input to continue reading from
+    pub next_line: u64,
+}
+
+/// State received from the predecessor, put into the processor only once
+/// the handover is finished
+#[derive(Default)]
+struct ReceivedState {
+    accounts: Vec<(ClientId, Account)>,
+    transactions: Vec<(TransactionId, Option<CreateTransactionCommand>)>,
+    index: Vec<(ClientId, Vec<TransactionId>)>,
+    watermarks: Vec<(Option<ClientId>, TransactionId)>,
+    /// State frame matched the frames received, and the index was sent
+    /// if `processor` keeps one
+    complete: bool,
+}
+
+/// Successor side of the handover: restores state sent by the predecessor
+/// into `processor`, and collects forwarded rows. Fails unless the
+/// predecessor finished the handover, in which case it keeps running, and
+/// `processor` is left untouched.
+pub fn receive_state(
+    input: impl Read,
+    processor: &mut InMemoryTransactionProcessor,
+) -> Result<Handover, HandoverError> {
+    let mut lines = BufReader::new(input).lines();
+    let mut next = || -> Result<Frame, HandoverError> {
+        let line = lines.next().ok_or(HandoverError::Truncated)??;
+        Ok(serde_json::from_str(&line)?)
+    };
+    match next()? {
+        Frame::Hello { protocol: PROTOCOL } => {}
+        Frame::Hello { protocol } => return Err(HandoverError::UnsupportedProtocol(protocol)),
+        _ => return Err(HandoverError::InvalidState("protocol".to_string())),
+    }
+    let mut state = ReceivedState::default();
+    let mut rows = Vec::new();
+    loop {
+        match next()? {
+            Frame::Account {
+                client,
+                state: encoded,
+            } => {
+                let acc = hex::decode(&encoded)
+                    .ok()
+                    .and_then(|bytes| Account::decode(&bytes))
+                    .ok_or_else(|| HandoverError::InvalidState(format!("client {client}")))?;
+                state.accounts.push((client, acc));
+            }
+            Frame::Transaction { tx, action, amount } => {
+                let command = match (action, amount) {
+                    (Some(action), Some(amount)) => Some(CreateTransactionCommand {
+                        tx_id: tx,
+                        action: CreateTransactionAction::from_name(&action)
+                            .ok_or_else(|| HandoverError::InvalidState(format!("tx {tx}")))?,
+                        amount: Money::new(amount)
+                            .map_err(|_| HandoverError::InvalidState(format!("tx {tx}")))?,
+                    }),
+                    _ => None,
+                };
+                state.transactions.push((tx, command));
+            }
+            Frame::ClientIndex { client, txs } => state.index.push((client, txs)),
+            Frame::Watermark { client, tx } => state.watermarks.push((client, tx)),
+            Frame::State {
+                accounts,
+                transactions,
+                indexed_clients,
+                watermarks,
+            } => {
+                let received = (
+                    state.accounts.len(),
+                    state.transactions.len(),
+                    state.watermarks.len(),
+                );
+                if received != (accounts, transactions, watermarks)
+                    || indexed_clients.is_some_and(|clients| clients != state.index.len())
+                {
+                    return Err(HandoverError::Truncated);
+                }
+                // transactions of clients would be missing from the index
+                if indexed_clients.is_none() && processor.indexed_clients().is_some() {
+                    return Err(HandoverError::InvalidState("client index".to_string()));
+                }
+                state.complete = true;
+            }
+            Frame::Row {
+                line,
+                kind,
+                client,
+                tx,
+                amount,
+                timestamp,
+                signature,
+                source,
+                trace_id,
+            } => {
+                let mut row =
+                    Transaction::new(TransactionKind::from_name(&kind), client, tx, amount);
+                row.timestamp = timestamp;
+                row.signature = signature;
+                row.source = source;
+                row.trace_id = trace_id;
+                rows.push((line, row));
+            }
+            Frame::Done { .. } if !state.complete => return Err(HandoverError::Truncated),
+            Frame::Done { next_line } => {
+                let handover = Handover {
+                    accounts: state.accounts.len(),
+                    transactions: state.transactions.len(),
+                    rows,
+                    next_line,
+                };
+                for (client, acc) in state.accounts {
+                    processor.restore_account(client, acc);
+                }
+                for (tx, command) in state.transactions {
+                    processor.restore_transaction(tx, command);
+                }
+                for (client, txs) in state.index {
+                    processor.restore_client_transactions(client, txs);
+                }
+                for (client, tx) in state.watermarks {
+                    processor.restore_tx_id_watermark(client, tx);
+                }
+                return Ok(handover);
+            }
+            Frame::Hello { .. } => {
+                return Err(HandoverError::InvalidState("protocol".to_string()));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::HandoverListener;
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        io,
+        os::unix::net::{UnixListener, UnixStream},
+        path::PathBuf,
+    };
+
+    /// Socket of the running process, polled between records for
+    /// a successor; it connects with [`UnixStream::connect`]
+    pub struct HandoverListener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl HandoverListener {
+        /// Listens at `path`, replacing the socket left by a predecessor
+        pub fn bind(path: impl Into<PathBuf>) -> io::Result<Self> {
+            let path = path.into();
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+            let listener = UnixListener::bind(&path)?;
+            listener.set_nonblocking(true)?;
+            Ok(Self { listener, path })
+        }
+
+        /// Connection of a successor, if one is waiting
+        pub fn poll(&self) -> io::Result<Option<UnixStream>> {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    Ok(Some(stream))
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                Err(err) => Err(err),
+            }
+        }
+    }
+
+    impl Drop for HandoverListener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use crate::{assert_account, processor::tx_id_order::TxIdOrder};
+
+    use super::*;
+
+    #[test]
+    fn successor_takes_over_state_and_forwarded_rows() {
+        let path =
+            std::env::temp_dir().join(format!("cute-ledger-handover-{}", std::process::id()));
+        let listener = HandoverListener::bind(&path).unwrap();
+        assert!(listener.poll().unwrap().is_none());
+
+        let mut predecessor = InMemoryTransactionProcessor::default()
+            .with_settled_tx_gc()
+            .with_client_index()
+            .with_tx_id_order(TxIdOrder::PerClient);
+        for (tx, kind) in [
+            (1, TransactionKind::Deposit),
+            (2, TransactionKind::Deposit),
+            (1, TransactionKind::Dispute),
+            (2, TransactionKind::Dispute),
+            (2, TransactionKind::Resolve),
+        ] {
+            let amount = matches!(kind, TransactionKind::Deposit).then_some(Decimal::TEN);
+            predecessor
+                .process_transaction(tx, 1, amount, kind)
+                .unwrap();
+        }
+
+        // small enough to fit into socket buffers
+        let successor = UnixStream::connect(&path).unwrap();
+        let stream = listener.poll().unwrap().unwrap();
+        let mut sender = HandoverSender::send_state(&predecessor, stream).unwrap();
+        sender
+            .forward(
+                7,
+                &Transaction::new(TransactionKind::Chargeback, 1, 1, None),
+            )
+            .unwrap();
+        sender.finish(8).unwrap();
+
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_client_index()
+            .with_tx_id_order(TxIdOrder::PerClient);
+        let handover = receive_state(successor, &mut processor).unwrap();
+        assert_eq!((handover.accounts, handover.transactions), (1, 2));
+        assert_eq!(processor.client_transactions(1), Some([1, 2].as_slice()));
+        // ids of the predecessor's transactions are still the lowest allowed
+        assert!(
+            processor
+                .process_transaction(0, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+                .is_err()
+        );
+        assert_eq!(handover.next_line, 8);
+        assert_account!(processor.account(1).unwrap(), available: 10, held: 10, open_disputes: [1]);
+        // retired transaction is still a duplicate
+        assert!(
+            processor
+                .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+                .is_err()
+        );
+        for (_, row) in handover.rows {
+            processor
+                .process_transaction(row.tx, row.client, row.amount, row.kind)
+                .unwrap();
+        }
+        assert_account!(processor.account(1).unwrap(), available: 10, held: 0, locked: true);
+    }
+
+    #[test]
+    fn unfinished_handover_is_rejected() {
+        let mut output = Vec::new();
+        let mut predecessor = InMemoryTransactionProcessor::default();
+        predecessor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        HandoverSender::send_state(&predecessor, &mut output).unwrap();
+        let mut processor = InMemoryTransactionProcessor::default();
+        assert!(matches!(
+            receive_state(output.as_slice(), &mut processor),
+            Err(HandoverError::Truncated)
+        ));
+        // nothing of the unfinished handover is restored
+        assert!(processor.accounts().next().is_none());
+
+        // state frame is missing
+        let done = b"{\"frame\":\"hello\",\"protocol\":2}\n{\"frame\":\"done\",\"next_line\":1}\n";
+        assert!(matches!(
+            receive_state(done.as_slice(), &mut processor),
+            Err(HandoverError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn queued_rows_are_not_handed_over() {
+        let mut predecessor = InMemoryTransactionProcessor::default().with_suspense();
+        predecessor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert!(matches!(
+            HandoverSender::send_state(&predecessor, Vec::new()),
+            Err(HandoverError::PendingRows("rows in suspense"))
+        ));
+
+        let mut predecessor = InMemoryTransactionProcessor::default().with_watchlist([1]);
+        predecessor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert!(matches!(
+            HandoverSender::send_state(&predecessor, Vec::new()),
+            Err(HandoverError::PendingRows("transactions queued for review"))
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+//! Finance books interest on escrowed (held) funds by their average daily
+//! balance. Held balance of every client is integrated over time, using row
+//! timestamps as seconds since unix epoch, and split by days of the run period.
+
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::Decimal;
+
+use crate::{money::MAX_SCALE, processor::ClientId};
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Average held balance of a client over a day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailyHeld {
+    pub client: ClientId,
+    /// Days since unix epoch
+    pub day: u64,
+    pub average_held: Decimal,
+}
+
+impl DailyHeld {
+    /// `YYYY-MM-DD` of the day
+    pub fn date(&self) -> String {
+        // days to civil date, proleptic Gregorian calendar
+        let days = self.day as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+}
+
+/// Held balances over time, see [`super::ServiceBuilder::held_accrual`]
+#[derive(Debug, Default)]
+pub struct HeldAccrual {
+    /// Current held balance of each client, and since when
+    held: HashMap<ClientId, (Decimal, u64)>,
+    /// Held balance integrated over seconds, by client and day
+    balance_seconds: BTreeMap<(ClientId, u64), Decimal>,
+    first: Option<u64>,
+    last: u64,
+}
+
+impl HeldAccrual {
+    /// Records held balance of the client after a row at `timestamp`.
+    /// Time doesn't go back, earlier timestamps count as the latest one.
+    pub fn record(&mut self, client: ClientId, timestamp: u64, held: Decimal) {
+        let now = timestamp.max(self.last);
+        self.first.get_or_insert(now);
+        self.last = now;
+        if let Some((previous, since)) = self.held.insert(client, (held, now)) {
+            self.accumulate(client, previous, since, now);
+        }
+    }
+
+    fn accumulate(&mut self, client: ClientId, held: Decimal, mut from: u64, to: u64) {
+        if held.is_zero() {
+            return;
+        }
+        while from < to {
+            let day = from / SECONDS_PER_DAY;
+            let until = to.min((day + 1) * SECONDS_PER_DAY);
+            *self.balance_seconds.entry((client, day)).or_default() +=
+                held * Decimal::from(until - from);
+            from = until;
+        }
+    }
+
+    /// Average held balance of every client and day of the run period, from the
+    /// first timestamp to the last one, ordered by client and day. Only covered
+    /// part of the first and last day is averaged over, days without held
+    /// funds are omitted.
+    pub fn finish(mut self) -> Vec<DailyHeld> {
+        let Some(first) = self.first else {
+            return Vec::new();
+        };
+        let last = self.last;
+        for (client, (held, since)) in std::mem::take(&mut self.held) {
+            self.accumulate(client, held, since, last);
+        }
+        self.balance_seconds
+            .into_iter()
+            .map(|((client, day), balance_seconds)| {
+                let start = first.max(day * SECONDS_PER_DAY);
+                let end = last.min((day + 1) * SECONDS_PER_DAY);
+                DailyHeld {
+                    client,
+                    day,
+                    average_held: (balance_seconds / Decimal::from(end - start))
+                        .round_dp(MAX_SCALE),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_held_balance_by_day() {
+        let day = SECONDS_PER_DAY;
+        let mut accrual = HeldAccrual::default();
+        accrual.record(1, 0, Decimal::ZERO);
+        // held for the second half of the first day and the whole second one
+        accrual.record(1, day / 2, Decimal::TEN);
+        accrual.record(2, day, Decimal::ONE);
+        accrual.record(1, 2 * day, Decimal::ZERO);
+        // run ends at noon of the third day
+        accrual.record(2, 2 * day + day / 2, Decimal::ONE);
+
+        let daily = accrual.finish();
+        let averages: Vec<_> = daily
+            .iter()
+            .map(|daily| (daily.client, daily.day, daily.average_held))
+            .collect();
+        assert_eq!(
+            averages,
+            [
+                (1, 0, Decimal::new(5, 0)),
+                (1, 1, Decimal::TEN),
+                (2, 1, Decimal::ONE),
+                (2, 2, Decimal::ONE),
+            ]
+        );
+        assert_eq!(daily[0].date(), "1970-01-01");
+        let leap_day = DailyHeld {
+            client: 1,
+            day: 19_782,
+            average_held: Decimal::ZERO,
+        };
+        assert_eq!(leap_day.date(), "2024-02-29");
+    }
+}
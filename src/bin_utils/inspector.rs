@@ -0,0 +1,328 @@
+//! Terminal UI over stored ledger state, for on-call engineers without
+//! database access: sortable accounts table, event timeline of the selected
+//! account, and queue of open disputes of all accounts.
+
+use std::io;
+
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    widgets::{Block, List, Row, Table, TableState},
+};
+use rust_decimal::Decimal;
+
+use crate::{
+    account::{Account, AccountEvent, AccountEventKind, TransactionId},
+    processor::ClientId,
+};
+
+/// Account with the events it was rebuilt from
+#[derive(Debug)]
+pub struct AccountRow {
+    pub client: ClientId,
+    pub account: Account,
+    pub events: Vec<AccountEvent>,
+}
+
+/// Dispute, that is neither resolved nor charged back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenDispute {
+    pub client: ClientId,
+    pub tx_id: TransactionId,
+    pub amount: Decimal,
+}
+
+/// State of all accounts, rebuilt from their stored events
+#[derive(Debug, Default)]
+pub struct LedgerSnapshot {
+    pub accounts: Vec<AccountRow>,
+}
+
+impl LedgerSnapshot {
+    pub fn from_events(events: impl IntoIterator<Item = (ClientId, Vec<AccountEvent>)>) -> Self {
+        let mut accounts: Vec<_> = events
+            .into_iter()
+            .map(|(client, events)| {
+                let mut account = Account::default();
+                for event in &events {
+                    account.apply(event);
+                }
+                AccountRow {
+                    client,
+                    account,
+                    events,
+                }
+            })
+            .collect();
+        accounts.sort_by_key(|row| row.client);
+        Self { accounts }
+    }
+
+    /// Open disputes of all accounts, by client and transaction
+    pub fn dispute_queue(&self) -> Vec<OpenDispute> {
+        self.accounts
+            .iter()
+            .flat_map(|row| {
+                let open = row.account.snapshot().open_disputes;
+                row.events
+                    .iter()
+                    .filter(move |event| {
+                        event.kind() == AccountEventKind::Disputed
+                            && open.contains(&event.transaction_id())
+                    })
+                    .map(|event| OpenDispute {
+                        client: row.client,
+                        tx_id: event.transaction_id(),
+                        amount: event.amount(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Column the accounts table is sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortColumn {
+    #[default]
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 5] = [
+        SortColumn::Client,
+        SortColumn::Available,
+        SortColumn::Held,
+        SortColumn::Total,
+        SortColumn::Locked,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            SortColumn::Client => "client",
+            SortColumn::Available => "available",
+            SortColumn::Held => "held",
+            SortColumn::Total => "total",
+            SortColumn::Locked => "locked",
+        }
+    }
+
+    fn next(self) -> Self {
+        let idx = Self::ALL
+            .iter()
+            .position(|column| *column == self)
+            .unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+pub struct Inspector {
+    snapshot: LedgerSnapshot,
+    disputes: Vec<OpenDispute>,
+    sort: SortColumn,
+    descending: bool,
+    table: TableState,
+}
+
+impl Inspector {
+    pub fn new(snapshot: LedgerSnapshot) -> Self {
+        let disputes = snapshot.dispute_queue();
+        let mut table = TableState::default();
+        if !snapshot.accounts.is_empty() {
+            table.select(Some(0));
+        }
+        Self {
+            snapshot,
+            disputes,
+            sort: SortColumn::default(),
+            descending: false,
+            table,
+        }
+    }
+
+    pub fn sort_by(&mut self, column: SortColumn, descending: bool) {
+        self.sort = column;
+        self.descending = descending;
+        self.snapshot.accounts.sort_by(|a, b| {
+            let (a, b) = if descending { (b, a) } else { (a, b) };
+            match column {
+                SortColumn::Client => a.client.cmp(&b.client),
+                SortColumn::Available => a.account.available().cmp(&b.account.available()),
+                SortColumn::Held => a.account.held().cmp(&b.account.held()),
+                SortColumn::Total => a.account.total_amount().cmp(&b.account.total_amount()),
+                SortColumn::Locked => a.account.locked().cmp(&b.account.locked()),
+            }
+            .then(a.client.cmp(&b.client))
+        });
+    }
+
+    pub fn selected(&self) -> Option<&AccountRow> {
+        self.snapshot.accounts.get(self.table.selected()?)
+    }
+
+    /// Applies key press, returns `false` when the inspector should quit
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Char('s') => self.sort_by(self.sort.next(), self.descending),
+            KeyCode::Char('r') => self.sort_by(self.sort, !self.descending),
+            _ => {}
+        }
+        // past the last row
+        if let Some(selected) = self.table.selected()
+            && selected >= self.snapshot.accounts.len()
+        {
+            self.table
+                .select(self.snapshot.accounts.len().checked_sub(1));
+        }
+        true
+    }
+
+    /// Runs until quit, restoring the terminal afterwards
+    pub fn run(mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.run_in(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn run_in(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key.code)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [accounts_area, details_area] =
+            Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(frame.area());
+        let [timeline_area, disputes_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(details_area);
+
+        let header = Row::new(SortColumn::ALL.map(|column| {
+            if column != self.sort {
+                column.name().to_string()
+            } else if self.descending {
+                format!("{} v", column.name())
+            } else {
+                format!("{} ^", column.name())
+            }
+        }))
+        .bold();
+        let rows = self.snapshot.accounts.iter().map(|row| {
+            Row::new([
+                row.client.to_string(),
+                row.account.available().to_string(),
+                row.account.held().to_string(),
+                row.account.total_amount().to_string(),
+                row.account.locked().to_string(),
+            ])
+        });
+        let table = Table::new(rows, [Constraint::Ratio(1, 5); 5])
+            .header(header)
+            .row_highlight_style(Style::new().reversed())
+            .block(Block::bordered().title(format!(
+                " accounts ({}) - j/k select, s sort, r reverse, q quit ",
+                self.snapshot.accounts.len()
+            )));
+        frame.render_stateful_widget(table, accounts_area, &mut self.table);
+
+        let (title, timeline) = match self.selected() {
+            Some(row) => (
+                format!(" events of client {} ", row.client),
+                row.events
+                    .iter()
+                    .enumerate()
+                    .map(|(version, event)| {
+                        format!(
+                            "{:>6} {:<16} tx {:<10} {}",
+                            version + 1,
+                            event.kind().name(),
+                            event.transaction_id(),
+                            event.amount()
+                        )
+                    })
+                    .collect(),
+            ),
+            None => (" events ".to_string(), Vec::new()),
+        };
+        frame.render_widget(
+            List::new(timeline).block(Block::bordered().title(title)),
+            timeline_area,
+        );
+
+        let disputes = self.disputes.iter().map(|dispute| {
+            format!(
+                "client {:<6} tx {:<10} {}",
+                dispute.client, dispute.tx_id, dispute.amount
+            )
+        });
+        frame.render_widget(
+            List::new(disputes).block(
+                Block::bordered().title(format!(" open disputes ({}) ", self.disputes.len())),
+            ),
+            disputes_area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_accounts_and_queues_open_disputes() {
+        let snapshot = LedgerSnapshot::from_events([
+            (
+                2,
+                vec![
+                    AccountEvent::new(1, Decimal::TEN, AccountEventKind::Deposited),
+                    AccountEvent::new(1, Decimal::TEN, AccountEventKind::Disputed),
+                ],
+            ),
+            (
+                1,
+                vec![
+                    AccountEvent::new(2, Decimal::ONE, AccountEventKind::Deposited),
+                    AccountEvent::new(2, Decimal::ONE, AccountEventKind::Disputed),
+                    AccountEvent::new(2, Decimal::ONE, AccountEventKind::Resolved),
+                ],
+            ),
+        ]);
+        assert_eq!(
+            snapshot.dispute_queue(),
+            [OpenDispute {
+                client: 2,
+                tx_id: 1,
+                amount: Decimal::TEN
+            }]
+        );
+
+        let mut inspector = Inspector::new(snapshot);
+        assert_eq!(inspector.selected().unwrap().client, 1);
+        // held, descending
+        inspector.handle_key(KeyCode::Char('s'));
+        inspector.handle_key(KeyCode::Char('s'));
+        inspector.handle_key(KeyCode::Char('r'));
+        assert_eq!(inspector.sort, SortColumn::Held);
+        assert_eq!(inspector.selected().unwrap().client, 2);
+        inspector.handle_key(KeyCode::Down);
+        inspector.handle_key(KeyCode::Down);
+        assert_eq!(inspector.selected().unwrap().events.len(), 3);
+        assert!(!inspector.handle_key(KeyCode::Char('q')));
+    }
+}
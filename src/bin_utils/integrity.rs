@@ -0,0 +1,339 @@
+//! Structural invariants of stored ledger state, checked by replaying the
+//! events of every account, e.g. after restoring a backup or fixing
+//! a database by hand. Violations name the client, transaction and position
+//! of the event, so the events can be found and repaired. Stored balances,
+//! e.g. the accounts table of SQLite or an accounts report, are compared with
+//! the replayed ones, as they can be edited apart from events.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    io::Read,
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{
+    account::{Account, AccountEvent, AccountEventKind, TransactionId},
+    history::Balance,
+    processor::ClientId,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Dispute of a transaction, that the client didn't create before it
+    OrphanDispute {
+        client: ClientId,
+        tx_id: TransactionId,
+        /// Position of the event among events of the client, from 1
+        event: usize,
+    },
+    /// Resolve, chargeback or escalation of a transaction not under dispute
+    NotDisputed {
+        client: ClientId,
+        tx_id: TransactionId,
+        kind: AccountEventKind,
+        event: usize,
+    },
+    /// Held funds differ from amounts of open disputes and authorizations
+    HeldMismatch {
+        client: ClientId,
+        held: Decimal,
+        disputed: Decimal,
+        authorized: Decimal,
+    },
+    /// Account is locked, but was never charged back
+    LockedWithoutChargeback { client: ClientId },
+    /// Stored balance differs from the one rebuilt from events
+    BalanceMismatch {
+        client: ClientId,
+        stored: Balance,
+        replayed: Balance,
+    },
+    /// Account has events, but no stored balance
+    MissingBalance { client: ClientId },
+}
+
+#[derive(Deserialize)]
+struct BalanceRow {
+    client: ClientId,
+    available: Decimal,
+    held: Decimal,
+    pending: Decimal,
+    locked: bool,
+}
+
+/// Reads balances from an accounts report in CSV format, e.g. the output
+/// of the run that wrote the events
+pub fn read_balances(input: impl Read) -> csv::Result<HashMap<ClientId, Balance>> {
+    csv::Reader::from_reader(input)
+        .deserialize()
+        .map(|row| {
+            let row: BalanceRow = row?;
+            Ok((
+                row.client,
+                Balance {
+                    available: row.available,
+                    held: row.held,
+                    pending: row.pending,
+                    locked: row.locked,
+                },
+            ))
+        })
+        .collect()
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::OrphanDispute {
+                client,
+                tx_id,
+                event,
+            } => write!(
+                f,
+                "client {client}: event {event} disputes transaction {tx_id}, that the client didn't create"
+            ),
+            Violation::NotDisputed {
+                client,
+                tx_id,
+                kind,
+                event,
+            } => write!(
+                f,
+                "client {client}: event {event} ({}) of transaction {tx_id}, that is not under dispute",
+                kind.name()
+            ),
+            Violation::HeldMismatch {
+                client,
+                held,
+                disputed,
+                authorized,
+            } => write!(
+                f,
+                "client {client}: held {held} differs from open disputes {disputed} and authorizations {authorized}"
+            ),
+            Violation::LockedWithoutChargeback { client } => {
+                write!(f, "client {client}: account is locked without a chargeback")
+            }
+            Violation::BalanceMismatch {
+                client,
+                stored,
+                replayed,
+            } => write!(
+                f,
+                "client {client}: stored balance ({stored}) differs from events ({replayed})"
+            ),
+            Violation::MissingBalance { client } => {
+                write!(
+                    f,
+                    "client {client}: account has events, but no stored balance"
+                )
+            }
+        }
+    }
+}
+
+/// Checks events of a single client, in the order they were applied.
+/// Disputes after an opening balance are not orphans, as compaction folds
+/// their deposits into it, and replaces chargebacks with a lock.
+pub fn check_client(
+    client: ClientId,
+    events: &[AccountEvent],
+    stored: Option<&Balance>,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut account = Account::default();
+    let mut created = HashSet::new();
+    let mut compacted = false;
+    let mut disputed: HashMap<TransactionId, Decimal> = HashMap::new();
+    let mut authorized = Decimal::ZERO;
+    let mut chargedback = false;
+    for (idx, event) in events.iter().enumerate() {
+        let tx_id = event.transaction_id();
+        match event.kind() {
+            AccountEventKind::Deposited
+            | AccountEventKind::Withdrawn
+            | AccountEventKind::DepositPending => {
+                created.insert(tx_id);
+            }
+            AccountEventKind::OpeningBalance => compacted = true,
+            AccountEventKind::Disputed => {
+                if !created.contains(&tx_id) && !compacted {
+                    violations.push(Violation::OrphanDispute {
+                        client,
+                        tx_id,
+                        event: idx + 1,
+                    });
+                }
+                disputed.insert(tx_id, event.amount());
+            }
+            kind @ (AccountEventKind::Resolved
+            | AccountEventKind::Chargedback
+            | AccountEventKind::Represented
+            | AccountEventKind::PreArbitrated) => {
+                if !account.is_under_dispute(tx_id) {
+                    violations.push(Violation::NotDisputed {
+                        client,
+                        tx_id,
+                        kind,
+                        event: idx + 1,
+                    });
+                }
+                if matches!(
+                    kind,
+                    AccountEventKind::Resolved | AccountEventKind::Chargedback
+                ) {
+                    disputed.remove(&tx_id);
+                }
+                if kind == AccountEventKind::Chargedback {
+                    chargedback = true;
+                }
+            }
+            AccountEventKind::Authorized => authorized += event.amount(),
+            AccountEventKind::Captured | AccountEventKind::Voided => authorized -= event.amount(),
+            // compaction replaces dropped chargebacks with a lock
+            AccountEventKind::Locked => chargedback |= compacted,
+            AccountEventKind::Settled
+            | AccountEventKind::Reinstated
+            | AccountEventKind::Unlocked
+            | AccountEventKind::Adjusted
+            | AccountEventKind::Erased => {}
+        }
+        account.apply(event);
+    }
+    let disputed = disputed.values().sum();
+    if account.held() != disputed + authorized {
+        violations.push(Violation::HeldMismatch {
+            client,
+            held: account.held(),
+            disputed,
+            authorized,
+        });
+    }
+    if account.locked() && !chargedback {
+        violations.push(Violation::LockedWithoutChargeback { client });
+    }
+    if let Some(stored) = stored
+        && *stored != Balance::of(&account)
+    {
+        violations.push(Violation::BalanceMismatch {
+            client,
+            stored: *stored,
+            replayed: Balance::of(&account),
+        });
+    }
+    violations
+}
+
+/// Checks events of all clients, and their stored balances, if given.
+/// Violations are ordered by client.
+pub fn check(
+    events: impl IntoIterator<Item = (ClientId, Vec<AccountEvent>)>,
+    stored: Option<&HashMap<ClientId, Balance>>,
+) -> Vec<Violation> {
+    let mut events: HashMap<_, _> = events.into_iter().collect();
+    let clients: BTreeSet<_> = events
+        .keys()
+        .chain(stored.into_iter().flat_map(HashMap::keys))
+        .copied()
+        .collect();
+    let mut violations = Vec::new();
+    for client in clients {
+        let events = events.remove(&client).unwrap_or_default();
+        let balance = stored.and_then(|stored| stored.get(&client));
+        if stored.is_some() && balance.is_none() {
+            violations.push(Violation::MissingBalance { client });
+        }
+        violations.extend(check_client(client, &events, balance));
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_broken_invariants() {
+        let valid = vec![
+            AccountEvent::new(1, 10.into(), AccountEventKind::Deposited),
+            AccountEvent::new(2, 5.into(), AccountEventKind::Deposited),
+            AccountEvent::new(1, 10.into(), AccountEventKind::Disputed),
+            AccountEvent::new(2, 5.into(), AccountEventKind::Disputed),
+            AccountEvent::new(2, 5.into(), AccountEventKind::Chargedback),
+        ];
+        assert_eq!(check_client(1, &valid, None), []);
+        let compacted = vec![
+            AccountEvent::opening_balance(10.into()),
+            AccountEvent::new(1, 3.into(), AccountEventKind::Disputed),
+            AccountEvent::locked(),
+        ];
+        assert_eq!(check_client(1, &compacted, None), []);
+
+        let broken = vec![
+            AccountEvent::new(1, 10.into(), AccountEventKind::Deposited),
+            AccountEvent::new(7, 4.into(), AccountEventKind::Disputed),
+            AccountEvent::new(1, 10.into(), AccountEventKind::Resolved),
+            AccountEvent::new(7, 1.into(), AccountEventKind::Resolved),
+        ];
+        let locked = vec![
+            AccountEvent::new(1, 10.into(), AccountEventKind::Deposited),
+            AccountEvent::locked(),
+        ];
+        let violations = check([(2, locked), (1, broken)], None);
+        assert_eq!(
+            violations,
+            [
+                Violation::OrphanDispute {
+                    client: 1,
+                    tx_id: 7,
+                    event: 2
+                },
+                Violation::NotDisputed {
+                    client: 1,
+                    tx_id: 1,
+                    kind: AccountEventKind::Resolved,
+                    event: 3
+                },
+                Violation::HeldMismatch {
+                    client: 1,
+                    held: (-7).into(),
+                    disputed: 0.into(),
+                    authorized: 0.into()
+                },
+                Violation::LockedWithoutChargeback { client: 2 },
+            ]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "client 1: event 2 disputes transaction 7, that the client didn't create"
+        );
+
+        let stored = read_balances(
+            "client,available,held,total,locked,pending\n1,10,0,10,false,0\n3,1,0,1,false,0\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let deposit = vec![AccountEvent::new(1, 10.into(), AccountEventKind::Deposited)];
+        let withdrawal = vec![
+            AccountEvent::new(2, 10.into(), AccountEventKind::Deposited),
+            AccountEvent::new(3, 4.into(), AccountEventKind::Withdrawn),
+        ];
+        assert_eq!(
+            check([(1, deposit), (2, withdrawal)], Some(&stored)),
+            [
+                Violation::MissingBalance { client: 2 },
+                Violation::BalanceMismatch {
+                    client: 3,
+                    stored: Balance {
+                        available: 1.into(),
+                        ..Default::default()
+                    },
+                    replayed: Balance::default(),
+                },
+            ]
+        );
+    }
+}
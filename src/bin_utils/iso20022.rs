@@ -0,0 +1,198 @@
+//! Practical subset of ISO 20022 bank-to-customer messages (camt.052,
+//! camt.053 and camt.054), so SEPA statements are processed directly.
+//! Every booked entry `Ntry` becomes a row of the client owning the account:
+//! credits are deposits, debits are withdrawals, and reversed entries
+//! (returns) dispute the original entry with the same `NtryRef`.
+
+use std::io::Read;
+
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::command::TransactionKind;
+
+use super::{account_clients::AccountClients, csv_parser::Transaction};
+
+#[derive(Debug, Error)]
+pub enum Iso20022Error {
+    #[error("Failed to read message: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid XML: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Entry at line {line} has no `{name}`")]
+    MissingElement { line: u64, name: &'static str },
+    #[error("Entry at line {line} has invalid `{name}`: `{value}`")]
+    InvalidValue {
+        line: u64,
+        name: &'static str,
+        value: String,
+    },
+    #[error("Entry at line {line} belongs to account `{account}` of unknown client")]
+    UnknownAccount { line: u64, account: String },
+}
+
+/// Rows of all booked entries, with line numbers of their `Ntry` elements
+pub fn parse_entries(
+    mut source: impl Read,
+    accounts: &AccountClients,
+) -> Result<Vec<(u64, Transaction)>, Iso20022Error> {
+    let mut xml = String::new();
+    source.read_to_string(&mut xml)?;
+    let document = Document::parse(&xml)?;
+    let mut rows = Vec::new();
+    for entry in document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Ntry")
+    {
+        let line = document.text_pos_at(entry.range().start).row as u64;
+        if let Some(row) = parse_entry(entry, line, accounts)? {
+            rows.push((line, row));
+        }
+    }
+    Ok(rows)
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+/// Text of the element at the `path` below `node`
+fn text<'a>(node: Node<'a, '_>, path: &[&str]) -> Option<&'a str> {
+    path.iter()
+        .try_fold(node, |node, name| child(node, name))?
+        .text()
+        .map(str::trim)
+}
+
+fn parse_entry(
+    entry: Node,
+    line: u64,
+    accounts: &AccountClients,
+) -> Result<Option<Transaction>, Iso20022Error> {
+    let required = |name| text(entry, &[name]).ok_or(Iso20022Error::MissingElement { line, name });
+    let invalid = |name, value: &str| Iso20022Error::InvalidValue {
+        line,
+        name,
+        value: value.to_string(),
+    };
+    // older versions have status code as text, newer ones in `Cd`
+    let status = text(entry, &["Sts"])
+        .filter(|status| !status.is_empty())
+        .or_else(|| text(entry, &["Sts", "Cd"]));
+    if status.is_some_and(|status| status != "BOOK") {
+        return Ok(None);
+    }
+
+    // account is given by the statement, report or notification
+    let account = entry
+        .parent()
+        .and_then(|parent| child(parent, "Acct"))
+        .and_then(|acct| text(acct, &["Id", "IBAN"]).or_else(|| text(acct, &["Id", "Othr", "Id"])))
+        .ok_or(Iso20022Error::MissingElement { line, name: "Acct" })?;
+    let client = accounts
+        .client(account)
+        .ok_or_else(|| Iso20022Error::UnknownAccount {
+            line,
+            account: account.to_string(),
+        })?;
+    let reference = required("NtryRef")?;
+    let tx = reference
+        .parse()
+        .map_err(|_| invalid("NtryRef", reference))?;
+    let amount = required("Amt")?;
+    let amount: Decimal = amount.parse().map_err(|_| invalid("Amt", amount))?;
+    let reversal = text(entry, &["RvslInd"]) == Some("true");
+    let (kind, amount) = match required("CdtDbtInd")? {
+        _ if reversal => (TransactionKind::Dispute, None),
+        "CRDT" => (TransactionKind::Deposit, Some(amount)),
+        "DBIT" => (TransactionKind::Withdrawal, Some(amount)),
+        other => return Err(invalid("CdtDbtInd", other)),
+    };
+    Ok(Some(Transaction {
+        kind,
+        client,
+        tx,
+        amount,
+        timestamp: None,
+        signature: None,
+        source: None,
+        trace_id: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.08">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Id>STMT-1</Id>
+      <Acct><Id><IBAN>DE89370400440532013000</IBAN></Id></Acct>
+      <Ntry>
+        <NtryRef>1</NtryRef>
+        <Amt Ccy="EUR">100.50</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+      </Ntry>
+      <Ntry>
+        <NtryRef>2</NtryRef>
+        <Amt Ccy="EUR">20</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+      </Ntry>
+      <Ntry>
+        <NtryRef>3</NtryRef>
+        <Amt Ccy="EUR">5</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>PDNG</Cd></Sts>
+      </Ntry>
+      <Ntry>
+        <NtryRef>1</NtryRef>
+        <Amt Ccy="EUR">100.50</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <RvslInd>true</RvslInd>
+        <Sts>BOOK</Sts>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+
+    #[test]
+    fn parse_statement_entries() {
+        let mut accounts = AccountClients::default();
+        accounts.insert("DE89370400440532013000".to_string(), 7);
+        let rows = parse_entries(STATEMENT.as_bytes(), &accounts).unwrap();
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|(line, row)| (*line, row.client, row.tx, row.kind.clone(), row.amount))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    7,
+                    7,
+                    1,
+                    TransactionKind::Deposit,
+                    Some(Decimal::new(10050, 2))
+                ),
+                (
+                    13,
+                    7,
+                    2,
+                    TransactionKind::Withdrawal,
+                    Some(Decimal::from(20))
+                ),
+                (25, 7, 1, TransactionKind::Dispute, None),
+            ]
+        );
+
+        let err = parse_entries(STATEMENT.as_bytes(), &AccountClients::default()).unwrap_err();
+        assert!(matches!(err, Iso20022Error::UnknownAccount { line: 7, .. }));
+    }
+}
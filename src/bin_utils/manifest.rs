@@ -0,0 +1,205 @@
+//! Run manifest lets downstream pipelines verify, which input and
+//! configuration produced the accounts report, and that it wasn't changed.
+//! Report can be signed as well, see [`ReportSigner`].
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::run_report::RunReport;
+
+#[derive(Debug, Serialize)]
+pub struct Manifest<C> {
+    pub engine_version: &'static str,
+    pub input_sha256: String,
+    pub output_sha256: String,
+    pub rows_read: u64,
+    pub rows_accepted: u64,
+    pub rows_skipped: u64,
+    /// Rejected rows count by error code
+    pub rows_rejected: BTreeMap<&'static str, u64>,
+    pub accounts_touched: usize,
+    /// Key the report is signed with, see [`ReportSigner::key_id`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key_id: Option<String>,
+    pub config: C,
+}
+
+impl<C: Serialize> Manifest<C> {
+    pub fn new(report: &RunReport, input_sha256: String, output_sha256: String, config: C) -> Self {
+        Self {
+            engine_version: env!("CARGO_PKG_VERSION"),
+            input_sha256,
+            output_sha256,
+            rows_read: report.rows_read,
+            rows_accepted: report.rows_accepted,
+            rows_skipped: report.rows_skipped,
+            rows_rejected: report.rows_rejected.clone(),
+            accounts_touched: report.accounts_touched,
+            signing_key_id: None,
+            config,
+        }
+    }
+
+    pub fn with_signing_key_id(mut self, key_id: String) -> Self {
+        self.signing_key_id = Some(key_id);
+        self
+    }
+
+    pub fn write_json(&self, output: impl Write) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(output, self)
+    }
+}
+
+/// Signs the accounts report with ed25519 key, so downstream consumers can
+/// verify it wasn't modified in transit. Detached signature is calculated
+/// over the SHA-256 digest of the report bytes, so the report is signed
+/// while it is streamed, see [`HashingWriter::digest`].
+pub struct ReportSigner {
+    key: SigningKey,
+}
+
+impl ReportSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key }
+    }
+
+    /// Hex encoded 32-byte secret key, surrounding whitespace is ignored
+    pub fn from_hex(secret: &str) -> Option<Self> {
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(secret.trim(), &mut bytes).ok()?;
+        Some(Self::new(SigningKey::from_bytes(&bytes)))
+    }
+
+    /// Identifies the key to consumers: first 8 bytes of SHA-256 of
+    /// the public key, hex encoded
+    pub fn key_id(&self) -> String {
+        key_id(&self.key.verifying_key())
+    }
+
+    /// Hex encoded signature of the report with given digest
+    pub fn sign(&self, report_sha256: &[u8; 32]) -> String {
+        hex::encode(self.key.sign(report_sha256).to_bytes())
+    }
+}
+
+pub fn key_id(key: &VerifyingKey) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+/// Checks hex encoded `signature` of the `report`
+pub fn verify_report(key: &VerifyingKey, report: &[u8], signature: &str) -> bool {
+    let mut bytes = [0; 64];
+    hex::decode_to_slice(signature.trim(), &mut bytes).is_ok()
+        && key
+            .verify(&Sha256::digest(report), &Signature::from_bytes(&bytes))
+            .is_ok()
+}
+
+/// Calculates SHA-256 of everything read through it
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Hex encoded hash of the bytes read so far
+    pub fn hash(&self) -> String {
+        hex::encode(self.hasher.clone().finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Calculates SHA-256 of everything written through it
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Hex encoded hash of the bytes written so far
+    pub fn hash(&self) -> String {
+        hex::encode(self.digest())
+    }
+
+    /// Hash of the bytes written so far
+    pub fn digest(&self) -> [u8; 32] {
+        self.hasher.clone().finalize().into()
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_streams() {
+        // sha256 of "abc"
+        let expected = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let mut reader = HashingReader::new("abc".as_bytes());
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        assert_eq!(reader.hash(), expected);
+
+        let mut writer = HashingWriter::new(Vec::new());
+        writer.write_all(b"ab").unwrap();
+        writer.write_all(b"c").unwrap();
+        assert_eq!(writer.hash(), expected);
+        assert_eq!(writer.inner, b"abc");
+    }
+
+    #[test]
+    fn signs_report() {
+        let signer = ReportSigner::from_hex(&format!("{}\n", hex::encode([7; 32]))).unwrap();
+        let public_key = SigningKey::from_bytes(&[7; 32]).verifying_key();
+        assert_eq!(signer.key_id(), key_id(&public_key));
+
+        let mut writer = HashingWriter::new(Vec::new());
+        writer.write_all(b"client,available\n1,1.5\n").unwrap();
+        let signature = signer.sign(&writer.digest());
+        assert!(verify_report(&public_key, &writer.inner, &signature));
+        assert!(!verify_report(
+            &public_key,
+            b"client,available\n1,2.5\n",
+            &signature
+        ));
+        assert!(ReportSigner::from_hex("abc").is_none());
+    }
+}
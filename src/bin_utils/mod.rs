@@ -1,51 +1,1153 @@
 //! This module could be a separate crate on its own, to bootstrap [`cute_ledger`] within binary
 //! but for simplicitly purposes, I include this module directly in binary.
 
-use std::io::{Read, Write};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+    sync::mpsc::Sender,
+    time::Instant,
+};
 
-use crate::processor::{
-    TransactionProcessError, TransactionProcessor,
-    in_memory_processor::InMemoryTransactionProcessor,
+use crate::{
+    account::TransactionId,
+    command::{AccountCommandError, TransactionKind},
+    history::Balance,
+    processor::{
+        ClientId, RejectKind, TransactionProcessError, TransactionProcessor,
+        in_memory_processor::InMemoryTransactionProcessor, savepoint::Savepoint,
+    },
+    stats::Stage,
+};
+use account_clients::AccountClients;
+use anyhow::{Context, Result};
+use circuit_breaker::{BreakerAction, CircuitBreaker};
+use config_reload::ConfigReload;
+use csv_parser::{ColumnMapping, CsvTransactionParser};
+use csv_parser::{InvalidRow, Transaction};
+use csv_printer::{Account, print_accounts, print_accounts_delta, print_accounts_extended};
+use error_sink::{ErrorSink, SilentSink};
+use fixed_width::{FixedWidthLayout, FixedWidthParser};
+use held_accrual::HeldAccrual;
+use normalize::{KindMappings, NormalizingParser};
+use number_format::NumberFormat;
+use progress::ProgressReporter;
+use reject_log::{Reject, RejectLog};
+use resource_usage::ResourceUsage;
+use row_outcome::{RowOutcome, RowStatus};
+use run_report::{
+    ConfigReloaded, OpenDispute, QuarantinedClient, QuarantinedSource, RolledBackBatch,
+    RunCounters, RunReport,
 };
-use anyhow::Result;
-use csv_parser::CsvTransactionParser;
-use csv_printer::{Account, print_accounts};
+use serde::Serialize;
+use signature::SignatureVerifier;
+use watermark::{LatePolicy, LateRow, Watermark};
+pub mod account_clients;
+#[cfg(feature = "async")]
+pub mod async_stream;
+pub mod cdc;
+pub mod circuit_breaker;
+pub mod config_reload;
 pub mod csv_parser;
 pub mod csv_printer;
+pub mod error_sink;
+pub mod export;
+#[cfg(feature = "fast-csv")]
+pub mod fast_csv_parser;
+pub mod fixed_width;
+pub mod handover;
+pub mod held_accrual;
+#[cfg(feature = "tui")]
+pub mod inspector;
+pub mod integrity;
+#[cfg(feature = "iso20022")]
+pub mod iso20022;
+pub mod manifest;
+pub mod mt940;
+pub mod normalize;
+pub mod number_format;
+pub mod planner;
+pub mod progress;
+pub mod reject_log;
+pub mod reorder;
+pub mod resource_usage;
+pub mod row_outcome;
+pub mod run_report;
+pub mod signature;
+pub mod simulation;
+pub mod standing_orders;
+pub mod statement_printer;
+pub mod watermark;
+#[cfg(feature = "xlsx")]
+pub mod xlsx_printer;
+
+/// What to do with rows of [`TransactionKind::Unknown`] type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownKindPolicy {
+    /// Count as rejected, like any other invalid row
+    #[default]
+    Reject,
+    /// Count as skipped, error is still passed to the error printer
+    /// to be reported as a warning
+    Skip,
+}
+
+impl FromStr for UnknownKindPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "skip" => Ok(Self::Skip),
+            other => Err(format!("unknown policy `{other}`")),
+        }
+    }
+}
+
+/// Format of the accounts report written to the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Ok(Self::Xlsx),
+            #[cfg(not(feature = "xlsx"))]
+            "xlsx" => Err("xlsx output requires `xlsx` feature to be enabled".to_string()),
+            other => Err(format!("unknown output format `{other}`")),
+        }
+    }
+}
+
+/// Parsed input rows, with line numbers used in error reports.
+/// Every parser of the input is a source, so new formats plug into [`Service`].
+pub trait TransactionSource: Iterator<Item = (u64, Transaction)> {
+    /// Row, at which the source stopped, as it couldn't be parsed
+    fn error(&self) -> Option<&InvalidRow> {
+        None
+    }
+}
+
+/// Rows parsed upfront, e.g. from ISO 20022 or MT940 statements
+impl TransactionSource for std::vec::IntoIter<(u64, Transaction)> {}
+
+/// Format of the input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    /// Fixed-width records, see [`fixed_width`]. Options cleaning up
+    /// CSV input (normalization, number format, columns) don't apply.
+    FixedWidth(FixedWidthLayout),
+    /// ISO 20022 camt messages, see [`iso20022`]
+    #[cfg(feature = "iso20022")]
+    Iso20022(AccountClients),
+    /// SWIFT MT940 statements, see [`mt940`]
+    Mt940(AccountClients),
+}
+
+/// Which accounts are written to the accounts report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccountsOutput {
+    /// Every account known to the processor
+    #[default]
+    All,
+    /// Only accounts created, or whose balances or locked status changed
+    /// during this run, useful when warm-starting from a previous state
+    Changed,
+    /// Like [`AccountsOutput::Changed`], with a row of empty balances for
+    /// every untouched account, so consumers can tell it still exists
+    ChangedWithTombstones,
+}
+
+impl FromStr for AccountsOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "changed" => Ok(Self::Changed),
+            "changed-with-tombstones" => Ok(Self::ChangedWithTombstones),
+            other => Err(format!("unknown accounts output `{other}`")),
+        }
+    }
+}
+
+/// Reads transactions from the input, processes them and writes accounts
+/// report to the output. Created with [`Service::builder`].
+pub struct Service<R, W, P = InMemoryTransactionProcessor, E = SilentSink> {
+    input: R,
+    output: W,
+    processor: P,
+    errors: E,
+    options: Options,
+}
+
+/// Everything configuring [`Service`] besides its input, output, processor
+/// and error sink
+#[derive(Default)]
+struct Options {
+    input_format: InputFormat,
+    output_format: OutputFormat,
+    accounts_output: AccountsOutput,
+    extended_report: bool,
+    extra_rows: Vec<Transaction>,
+    reorder_buffer: usize,
+    watermark: Option<Watermark>,
+    late_policy: LatePolicy,
+    verifier: Option<SignatureVerifier>,
+    normalize: bool,
+    kind_mappings: KindMappings,
+    number_format: NumberFormat,
+    columns: ColumnMapping,
+    unknown_kinds: UnknownKindPolicy,
+    deterministic: bool,
+    source: Option<String>,
+    progress: Option<ProgressReporter>,
+    isolate_clients: bool,
+    track_rejected_txs: bool,
+    tentative_sources: HashSet<String>,
+    max_error_rate: f64,
+    breaker: Option<CircuitBreaker>,
+    risk_config: Option<ConfigReload>,
+    held_accrual: bool,
+    open_disputes: bool,
+    rejects: Option<RejectLog>,
+    resource_usage: bool,
+}
+
+impl Service<(), ()> {
+    /// Input must be set, before the service can be built, and output before
+    /// it can [`Service::run`]; [`Service::run_into`] needs none.
+    /// Processor defaults to a fresh [`InMemoryTransactionProcessor`].
+    /// Errors are ignored, unless error sink is set.
+    pub fn builder() -> ServiceBuilder<(), (), InMemoryTransactionProcessor, SilentSink> {
+        ServiceBuilder {
+            input: (),
+            output: (),
+            processor: InMemoryTransactionProcessor::default(),
+            errors: SilentSink,
+            options: Options::default(),
+        }
+    }
+}
+
+/// Builds [`Service`], see [`Service::builder`]
+pub struct ServiceBuilder<R, W, P, E> {
+    input: R,
+    output: W,
+    processor: P,
+    errors: E,
+    options: Options,
+}
+
+impl<R, W, P, E> ServiceBuilder<R, W, P, E> {
+    pub fn input<I: Read>(self, input: I) -> ServiceBuilder<I, W, P, E> {
+        ServiceBuilder {
+            input,
+            output: self.output,
+            processor: self.processor,
+            errors: self.errors,
+            options: self.options,
+        }
+    }
+
+    /// Accounts report is written here, pass `&mut writer` to keep using it afterwards
+    pub fn output<O: Write>(self, output: O) -> ServiceBuilder<R, O, P, E> {
+        ServiceBuilder {
+            input: self.input,
+            output,
+            processor: self.processor,
+            errors: self.errors,
+            options: self.options,
+        }
+    }
+
+    pub fn processor<T: TransactionProcessor>(self, processor: T) -> ServiceBuilder<R, W, T, E> {
+        ServiceBuilder {
+            input: self.input,
+            output: self.output,
+            processor,
+            errors: self.errors,
+            options: self.options,
+        }
+    }
+
+    pub fn input_format(mut self, format: InputFormat) -> Self {
+        self.options.input_format = format;
+        self
+    }
+
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.options.output_format = format;
+        self
+    }
+
+    /// Which accounts to print, tombstones are only written in CSV output
+    pub fn accounts_output(mut self, accounts_output: AccountsOutput) -> Self {
+        self.options.accounts_output = accounts_output;
+        self
+    }
+
+    /// Prints custom fields of accounts in `extensions` column of CSV output,
+    /// see [`crate::account::Account::extensions`]
+    pub fn extended_report(mut self, extended_report: bool) -> Self {
+        self.options.extended_report = extended_report;
+        self
+    }
+
+    /// Synthetic transactions (e.g. expanded standing orders) processed after
+    /// the input, errors for them are reported with line 0.
+    pub fn extra_rows(mut self, rows: Vec<Transaction>) -> Self {
+        self.options.extra_rows = rows;
+        self
+    }
+
+    /// Size of reordering buffer for input rows, see [`reorder::Reorder`],
+    /// 0 keeps the original order.
+    pub fn reorder_buffer(mut self, size: usize) -> Self {
+        self.options.reorder_buffer = size;
+        self
+    }
+
+    /// Tracks event-time watermark of rows with `timestamp` column, trailing the
+    /// highest timestamp by `allowed_lateness`, and handles rows behind it by
+    /// `policy`, so reports of closed periods don't change with delayed rows
+    pub fn late_events(mut self, allowed_lateness: u64, policy: LatePolicy) -> Self {
+        self.options.watermark = Some(Watermark::new(allowed_lateness));
+        self.options.late_policy = policy;
+        self
+    }
+
+    /// Verifies signatures of input rows, for clients with known public keys
+    pub fn verifier(mut self, verifier: SignatureVerifier) -> Self {
+        self.options.verifier = Some(verifier);
+        self
+    }
+
+    /// Clean up input rows before parsing, see [`normalize`]
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.options.normalize = normalize;
+        self
+    }
+
+    /// Maps partner-specific types and sign conventions onto canonical types
+    /// by source of each row, while CSV input is normalized, see [`normalize`]
+    pub fn kind_mappings(mut self, mappings: KindMappings) -> Self {
+        self.options.kind_mappings = mappings;
+        self
+    }
+
+    /// How amounts are written in the input
+    pub fn number_format(mut self, number_format: NumberFormat) -> Self {
+        self.options.number_format = number_format;
+        self
+    }
+
+    /// Input columns named differently from the expected ones
+    pub fn columns(mut self, columns: ColumnMapping) -> Self {
+        self.options.columns = columns;
+        self
+    }
+
+    pub fn unknown_kinds(mut self, policy: UnknownKindPolicy) -> Self {
+        self.options.unknown_kinds = policy;
+        self
+    }
+
+    /// Print accounts ordered by client id, and leave out anything depending
+    /// on time or hashing, so outputs are byte identical across runs and platforms
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Origin of rows without `source` column, e.g. input file name,
+    /// recorded in event history for audit
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.options.source = Some(source.into());
+        self
+    }
+
+    /// Reports progress periodically while rows are processed
+    pub fn progress(mut self, progress: ProgressReporter) -> Self {
+        self.options.progress = Some(progress);
+        self
+    }
+
+    /// When processing a row panics or violates an invariant of the account
+    /// ([`TransactionProcessError::ApplyErr`]), quarantine its client: skip its
+    /// remaining rows and report it, but keep processing other clients.
+    /// Rows of quarantined clients are rejected with `client_quarantined` code.
+    pub fn isolate_clients(mut self, isolate_clients: bool) -> Self {
+        self.options.isolate_clients = isolate_clients;
+        self
+    }
+
+    /// Remembers lines of rejected deposits and withdrawals, so rows referencing
+    /// them are rejected with [`AccountCommandError::ReferencedTxRejected`]
+    /// instead of a plain missing transaction error
+    pub fn track_rejected_txs(mut self, track_rejected_txs: bool) -> Self {
+        self.options.track_rejected_txs = track_rejected_txs;
+        self
+    }
+
+    /// Applies each section of consecutive rows from these sources tentatively,
+    /// e.g. one partner's part of a combined file, and rolls it back wholesale
+    /// when the share of its rejected rows exceeds `max_error_rate`. Accepted
+    /// rows of rolled back sections are counted as rejected with
+    /// `batch_rolled_back` code, the processor must support savepoints.
+    pub fn tentative_sources(
+        mut self,
+        sources: impl IntoIterator<Item = String>,
+        max_error_rate: f64,
+    ) -> Self {
+        self.options.tentative_sources = sources.into_iter().collect();
+        self.options.max_error_rate = max_error_rate;
+        self
+    }
+
+    /// Trips when the rolling reject rate exceeds its threshold, and halts the
+    /// run or quarantines the offending client or source, see [`circuit_breaker`]
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.options.breaker = Some(breaker);
+        self
+    }
+
+    /// Applies the risk config before the first row, failing the run when it
+    /// can't be, and reloads it whenever it changes, see [`config_reload`]
+    pub fn risk_config(mut self, risk_config: ConfigReload) -> Self {
+        self.options.risk_config = Some(risk_config);
+        self
+    }
+
+    /// Integrates held balances over row timestamps (seconds since unix epoch),
+    /// and reports average held balance of every client and day, see [`held_accrual`]
+    pub fn held_accrual(mut self, held_accrual: bool) -> Self {
+        self.options.held_accrual = held_accrual;
+        self
+    }
+
+    /// Reports disputes open at the end of the run with their stage and
+    /// case, see [`crate::account::DisputeCase`]
+    pub fn open_disputes(mut self, open_disputes: bool) -> Self {
+        self.options.open_disputes = open_disputes;
+        self
+    }
+
+    /// Records every rejected row, in addition to the error printer
+    pub fn rejects(mut self, rejects: RejectLog) -> Self {
+        self.options.rejects = Some(rejects);
+        self
+    }
+
+    /// Report peak memory, allocations and map sizes, see [`resource_usage`]
+    pub fn resource_usage(mut self, resource_usage: bool) -> Self {
+        self.options.resource_usage = resource_usage;
+        self
+    }
+
+    /// Receives error of every rejected row with its line number, see [`error_sink`]
+    pub fn on_error<S: ErrorSink>(self, errors: S) -> ServiceBuilder<R, W, P, S> {
+        ServiceBuilder {
+            input: self.input,
+            output: self.output,
+            processor: self.processor,
+            errors,
+            options: self.options,
+        }
+    }
+}
+
+impl<R, W, P, E> ServiceBuilder<R, W, P, E>
+where
+    R: Read,
+    P: TransactionProcessor,
+    E: ErrorSink,
+{
+    pub fn build(self) -> Service<R, W, P, E> {
+        Service {
+            input: self.input,
+            output: self.output,
+            processor: self.processor,
+            errors: self.errors,
+            options: self.options,
+        }
+    }
+}
+
+impl<R, W, P, E> Service<R, W, P, E>
+where
+    R: Read,
+    W: Write,
+    P: TransactionProcessor,
+    E: ErrorSink,
+{
+    /// Processes all transactions, prints accounts report and returns
+    /// summary of the run.
+    pub fn run(self) -> Result<RunReport> {
+        self.run_with_outcomes(None)
+    }
 
-pub struct Service<'w, R, W: 'w> {
-    pub input: R,
-    pub output: &'w mut W,
-    pub error_printer: Box<dyn FnMut(u64, TransactionProcessError)>,
+    /// Same as [`Service::run`], and sends outcome of every row with resulting
+    /// balances to `outcomes` as soon as it is processed, e.g. for live dashboards.
+    /// Processing continues, when the receiver is gone.
+    pub fn run_streaming(self, outcomes: Sender<RowOutcome>) -> Result<RunReport> {
+        self.run_with_outcomes(Some(outcomes))
+    }
+
+    fn run_with_outcomes(self, outcomes: Option<Sender<RowOutcome>>) -> Result<RunReport> {
+        let started = Instant::now();
+        let Self {
+            input,
+            mut output,
+            mut processor,
+            mut errors,
+            options,
+        } = self;
+        let accounts_output = options.accounts_output;
+        let extended_report = options.extended_report;
+        let output_format = options.output_format;
+        let deterministic = options.deterministic;
+        let resource_usage = options.resource_usage;
+        let open_disputes = options.open_disputes;
+        let mut counters = RunCounters::default();
+        let initial: HashMap<ClientId, Balance> = match accounts_output {
+            AccountsOutput::All => HashMap::new(),
+            AccountsOutput::Changed | AccountsOutput::ChangedWithTombstones => processor
+                .accounts()
+                .map(|(client_id, acc)| (client_id, Balance::of(acc)))
+                .collect(),
+        };
+        process_input(
+            input,
+            options,
+            outcomes,
+            &mut processor,
+            &mut errors,
+            &mut counters,
+        )?;
+        processor
+            .flush_subscribers()
+            .context("Failed to write applied events")?;
+        processor
+            .commit_pending()
+            .context("Failed to commit processed transactions")?;
+
+        let stats = processor.stats().cloned().unwrap_or_default();
+        let mut untouched = Vec::new();
+        let mut accounts: Vec<_> = processor
+            .accounts()
+            .filter(|(client_id, acc)| {
+                if accounts_output == AccountsOutput::All
+                    || initial.get(client_id) != Some(&Balance::of(acc))
+                {
+                    return true;
+                }
+                untouched.push(*client_id);
+                false
+            })
+            .map(|(client_id, acc)| Account::of(client_id, acc))
+            .collect();
+        if deterministic {
+            accounts.sort_by_key(|acc| acc.client);
+            untouched.sort_unstable();
+        }
+        let accounts = accounts.into_iter();
+        match output_format {
+            OutputFormat::Csv if accounts_output == AccountsOutput::ChangedWithTombstones => {
+                print_accounts_delta(&mut output, accounts, &untouched)?
+            }
+            OutputFormat::Csv if extended_report => print_accounts_extended(&mut output, accounts)?,
+            OutputFormat::Csv => print_accounts(&mut output, accounts)?,
+            #[cfg(feature = "xlsx")]
+            OutputFormat::Xlsx if deterministic => xlsx_printer::print_accounts_xlsx(
+                &mut output,
+                accounts,
+                // timings differ between runs
+                &Default::default(),
+                true,
+            )?,
+            #[cfg(feature = "xlsx")]
+            OutputFormat::Xlsx => {
+                xlsx_printer::print_accounts_xlsx(&mut output, accounts, &stats, false)?
+            }
+        }
+        let suspense = processor.suspense();
+        counters.report.compliance = processor.compliance();
+        counters.report.reviews = processor.reviews();
+        let flags = processor.flags();
+        if open_disputes {
+            let mut disputes: Vec<_> = processor
+                .accounts()
+                .flat_map(|(client, acc)| {
+                    acc.open_disputes()
+                        .map(move |(tx, stage, case)| OpenDispute {
+                            client,
+                            tx,
+                            stage,
+                            case: case.cloned().unwrap_or_default(),
+                        })
+                })
+                .collect();
+            disputes.sort_unstable_by_key(|dispute| (dispute.client, dispute.tx));
+            counters.report.open_disputes = disputes;
+        }
+        if resource_usage {
+            counters.report.resources = Some(ResourceUsage::collect(processor.map_sizes()));
+        }
+        Ok(counters.finish(started.elapsed(), stats, suspense, flags))
+    }
 }
 
-impl<'w, R, W> Service<'w, R, W>
+impl<R, W, P, E> Service<R, W, P, E>
 where
     R: Read,
-    W: Write + 'w,
+    P: TransactionProcessor,
+    E: ErrorSink,
 {
-    pub fn run(mut self) -> Result<()> {
-        let parser = CsvTransactionParser::new(self.input);
+    /// Processes all transactions into the processor of the service and returns
+    /// it back, so several inputs can be processed sequentially into the same
+    /// state. Accounts report is not printed, so output is not needed.
+    pub fn run_into(self) -> Result<P> {
+        let Self {
+            input,
+            mut processor,
+            mut errors,
+            options,
+            ..
+        } = self;
+        process_input(
+            input,
+            options,
+            None,
+            &mut processor,
+            &mut errors,
+            &mut RunCounters::default(),
+        )?;
+        processor
+            .flush_subscribers()
+            .context("Failed to write applied events")?;
+        processor
+            .commit_pending()
+            .context("Failed to commit processed transactions")?;
+        Ok(processor)
+    }
+}
 
-        let mut processor = InMemoryTransactionProcessor::default();
+fn process_input<R: Read, P: TransactionProcessor>(
+    source: R,
+    options: Options,
+    outcomes: Option<Sender<RowOutcome>>,
+    processor: &mut P,
+    errors: &mut dyn ErrorSink,
+    counters: &mut RunCounters,
+) -> Result<()> {
+    let mut normalized = Vec::new();
+    let mut parser: Box<dyn TransactionSource> = match options.input_format {
+        InputFormat::FixedWidth(layout) => Box::new(FixedWidthParser::new(source, layout)),
+        #[cfg(feature = "iso20022")]
+        InputFormat::Iso20022(accounts) => {
+            Box::new(iso20022::parse_entries(source, &accounts)?.into_iter())
+        }
+        InputFormat::Mt940(accounts) => {
+            Box::new(mt940::parse_statements(source, &accounts)?.into_iter())
+        }
+        InputFormat::Csv if options.normalize || !options.kind_mappings.is_empty() => Box::new(
+            NormalizingParser::new(
+                source,
+                options.number_format,
+                &options.columns,
+                &mut normalized,
+            )
+            .with_kind_mappings(&options.kind_mappings, options.source.as_deref()),
+        ),
+        InputFormat::Csv
+            if options.number_format != NumberFormat::Plain || !options.columns.is_empty() =>
+        {
+            Box::new(CsvTransactionParser::with_columns(
+                source,
+                options.number_format,
+                &options.columns,
+            ))
+        }
+        InputFormat::Csv => {
+            #[cfg(feature = "fast-csv")]
+            let parser = fast_csv_parser::AutoTransactionParser::new(source);
+            #[cfg(not(feature = "fast-csv"))]
+            let parser = CsvTransactionParser::new(source);
+            Box::new(parser)
+        }
+    };
+    let mut progress = options.progress;
+    // messages are only needed for reject log and outcomes
+    let detailed = options.rejects.is_some() || outcomes.is_some();
+    let rejected = |code, message: &dyn ToString| RowStatus::Rejected {
+        code,
+        message: if detailed {
+            message.to_string()
+        } else {
+            String::new()
+        },
+    };
+    let mut effects = RowEffects {
+        outcomes,
+        rejects: options.rejects,
+        breaker: options.breaker,
+        rejected_txs: options.track_rejected_txs.then(HashMap::new),
+        quarantined: HashSet::new(),
+        quarantined_sources: HashSet::new(),
+    };
+    let mut risk_config = options.risk_config;
+    if let Some(reload) = &mut risk_config {
+        reload
+            .load()
+            .and_then(|config| config_reload::apply(&config, processor, effects.breaker.as_mut()))
+            .with_context(|| {
+                format!("Failed to apply risk config `{}`", reload.path().display())
+            })?;
+    }
+    let mut watermark = options.watermark;
+    let mut batch: Option<TentativeBatch> = None;
+    let mut accrual = options.held_accrual.then(HeldAccrual::default);
+    // rows without timestamp happen at the time of the previous row
+    let mut last_timestamp = 0;
+    let mut rows = reorder::Reorder::new(parser.by_ref(), options.reorder_buffer)
+        .chain(options.extra_rows.into_iter().map(|row| (0, row)));
 
-        for (line, row) in parser {
-            if let Err(err) =
-                processor.process_transaction(row.tx, row.client, row.amount, row.kind)
+    loop {
+        if let Some(progress) = &mut progress {
+            progress.tick(&counters.report);
+        }
+        let started = Instant::now();
+        let Some((line, row)) = rows.next() else {
+            break;
+        };
+        if let Some(stats) = processor.stats_mut() {
+            stats.record(&row.kind, Stage::Parse, started.elapsed());
+        }
+        // after the row is read, as reading may wait long for it
+        if let Some(reload) = &mut risk_config
+            && let Some(config) = reload.poll()
+        {
+            let applied = config.and_then(|config| {
+                config_reload::apply(&config, processor, effects.breaker.as_mut())
+            });
+            counters.report.config_reloads.push(ConfigReloaded {
+                after_rows: counters.report.rows_read,
+                error: applied.err().map(|err| err.to_string()),
+            });
+        }
+        let source = row.source.as_deref().or(options.source.as_deref());
+        let trace_id = row.trace_id.as_deref();
+        if let Some(open) = batch.take_if(|open| Some(open.source.as_str()) != source) {
+            open.close(processor, options.max_error_rate, counters, &mut effects);
+            if counters.report.halted.is_some() {
+                break;
+            }
+        }
+        counters.row_read(row.client);
+        if batch.is_none()
+            && let Some(source) = source
+            && options.tentative_sources.contains(source)
+        {
+            let Some(savepoint) = processor.savepoint() else {
+                anyhow::bail!("Processor can't apply rows of `{source}` tentatively");
+            };
+            batch = Some(TentativeBatch {
+                source: source.to_string(),
+                savepoint,
+                first_line: line,
+                last_line: line,
+                accepted: 0,
+                rejected: 0,
+                held: Vec::new(),
+            });
+        }
+        let status = 'row: {
+            if let (Some(watermark), Some(timestamp)) = (&mut watermark, row.timestamp)
+                && let Some(current) = watermark.observe(timestamp)
             {
-                (self.error_printer)(line, err);
+                counters.report.rows_late += 1;
+                let err = AccountCommandError::LateEvent { watermark: current };
+                match options.late_policy {
+                    LatePolicy::Apply => {}
+                    LatePolicy::Divert => {
+                        counters.row_skipped();
+                        counters.report.late.push(LateRow {
+                            line,
+                            row: row.clone(),
+                            watermark: current,
+                        });
+                        errors.report_traced(line, trace_id, err.into());
+                        break 'row RowStatus::Skipped;
+                    }
+                    LatePolicy::Reject => {
+                        let err = TransactionProcessError::from(err);
+                        counters.row_rejected(err.code(), err.reject_kind());
+                        let status = rejected(err.code(), &err);
+                        errors.report_traced(line, trace_id, err);
+                        break 'row status;
+                    }
+                }
             }
+            if let TransactionKind::Unknown(kind) = &row.kind
+                && options.unknown_kinds == UnknownKindPolicy::Skip
+            {
+                counters.row_skipped();
+                let kind = kind.clone();
+                errors.report_traced(
+                    line,
+                    trace_id,
+                    AccountCommandError::UnknownKind { kind }.into(),
+                );
+                break 'row RowStatus::Skipped;
+            }
+            // synthetic rows (line 0) are not signed
+            if line > 0
+                && let Some(verifier) = &options.verifier
+                && let Err(err) = verifier.verify(&row)
+            {
+                counters.row_rejected(err.code(), err.reject_kind());
+                let status = rejected(err.code(), &err);
+                errors.report_traced(line, trace_id, err);
+                break 'row status;
+            }
+            if effects.quarantined.contains(&row.client) {
+                counters.row_rejected("client_quarantined", RejectKind::Technical);
+                break 'row rejected("client_quarantined", &"Client is quarantined");
+            }
+            if let Some(source) = source
+                && effects.quarantined_sources.contains(source)
+            {
+                counters.row_rejected("source_quarantined", RejectKind::Technical);
+                break 'row rejected("source_quarantined", &"Source is quarantined");
+            }
+            let client = row.client;
+            let mut process = || {
+                processor.process_transaction_traced(
+                    row.tx,
+                    row.client,
+                    row.amount,
+                    row.kind.clone(),
+                    source,
+                    trace_id,
+                )
+            };
+            let result = if options.isolate_clients {
+                let result = panic::catch_unwind(AssertUnwindSafe(process))
+                    .map_err(|payload| panic_message(payload.as_ref()));
+                // invariant violations poison the client just like panics
+                match result {
+                    Ok(Err(TransactionProcessError::ApplyErr(err))) => Err(err.to_string()),
+                    result => result,
+                }
+            } else {
+                Ok(process())
+            };
+            let result = match result {
+                Ok(result) => result,
+                Err(reason) => {
+                    effects.quarantined.insert(client);
+                    counters.row_rejected("client_quarantined", RejectKind::Technical);
+                    let status = rejected("client_quarantined", &reason);
+                    counters.report.quarantined.push(QuarantinedClient {
+                        client,
+                        line,
+                        reason,
+                    });
+                    break 'row status;
+                }
+            };
+            let result = result.map_err(|err| match err {
+                TransactionProcessError::CommandErr(AccountCommandError::ExistingTxRequired {
+                    action,
+                }) if let Some(rejected_txs) = &effects.rejected_txs
+                    && let Some(&line) = rejected_txs.get(&row.tx) =>
+                {
+                    AccountCommandError::ReferencedTxRejected { action, line }.into()
+                }
+                err => err,
+            });
+            match result {
+                Ok(outcome) if outcome.applied.is_none() => {
+                    counters.row_deferred();
+                    RowStatus::Deferred
+                }
+                Ok(_) => {
+                    counters.row_accepted();
+                    RowStatus::Accepted
+                }
+                Err(
+                    err @ TransactionProcessError::CommandErr(
+                        AccountCommandError::ZeroAmountSkipped { .. }
+                        | AccountCommandError::RepeatedModifySkipped { .. },
+                    ),
+                ) => {
+                    counters.row_skipped();
+                    errors.report_traced(line, trace_id, err);
+                    RowStatus::Skipped
+                }
+                Err(err) => {
+                    counters.row_rejected(err.code(), err.reject_kind());
+                    let status = rejected(err.code(), &err);
+                    errors.report_traced(line, trace_id, err);
+                    status
+                }
+            }
+        };
+        last_timestamp = row.timestamp.unwrap_or(last_timestamp);
+        if let Some(accrual) = &mut accrual {
+            let held = processor
+                .account(row.client)
+                .map(|acc| acc.held())
+                .unwrap_or_default();
+            accrual.record(row.client, last_timestamp, held);
+        }
+        effects.track_rejected(line, &row, &status);
+        let processed = ProcessedRow {
+            line,
+            source: source.map(str::to_string),
+            balance: if effects.outcomes.is_some() {
+                processor.account(row.client).map(Balance::of)
+            } else {
+                None
+            },
+            row,
+            status,
+        };
+        match &mut batch {
+            Some(batch) => batch.hold(processed),
+            None => effects.emit(processed, counters),
+        }
+        if counters.report.halted.is_some() {
+            break;
+        }
+    }
+    if let Some(batch) = batch {
+        batch.close(processor, options.max_error_rate, counters, &mut effects);
+    }
+    if let Some(accrual) = accrual {
+        counters.report.held_accrual = accrual.finish();
+    }
+    drop(rows);
+    let invalid = parser.error().cloned();
+    drop(parser);
+    counters.report.normalized = normalized;
+    counters.report.watermark = watermark.and_then(|watermark| watermark.current());
+    if let Some(progress) = &mut progress {
+        progress.finish(&counters.report);
+    }
+    if let Some(rejects) = effects.rejects {
+        rejects.finish()?;
+    }
+    errors.finish()?;
+    match invalid {
+        Some(invalid) => Err(invalid.into()),
+        None => Ok(()),
+    }
+}
+
+/// Row with its final status, and balance of its client after it
+struct ProcessedRow {
+    line: u64,
+    row: Transaction,
+    source: Option<String>,
+    status: RowStatus,
+    /// Only looked up for outcomes
+    balance: Option<Balance>,
+}
+
+/// Outputs of processed rows, and what they change for the following rows
+struct RowEffects {
+    outcomes: Option<Sender<RowOutcome>>,
+    rejects: Option<RejectLog>,
+    breaker: Option<CircuitBreaker>,
+    /// Lines of rejected created transactions, when tracked
+    rejected_txs: Option<HashMap<TransactionId, u64>>,
+    quarantined: HashSet<ClientId>,
+    quarantined_sources: HashSet<String>,
+}
+
+impl RowEffects {
+    /// Remembers the line of a rejected created transaction, so rows
+    /// referring to it are rejected with it. A rejected duplicate doesn't
+    /// count, as the transaction it repeats exists.
+    fn track_rejected(&mut self, line: u64, row: &Transaction, status: &RowStatus) {
+        if let Some(rejected_txs) = &mut self.rejected_txs
+            && let RowStatus::Rejected { code, .. } = status
+            && *code != "duplicate_transaction"
+            && !row.kind.is_modify()
+        {
+            rejected_txs.insert(row.tx, line);
         }
+    }
+
+    /// Reports the row to the circuit breaker, reject log and outcomes
+    fn emit(&mut self, processed: ProcessedRow, counters: &mut RunCounters) {
+        let ProcessedRow {
+            line,
+            row,
+            source,
+            status,
+            balance,
+        } = processed;
+        let quarantined_row = matches!(
+            status,
+            RowStatus::Rejected {
+                code: "client_quarantined" | "source_quarantined",
+                ..
+            }
+        );
+        if let Some(breaker) = &mut self.breaker
+            && !quarantined_row
+            && let Some(trip) = breaker.record(
+                line,
+                row.client,
+                source.as_deref(),
+                matches!(status, RowStatus::Rejected { .. }),
+            )
+        {
+            let reason = format!(
+                "{:.0}% of the last {} rows rejected",
+                trip.reject_rate * 100.0,
+                breaker.window()
+            );
+            match (breaker.action(), &trip.source) {
+                (BreakerAction::QuarantineClient, _) => {
+                    self.quarantined.insert(trip.client);
+                    counters.report.quarantined.push(QuarantinedClient {
+                        client: trip.client,
+                        line,
+                        reason,
+                    });
+                }
+                (BreakerAction::QuarantineSource, Some(source)) => {
+                    self.quarantined_sources.insert(source.clone());
+                    counters.report.quarantined_sources.push(QuarantinedSource {
+                        source: source.clone(),
+                        line,
+                        reason,
+                    });
+                }
+                (BreakerAction::Halt | BreakerAction::QuarantineSource, _) => {
+                    counters.report.halted = Some(trip);
+                }
+            }
+        }
+        if let (Some(rejects), RowStatus::Rejected { code, message }) = (&mut self.rejects, &status)
+        {
+            rejects.record(&Reject {
+                line,
+                tx: row.tx,
+                client: row.client,
+                kind: row.kind.name(),
+                amount: row.amount,
+                error_code: code,
+                error_message: message,
+                trace_id: row.trace_id.as_deref(),
+            });
+        }
+        if let Some(outcomes) = &self.outcomes {
+            // receiver may be gone, e.g. dashboard was closed
+            let _ = outcomes.send(RowOutcome {
+                line,
+                tx: row.tx,
+                client: row.client,
+                status,
+                balance,
+            });
+        }
+    }
+}
+
+/// Section of rows applied tentatively, see [`ServiceBuilder::tentative_sources`].
+/// Its rows are reported once it's known, whether it's kept.
+struct TentativeBatch {
+    source: String,
+    savepoint: Savepoint,
+    first_line: u64,
+    last_line: u64,
+    accepted: u64,
+    rejected: u64,
+    held: Vec<ProcessedRow>,
+}
+
+impl TentativeBatch {
+    fn hold(&mut self, processed: ProcessedRow) {
+        self.last_line = processed.line;
+        match processed.status {
+            RowStatus::Accepted => self.accepted += 1,
+            RowStatus::Rejected { .. } => self.rejected += 1,
+            RowStatus::Skipped | RowStatus::Deferred => {}
+        }
+        self.held.push(processed);
+    }
+
+    /// Keeps the section, or rolls it back if too many of its rows were
+    /// rejected, and then reports its rows
+    fn close<P: TransactionProcessor>(
+        self,
+        processor: &mut P,
+        max_error_rate: f64,
+        counters: &mut RunCounters,
+        effects: &mut RowEffects,
+    ) {
+        let rows = self.held.len() as u64;
+        let error_rate = self.rejected as f64 / rows.max(1) as f64;
+        if error_rate <= max_error_rate {
+            processor.release(self.savepoint);
+            for processed in self.held {
+                effects.emit(processed, counters);
+            }
+            return;
+        }
+        processor.rollback_to(self.savepoint);
+        counters.report.rows_accepted -= self.accepted;
+        for mut processed in self.held {
+            if processed.status == RowStatus::Accepted {
+                counters.row_rejected("batch_rolled_back", RejectKind::Business);
+                processed.status = RowStatus::Rejected {
+                    code: "batch_rolled_back",
+                    message: format!("Section of `{}` was rolled back", self.source),
+                };
+                effects.track_rejected(processed.line, &processed.row, &processed.status);
+            }
+            if effects.outcomes.is_some() {
+                processed.balance = processor.account(processed.row.client).map(Balance::of);
+            }
+            effects.emit(processed, counters);
+        }
+        counters.report.rolled_back.push(RolledBackBatch {
+            source: self.source,
+            first_line: self.first_line,
+            last_line: self.last_line,
+            rows,
+            rejected: self.rejected,
+        });
+    }
+}
 
-        print_accounts(
-            self.output,
-            processor.accounts.iter().map(|(client_id, acc)| Account {
-                client: *client_id,
-                available: acc.available(),
-                held: acc.held(),
-                locked: acc.locked(),
-                total: acc.total_amount(),
-            }),
-        )
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panic".to_string()
     }
 }
@@ -0,0 +1,189 @@
+//! SWIFT MT940 customer statements. Every statement line (`:61:`) becomes
+//! a row of the client owning the statement account (`:25:`): credits are
+//! deposits and debits are withdrawals, while reversals of credits are
+//! withdrawals and reversals of debits are deposits.
+//! Statement lines carry no numeric reference, so tx ids are derived from
+//! the statement reference (`:20:`) and the position of the line, thus
+//! re-reading the same statement yields the same ids.
+
+use std::io::{BufRead, BufReader, Read};
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    account::TransactionId,
+    command::TransactionKind,
+    id_allocator::{DEFAULT_FIRST_TX_ID, HashedAllocator, IdAllocator},
+};
+
+use super::{account_clients::AccountClients, csv_parser::Transaction};
+
+#[derive(Debug, Error)]
+pub enum Mt940Error {
+    #[error("Failed to read statement: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Statement line at line {line} has no preceding `{tag}` field")]
+    MissingField { line: u64, tag: &'static str },
+    #[error("Invalid statement line at line {line}: `{value}`")]
+    InvalidLine { line: u64, value: String },
+    #[error("Statement line at line {line} belongs to account `{account}` of unknown client")]
+    UnknownAccount { line: u64, account: String },
+    #[error("Statement `{reference}` has too many lines")]
+    IdsExhausted { reference: String },
+}
+
+/// Ids of statement lines stay below the range reserved for synthetic transactions
+const TX_IDS: std::ops::RangeInclusive<TransactionId> = 0..=DEFAULT_FIRST_TX_ID - 1;
+
+/// Rows of all statement lines, with line numbers of their `:61:` fields
+pub fn parse_statements(
+    source: impl Read,
+    accounts: &AccountClients,
+) -> Result<Vec<(u64, Transaction)>, Mt940Error> {
+    let mut rows = Vec::new();
+    let mut reference: Option<(String, HashedAllocator)> = None;
+    let mut client = None;
+    for (index, text) in BufReader::new(source).lines().enumerate() {
+        let text = text?;
+        let line = index as u64 + 1;
+        // tags start a field, other lines (SWIFT envelope, field continuations) are skipped
+        let Some((tag, value)) = text
+            .strip_prefix(':')
+            .and_then(|field| field.split_once(':'))
+        else {
+            continue;
+        };
+        let value = value.trim();
+        match tag {
+            "20" => {
+                let ids = HashedAllocator::new(value, TX_IDS);
+                reference = Some((value.to_string(), ids));
+                client = None;
+            }
+            "25" => {
+                client =
+                    Some(
+                        accounts
+                            .client(value)
+                            .ok_or_else(|| Mt940Error::UnknownAccount {
+                                line,
+                                account: value.to_string(),
+                            })?,
+                    );
+            }
+            "61" => {
+                let (reference, ids) = reference
+                    .as_mut()
+                    .ok_or(Mt940Error::MissingField { line, tag: ":20:" })?;
+                let client = client.ok_or(Mt940Error::MissingField { line, tag: ":25:" })?;
+                let (kind, amount) =
+                    parse_statement_line(value).ok_or_else(|| Mt940Error::InvalidLine {
+                        line,
+                        value: value.to_string(),
+                    })?;
+                let tx = ids.allocate().ok_or_else(|| Mt940Error::IdsExhausted {
+                    reference: reference.clone(),
+                })?;
+                rows.push((
+                    line,
+                    Transaction {
+                        kind,
+                        client,
+                        tx,
+                        amount: Some(amount),
+                        timestamp: None,
+                        signature: None,
+                        source: None,
+                        trace_id: None,
+                    },
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(rows)
+}
+
+/// Kind and amount of `:61:` value, e.g. `2301020102C100,50NTRFNONREF`:
+/// value date, optional entry date, debit/credit mark, optional funds code
+/// and amount with decimal comma, followed by references
+fn parse_statement_line(value: &str) -> Option<(TransactionKind, Decimal)> {
+    let rest = value.get(6..)?;
+    let rest = match rest.get(..4) {
+        Some(entry_date) if entry_date.bytes().all(|b| b.is_ascii_digit()) => &rest[4..],
+        _ => rest,
+    };
+    let (kind, rest) = if let Some(rest) = rest.strip_prefix("RC") {
+        (TransactionKind::Withdrawal, rest)
+    } else if let Some(rest) = rest.strip_prefix("RD") {
+        (TransactionKind::Deposit, rest)
+    } else if let Some(rest) = rest.strip_prefix('C') {
+        (TransactionKind::Deposit, rest)
+    } else {
+        (TransactionKind::Withdrawal, rest.strip_prefix('D')?)
+    };
+    let rest = rest
+        .strip_prefix(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(rest);
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .unwrap_or(rest.len());
+    let amount = rest[..end].replacen(',', ".", 1).parse().ok()?;
+    Some((kind, amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = "{1:F01BANKBEBBAXXX0000000000}{4:
+:20:STMT-2023-01
+:25:NL91ABNA0417164300
+:28C:1/1
+:60F:C230101EUR1000,00
+:61:2301020102C100,50NTRFNONREF//B1
+:86:Invoice 42
+paid in full
+:61:230103D20,NMSCNONREF
+:61:230104RCR5,00NTRFNONREF
+:62F:C230104EUR1075,50
+-}
+";
+
+    #[test]
+    fn parse_statement_lines() {
+        let mut accounts = AccountClients::default();
+        accounts.insert("NL91ABNA0417164300".to_string(), 7);
+        let rows = parse_statements(STATEMENT.as_bytes(), &accounts).unwrap();
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|(line, row)| (*line, row.client, row.kind.clone(), row.amount))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (6, 7, TransactionKind::Deposit, Some(Decimal::new(10050, 2))),
+                (9, 7, TransactionKind::Withdrawal, Some(Decimal::from(20))),
+                (10, 7, TransactionKind::Withdrawal, Some(Decimal::from(5))),
+            ]
+        );
+
+        // ids are stable for the same statement reference
+        let ids = |statement: &str| -> Vec<_> {
+            parse_statements(statement.as_bytes(), &accounts)
+                .unwrap()
+                .into_iter()
+                .map(|(_, row)| row.tx)
+                .collect()
+        };
+        assert_eq!(ids(STATEMENT), ids(STATEMENT));
+        assert_ne!(
+            ids(STATEMENT),
+            ids(&STATEMENT.replace("STMT-2023-01", "STMT-2023-02"))
+        );
+
+        let err = parse_statements(STATEMENT.as_bytes(), &AccountClients::default()).unwrap_err();
+        assert!(matches!(err, Mt940Error::UnknownAccount { line: 3, .. }));
+    }
+}
@@ -0,0 +1,354 @@
+//! Partner files are often messy: padded fields, capitalized or
+//! misspelled types, decimal commas. Normalization pass cleans each
+//! record before it is deserialized, and reports which rows were changed.
+//! Partner-specific type strings and sign conventions are mapped onto
+//! canonical types here as well, by source of the row.
+
+use std::{collections::HashMap, fmt::Display, io::Read};
+
+use csv::StringRecord;
+use serde::Deserialize;
+
+use crate::command::TransactionKind;
+
+use super::{
+    TransactionSource,
+    csv_parser::{ColumnMapping, CsvTransactionParser, InvalidRow, Transaction},
+    number_format::NumberFormat,
+};
+
+/// Alternative spellings of transaction types, after lowercasing
+const KIND_SYNONYMS: [(&str, &str); 6] = [
+    ("withdraw", "withdrawal"),
+    ("charge-back", "chargeback"),
+    ("charge_back", "chargeback"),
+    ("pending-deposit", "pending_deposit"),
+    ("pendingdeposit", "pending_deposit"),
+    ("cancel", "void"),
+];
+
+/// Type strings and sign convention of one partner
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceMapping {
+    /// Partner's type string and the canonical type it stands for
+    pub kinds: HashMap<String, TransactionKind>,
+    /// Negative amounts of deposits are withdrawals of the absolute amount
+    pub signed_amounts: bool,
+}
+
+/// Mappings by source of rows (`source` column, or input file name),
+/// as JSON configuration:
+///
+/// ```json
+/// {
+///     "partner-a": { "kinds": { "CR": "deposit", "DR": "withdrawal", "RV": "chargeback" } },
+///     "partner-b": { "kinds": { "TXN": "deposit" }, "signed_amounts": true }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct KindMappings {
+    sources: HashMap<String, SourceMapping>,
+}
+
+impl KindMappings {
+    /// Fails on mappings onto unknown types
+    pub fn from_json(source: impl Read) -> serde_json::Result<Self> {
+        let mappings: Self = serde_json::from_reader(source)?;
+        for (source, mapping) in &mappings.sources {
+            for (alias, kind) in &mapping.kinds {
+                if let TransactionKind::Unknown(name) = kind {
+                    return Err(serde::de::Error::custom(format!(
+                        "`{alias}` of `{source}` is mapped to unknown type `{name}`"
+                    )));
+                }
+            }
+        }
+        Ok(mappings)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn get(&self, source: &str) -> Option<&SourceMapping> {
+        self.sources.get(source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// Leading or trailing whitespace removed
+    Trimmed,
+    /// Type lowercased
+    KindCase,
+    /// Type replaced by its canonical name
+    KindSynonym,
+    /// Partner-specific type replaced by the mapped one
+    KindAlias,
+    /// Deposit with negative amount turned into withdrawal
+    SignedAmount,
+    /// Decimal comma replaced by a point
+    DecimalSeparator,
+}
+
+impl Display for Normalization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Normalization::Trimmed => "trimmed",
+            Normalization::KindCase => "type case",
+            Normalization::KindSynonym => "type synonym",
+            Normalization::KindAlias => "type alias",
+            Normalization::SignedAmount => "signed amount",
+            Normalization::DecimalSeparator => "decimal separator",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedRow {
+    pub line: u64,
+    pub changes: Vec<Normalization>,
+}
+
+/// Positions of normalized columns in records
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizedColumns {
+    pub kind: Option<usize>,
+    pub amount: Option<usize>,
+    /// Decimal commas are only guessed when number format is not given
+    pub guess_decimal_comma: bool,
+}
+
+/// Parses transaction list in CSV format, normalizing every record first.
+/// Changed rows are appended to the `report`. Stops at the first row,
+/// that cannot be parsed after normalization.
+pub struct NormalizingParser<'a, R> {
+    parser: CsvTransactionParser<R>,
+    columns: NormalizedColumns,
+    source_idx: Option<usize>,
+    mappings: Option<&'a KindMappings>,
+    /// Source of rows without `source` column
+    default_source: Option<&'a str>,
+    report: &'a mut Vec<NormalizedRow>,
+}
+
+impl<'a, R> NormalizingParser<'a, R>
+where
+    R: Read,
+{
+    pub fn new(
+        source: R,
+        number_format: NumberFormat,
+        columns: &ColumnMapping,
+        report: &'a mut Vec<NormalizedRow>,
+    ) -> Self {
+        let parser = CsvTransactionParser::untrimmed(source, number_format, columns);
+        let position = |name| parser.headers().iter().position(|header| header == name);
+        let columns = NormalizedColumns {
+            kind: position("type"),
+            amount: position("amount"),
+            guess_decimal_comma: number_format == NumberFormat::Plain,
+        };
+        let source_idx = position("source");
+        Self {
+            parser,
+            columns,
+            source_idx,
+            mappings: None,
+            default_source: None,
+            report,
+        }
+    }
+
+    /// Maps partner-specific types and signs by source of each row,
+    /// `default_source` is the source of rows without `source` column
+    pub fn with_kind_mappings(
+        mut self,
+        mappings: &'a KindMappings,
+        default_source: Option<&'a str>,
+    ) -> Self {
+        self.mappings = Some(mappings);
+        self.default_source = default_source;
+        self
+    }
+}
+
+impl<R> Iterator for NormalizingParser<'_, R>
+where
+    R: Read,
+{
+    type Item = (u64, Transaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.parser.read_record()?;
+        let record = self.parser.record_mut();
+        let mapping = self.mappings.and_then(|mappings| {
+            let source = self
+                .source_idx
+                .and_then(|idx| record.get(idx))
+                .map(str::trim)
+                .filter(|source| !source.is_empty())
+                .or(self.default_source)?;
+            mappings.get(source)
+        });
+        let changes = normalize_record(record, self.columns, mapping);
+        if !changes.is_empty() {
+            self.report.push(NormalizedRow { line, changes });
+        }
+        self.parser.parse_record(line).map(|row| (line, row))
+    }
+}
+
+impl<R> TransactionSource for NormalizingParser<'_, R>
+where
+    R: Read,
+{
+    fn error(&self) -> Option<&InvalidRow> {
+        self.parser.error()
+    }
+}
+
+/// Normalizes record in place, and returns applied changes
+pub fn normalize_record(
+    record: &mut StringRecord,
+    columns: NormalizedColumns,
+    mapping: Option<&SourceMapping>,
+) -> Vec<Normalization> {
+    let mut changes = Vec::new();
+    let mut fields: Vec<String> = record.iter().map(str::to_string).collect();
+    if fields.iter().any(|field| field.trim() != field) {
+        changes.push(Normalization::Trimmed);
+        for field in &mut fields {
+            *field = field.trim().to_string();
+        }
+    }
+    if let Some(kind) = columns.kind.and_then(|idx| fields.get_mut(idx)) {
+        if let Some(canonical) = mapping.and_then(|mapping| mapping.kinds.get(kind.as_str())) {
+            changes.push(Normalization::KindAlias);
+            *kind = canonical.name().to_string();
+        }
+        if kind.chars().any(|c| c.is_uppercase()) {
+            changes.push(Normalization::KindCase);
+            *kind = kind.to_lowercase();
+        }
+        if let Some((_, canonical)) = KIND_SYNONYMS.iter().find(|(synonym, _)| synonym == kind) {
+            changes.push(Normalization::KindSynonym);
+            *kind = canonical.to_string();
+        }
+    }
+    if mapping.is_some_and(|mapping| mapping.signed_amounts)
+        && let (Some(kind_idx), Some(amount_idx)) = (columns.kind, columns.amount)
+        && fields.get(kind_idx).map(String::as_str) == Some(TransactionKind::Deposit.name())
+        && let Some(amount) = fields[amount_idx].strip_prefix('-')
+    {
+        changes.push(Normalization::SignedAmount);
+        fields[amount_idx] = amount.to_string();
+        fields[kind_idx] = TransactionKind::Withdrawal.name().to_string();
+    }
+    if let Some(amount) = columns
+        .amount
+        .filter(|_| columns.guess_decimal_comma)
+        .and_then(|idx| fields.get_mut(idx))
+        && amount.contains(',')
+        && !amount.contains('.')
+    {
+        changes.push(Normalization::DecimalSeparator);
+        *amount = amount.replace(',', ".");
+    }
+    if !changes.is_empty() {
+        *record = StringRecord::from(fields);
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    #[test]
+    fn maps_partner_kinds_by_source() {
+        let mappings = KindMappings::from_json(
+            r#"{"bank": {"kinds": {"CR": "deposit", "DR": "withdrawal"}},
+                "wallet": {"kinds": {"TXN": "deposit"}, "signed_amounts": true}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let input = "type,client,tx,amount,source\n\
+            CR,1,1,5,\n\
+            DR,1,2,1,\n\
+            TXN,1,3,-2.5,wallet\n\
+            TXN,1,4,2.5,wallet\n\
+            deposit,1,5,-1,bank\n";
+        let mut report = Vec::new();
+        let rows: Vec<_> = NormalizingParser::new(
+            input.as_bytes(),
+            NumberFormat::Plain,
+            &ColumnMapping::default(),
+            &mut report,
+        )
+        .with_kind_mappings(&mappings, Some("bank"))
+        .map(|(_, row)| (row.kind, row.amount))
+        .collect();
+        assert_eq!(
+            rows,
+            [
+                (TransactionKind::Deposit, Some(Decimal::new(5, 0))),
+                (TransactionKind::Withdrawal, Some(Decimal::ONE)),
+                (TransactionKind::Withdrawal, Some(Decimal::new(25, 1))),
+                (TransactionKind::Deposit, Some(Decimal::new(25, 1))),
+                // bank amounts are not signed
+                (TransactionKind::Deposit, Some(Decimal::NEGATIVE_ONE)),
+            ]
+        );
+        assert_eq!(
+            report[2].changes,
+            [Normalization::KindAlias, Normalization::SignedAmount]
+        );
+
+        let err = KindMappings::from_json(r#"{"bank": {"kinds": {"CR": "credit"}}}"#.as_bytes())
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown type `credit`"), "{err}");
+    }
+
+    #[test]
+    fn normalizes_messy_rows() {
+        let input = "type, client, tx, amount\n\
+            deposit,1,1,1.5\n\
+            \x20Withdraw ,1,2,\"0,5\"\n\
+            Charge-Back,1,1,\n";
+        let mut report = Vec::new();
+        let rows: Vec<_> = NormalizingParser::new(
+            input.as_bytes(),
+            NumberFormat::Plain,
+            &ColumnMapping::default(),
+            &mut report,
+        )
+        .collect();
+
+        assert_eq!(rows[1].1.kind, TransactionKind::Withdrawal);
+        assert_eq!(rows[1].1.amount, Some(Decimal::new(5, 1)));
+        assert_eq!(rows[2].1.kind, TransactionKind::Chargeback);
+        assert_eq!(
+            report,
+            vec![
+                NormalizedRow {
+                    line: 3,
+                    changes: vec![
+                        Normalization::Trimmed,
+                        Normalization::KindCase,
+                        Normalization::KindSynonym,
+                        Normalization::DecimalSeparator
+                    ]
+                },
+                NormalizedRow {
+                    line: 4,
+                    changes: vec![Normalization::KindCase, Normalization::KindSynonym]
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Amounts exported from spreadsheets or banking software are often written
+//! with thousands separators, and with decimal comma in many locales.
+
+use std::{borrow::Cow, str::FromStr};
+
+use serde::Serialize;
+
+/// How amounts are written in the input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum NumberFormat {
+    /// `1234.56`, no thousands separators
+    #[default]
+    #[serde(rename = "plain")]
+    Plain,
+    /// `1,234.56`
+    #[serde(rename = "point")]
+    DecimalPoint,
+    /// `1.234,56`
+    #[serde(rename = "comma")]
+    DecimalComma,
+}
+
+impl FromStr for NumberFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "point" | "1,234.56" => Ok(Self::DecimalPoint),
+            "comma" | "1.234,56" => Ok(Self::DecimalComma),
+            other => Err(format!("unknown number format `{other}`")),
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Rewrites amount into `1234.56` form. Thousands separators must split
+    /// integer part into groups of three digits, so e.g. `1,5` is rejected
+    /// instead of being read as 15 with [`NumberFormat::DecimalPoint`].
+    pub fn to_plain<'a>(&self, amount: &'a str) -> Result<Cow<'a, str>, String> {
+        let (group, decimal) = match self {
+            NumberFormat::Plain => return Ok(Cow::Borrowed(amount)),
+            NumberFormat::DecimalPoint => (',', '.'),
+            NumberFormat::DecimalComma => ('.', ','),
+        };
+        let invalid = || format!("amount `{amount}` does not match number format");
+        if amount.is_empty() {
+            return Ok(Cow::Borrowed(amount));
+        }
+        let (integer, fraction) = match amount.split_once(decimal) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (amount, None),
+        };
+        let digits = integer.strip_prefix(['-', '+']).unwrap_or(integer);
+        let mut groups = digits.split(group);
+        let first = groups.next().unwrap_or_default();
+        let groups_valid = (1..=3).contains(&first.len()) && groups.all(|group| group.len() == 3)
+            || !digits.contains(group);
+        if !groups_valid || fraction.is_some_and(|fraction| fraction.contains([group, decimal])) {
+            return Err(invalid());
+        }
+        let mut plain = integer.replace(group, "");
+        if let Some(fraction) = fraction {
+            plain.push('.');
+            plain.push_str(fraction);
+        }
+        Ok(Cow::Owned(plain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_plain() {
+        let point = NumberFormat::DecimalPoint;
+        assert_eq!(point.to_plain("1,234.56").unwrap(), "1234.56");
+        assert_eq!(point.to_plain("-1,234,567").unwrap(), "-1234567");
+        assert_eq!(point.to_plain("12.5").unwrap(), "12.5");
+        assert!(point.to_plain("1,5").is_err());
+        assert!(point.to_plain("1.2.3").is_err());
+
+        let comma = NumberFormat::DecimalComma;
+        assert_eq!(comma.to_plain("1.234,56").unwrap(), "1234.56");
+        assert_eq!(comma.to_plain("0,5").unwrap(), "0.5");
+        assert!(comma.to_plain("1.5").is_err());
+        assert!(comma.to_plain("1.234.5,6").is_err());
+
+        assert_eq!(NumberFormat::Plain.to_plain("1,5").unwrap(), "1,5");
+    }
+}
@@ -0,0 +1,322 @@
+//! Pre-run planning: the input is profiled (optionally from a sample of its
+//! rows), and memory, disk and time needed by each backend are predicted,
+//! so users can choose between in-memory and disk-backed processors.
+
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
+    mem::size_of,
+    ops::RangeInclusive,
+    time::Duration,
+};
+
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::{
+    account::{Account, TransactionId},
+    command::{CreateTransactionCommand, TransactionKind},
+    processor::{ClientId, bloom::mix, tx_store::TxStore},
+};
+
+use super::{TransactionSource, csv_parser::CsvTransactionParser};
+
+/// Rough throughputs of the backends, in rows per second
+const IN_MEMORY_ROWS_PER_SEC: u64 = 2_000_000;
+const EVENT_STORE_ROWS_PER_SEC: u64 = 300_000;
+/// Every transaction is a separate commit, unless group commit is enabled
+const SQLITE_ROWS_PER_SEC: u64 = 20_000;
+
+/// Approximate bytes per stored event and transaction on disk
+const EVENT_STORE_EVENT_BYTES: u64 = 32;
+const SQLITE_EVENT_BYTES: u64 = 48;
+const SQLITE_TX_BYTES: u64 = 40;
+
+/// Precisions of [`HyperLogLog`], from 16 registers to 64 KiB of them
+pub const HLL_PRECISIONS: RangeInclusive<u32> = 4..=16;
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("HyperLogLog precision must be within {HLL_PRECISIONS:?}, got {0}")]
+pub struct InvalidPrecision(pub u32);
+
+/// Estimates number of distinct values in constant memory
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    precision: u32,
+}
+
+impl HyperLogLog {
+    /// `2^precision` registers, with standard error of about `1.04 / sqrt(2^precision)`,
+    /// precision must be within [`HLL_PRECISIONS`]
+    pub fn new(precision: u32) -> Result<Self, InvalidPrecision> {
+        if !HLL_PRECISIONS.contains(&precision) {
+            return Err(InvalidPrecision(precision));
+        }
+        Ok(Self {
+            registers: vec![0; 1 << precision],
+            precision,
+        })
+    }
+
+    pub fn insert(&mut self, value: u64) {
+        let hash = mix(value);
+        let register = (hash >> (64 - self.precision)) as usize;
+        let rank = ((hash << self.precision) | (1 << (self.precision - 1))).leading_zeros() + 1;
+        self.registers[register] = self.registers[register].max(rank as u8);
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-(*rank as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let empty = self.registers.iter().filter(|rank| **rank == 0).count();
+        if estimate <= 2.5 * m && empty > 0 {
+            // linear counting is more accurate for small cardinalities
+            return (m * (m / empty as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(12).expect("precision is within range")
+    }
+}
+
+/// Shape of the input, measured on the rows read
+#[derive(Debug, Clone, Default)]
+pub struct InputProfile {
+    pub rows: u64,
+    /// Deposits and other transactions creating records
+    pub created: u64,
+    pub withdrawals: u64,
+    pub modifies: u64,
+    /// All rows were read, so nothing is extrapolated
+    pub complete: bool,
+    bytes_read: u64,
+    total_bytes: Option<u64>,
+    clients: HyperLogLog,
+    txs: HyperLogLog,
+}
+
+impl InputProfile {
+    /// Profiles at most `max_rows` rows of CSV input of `total_bytes` size,
+    /// which is used to extrapolate from the rows read. Fails at the first
+    /// row that can't be parsed.
+    pub fn sample(
+        reader: impl Read,
+        total_bytes: Option<u64>,
+        max_rows: Option<u64>,
+    ) -> Result<Self> {
+        let mut profile = Self {
+            complete: true,
+            total_bytes,
+            ..Default::default()
+        };
+        let Some(max_rows) = max_rows else {
+            profile.profile(reader)?;
+            return Ok(profile);
+        };
+        // sampled lines are cut off exactly, as the parser reads ahead
+        let mut reader = BufReader::new(reader);
+        let mut sample = Vec::new();
+        for _ in 0..=max_rows {
+            if reader.read_until(b'\n', &mut sample)? == 0 {
+                break;
+            }
+        }
+        profile.complete = reader.fill_buf()?.is_empty();
+        profile.bytes_read = sample.len() as u64;
+        profile.profile(sample.as_slice())?;
+        Ok(profile)
+    }
+
+    fn profile(&mut self, reader: impl Read) -> Result<()> {
+        let mut parser = CsvTransactionParser::new(reader);
+        for (_, tx) in &mut parser {
+            self.rows += 1;
+            self.clients.insert(tx.client as u64);
+            match tx.kind {
+                kind if kind.is_modify() => self.modifies += 1,
+                TransactionKind::Withdrawal => {
+                    self.withdrawals += 1;
+                    self.created += 1;
+                    self.txs.insert(tx.tx as u64);
+                }
+                _ => {
+                    self.created += 1;
+                    self.txs.insert(tx.tx as u64);
+                }
+            }
+        }
+        match parser.error() {
+            Some(invalid) => Err(invalid.clone().into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Ratio of the whole input to the rows read
+    fn scale(&self) -> f64 {
+        match self.total_bytes {
+            Some(total) if !self.complete && self.bytes_read > 0 => {
+                (total as f64 / self.bytes_read as f64).max(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    fn extrapolate(&self, count: u64) -> u64 {
+        (count as f64 * self.scale()).round() as u64
+    }
+
+    pub fn estimated_rows(&self) -> u64 {
+        self.extrapolate(self.rows)
+    }
+
+    /// Distinct clients of the rows read, as clients of the rest are mostly the same
+    pub fn estimated_clients(&self) -> u64 {
+        self.clients.estimate().min(ClientId::MAX as u64 + 1)
+    }
+
+    /// Distinct ids of created transactions
+    pub fn estimated_txs(&self) -> u64 {
+        self.extrapolate(self.txs.estimate())
+            .min(TransactionId::MAX as u64 + 1)
+    }
+
+    /// Predicted requirements of every backend
+    pub fn plan(&self) -> Vec<BackendEstimate> {
+        let rows = self.estimated_rows();
+        let txs = self.estimated_txs();
+        let withdrawals = self.extrapolate(self.withdrawals).min(txs);
+        let accounts = map_bytes(self.estimated_clients(), size_of::<(ClientId, Account)>());
+        let time = |rows_per_sec| Duration::from_secs_f64(rows as f64 / rows_per_sec as f64);
+        vec![
+            BackendEstimate {
+                backend: "in-memory",
+                memory_bytes: accounts + TxStore::estimated_bytes(txs, 0),
+                disk_bytes: 0,
+                time: time(IN_MEMORY_ROWS_PER_SEC),
+            },
+            BackendEstimate {
+                backend: "in-memory compact",
+                memory_bytes: accounts + TxStore::estimated_bytes(txs, withdrawals),
+                disk_bytes: 0,
+                time: time(IN_MEMORY_ROWS_PER_SEC),
+            },
+            BackendEstimate {
+                backend: "event-store",
+                memory_bytes: accounts
+                    + map_bytes(txs, size_of::<(TransactionId, CreateTransactionCommand)>()),
+                disk_bytes: rows * EVENT_STORE_EVENT_BYTES,
+                time: time(EVENT_STORE_ROWS_PER_SEC),
+            },
+            BackendEstimate {
+                backend: "sqlite",
+                memory_bytes: accounts,
+                disk_bytes: rows * SQLITE_EVENT_BYTES + txs * SQLITE_TX_BYTES,
+                time: time(SQLITE_ROWS_PER_SEC),
+            },
+        ]
+    }
+}
+
+/// Hash map of `entries`, at 7/8 load factor with a control byte per bucket
+fn map_bytes(entries: u64, entry_size: usize) -> u64 {
+    (entries * 8 / 7).next_power_of_two() * (entry_size as u64 + 1)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendEstimate {
+    pub backend: &'static str,
+    pub memory_bytes: u64,
+    pub disk_bytes: u64,
+    pub time: Duration,
+}
+
+impl Display for InputProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let estimated = if self.complete { "" } else { " (estimated)" };
+        writeln!(f, "rows:             {}{estimated}", self.estimated_rows())?;
+        if !self.complete {
+            writeln!(f, "rows sampled:     {}", self.rows)?;
+        }
+        writeln!(f, "distinct clients: ~{}", self.estimated_clients())?;
+        writeln!(f, "distinct txs:     ~{}", self.estimated_txs())?;
+        writeln!(f, "modify rows:      {}", self.extrapolate(self.modifies))?;
+        for estimate in self.plan() {
+            writeln!(
+                f,
+                "  {}: memory {} bytes, disk {} bytes, time {:?}",
+                estimate.backend, estimate.memory_bytes, estimate.disk_bytes, estimate.time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bin_utils::csv_parser::InvalidRow;
+
+    #[test]
+    fn hyperloglog_estimates_within_few_percent() {
+        for distinct in [100u64, 10_000, 1_000_000] {
+            let mut hll = HyperLogLog::default();
+            for value in 0..distinct {
+                // duplicates don't count
+                hll.insert(value);
+                hll.insert(value);
+            }
+            let error = (hll.estimate() as f64 - distinct as f64).abs() / distinct as f64;
+            assert!(error < 0.05, "{distinct}: {}", hll.estimate());
+        }
+    }
+
+    #[test]
+    fn hyperloglog_precision_is_bounded() {
+        assert_eq!(HyperLogLog::new(0).unwrap_err(), InvalidPrecision(0));
+        assert_eq!(HyperLogLog::new(17).unwrap_err(), InvalidPrecision(17));
+        for precision in HLL_PRECISIONS {
+            let mut hll = HyperLogLog::new(precision).unwrap();
+            hll.insert(1);
+            assert_eq!(hll.estimate(), 1);
+        }
+    }
+
+    #[test]
+    fn malformed_row_fails_profile() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,x,2,1.0\n";
+        let err = InputProfile::sample(input.as_bytes(), None, None).unwrap_err();
+        assert_eq!(err.downcast_ref::<InvalidRow>().unwrap().line, 3);
+    }
+
+    #[test]
+    fn sample_is_extrapolated() {
+        let mut input = "type,client,tx,amount\n".to_string();
+        for tx in 0..1000 {
+            input.push_str(&format!("deposit,{},{tx},1.0\n", tx % 10));
+        }
+        let total_bytes = input.len() as u64;
+        let full = InputProfile::sample(input.as_bytes(), Some(total_bytes), None).unwrap();
+        assert!(full.complete);
+        assert_eq!(full.estimated_rows(), 1000);
+        assert_eq!(full.estimated_clients(), 10);
+
+        let sampled = InputProfile::sample(input.as_bytes(), Some(total_bytes), Some(100)).unwrap();
+        assert!(!sampled.complete);
+        assert_eq!(sampled.rows, 100);
+        let rows = sampled.estimated_rows();
+        assert!((900..=1100).contains(&rows), "{rows}");
+        assert!(sampled.estimated_txs() > 800);
+        assert_eq!(sampled.plan()[0].backend, "in-memory");
+    }
+}
@@ -0,0 +1,129 @@
+//! Periodic progress of the run as single-line JSON events, so
+//! orchestrators (Airflow, Argo) can surface it without parsing logs.
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use super::run_report::RunReport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressEvent {
+    Progress,
+    /// Last event, all rows were processed
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub event: ProgressEvent,
+    pub rows_read: u64,
+    pub rows_accepted: u64,
+    pub rows_rejected: u64,
+    pub rows_skipped: u64,
+    pub elapsed_ms: u128,
+    pub rows_per_sec: f64,
+}
+
+/// Passes [`Progress`] to the sink at most once per interval, and once at the end
+pub struct ProgressReporter {
+    interval: Duration,
+    sink: Box<dyn FnMut(&Progress)>,
+    started: Instant,
+    last: Instant,
+}
+
+impl ProgressReporter {
+    pub fn new(interval: Duration, sink: Box<dyn FnMut(&Progress)>) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            sink,
+            started: now,
+            last: now,
+        }
+    }
+
+    /// Writes every event as a JSON line
+    pub fn json_lines(interval: Duration, mut output: impl Write + 'static) -> Self {
+        Self::new(
+            interval,
+            Box::new(move |progress| {
+                // progress is best effort, it must not fail the run
+                if serde_json::to_writer(&mut output, progress).is_ok() {
+                    let _ = writeln!(output);
+                    let _ = output.flush();
+                }
+            }),
+        )
+    }
+
+    pub(super) fn tick(&mut self, report: &RunReport) {
+        let now = Instant::now();
+        if now - self.last >= self.interval {
+            self.last = now;
+            self.emit(ProgressEvent::Progress, report);
+        }
+    }
+
+    pub(super) fn finish(&mut self, report: &RunReport) {
+        self.emit(ProgressEvent::Done, report);
+    }
+
+    fn emit(&mut self, event: ProgressEvent, report: &RunReport) {
+        let elapsed = self.started.elapsed();
+        let seconds = elapsed.as_secs_f64();
+        let progress = Progress {
+            event,
+            rows_read: report.rows_read,
+            rows_accepted: report.rows_accepted,
+            rows_rejected: report.total_rejected(),
+            rows_skipped: report.rows_skipped,
+            elapsed_ms: elapsed.as_millis(),
+            rows_per_sec: if seconds > 0.0 {
+                report.rows_read as f64 / seconds
+            } else {
+                0.0
+            },
+        };
+        (self.sink)(&progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn emits_progress_and_done() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let mut reporter = ProgressReporter::new(
+            Duration::ZERO,
+            Box::new(move |progress| sink.borrow_mut().push(progress.clone())),
+        );
+        let mut report = RunReport {
+            rows_read: 2,
+            rows_accepted: 1,
+            ..Default::default()
+        };
+        report.rows_rejected.insert("insufficient_funds", 1);
+        reporter.tick(&report);
+        reporter.finish(&report);
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, ProgressEvent::Progress);
+        assert_eq!(events[1].event, ProgressEvent::Done);
+        assert_eq!(events[1].rows_rejected, 1);
+        let json = serde_json::to_value(&events[1]).unwrap();
+        assert_eq!(json["event"], "done");
+        assert_eq!(json["rows_read"], 2);
+    }
+}
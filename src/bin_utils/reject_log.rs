@@ -0,0 +1,97 @@
+//! Every rejected row as a JSON line, with stable error code,
+//! so rejects can be loaded into a warehouse and aggregated.
+
+use std::io::{self, Write};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{account::TransactionId, processor::ClientId};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reject<'a> {
+    pub line: u64,
+    pub tx: TransactionId,
+    pub client: ClientId,
+    pub kind: &'a str,
+    pub amount: Option<Decimal>,
+    pub error_code: &'static str,
+    pub error_message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<&'a str>,
+}
+
+/// Writes [`Reject`] records as JSON lines, the first write error is kept
+/// and returned when the run completes
+pub struct RejectLog {
+    output: Box<dyn Write>,
+    error: Option<io::Error>,
+}
+
+impl RejectLog {
+    pub fn json_lines(output: impl Write + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            error: None,
+        }
+    }
+
+    pub(super) fn record(&mut self, reject: &Reject) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut self.output, reject)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.output));
+        self.error = result.err();
+    }
+
+    pub(super) fn finish(mut self) -> io::Result<()> {
+        match self.error {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let output = Shared::default();
+        let mut log = RejectLog::json_lines(output.clone());
+        log.record(&Reject {
+            line: 3,
+            tx: 7,
+            client: 1,
+            kind: "withdrawal",
+            amount: Some(Decimal::TEN),
+            error_code: "insufficient_funds",
+            error_message: "Insufficient funds",
+            trace_id: Some("pay-42"),
+        });
+        log.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.take()).unwrap(),
+            "{\"line\":3,\"tx\":7,\"client\":1,\"kind\":\"withdrawal\",\"amount\":\"10\",\
+             \"error_code\":\"insufficient_funds\",\"error_message\":\"Insufficient funds\",\"trace_id\":\"pay-42\"}\n"
+        );
+    }
+}
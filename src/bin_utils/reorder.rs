@@ -0,0 +1,125 @@
+//! Near-real-time feeds may deliver a dispute slightly before the deposit
+//! it refers to. Reordering buffer holds a window of rows and releases them
+//! ordered by their `timestamp` column, modify rows going after create rows
+//! with the same timestamp.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use super::csv_parser::Transaction;
+
+struct Buffered {
+    /// (timestamp, is modify, arrival order)
+    key: (u64, bool, u64),
+    line: u64,
+    row: Transaction,
+}
+
+impl PartialEq for Buffered {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Buffered {}
+
+impl PartialOrd for Buffered {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Buffered {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Bounded reordering buffer over `(line, row)` iterator.
+/// Rows without timestamp inherit timestamp of the previous row,
+/// so buffer of size 0 keeps the original order.
+pub struct Reorder<I> {
+    iter: I,
+    capacity: usize,
+    buffer: BinaryHeap<Reverse<Buffered>>,
+    arrived: u64,
+    last_timestamp: u64,
+}
+
+impl<I> Reorder<I>
+where
+    I: Iterator<Item = (u64, Transaction)>,
+{
+    pub fn new(iter: I, capacity: usize) -> Self {
+        Self {
+            iter,
+            capacity,
+            buffer: BinaryHeap::with_capacity(capacity + 1),
+            arrived: 0,
+            last_timestamp: 0,
+        }
+    }
+}
+
+impl<I> Iterator for Reorder<I>
+where
+    I: Iterator<Item = (u64, Transaction)>,
+{
+    type Item = (u64, Transaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.len() <= self.capacity {
+            let Some((line, row)) = self.iter.next() else {
+                break;
+            };
+            if let Some(timestamp) = row.timestamp {
+                self.last_timestamp = timestamp;
+            }
+            self.arrived += 1;
+            self.buffer.push(Reverse(Buffered {
+                key: (self.last_timestamp, row.kind.is_modify(), self.arrived),
+                line,
+                row,
+            }));
+        }
+        self.buffer
+            .pop()
+            .map(|Reverse(buffered)| (buffered.line, buffered.row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bin_utils::csv_parser::CsvTransactionParser;
+
+    use super::*;
+
+    fn reordered(input: &str, capacity: usize) -> Vec<(u64, u32)> {
+        Reorder::new(CsvTransactionParser::new(input.as_bytes()), capacity)
+            .map(|(line, row)| (line, row.tx))
+            .collect()
+    }
+
+    #[test]
+    fn modify_waits_for_parent() {
+        let input = "type,client,tx,amount,timestamp\n\
+                     deposit,1,1,1,10\n\
+                     dispute,1,2,,12\n\
+                     deposit,1,3,1,13\n\
+                     deposit,1,2,1,12\n\
+                     deposit,1,4,1,14\n";
+        assert_eq!(
+            reordered(input, 2),
+            vec![(2, 1), (5, 2), (3, 2), (4, 3), (6, 4)]
+        );
+        // without buffer original order is kept
+        assert_eq!(
+            reordered(input, 0),
+            vec![(2, 1), (3, 2), (4, 3), (5, 2), (6, 4)]
+        );
+        // window is too small to reach the parent
+        assert_eq!(
+            reordered(input, 1),
+            vec![(2, 1), (3, 2), (5, 2), (4, 3), (6, 4)]
+        );
+    }
+}
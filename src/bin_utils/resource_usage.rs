@@ -0,0 +1,146 @@
+//! Resources used by the run, to size containers for different input volumes.
+//! Allocation stats are only available when [`TrackingAllocator`] is installed
+//! as the global allocator, which the binary does with `alloc-stats` feature.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static CURRENT_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// System allocator counting allocations and bytes in use
+pub struct TrackingAllocator;
+
+impl TrackingAllocator {
+    fn allocated(size: usize) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+        let current = CURRENT_BYTES.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+        PEAK_BYTES.fetch_max(current, Ordering::Relaxed);
+    }
+
+    fn deallocated(size: usize) {
+        CURRENT_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: forwarded to the system allocator with the same layout
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            Self::allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by the system allocator with this layout
+        unsafe { System.dealloc(ptr, layout) };
+        Self::deallocated(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // SAFETY: `ptr` was allocated by the system allocator with this layout
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            Self::deallocated(layout.size());
+            Self::allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    pub peak_bytes: u64,
+}
+
+impl AllocationStats {
+    /// Stats since the start of the process, `None` when
+    /// [`TrackingAllocator`] is not the global allocator
+    pub fn current() -> Option<Self> {
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        (allocations > 0).then(|| Self {
+            allocations,
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+            peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Peak resident set size, only known on Linux
+    pub peak_rss_bytes: Option<u64>,
+    pub allocations: Option<AllocationStats>,
+    /// Entries of processor's maps, see [`crate::processor::TransactionProcessor::map_sizes`]
+    pub map_sizes: Vec<(&'static str, usize)>,
+}
+
+impl ResourceUsage {
+    pub fn collect(map_sizes: Vec<(&'static str, usize)>) -> Self {
+        Self {
+            peak_rss_bytes: std::fs::read_to_string("/proc/self/status")
+                .ok()
+                .and_then(|status| peak_rss(&status)),
+            allocations: AllocationStats::current(),
+            map_sizes,
+        }
+    }
+}
+
+/// `VmHWM` line of `/proc/self/status`, which is in kB
+fn peak_rss(status: &str) -> Option<u64> {
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?;
+    let kb: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+impl Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(peak_rss) = self.peak_rss_bytes {
+            writeln!(f, "peak rss:         {peak_rss} bytes")?;
+        }
+        if let Some(allocations) = &self.allocations {
+            writeln!(f, "allocations:      {}", allocations.allocations)?;
+            writeln!(f, "allocated:        {} bytes", allocations.allocated_bytes)?;
+            writeln!(f, "peak heap:        {} bytes", allocations.peak_bytes)?;
+        }
+        for (map, size) in &self.map_sizes {
+            writeln!(f, "  {map}: {size}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_peak_rss_and_prints_usage() {
+        let status = "Name:\tcute-ledger\nVmPeak:\t  20000 kB\nVmHWM:\t    1500 kB\n";
+        assert_eq!(peak_rss(status), Some(1500 * 1024));
+        assert_eq!(peak_rss("Name:\tcute-ledger\n"), None);
+
+        let usage = ResourceUsage {
+            peak_rss_bytes: Some(1024),
+            allocations: None,
+            map_sizes: vec![("accounts", 2)],
+        };
+        assert_eq!(
+            usage.to_string(),
+            "peak rss:         1024 bytes\n  accounts: 2\n"
+        );
+    }
+}
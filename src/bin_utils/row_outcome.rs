@@ -0,0 +1,30 @@
+//! Outcome of every processed row, pushed to a channel while the run
+//! proceeds, see [`super::Service::run_streaming`].
+
+use crate::{account::TransactionId, history::Balance, processor::ClientId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowStatus {
+    Accepted,
+    Rejected {
+        code: &'static str,
+        message: String,
+    },
+    /// Row of unknown type, skipped by [`super::UnknownKindPolicy::Skip`]
+    Skipped,
+    /// Row not applied yet: parked in suspense, held over balance cap,
+    /// or queued for review
+    Deferred,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowOutcome {
+    /// Input line, 0 for synthetic rows
+    pub line: u64,
+    pub tx: TransactionId,
+    pub client: ClientId,
+    pub status: RowStatus,
+    /// Balances of the client account after the row, `None` if the
+    /// account doesn't exist
+    pub balance: Option<Balance>,
+}
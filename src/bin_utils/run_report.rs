@@ -0,0 +1,395 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use super::{
+    circuit_breaker::BreakerTrip, held_accrual::DailyHeld, normalize::NormalizedRow,
+    resource_usage::ResourceUsage, watermark::LateRow,
+};
+use crate::{
+    account::{DisputeCase, DisputeStage, TransactionId},
+    processor::{
+        ClientId, RejectKind, kyc::ComplianceReport, suspense::SuspenseReport,
+        watchlist::ReviewReport,
+    },
+    projection::FraudFlag,
+    stats::PipelineStats,
+};
+
+/// Client, whose row panicked the processor, see [`super::ServiceBuilder::isolate_clients`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedClient {
+    pub client: ClientId,
+    pub line: u64,
+    pub reason: String,
+}
+
+/// Dispute open at the end of the run, see [`super::ServiceBuilder::open_disputes`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenDispute {
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub stage: Option<DisputeStage>,
+    /// Empty case, if it was never updated
+    pub case: DisputeCase,
+}
+
+/// Source, whose rows stopped being processed by [`super::circuit_breaker`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedSource {
+    pub source: String,
+    pub line: u64,
+    pub reason: String,
+}
+
+/// Section of rows rolled back, see [`super::ServiceBuilder::tentative_sources`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RolledBackBatch {
+    pub source: String,
+    pub first_line: u64,
+    pub last_line: u64,
+    pub rows: u64,
+    /// Rows rejected before the rollback
+    pub rejected: u64,
+}
+
+/// Risk config reloaded during the run, see [`super::ServiceBuilder::risk_config`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigReloaded {
+    /// Rows read before the reload
+    pub after_rows: u64,
+    /// Why the config was not applied, previous one is kept then
+    pub error: Option<String>,
+}
+
+/// Outcome of [`super::Service::run`], so callers can assert on results
+/// and emit metrics without parsing error output.
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    pub rows_read: u64,
+    pub rows_accepted: u64,
+    /// Rejected rows count by error code
+    pub rows_rejected: BTreeMap<&'static str, u64>,
+    /// Rejected rows count by [`RejectKind`]
+    pub rows_rejected_by_kind: BTreeMap<RejectKind, u64>,
+    /// Rows of unknown type, skipped by [`super::UnknownKindPolicy::Skip`],
+    /// zero-amount ones skipped by [`crate::command::ZeroAmountPolicy::Skip`],
+    /// and late ones diverted by [`super::watermark::LatePolicy::Divert`]
+    pub rows_skipped: u64,
+    /// Rows not applied when processed: parked in suspense, held over
+    /// balance cap, or queued for review
+    pub rows_deferred: u64,
+    pub accounts_touched: usize,
+    pub duration: Duration,
+    pub stats: PipelineStats,
+    pub suspense: Option<SuspenseReport>,
+    /// Rejected transactions of unverified clients
+    pub compliance: Option<ComplianceReport>,
+    /// Transactions of watched clients, queued for review
+    pub reviews: Option<ReviewReport>,
+    /// Clients flagged by fraud heuristics
+    pub flags: Vec<FraudFlag>,
+    /// Rows changed by normalization pass
+    pub normalized: Vec<NormalizedRow>,
+    /// Rows behind the event-time watermark, see [`super::ServiceBuilder::late_events`]
+    pub rows_late: u64,
+    /// Watermark at the end of the run, if tracked
+    pub watermark: Option<u64>,
+    /// Late rows diverted by [`super::watermark::LatePolicy::Divert`]
+    pub late: Vec<LateRow>,
+    /// Average held balances by client and day, see [`super::ServiceBuilder::held_accrual`]
+    pub held_accrual: Vec<DailyHeld>,
+    /// Open disputes with their cases, ordered by client and transaction
+    pub open_disputes: Vec<OpenDispute>,
+    /// Tentative sections with too many rejected rows
+    pub rolled_back: Vec<RolledBackBatch>,
+    /// Clients, whose rows stopped being processed
+    pub quarantined: Vec<QuarantinedClient>,
+    pub quarantined_sources: Vec<QuarantinedSource>,
+    /// Reloads of the risk config after the first row, including failed ones
+    pub config_reloads: Vec<ConfigReloaded>,
+    /// Processing was stopped by [`super::circuit_breaker`], remaining rows were not read
+    pub halted: Option<BreakerTrip>,
+    /// Collected when requested by [`super::ServiceBuilder::resource_usage`]
+    pub resources: Option<ResourceUsage>,
+}
+
+impl RunReport {
+    pub fn total_rejected(&self) -> u64 {
+        self.rows_rejected.values().sum()
+    }
+
+    pub fn rejected_of_kind(&self, kind: RejectKind) -> u64 {
+        self.rows_rejected_by_kind
+            .get(&kind)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Suspended rows, whose transaction never arrived
+    pub fn unmatched(&self) -> u64 {
+        self.suspense
+            .as_ref()
+            .map_or(0, |suspense| suspense.unmatched.len() as u64)
+    }
+
+    /// Outcome of the run for the process exit code, with `strict` outcomes
+    /// escalated to [`ExitStatus::Fatal`]
+    pub fn exit_status(&self, strict: &[StrictCategory]) -> ExitStatus {
+        let escalated = strict.iter().any(|category| match category {
+            StrictCategory::Rejects => self.rejected_of_kind(RejectKind::Business) > 0,
+            StrictCategory::Invalid => self.rejected_of_kind(RejectKind::InvalidInput) > 0,
+            StrictCategory::Skipped => self.rows_skipped > 0,
+        });
+        if escalated || self.halted.is_some() || self.rejected_of_kind(RejectKind::Technical) > 0 {
+            ExitStatus::Fatal
+        } else if self.rejected_of_kind(RejectKind::InvalidInput) > 0 {
+            ExitStatus::InvalidInput
+        } else if self.rejected_of_kind(RejectKind::Business) > 0 || self.unmatched() > 0 {
+            ExitStatus::Rejected
+        } else {
+            ExitStatus::Clean
+        }
+    }
+}
+
+/// Exit code contract of the CLI, so pipelines can branch on outcomes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Every row was applied (skipped rows are warnings)
+    Clean = 0,
+    /// Run completed, some rows were refused by business rules, or stayed
+    /// in suspense unmatched
+    Rejected = 2,
+    /// Some rows were malformed, or the input couldn't be parsed at all
+    InvalidInput = 3,
+    /// Run failed or was halted, or some rows failed for technical reasons, so results
+    /// can't be trusted; also outcomes escalated by [`StrictCategory`]
+    Fatal = 4,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Outcomes treated as failures in strict mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrictCategory {
+    /// Rows rejected by business rules
+    Rejects,
+    /// Malformed rows
+    Invalid,
+    /// Rows skipped with a warning
+    Skipped,
+}
+
+impl FromStr for StrictCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rejects" => Ok(Self::Rejects),
+            "invalid" => Ok(Self::Invalid),
+            "skipped" => Ok(Self::Skipped),
+            other => Err(format!("unknown strict category `{other}`")),
+        }
+    }
+}
+
+/// Accumulates [`RunReport`] while rows are processed
+#[derive(Default)]
+pub(super) struct RunCounters {
+    pub report: RunReport,
+    pub clients: HashSet<ClientId>,
+}
+
+impl RunCounters {
+    pub fn row_read(&mut self, client: ClientId) {
+        self.report.rows_read += 1;
+        self.clients.insert(client);
+    }
+
+    pub fn row_accepted(&mut self) {
+        self.report.rows_accepted += 1;
+    }
+
+    pub fn row_skipped(&mut self) {
+        self.report.rows_skipped += 1;
+    }
+
+    pub fn row_deferred(&mut self) {
+        self.report.rows_deferred += 1;
+    }
+
+    pub fn row_rejected(&mut self, code: &'static str, kind: RejectKind) {
+        *self.report.rows_rejected.entry(code).or_default() += 1;
+        *self.report.rows_rejected_by_kind.entry(kind).or_default() += 1;
+    }
+
+    pub fn finish(
+        mut self,
+        duration: Duration,
+        stats: PipelineStats,
+        suspense: Option<SuspenseReport>,
+        flags: Vec<FraudFlag>,
+    ) -> RunReport {
+        self.report.accounts_touched = self.clients.len();
+        self.report.duration = duration;
+        self.report.stats = stats;
+        self.report.suspense = suspense;
+        self.report.flags = flags;
+        self.report
+    }
+}
+
+impl Display for RunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "rows read:        {}", self.rows_read)?;
+        writeln!(f, "rows accepted:    {}", self.rows_accepted)?;
+        writeln!(f, "rows rejected:    {}", self.total_rejected())?;
+        for (code, count) in &self.rows_rejected {
+            writeln!(f, "  {code}: {count}")?;
+        }
+        if self.rows_skipped > 0 {
+            writeln!(f, "rows skipped:     {}", self.rows_skipped)?;
+        }
+        if self.rows_deferred > 0 {
+            writeln!(f, "rows deferred:    {}", self.rows_deferred)?;
+        }
+        writeln!(f, "accounts touched: {}", self.accounts_touched)?;
+        writeln!(f, "duration:         {:?}", self.duration)?;
+        if let Some(suspense) = &self.suspense {
+            write!(f, "{suspense}")?;
+        }
+        if let Some(compliance) = &self.compliance {
+            write!(f, "{compliance}")?;
+        }
+        if let Some(reviews) = &self.reviews {
+            write!(f, "{reviews}")?;
+        }
+        if let Some(watermark) = self.watermark {
+            writeln!(f, "watermark:        {watermark}")?;
+            writeln!(f, "rows late:        {}", self.rows_late)?;
+        }
+        if !self.late.is_empty() {
+            writeln!(f, "rows diverted:    {}", self.late.len())?;
+        }
+        for batch in &self.rolled_back {
+            writeln!(
+                f,
+                "rolled back:      `{}` lines {}-{}, {} of {} rows rejected",
+                batch.source, batch.first_line, batch.last_line, batch.rejected, batch.rows
+            )?;
+        }
+        if !self.quarantined.is_empty() {
+            writeln!(f, "quarantined:      {}", self.quarantined.len())?;
+            for client in &self.quarantined {
+                writeln!(
+                    f,
+                    "  client {} at line {}: {}",
+                    client.client, client.line, client.reason
+                )?;
+            }
+        }
+        for source in &self.quarantined_sources {
+            writeln!(
+                f,
+                "quarantined:      source `{}` at line {}: {}",
+                source.source, source.line, source.reason
+            )?;
+        }
+        for reload in &self.config_reloads {
+            match &reload.error {
+                Some(err) => writeln!(
+                    f,
+                    "config reload:    after {} rows failed: {err}",
+                    reload.after_rows
+                )?,
+                None => writeln!(f, "config reload:    after {} rows", reload.after_rows)?,
+            }
+        }
+        if let Some(trip) = &self.halted {
+            writeln!(
+                f,
+                "halted:           at line {}, {:.0}% of rows rejected",
+                trip.line,
+                trip.reject_rate * 100.0
+            )?;
+        }
+        if !self.normalized.is_empty() {
+            writeln!(f, "rows normalized:  {}", self.normalized.len())?;
+            for row in &self.normalized {
+                let changes: Vec<_> = row.changes.iter().map(ToString::to_string).collect();
+                writeln!(f, "  line {}: {}", row.line, changes.join(", "))?;
+            }
+        }
+        if !self.flags.is_empty() {
+            writeln!(f, "flagged clients:  {}", self.flags.len())?;
+            for flag in &self.flags {
+                writeln!(f, "  client {}: {}", flag.client, flag.reason)?;
+            }
+        }
+        if let Some(resources) = &self.resources {
+            write!(f, "{resources}")?;
+        }
+        write!(f, "{}", self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::TransactionKind, processor::suspense::SuspendedRow};
+
+    #[test]
+    fn exit_status_by_worst_outcome() {
+        let mut counters = RunCounters::default();
+        counters.row_skipped();
+        assert_eq!(counters.report.exit_status(&[]), ExitStatus::Clean);
+        assert_eq!(
+            counters.report.exit_status(&[StrictCategory::Skipped]),
+            ExitStatus::Fatal
+        );
+
+        counters.row_rejected("insufficient_funds", RejectKind::Business);
+        assert_eq!(counters.report.exit_status(&[]), ExitStatus::Rejected);
+        counters.row_rejected("negative_amount", RejectKind::InvalidInput);
+        assert_eq!(counters.report.exit_status(&[]), ExitStatus::InvalidInput);
+        assert_eq!(
+            counters.report.exit_status(&[StrictCategory::Rejects]),
+            ExitStatus::Fatal
+        );
+        counters.row_rejected("storage_error", RejectKind::Technical);
+        assert_eq!(counters.report.exit_status(&[]), ExitStatus::Fatal);
+    }
+
+    #[test]
+    fn unmatched_rows_are_not_clean() {
+        let mut counters = RunCounters::default();
+        counters.row_deferred();
+        let mut suspense = SuspenseReport {
+            parked: 1,
+            ..Default::default()
+        };
+        counters.report.suspense = Some(suspense.clone());
+        assert_eq!(counters.report.exit_status(&[]), ExitStatus::Clean);
+
+        suspense.unmatched.push(SuspendedRow {
+            tx_id: 1,
+            client_id: 1,
+            kind: TransactionKind::Dispute,
+            source: None,
+            trace_id: None,
+        });
+        counters.report.suspense = Some(suspense);
+        assert_eq!(counters.report.exit_status(&[]), ExitStatus::Rejected);
+    }
+}
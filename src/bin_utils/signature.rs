@@ -0,0 +1,130 @@
+//! Rows coming from partner institutions are signed with the client's
+//! ed25519 key, so they cannot be altered or injected on the way.
+//! Signature is calculated over the canonical form of the row, see [`canonical_row`].
+
+use std::{collections::HashMap, io::Read};
+
+use csv::Trim;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::processor::{ClientId, TransactionProcessError};
+
+use super::csv_parser::Transaction;
+
+#[derive(Debug, Error)]
+pub enum ClientKeysError {
+    #[error("Invalid public key of client {client}")]
+    InvalidKey { client: ClientId },
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+}
+
+#[derive(Deserialize)]
+struct ClientKeyRow {
+    client: ClientId,
+    public_key: String,
+}
+
+/// Public keys of clients, whose rows must be signed.
+/// Rows of other clients are accepted without signature.
+#[derive(Debug, Default)]
+pub struct SignatureVerifier {
+    keys: HashMap<ClientId, VerifyingKey>,
+}
+
+impl SignatureVerifier {
+    /// Parses CSV with `client,public_key` columns, key is hex encoded
+    pub fn parse_keys(source: impl Read) -> Result<Self, ClientKeysError> {
+        let mut verifier = Self::default();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(source);
+        for row in reader.deserialize() {
+            let ClientKeyRow { client, public_key } = row?;
+            let key = decode_hex::<32>(&public_key)
+                .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+                .ok_or(ClientKeysError::InvalidKey { client })?;
+            verifier.add_key(client, key);
+        }
+        Ok(verifier)
+    }
+
+    pub fn add_key(&mut self, client: ClientId, key: VerifyingKey) {
+        self.keys.insert(client, key);
+    }
+
+    pub fn verify(&self, row: &Transaction) -> Result<(), TransactionProcessError> {
+        let Some(key) = self.keys.get(&row.client) else {
+            return Ok(());
+        };
+        let signature = row
+            .signature
+            .as_deref()
+            .ok_or(TransactionProcessError::SignatureErr("row is not signed"))?;
+        let signature = decode_hex::<64>(signature)
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .ok_or(TransactionProcessError::SignatureErr("malformed signature"))?;
+        key.verify(canonical_row(row).as_bytes(), &signature)
+            .map_err(|_| TransactionProcessError::SignatureErr("signature does not match"))
+    }
+}
+
+/// `type,client,tx,amount` with normalized amount (no trailing zeros),
+/// or empty amount when it is missing
+pub fn canonical_row(row: &Transaction) -> String {
+    let amount = row
+        .amount
+        .map(|amount| amount.normalize().to_string())
+        .unwrap_or_default();
+    format!("{},{},{},{amount}", row.kind.name(), row.client, row.tx)
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    let mut bytes = [0; N];
+    hex::decode_to_slice(value, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use crate::bin_utils::csv_parser::CsvTransactionParser;
+
+    use super::*;
+
+    #[test]
+    fn verifies_signed_rows() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let keys = format!(
+            "client,public_key\n1,{}\n",
+            hex::encode(signing_key.verifying_key().as_bytes())
+        );
+        let verifier = SignatureVerifier::parse_keys(keys.as_bytes()).unwrap();
+        let signature = hex::encode(signing_key.sign(b"deposit,1,1,1.5").to_bytes());
+
+        let input = format!(
+            "type,client,tx,amount,signature\n\
+            deposit,1,1,1.50,{signature}\n\
+            deposit,1,1,2.0,{signature}\n\
+            deposit,1,2,1.0,\n\
+            deposit,2,3,1.0,\n"
+        );
+        let results: Vec<_> = CsvTransactionParser::new(input.as_bytes())
+            .map(|(_, row)| verifier.verify(&row).map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(()),
+                Err("Invalid signature: signature does not match".to_string()),
+                Err("Invalid signature: row is not signed".to_string()),
+                // client without key
+                Ok(()),
+            ]
+        );
+        assert!(SignatureVerifier::parse_keys("client,public_key\n1,abc\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Capacity tests: processors are driven with generated activity of a
+//! configured number of days, and sustained throughput and latency
+//! percentiles of every backend are reported, before committing to one.
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    account::TransactionId,
+    command::TransactionKind,
+    processor::{ClientId, TransactionProcessor, bloom::mix},
+    stats::StageTimings,
+};
+
+use super::{csv_parser::Transaction, held_accrual::SECONDS_PER_DAY};
+
+/// Share of deposits among rows that neither open nor close a dispute
+const DEPOSIT_SHARE: f64 = 0.7;
+/// Share of disputes closed by chargeback rather than resolve
+const CHARGEBACK_SHARE: f64 = 0.1;
+/// Throughput is measured over this many equal slices of the run, the
+/// slowest one is the sustained throughput
+const SLICES: u64 = 10;
+
+/// Backend to simulate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulatedBackend {
+    InMemory,
+    /// File event store in a temporary directory
+    EventStore,
+    /// SQLite database in a temporary directory
+    Sqlite,
+}
+
+impl SimulatedBackend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::InMemory => "in-memory",
+            Self::EventStore => "event-store",
+            Self::Sqlite => "sqlite",
+        }
+    }
+}
+
+impl FromStr for SimulatedBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "in-memory" => Ok(Self::InMemory),
+            "event-store" => Ok(Self::EventStore),
+            "sqlite" => Ok(Self::Sqlite),
+            other => Err(format!("unknown backend `{other}`")),
+        }
+    }
+}
+
+/// Generated activity: clients picked uniformly at random deposit,
+/// withdraw, and dispute their latest deposit, disputes are resolved or
+/// charged back with the next row of the client
+#[derive(Debug, Clone)]
+pub struct WorkloadProfile {
+    pub clients: ClientId,
+    /// Simulated transactions per second of activity
+    pub tx_per_sec: u64,
+    /// Chance of a row disputing the latest deposit of the client
+    pub dispute_probability: f64,
+    /// Simulated days of activity
+    pub days: u64,
+    pub seed: u64,
+}
+
+impl WorkloadProfile {
+    pub fn transactions(&self) -> u64 {
+        self.tx_per_sec * self.days * SECONDS_PER_DAY
+    }
+
+    /// Rows of the simulated activity, timestamped in seconds since its start
+    pub fn rows(&self) -> Workload {
+        Workload {
+            profile: self.clone(),
+            generated: 0,
+            clients: vec![ClientState::default(); usize::from(self.clients)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ClientState {
+    latest_deposit: Option<TransactionId>,
+    open_dispute: Option<TransactionId>,
+}
+
+/// Iterator over rows of [`WorkloadProfile`]
+#[derive(Debug)]
+pub struct Workload {
+    profile: WorkloadProfile,
+    generated: u64,
+    clients: Vec<ClientState>,
+}
+
+impl Workload {
+    /// Uniformly distributed in `0.0..1.0`, the same for the same seed and row
+    fn random(&self, salt: u64) -> f64 {
+        let bits = mix(self.profile.seed ^ mix(self.generated.wrapping_mul(4) + salt));
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Iterator for Workload {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.generated >= self.profile.transactions() || self.clients.is_empty() {
+            return None;
+        }
+        let idx = (self.random(0) * self.clients.len() as f64) as usize;
+        let client = idx as ClientId + 1;
+        let tx = TransactionId::try_from(self.generated + 1).ok()?;
+        let amount = Decimal::new(100 + (self.random(1) * 10_000.0) as i64, 2);
+        let roll = self.random(2);
+        let deposit = self.random(3) < DEPOSIT_SHARE;
+        let state = &mut self.clients[idx];
+        let mut row = if let Some(disputed) = state.open_dispute.take() {
+            let kind = if roll < CHARGEBACK_SHARE {
+                TransactionKind::Chargeback
+            } else {
+                TransactionKind::Resolve
+            };
+            Transaction::new(kind, client, disputed, None)
+        } else if roll < self.profile.dispute_probability
+            && let Some(deposit) = state.latest_deposit.take()
+        {
+            state.open_dispute = Some(deposit);
+            Transaction::new(TransactionKind::Dispute, client, deposit, None)
+        } else if deposit {
+            state.latest_deposit = Some(tx);
+            Transaction::new(TransactionKind::Deposit, client, tx, Some(amount))
+        } else {
+            Transaction::new(TransactionKind::Withdrawal, client, tx, Some(amount))
+        };
+        row.timestamp = Some(self.generated / self.profile.tx_per_sec.max(1));
+        self.generated += 1;
+        Some(row)
+    }
+}
+
+/// Throughput and latencies of a single backend
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    pub backend: String,
+    pub transactions: u64,
+    pub rejected: u64,
+    pub elapsed: Duration,
+    /// Transactions per second of the whole run
+    pub throughput: f64,
+    /// Transactions per second of the slowest tenth of the run
+    pub sustained_throughput: f64,
+    /// Time spent in every `process_transaction` call
+    pub latency: StageTimings,
+}
+
+impl Display for SimulationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {} transactions ({} rejected) in {:.2?}",
+            self.backend, self.transactions, self.rejected, self.elapsed
+        )?;
+        writeln!(
+            f,
+            "  throughput {:.0} tx/s, sustained {:.0} tx/s",
+            self.throughput, self.sustained_throughput
+        )?;
+        writeln!(
+            f,
+            "  latency p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+            self.latency.p50(),
+            self.latency.p95(),
+            self.latency.p99(),
+            self.latency.max()
+        )
+    }
+}
+
+/// Drives `processor` with the whole workload of `profile`
+pub fn simulate<P: TransactionProcessor>(
+    profile: &WorkloadProfile,
+    backend: &str,
+    processor: &mut P,
+) -> SimulationReport {
+    let transactions = profile.transactions();
+    let slice = (transactions / SLICES).max(1);
+    let mut latency = StageTimings::default();
+    let mut rejected = 0;
+    let mut slowest = Duration::ZERO;
+    let started = Instant::now();
+    let mut slice_started = started;
+    for (idx, row) in profile.rows().enumerate() {
+        let call = Instant::now();
+        let result = processor.process_transaction(row.tx, row.client, row.amount, row.kind);
+        latency.record(call.elapsed());
+        rejected += u64::from(result.is_err());
+        if (idx as u64 + 1).is_multiple_of(slice) {
+            slowest = slowest.max(slice_started.elapsed());
+            slice_started = Instant::now();
+        }
+    }
+    let elapsed = started.elapsed();
+    let per_sec = |count: u64, elapsed: Duration| count as f64 / elapsed.as_secs_f64().max(1e-9);
+    SimulationReport {
+        backend: backend.to_string(),
+        transactions: latency.count(),
+        rejected,
+        elapsed,
+        throughput: per_sec(latency.count(), elapsed),
+        sustained_throughput: if slowest.is_zero() {
+            per_sec(latency.count(), elapsed)
+        } else {
+            per_sec(slice, slowest)
+        },
+        latency,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processor::in_memory_processor::InMemoryTransactionProcessor;
+
+    use super::*;
+
+    #[test]
+    fn simulates_deterministic_workload() {
+        let profile = WorkloadProfile {
+            clients: 10,
+            tx_per_sec: 1,
+            dispute_probability: 0.2,
+            days: 1,
+            seed: 7,
+        };
+        let rows: Vec<_> = profile.rows().collect();
+        assert_eq!(rows.len(), 86_400);
+        assert_eq!(rows.last().unwrap().timestamp, Some(86_399));
+        let disputes = |kind| rows.iter().filter(|row| row.kind == kind).count();
+        assert!(disputes(TransactionKind::Dispute) > 0);
+        assert!(disputes(TransactionKind::Resolve) > disputes(TransactionKind::Chargeback));
+        // same seed, same activity
+        let again: Vec<_> = profile
+            .rows()
+            .take(100)
+            .map(|row| (row.client, row.tx))
+            .collect();
+        let first: Vec<_> = rows
+            .iter()
+            .take(100)
+            .map(|row| (row.client, row.tx))
+            .collect();
+        assert_eq!(again, first);
+
+        let mut processor = InMemoryTransactionProcessor::default();
+        let report = simulate(&profile, "in-memory", &mut processor);
+        assert_eq!(report.transactions, 86_400);
+        // withdrawals above balance, and rows of accounts locked by chargebacks
+        assert!(report.rejected > 0 && report.rejected < report.transactions);
+        assert!(report.sustained_throughput <= report.throughput * 1.01);
+        assert!(report.latency.p50() <= report.latency.p99());
+    }
+}
@@ -0,0 +1,165 @@
+//! Standing orders are compact recurring instructions, e.g. monthly
+//! subscription debit, which are expanded into concrete transactions.
+
+use std::io::Read;
+
+use csv::Trim;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{command::TransactionKind, id_allocator::IdAllocator, processor::ClientId};
+
+use super::csv_parser::Transaction;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StandingOrder {
+    #[serde(rename = "type")]
+    pub kind: TransactionKind,
+    pub client: ClientId,
+    pub amount: Decimal,
+    /// Number of ticks (e.g. days) between occurrences
+    pub interval: u32,
+    pub count: u32,
+    /// Tick of the first occurrence
+    #[serde(default)]
+    pub start: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum StandingOrderError {
+    #[error("Standing order #{index} has unsupported type {kind:?}")]
+    UnsupportedKind { index: usize, kind: TransactionKind },
+    #[error("Standing order #{index} repeats, so interval must be positive")]
+    ZeroInterval { index: usize },
+    #[error("Not enough tx ids left for expanded transactions")]
+    TxIdsExhausted,
+    #[error("Failed to read standing orders: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+/// Reads standing orders with `type,client,amount,interval,count[,start]` columns
+pub fn parse_standing_orders<R: Read>(source: R) -> Result<Vec<StandingOrder>, StandingOrderError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_reader(source);
+    Ok(reader.deserialize().collect::<Result<_, _>>()?)
+}
+
+/// Expands orders into transactions ordered by occurrence tick (ties are
+/// resolved by order position), and assigns tx ids from `ids` in that order,
+/// so the same orders always produce the same ids from a deterministic allocator.
+pub fn expand(
+    orders: &[StandingOrder],
+    ids: &mut dyn IdAllocator,
+) -> Result<Vec<Transaction>, StandingOrderError> {
+    let mut occurrences = Vec::new();
+    for (index, order) in orders.iter().enumerate() {
+        if !matches!(
+            order.kind,
+            TransactionKind::Deposit | TransactionKind::Withdrawal
+        ) {
+            return Err(StandingOrderError::UnsupportedKind {
+                index,
+                kind: order.kind.clone(),
+            });
+        }
+        if order.count > 1 && order.interval == 0 {
+            return Err(StandingOrderError::ZeroInterval { index });
+        }
+        for n in 0..order.count as u64 {
+            let tick = order.start as u64 + n * order.interval as u64;
+            occurrences.push((tick, index));
+        }
+    }
+    occurrences.sort();
+
+    occurrences
+        .into_iter()
+        .map(|(_, index)| {
+            let order = &orders[index];
+            let tx = ids.allocate().ok_or(StandingOrderError::TxIdsExhausted)?;
+            Ok(Transaction {
+                kind: order.kind.clone(),
+                client: order.client,
+                tx,
+                amount: Some(order.amount),
+                timestamp: None,
+                signature: None,
+                source: None,
+                trace_id: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::id_allocator::RangeAllocator;
+
+    use super::*;
+
+    #[test]
+    fn expand_orders() {
+        let orders = parse_standing_orders(
+            "type, client, amount, interval, count, start\n\
+             withdrawal, 1, 9.99, 30, 3, 10\n\
+             deposit, 2, 100, 30, 2, 0\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let txs = expand(&orders, &mut RangeAllocator::starting_at(100)).unwrap();
+        let summary: Vec<_> = txs
+            .iter()
+            .map(|t| (t.tx, t.client, t.kind.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (100, 2, TransactionKind::Deposit),
+                (101, 1, TransactionKind::Withdrawal),
+                (102, 2, TransactionKind::Deposit),
+                (103, 1, TransactionKind::Withdrawal),
+                (104, 1, TransactionKind::Withdrawal),
+            ]
+        );
+        assert_eq!(
+            txs[1].amount,
+            Some(Decimal::from_str_exact("9.99").unwrap())
+        );
+    }
+
+    #[test]
+    fn reject_invalid_orders() {
+        let order = StandingOrder {
+            kind: TransactionKind::Dispute,
+            client: 1,
+            amount: Decimal::ONE,
+            interval: 1,
+            count: 1,
+            start: 0,
+        };
+        assert!(matches!(
+            expand(std::slice::from_ref(&order), &mut RangeAllocator::default()),
+            Err(StandingOrderError::UnsupportedKind { index: 0, .. })
+        ));
+        let order = StandingOrder {
+            kind: TransactionKind::Deposit,
+            interval: 0,
+            count: 2,
+            ..order
+        };
+        assert!(matches!(
+            expand(std::slice::from_ref(&order), &mut RangeAllocator::default()),
+            Err(StandingOrderError::ZeroInterval { index: 0 })
+        ));
+        let order = StandingOrder {
+            interval: 1,
+            ..order
+        };
+        assert!(matches!(
+            expand(&[order], &mut RangeAllocator::starting_at(u32::MAX)),
+            Err(StandingOrderError::TxIdsExhausted)
+        ));
+    }
+}
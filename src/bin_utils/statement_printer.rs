@@ -0,0 +1,162 @@
+use std::{io::Write, str::FromStr};
+
+use csv::Writer;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::history::{Balance, EventSeq, Statement};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatementFormat {
+    #[default]
+    Text,
+    Csv,
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!("unknown statement format `{other}`")),
+        }
+    }
+}
+
+/// Statement row, opening and closing balances are rows without seq and tx
+#[derive(Debug, Serialize)]
+struct Row {
+    seq: Option<EventSeq>,
+    tx: Option<u32>,
+    kind: String,
+    amount: Option<Decimal>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    pending: Decimal,
+    locked: bool,
+    source: Option<String>,
+}
+
+impl Row {
+    fn balance(kind: &str, balance: &Balance) -> Self {
+        Self {
+            seq: None,
+            tx: None,
+            kind: kind.to_string(),
+            amount: None,
+            available: balance.available,
+            held: balance.held,
+            total: balance.total(),
+            pending: balance.pending,
+            locked: balance.locked,
+            source: None,
+        }
+    }
+}
+
+pub fn print_statement<W>(
+    output: &mut W,
+    statement: &Statement,
+    format: StatementFormat,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    match format {
+        StatementFormat::Text => print_text(output, statement)?,
+        StatementFormat::Csv => print_csv(output, statement)?,
+    }
+    Ok(())
+}
+
+fn print_text<W: Write>(output: &mut W, statement: &Statement) -> std::io::Result<()> {
+    let balance = |b: &Balance| {
+        format!(
+            "available {}, held {}, total {}, pending {}{}",
+            b.available,
+            b.held,
+            b.total(),
+            b.pending,
+            if b.locked { ", locked" } else { "" }
+        )
+    };
+    writeln!(output, "Statement for client {}", statement.client)?;
+    writeln!(output, "Opening balance: {}", balance(&statement.opening))?;
+    writeln!(
+        output,
+        "{:>8} {:>10} {:<14} {:>14} {:>14} {:>14}  source",
+        "seq", "tx", "event", "amount", "available", "held"
+    )?;
+    for line in &statement.lines {
+        writeln!(
+            output,
+            "{:>8} {:>10} {:<14} {:>14} {:>14} {:>14}  {}",
+            line.seq,
+            line.tx,
+            format!("{:?}", line.kind),
+            line.amount,
+            line.balance.available,
+            line.balance.held,
+            line.source.as_deref().unwrap_or_default()
+        )?;
+    }
+    writeln!(output, "Closing balance: {}", balance(&statement.closing))
+}
+
+fn print_csv<W: Write>(output: &mut W, statement: &Statement) -> anyhow::Result<()> {
+    let mut writer = Writer::from_writer(output);
+    writer.serialize(Row::balance("opening", &statement.opening))?;
+    for line in &statement.lines {
+        writer.serialize(Row {
+            seq: Some(line.seq),
+            tx: Some(line.tx),
+            kind: format!("{:?}", line.kind).to_lowercase(),
+            amount: Some(line.amount),
+            source: line.source.as_deref().map(str::to_string),
+            ..Row::balance("", &line.balance)
+        })?;
+    }
+    writer.serialize(Row::balance("closing", &statement.closing))?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::TransactionKind,
+        processor::{TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn csv_statement() {
+        let mut processor = InMemoryTransactionProcessor::default().with_history();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TWO), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction_from(
+                2,
+                1,
+                Some(Decimal::ONE),
+                TransactionKind::Withdrawal,
+                Some("partner-7"),
+            )
+            .unwrap();
+        let statement = processor.statement(1, 1..EventSeq::MAX).unwrap();
+        let mut output = Vec::new();
+        print_statement(&mut output, &statement, StatementFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "seq,tx,kind,amount,available,held,total,pending,locked,source\n\
+             ,,opening,,2,0,2,0,false,\n\
+             1,2,withdrawn,1,1,0,1,0,false,partner-7\n\
+             ,,closing,,1,0,1,0,false,\n"
+        );
+    }
+}
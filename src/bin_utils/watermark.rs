@@ -0,0 +1,98 @@
+//! Event-time watermark of streaming feeds with `timestamp` column. The
+//! watermark trails the highest timestamp seen by the allowed lateness, and
+//! rows behind it are late: applying them would change periods that may
+//! already be reported, so they can be diverted to a late queue or rejected.
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use super::csv_parser::Transaction;
+
+/// What to do with rows behind the watermark
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatePolicy {
+    /// Apply as usual, only count them
+    #[default]
+    Apply,
+    /// Don't apply, collect into [`super::run_report::RunReport::late`]
+    /// to be processed with the next period
+    Divert,
+    /// Reject with `late_event` code
+    Reject,
+}
+
+impl FromStr for LatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "apply" => Ok(Self::Apply),
+            "divert" => Ok(Self::Divert),
+            "reject" => Ok(Self::Reject),
+            other => Err(format!("unknown late events policy `{other}`")),
+        }
+    }
+}
+
+/// Row diverted by [`LatePolicy::Divert`]
+#[derive(Debug, Clone)]
+pub struct LateRow {
+    pub line: u64,
+    pub row: Transaction,
+    /// Watermark the row arrived behind
+    pub watermark: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Watermark {
+    allowed_lateness: u64,
+    highest: Option<u64>,
+}
+
+impl Watermark {
+    pub fn new(allowed_lateness: u64) -> Self {
+        Self {
+            allowed_lateness,
+            highest: None,
+        }
+    }
+
+    /// Timestamps lower than this are late, `None` before any timestamp is seen
+    pub fn current(&self) -> Option<u64> {
+        self.highest
+            .map(|highest| highest.saturating_sub(self.allowed_lateness))
+    }
+
+    /// Advances the watermark by timestamp of a row, and returns the
+    /// watermark the row is late for
+    pub fn observe(&mut self, timestamp: u64) -> Option<u64> {
+        let late = self.current().filter(|watermark| timestamp < *watermark);
+        self.highest = Some(
+            self.highest
+                .map_or(timestamp, |highest| highest.max(timestamp)),
+        );
+        late
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_behind_allowed_lateness_are_late() {
+        let mut watermark = Watermark::new(10);
+        assert_eq!(watermark.current(), None);
+        assert_eq!(watermark.observe(100), None);
+        assert_eq!(watermark.current(), Some(90));
+        assert_eq!(watermark.observe(95), None);
+        assert_eq!(watermark.observe(90), None);
+        assert_eq!(watermark.observe(89), Some(90));
+        // late rows don't move the watermark back
+        assert_eq!(watermark.current(), Some(90));
+        assert_eq!(watermark.observe(120), None);
+        assert_eq!(watermark.observe(100), Some(110));
+    }
+}
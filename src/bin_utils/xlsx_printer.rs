@@ -0,0 +1,191 @@
+use std::io::Write;
+
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use rust_xlsxwriter::{
+    DocProperties, ExcelDateTime, Format, FormatBorder, Workbook, Worksheet, XlsxError,
+};
+
+use crate::stats::PipelineStats;
+
+use super::csv_printer::Account;
+
+const AMOUNT_FORMAT: &str = "#,##0.0000";
+
+/// Writes accounts into XLSX workbook with two sheets:
+/// "Accounts" (same columns as CSV output) and "Summary" (totals and
+/// per-stage processing timings). When `deterministic`, creation time
+/// of the workbook is fixed, so the same accounts produce the same bytes.
+pub fn print_accounts_xlsx<W>(
+    output: &mut W,
+    accounts: impl Iterator<Item = Account>,
+    stats: &PipelineStats,
+    deterministic: bool,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let workbook = build_workbook(accounts, stats).and_then(|mut wb| {
+        if deterministic {
+            wb.set_properties(&DocProperties::new().set_creation_datetime(&fixed_epoch()?));
+        }
+        wb.save_to_buffer()
+    });
+    let buffer = match workbook {
+        Ok(buffer) => buffer,
+        Err(err) => anyhow::bail!("Failed to build XLSX report: {err}"),
+    };
+    if let Err(err) = output.write_all(&buffer).and_then(|_| output.flush()) {
+        anyhow::bail!("Failed to write XLSX report: {err}")
+    }
+    Ok(())
+}
+
+/// Creation time of deterministic workbooks
+fn fixed_epoch() -> Result<ExcelDateTime, XlsxError> {
+    ExcelDateTime::from_ymd(2000, 1, 1)
+}
+
+#[derive(Default)]
+struct Summary {
+    accounts: u32,
+    locked: u32,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+}
+
+fn build_workbook(
+    accounts: impl Iterator<Item = Account>,
+    stats: &PipelineStats,
+) -> Result<Workbook, XlsxError> {
+    let header = Format::new()
+        .set_bold()
+        .set_border_bottom(FormatBorder::Thin);
+    let amount = Format::new().set_num_format(AMOUNT_FORMAT);
+
+    let mut workbook = Workbook::new();
+    let mut summary = Summary::default();
+
+    let sheet = workbook.add_worksheet().set_name("Accounts")?;
+    write_header(
+        sheet,
+        &header,
+        &["client", "available", "held", "total", "locked", "pending"],
+    )?;
+    sheet.set_freeze_panes(1, 0)?;
+    for (idx, acc) in accounts.enumerate() {
+        let row = idx as u32 + 1;
+        sheet.write_number(row, 0, acc.client)?;
+        sheet.write_number_with_format(row, 1, to_f64(acc.available), &amount)?;
+        sheet.write_number_with_format(row, 2, to_f64(acc.held), &amount)?;
+        sheet.write_number_with_format(row, 3, to_f64(acc.total), &amount)?;
+        sheet.write_boolean(row, 4, acc.locked)?;
+        sheet.write_number_with_format(row, 5, to_f64(acc.pending), &amount)?;
+
+        summary.accounts += 1;
+        summary.locked += acc.locked as u32;
+        summary.available += acc.available;
+        summary.held += acc.held;
+        summary.total += acc.total;
+    }
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Summary")?;
+    write_header(sheet, &header, &["metric", "value"])?;
+    sheet.write_string(1, 0, "accounts")?;
+    sheet.write_number(1, 1, summary.accounts)?;
+    sheet.write_string(2, 0, "locked accounts")?;
+    sheet.write_number(2, 1, summary.locked)?;
+    sheet.write_string(3, 0, "total available")?;
+    sheet.write_number_with_format(3, 1, to_f64(summary.available), &amount)?;
+    sheet.write_string(4, 0, "total held")?;
+    sheet.write_number_with_format(4, 1, to_f64(summary.held), &amount)?;
+    sheet.write_string(5, 0, "total")?;
+    sheet.write_number_with_format(5, 1, to_f64(summary.total), &amount)?;
+
+    let first_row = 7;
+    write_header_at(
+        sheet,
+        first_row,
+        &header,
+        &["kind", "stage", "count", "p50 (µs)", "p95 (µs)", "p99 (µs)"],
+    )?;
+    for (idx, (kind, stage, timings)) in stats.iter().enumerate() {
+        let row = first_row + 1 + idx as u32;
+        sheet.write_string(row, 0, format!("{kind:?}"))?;
+        sheet.write_string(row, 1, format!("{stage:?}"))?;
+        sheet.write_number(row, 2, timings.count() as f64)?;
+        sheet.write_number(row, 3, timings.p50().as_secs_f64() * 1e6)?;
+        sheet.write_number(row, 4, timings.p95().as_secs_f64() * 1e6)?;
+        sheet.write_number(row, 5, timings.p99().as_secs_f64() * 1e6)?;
+    }
+    sheet.autofit();
+
+    Ok(workbook)
+}
+
+fn write_header(sheet: &mut Worksheet, format: &Format, names: &[&str]) -> Result<(), XlsxError> {
+    write_header_at(sheet, 0, format, names)
+}
+
+fn write_header_at(
+    sheet: &mut Worksheet,
+    row: u32,
+    format: &Format,
+    names: &[&str],
+) -> Result<(), XlsxError> {
+    for (col, name) in names.iter().enumerate() {
+        sheet.write_string_with_format(row, col as u16, *name, format)?;
+    }
+    Ok(())
+}
+
+// Excel stores numbers as f64 anyway, so precision loss is unavoidable here
+fn to_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use super::*;
+
+    #[test]
+    fn writes_valid_zip_archive() {
+        let mut output = Vec::new();
+        print_accounts_xlsx(
+            &mut output,
+            [Account {
+                client: 1,
+                available: Decimal::from_f64(1.5).unwrap(),
+                held: Decimal::from_u32(2).unwrap(),
+                total: Decimal::from_f64(3.5).unwrap(),
+                locked: false,
+                pending: Decimal::ZERO,
+                extensions: Default::default(),
+            }]
+            .into_iter(),
+            &PipelineStats::default(),
+            false,
+        )
+        .unwrap();
+        // xlsx is a zip archive, which always starts with "PK" signature
+        assert!(output.starts_with(b"PK"));
+    }
+
+    #[test]
+    fn deterministic_output_is_created_at_fixed_epoch() {
+        let created_at = |time: &ExcelDateTime| {
+            let mut workbook = build_workbook([].into_iter(), &PipelineStats::default()).unwrap();
+            workbook.set_properties(&DocProperties::new().set_creation_datetime(time));
+            workbook.save_to_buffer().unwrap()
+        };
+        let mut output = Vec::new();
+        print_accounts_xlsx(&mut output, [].into_iter(), &PipelineStats::default(), true).unwrap();
+        assert_eq!(output, created_at(&fixed_epoch().unwrap()));
+        // creation time is the only thing differing between runs
+        let later = ExcelDateTime::from_ymd(2001, 1, 1).unwrap();
+        assert_ne!(output, created_at(&later));
+    }
+}
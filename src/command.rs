@@ -1,46 +1,223 @@
-use std::collections::hash_map::Entry;
+use std::str::FromStr;
 
-use rust_decimal::{Decimal, prelude::Zero};
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize};
 use thiserror::Error;
 
-use crate::account::TransactionId;
+use crate::{
+    account::TransactionId,
+    money::{MAX_SCALE, Money, MoneyError},
+};
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TransactionKind {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    /// Deposit which becomes available only after settlement
+    PendingDeposit,
+    Settle,
+    /// Holds funds until authorization is captured or voided
+    Authorize,
+    Capture,
+    Void,
+    /// Restores funds of charged back transaction, e.g. after the chargeback
+    /// was reversed by card network, and unlocks the account
+    Reinstate,
+    /// Merchant contests the dispute with evidence
+    Representment,
+    /// Cardholder escalates the contested dispute
+    PreArbitration,
+    /// Type not known to this version, so that input can be processed
+    /// further, and such row rejected or skipped with its line reported
+    Unknown(String),
+}
+
+impl<'de> Deserialize<'de> for TransactionKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Self::from_name(&name))
+    }
+}
+
+impl TransactionKind {
+    /// Whether transaction refers to previously created transaction
+    pub fn is_modify(&self) -> bool {
+        matches!(
+            self,
+            TransactionKind::Dispute
+                | TransactionKind::Resolve
+                | TransactionKind::Chargeback
+                | TransactionKind::Settle
+                | TransactionKind::Capture
+                | TransactionKind::Void
+                | TransactionKind::Reinstate
+                | TransactionKind::Representment
+                | TransactionKind::PreArbitration
+        )
+    }
+
+    /// Action of modify transaction, `None` for created ones
+    pub fn modify_action(&self) -> Option<ModifyTransactionAction> {
+        match self {
+            TransactionKind::Dispute => Some(ModifyTransactionAction::Dispute),
+            TransactionKind::Resolve => Some(ModifyTransactionAction::Resolve),
+            TransactionKind::Chargeback => Some(ModifyTransactionAction::Chargeback),
+            TransactionKind::Settle => Some(ModifyTransactionAction::Settle),
+            TransactionKind::Capture => Some(ModifyTransactionAction::Capture),
+            TransactionKind::Void => Some(ModifyTransactionAction::Void),
+            TransactionKind::Reinstate => Some(ModifyTransactionAction::Reinstate),
+            TransactionKind::Representment => Some(ModifyTransactionAction::Representment),
+            TransactionKind::PreArbitration => Some(ModifyTransactionAction::PreArbitration),
+            _ => None,
+        }
+    }
+
+    pub const KNOWN: [TransactionKind; 13] = [
+        TransactionKind::Deposit,
+        TransactionKind::Withdrawal,
+        TransactionKind::Dispute,
+        TransactionKind::Resolve,
+        TransactionKind::Chargeback,
+        TransactionKind::PendingDeposit,
+        TransactionKind::Settle,
+        TransactionKind::Authorize,
+        TransactionKind::Capture,
+        TransactionKind::Void,
+        TransactionKind::Reinstate,
+        TransactionKind::Representment,
+        TransactionKind::PreArbitration,
+    ];
+
+    /// Name used in input files
+    pub fn name(&self) -> &str {
+        match self {
+            TransactionKind::Deposit => "deposit",
+            TransactionKind::Withdrawal => "withdrawal",
+            TransactionKind::Dispute => "dispute",
+            TransactionKind::Resolve => "resolve",
+            TransactionKind::Chargeback => "chargeback",
+            TransactionKind::PendingDeposit => "pending_deposit",
+            TransactionKind::Settle => "settle",
+            TransactionKind::Authorize => "authorize",
+            TransactionKind::Capture => "capture",
+            TransactionKind::Void => "void",
+            TransactionKind::Reinstate => "reinstate",
+            TransactionKind::Representment => "representment",
+            TransactionKind::PreArbitration => "pre_arbitration",
+            TransactionKind::Unknown(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        Self::KNOWN
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .unwrap_or_else(|| TransactionKind::Unknown(name.to_string()))
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CreateTransactionAction {
     Deposit,
     Withdraw,
+    PendingDeposit,
+    Authorize,
+}
+
+impl CreateTransactionAction {
+    /// Stable name, used by storage backends
+    pub fn name(&self) -> &'static str {
+        match self {
+            CreateTransactionAction::Deposit => "deposit",
+            CreateTransactionAction::Withdraw => "withdraw",
+            CreateTransactionAction::PendingDeposit => "pending_deposit",
+            CreateTransactionAction::Authorize => "authorize",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            CreateTransactionAction::Deposit,
+            CreateTransactionAction::Withdraw,
+            CreateTransactionAction::PendingDeposit,
+            CreateTransactionAction::Authorize,
+        ]
+        .into_iter()
+        .find(|action| action.name() == name)
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModifyTransactionAction {
     Dispute,
     Resolve,
     Chargeback,
+    Settle,
+    Capture,
+    Void,
+    Reinstate,
+    Representment,
+    PreArbitration,
+}
+
+impl ModifyTransactionAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModifyTransactionAction::Dispute => "dispute",
+            ModifyTransactionAction::Resolve => "resolve",
+            ModifyTransactionAction::Chargeback => "chargeback",
+            ModifyTransactionAction::Settle => "settle",
+            ModifyTransactionAction::Capture => "capture",
+            ModifyTransactionAction::Void => "void",
+            ModifyTransactionAction::Reinstate => "reinstate",
+            ModifyTransactionAction::Representment => "representment",
+            ModifyTransactionAction::PreArbitration => "pre_arbitration",
+        }
+    }
+}
+
+/// What to do with zero-amount deposits, withdrawals and other created transactions,
+/// which don't change balances but take up space in the transactions index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZeroAmountPolicy {
+    #[default]
+    Accept,
+    /// Not applied, reported with [`AccountCommandError::ZeroAmountSkipped`]
+    /// to be treated as a warning
+    Skip,
+    /// Rejected with [`AccountCommandError::ZeroAmount`]
+    Reject,
+}
+
+impl FromStr for ZeroAmountPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "accept" => Ok(Self::Accept),
+            "skip" => Ok(Self::Skip),
+            "reject" => Ok(Self::Reject),
+            other => Err(format!("unknown zero amount policy `{other}`")),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CreateTransactionCommand {
     pub tx_id: TransactionId,
     pub action: CreateTransactionAction,
-    pub amount: Decimal,
+    pub amount: Money,
 }
 
 #[derive(Debug, Clone)]
 pub struct ModifyTransactionCommand {
     pub tx_id: TransactionId,
     pub action: ModifyTransactionAction,
-    pub amount: Decimal,
+    pub amount: Money,
     pub create_action: CreateTransactionAction,
 }
 
@@ -50,10 +227,55 @@ pub enum AccountCommandError {
     AmountRequired { action: CreateTransactionAction },
     #[error("Amount must not be negative for {action:?}")]
     NegativeAmount { action: CreateTransactionAction },
+    #[error("Amount of {action:?} has {scale} decimal places, at most {MAX_SCALE} are allowed")]
+    AmountTooPrecise {
+        action: CreateTransactionAction,
+        scale: u32,
+    },
     #[error("There should be an existing transaction for {action:?}")]
     ExistingTxRequired { action: ModifyTransactionAction },
     #[error("There shouldn't be an existing transaction for {action:?}")]
     DuplicateTransaction { action: CreateTransactionAction },
+    #[error("Unknown transaction type `{kind}`")]
+    UnknownKind { kind: String },
+    #[error("Amount of {action:?} must not be zero")]
+    ZeroAmount { action: CreateTransactionAction },
+    #[error("Amount of {action:?} is zero")]
+    ZeroAmountSkipped { action: CreateTransactionAction },
+    #[error("{action:?} was already applied to the transaction")]
+    RepeatedModifySkipped { action: ModifyTransactionAction },
+    #[error("Transaction id is lower than {highest}, the highest one accepted")]
+    TxIdNotIncreasing { highest: TransactionId },
+    #[error("Transaction referenced by {action:?} was rejected at line {line}")]
+    ReferencedTxRejected {
+        action: ModifyTransactionAction,
+        line: u64,
+    },
+    #[error("Event time is behind watermark {watermark}")]
+    LateEvent { watermark: u64 },
+    #[error("Transaction referenced by {action:?} is settled and its record was retired")]
+    TransactionRetired { action: ModifyTransactionAction },
+}
+
+impl AccountCommandError {
+    /// Stable identifier of the error, that doesn't change with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountCommandError::AmountRequired { .. } => "amount_required",
+            AccountCommandError::NegativeAmount { .. } => "negative_amount",
+            AccountCommandError::AmountTooPrecise { .. } => "amount_too_precise",
+            AccountCommandError::ExistingTxRequired { .. } => "existing_tx_required",
+            AccountCommandError::DuplicateTransaction { .. } => "duplicate_transaction",
+            AccountCommandError::UnknownKind { .. } => "unknown_kind",
+            AccountCommandError::ZeroAmount { .. } => "zero_amount",
+            AccountCommandError::ZeroAmountSkipped { .. } => "zero_amount_skipped",
+            AccountCommandError::RepeatedModifySkipped { .. } => "repeated_modify_skipped",
+            AccountCommandError::TxIdNotIncreasing { .. } => "tx_id_not_increasing",
+            AccountCommandError::ReferencedTxRejected { .. } => "referenced_tx_rejected",
+            AccountCommandError::LateEvent { .. } => "late_event",
+            AccountCommandError::TransactionRetired { .. } => "transaction_retired",
+        }
+    }
 }
 
 pub enum AccountCommand {
@@ -62,72 +284,132 @@ pub enum AccountCommand {
 }
 
 impl AccountCommand {
+    /// `existing_tx` is previously created transaction with the same `tx_id`, if any
     pub fn parse_command(
-        tx_entry: &Entry<'_, TransactionId, CreateTransactionCommand>,
-        kind: TransactionKind,
+        tx_id: TransactionId,
+        existing_tx: Option<&CreateTransactionCommand>,
+        kind: &TransactionKind,
         amount: Option<Decimal>,
+        zero_amounts: ZeroAmountPolicy,
     ) -> Result<Self, AccountCommandError> {
         match kind {
             TransactionKind::Deposit => Ok(Self::CreateTx(Self::parse_create_command(
-                tx_entry,
+                tx_id,
+                existing_tx,
                 amount,
                 CreateTransactionAction::Deposit,
+                zero_amounts,
             )?)),
             TransactionKind::Withdrawal => Ok(Self::CreateTx(Self::parse_create_command(
-                tx_entry,
+                tx_id,
+                existing_tx,
                 amount,
                 CreateTransactionAction::Withdraw,
+                zero_amounts,
             )?)),
             TransactionKind::Dispute => Ok(Self::ModifyTx(Self::parse_modify_command(
-                tx_entry,
+                existing_tx,
                 ModifyTransactionAction::Dispute,
             )?)),
             TransactionKind::Resolve => Ok(Self::ModifyTx(Self::parse_modify_command(
-                tx_entry,
+                existing_tx,
                 ModifyTransactionAction::Resolve,
             )?)),
             TransactionKind::Chargeback => Ok(Self::ModifyTx(Self::parse_modify_command(
-                tx_entry,
+                existing_tx,
                 ModifyTransactionAction::Chargeback,
             )?)),
+            TransactionKind::PendingDeposit => Ok(Self::CreateTx(Self::parse_create_command(
+                tx_id,
+                existing_tx,
+                amount,
+                CreateTransactionAction::PendingDeposit,
+                zero_amounts,
+            )?)),
+            TransactionKind::Settle => Ok(Self::ModifyTx(Self::parse_modify_command(
+                existing_tx,
+                ModifyTransactionAction::Settle,
+            )?)),
+            TransactionKind::Authorize => Ok(Self::CreateTx(Self::parse_create_command(
+                tx_id,
+                existing_tx,
+                amount,
+                CreateTransactionAction::Authorize,
+                zero_amounts,
+            )?)),
+            TransactionKind::Capture => Ok(Self::ModifyTx(Self::parse_modify_command(
+                existing_tx,
+                ModifyTransactionAction::Capture,
+            )?)),
+            TransactionKind::Void => Ok(Self::ModifyTx(Self::parse_modify_command(
+                existing_tx,
+                ModifyTransactionAction::Void,
+            )?)),
+            TransactionKind::Reinstate => Ok(Self::ModifyTx(Self::parse_modify_command(
+                existing_tx,
+                ModifyTransactionAction::Reinstate,
+            )?)),
+            TransactionKind::Representment => Ok(Self::ModifyTx(Self::parse_modify_command(
+                existing_tx,
+                ModifyTransactionAction::Representment,
+            )?)),
+            TransactionKind::PreArbitration => Ok(Self::ModifyTx(Self::parse_modify_command(
+                existing_tx,
+                ModifyTransactionAction::PreArbitration,
+            )?)),
+            TransactionKind::Unknown(kind) => {
+                Err(AccountCommandError::UnknownKind { kind: kind.clone() })
+            }
         }
     }
 
     fn parse_create_command(
-        entry: &Entry<'_, TransactionId, CreateTransactionCommand>,
+        tx_id: TransactionId,
+        existing_tx: Option<&CreateTransactionCommand>,
         amount: Option<Decimal>,
         action: CreateTransactionAction,
+        zero_amounts: ZeroAmountPolicy,
     ) -> Result<CreateTransactionCommand, AccountCommandError> {
-        let Entry::Vacant(entry) = entry else {
+        if existing_tx.is_some() {
             return Err(AccountCommandError::DuplicateTransaction { action });
         };
-        if let Some(amount) = amount {
-            if amount >= Decimal::zero() {
-                Ok(CreateTransactionCommand {
-                    tx_id: *entry.key(),
-                    action,
-                    amount,
-                })
-            } else {
-                Err(AccountCommandError::NegativeAmount { action })
+        let Some(amount) = amount else {
+            return Err(AccountCommandError::AmountRequired { action });
+        };
+        if amount.is_zero() {
+            match zero_amounts {
+                ZeroAmountPolicy::Accept => {}
+                ZeroAmountPolicy::Skip => {
+                    return Err(AccountCommandError::ZeroAmountSkipped { action });
+                }
+                ZeroAmountPolicy::Reject => return Err(AccountCommandError::ZeroAmount { action }),
+            }
+        }
+        match Money::new(amount) {
+            Ok(amount) => Ok(CreateTransactionCommand {
+                tx_id,
+                action,
+                amount,
+            }),
+            Err(MoneyError::Negative) => Err(AccountCommandError::NegativeAmount { action }),
+            Err(MoneyError::TooPrecise { scale }) => {
+                Err(AccountCommandError::AmountTooPrecise { action, scale })
             }
-        } else {
-            Err(AccountCommandError::AmountRequired { action })
         }
     }
 
     fn parse_modify_command(
-        tx_entry: &Entry<'_, TransactionId, CreateTransactionCommand>,
+        existing_tx: Option<&CreateTransactionCommand>,
         action: ModifyTransactionAction,
     ) -> Result<ModifyTransactionCommand, AccountCommandError> {
-        let Entry::Occupied(entry) = tx_entry else {
+        let Some(existing_tx) = existing_tx else {
             return Err(AccountCommandError::ExistingTxRequired { action });
         };
         Ok(ModifyTransactionCommand {
-            tx_id: *entry.key(),
+            tx_id: existing_tx.tx_id,
             action,
-            amount: entry.get().amount,
-            create_action: entry.get().action,
+            amount: existing_tx.amount,
+            create_action: existing_tx.action,
         })
     }
 }
@@ -0,0 +1,89 @@
+//! Curated corpus of tricky inputs with expected accounts reports.
+//! Alternative [`TransactionProcessor`] implementations can run
+//! [`verify`] to prove they are equivalent to the in-memory processor.
+
+use std::str::from_utf8;
+
+use crate::{bin_utils::Service, processor::TransactionProcessor};
+
+pub struct ConformanceCase {
+    pub name: &'static str,
+    /// Transactions in CSV format
+    pub input: &'static str,
+    /// Accounts report in CSV format, ordered by client id
+    pub expected: &'static str,
+}
+
+macro_rules! case {
+    ($name:literal) => {
+        ConformanceCase {
+            name: $name,
+            input: include_str!(concat!("corpus/", $name, ".csv")),
+            expected: include_str!(concat!("corpus/", $name, ".expected.csv")),
+        }
+    };
+}
+
+pub const CASES: [ConformanceCase; 5] = [
+    case!("duplicate_tx_ids"),
+    case!("dispute_before_deposit"),
+    case!("locked_account"),
+    case!("precision"),
+    case!("dispute_edge_cases"),
+];
+
+/// Case, whose accounts report differs from the expected one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceFailure {
+    pub name: &'static str,
+    pub expected: &'static str,
+    pub actual: String,
+}
+
+/// Processes case input into `processor`, and returns accounts report
+/// ordered by client id
+pub fn run_case<P: TransactionProcessor>(
+    case: &ConformanceCase,
+    processor: P,
+) -> anyhow::Result<String> {
+    let mut output = Vec::new();
+    let service = Service::builder()
+        .input(case.input.as_bytes())
+        .output(&mut output)
+        .processor(processor)
+        .deterministic(true)
+        .build();
+    service.run()?;
+    Ok(from_utf8(&output)?.to_string())
+}
+
+/// Runs every case with a fresh processor, and returns the failed ones
+pub fn verify<P: TransactionProcessor>(
+    mut new_processor: impl FnMut() -> P,
+) -> anyhow::Result<Vec<ConformanceFailure>> {
+    let mut failures = Vec::new();
+    for case in &CASES {
+        let actual = run_case(case, new_processor())?;
+        if actual != case.expected {
+            failures.push(ConformanceFailure {
+                name: case.name,
+                expected: case.expected,
+                actual,
+            });
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processor::in_memory_processor::InMemoryTransactionProcessor;
+
+    use super::*;
+
+    #[test]
+    fn in_memory_processor_conforms() {
+        let failures = verify(InMemoryTransactionProcessor::default).unwrap();
+        assert_eq!(failures, Vec::new());
+    }
+}
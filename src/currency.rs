@@ -0,0 +1,331 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use thiserror::Error;
+
+use crate::account::TransactionId;
+
+/// ISO 4217 alphabetic currency code, e.g. `EUR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub fn code(&self) -> &str {
+        // always constructed from ascii uppercase letters
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    /// Number of minor unit digits, as in ISO 4217; unlisted currencies have two
+    pub fn exponent(&self) -> u32 {
+        match &self.0 {
+            b"BIF" | b"CLP" | b"DJF" | b"GNF" | b"ISK" | b"JPY" | b"KMF" | b"KRW" | b"PYG"
+            | b"RWF" | b"UGX" | b"UYI" | b"VND" | b"VUV" | b"XAF" | b"XOF" | b"XPF" => 0,
+            b"BHD" | b"IQD" | b"JOD" | b"KWD" | b"LYD" | b"OMR" | b"TND" => 3,
+            b"CLF" | b"UYW" => 4,
+            _ => 2,
+        }
+    }
+
+    /// Rejects amounts with more fractional digits than the currency has,
+    /// trailing zeros don't count
+    pub fn validate_amount(&self, amount: Decimal) -> Result<(), CurrencyError> {
+        let scale = amount.normalize().scale();
+        if scale > self.exponent() {
+            return Err(CurrencyError::ExcessPrecision {
+                currency: *self,
+                scale,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = CurrencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            [a, b, c] if [a, b, c].iter().all(|ch| ch.is_ascii_alphabetic()) => Ok(Self([
+                a.to_ascii_uppercase(),
+                b.to_ascii_uppercase(),
+                c.to_ascii_uppercase(),
+            ])),
+            _ => Err(CurrencyError::InvalidCode(s.to_string())),
+        }
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CurrencyError {
+    #[error("`{0}` is not a valid currency code")]
+    InvalidCode(String),
+    #[error("Amount must be positive")]
+    NonPositiveAmount,
+    #[error("Exchange rate must be positive")]
+    NonPositiveRate,
+    #[error("Cannot exchange {0} into itself")]
+    SameCurrency(Currency),
+    #[error("Insufficient funds in {0}")]
+    InsufficientFunds(Currency),
+    #[error("Amount is too large")]
+    Overflow,
+    #[error("Amount has {scale} decimal places, but {currency} has {}", currency.exponent())]
+    ExcessPrecision { currency: Currency, scale: u32 },
+    #[error("Exchanged amount is less than the minor unit of {0}")]
+    AmountTooSmall(Currency),
+}
+
+/// Transactions supported by multi-currency account
+#[derive(Debug, Clone, Copy)]
+pub enum CurrencyTransactionKind {
+    Deposit {
+        currency: Currency,
+    },
+    Withdrawal {
+        currency: Currency,
+    },
+    /// Converts `amount` of `from_currency` into `amount * rate` of `to_currency`,
+    /// rounded down to minor units of `to_currency`
+    Exchange {
+        from_currency: Currency,
+        to_currency: Currency,
+        rate: Decimal,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrencyEventKind {
+    Credited,
+    Debited,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CurrencyEvent {
+    pub transaction_id: TransactionId,
+    pub currency: Currency,
+    pub amount: Decimal,
+    pub kind: CurrencyEventKind,
+}
+
+/// Account holding separate available balance for each currency.
+/// Same as [`crate::account::Account`], state is only changed by applying events.
+#[derive(Debug, Default)]
+pub struct MultiCurrencyAccount {
+    balances: HashMap<Currency, Decimal>,
+}
+
+impl MultiCurrencyAccount {
+    pub fn available(&self, currency: Currency) -> Decimal {
+        self.balances.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn balances(&self) -> impl Iterator<Item = (Currency, Decimal)> {
+        self.balances
+            .iter()
+            .map(|(currency, amount)| (*currency, *amount))
+    }
+
+    pub fn apply(&mut self, event: &CurrencyEvent) {
+        let balance = self.balances.entry(event.currency).or_default();
+        match event.kind {
+            CurrencyEventKind::Credited => *balance += event.amount,
+            CurrencyEventKind::Debited => *balance -= event.amount,
+        }
+    }
+
+    /// Exchange produces a debit and a credit event, which must be applied together
+    pub fn handle_transaction(
+        &self,
+        transaction_id: TransactionId,
+        kind: CurrencyTransactionKind,
+        amount: Decimal,
+    ) -> Result<Vec<CurrencyEvent>, CurrencyError> {
+        if amount <= Decimal::ZERO {
+            return Err(CurrencyError::NonPositiveAmount);
+        }
+        let event = |currency, amount, kind| CurrencyEvent {
+            transaction_id,
+            currency,
+            amount,
+            kind,
+        };
+        match kind {
+            CurrencyTransactionKind::Deposit { currency } => {
+                currency.validate_amount(amount)?;
+                Ok(vec![event(currency, amount, CurrencyEventKind::Credited)])
+            }
+            CurrencyTransactionKind::Withdrawal { currency } => {
+                currency.validate_amount(amount)?;
+                self.ensure_available(currency, amount)?;
+                Ok(vec![event(currency, amount, CurrencyEventKind::Debited)])
+            }
+            CurrencyTransactionKind::Exchange {
+                from_currency,
+                to_currency,
+                rate,
+            } => {
+                if rate <= Decimal::ZERO {
+                    return Err(CurrencyError::NonPositiveRate);
+                }
+                if from_currency == to_currency {
+                    return Err(CurrencyError::SameCurrency(from_currency));
+                }
+                from_currency.validate_amount(amount)?;
+                self.ensure_available(from_currency, amount)?;
+                let converted = amount
+                    .checked_mul(rate)
+                    .ok_or(CurrencyError::Overflow)?
+                    .round_dp_with_strategy(to_currency.exponent(), RoundingStrategy::ToZero);
+                // otherwise the debited funds would vanish
+                if converted.is_zero() {
+                    return Err(CurrencyError::AmountTooSmall(to_currency));
+                }
+                Ok(vec![
+                    event(from_currency, amount, CurrencyEventKind::Debited),
+                    event(to_currency, converted, CurrencyEventKind::Credited),
+                ])
+            }
+        }
+    }
+
+    fn ensure_available(&self, currency: Currency, amount: Decimal) -> Result<(), CurrencyError> {
+        if self.available(currency) >= amount {
+            Ok(())
+        } else {
+            Err(CurrencyError::InsufficientFunds(currency))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use super::*;
+
+    fn currency(code: &str) -> Currency {
+        code.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_currency() {
+        assert_eq!(currency("eur").to_string(), "EUR");
+        assert!("EURO".parse::<Currency>().is_err());
+        assert!("E1R".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn exchange() {
+        let (eur, usd) = (currency("EUR"), currency("USD"));
+        let mut acc = MultiCurrencyAccount::default();
+        for evt in acc
+            .handle_transaction(
+                1,
+                CurrencyTransactionKind::Deposit { currency: eur },
+                Decimal::from_u32(100).unwrap(),
+            )
+            .unwrap()
+        {
+            acc.apply(&evt);
+        }
+
+        let exchange = CurrencyTransactionKind::Exchange {
+            from_currency: eur,
+            to_currency: usd,
+            rate: Decimal::from_str_exact("1.1").unwrap(),
+        };
+        let events = acc
+            .handle_transaction(2, exchange, Decimal::from_u32(40).unwrap())
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, CurrencyEventKind::Debited);
+        assert_eq!(events[1].kind, CurrencyEventKind::Credited);
+        for evt in &events {
+            acc.apply(evt);
+        }
+        assert_eq!(acc.available(eur), Decimal::from_u32(60).unwrap());
+        assert_eq!(acc.available(usd), Decimal::from_u32(44).unwrap());
+
+        // not enough funds in source currency
+        let err = acc
+            .handle_transaction(3, exchange, Decimal::from_u32(61).unwrap())
+            .unwrap_err();
+        assert!(matches!(err, CurrencyError::InsufficientFunds(c) if c == eur));
+
+        let err = acc
+            .handle_transaction(
+                4,
+                CurrencyTransactionKind::Exchange {
+                    from_currency: eur,
+                    to_currency: eur,
+                    rate: Decimal::ONE,
+                },
+                Decimal::ONE,
+            )
+            .unwrap_err();
+        assert!(matches!(err, CurrencyError::SameCurrency(_)));
+    }
+
+    #[test]
+    fn amounts_fit_currency_exponent() {
+        let (jpy, bhd, eur) = (currency("JPY"), currency("BHD"), currency("EUR"));
+        assert_eq!((jpy.exponent(), bhd.exponent(), eur.exponent()), (0, 3, 2));
+        let mut acc = MultiCurrencyAccount::default();
+        let deposit = |currency| CurrencyTransactionKind::Deposit { currency };
+        let amount = |value: &str| Decimal::from_str_exact(value).unwrap();
+
+        let err = acc
+            .handle_transaction(1, deposit(jpy), amount("100.5"))
+            .unwrap_err();
+        assert!(
+            matches!(err, CurrencyError::ExcessPrecision { currency, scale: 1 } if currency == jpy)
+        );
+        // trailing zeros are not precision
+        assert!(
+            acc.handle_transaction(2, deposit(jpy), amount("100.00"))
+                .is_ok()
+        );
+        assert!(
+            acc.handle_transaction(3, deposit(bhd), amount("1.0001"))
+                .is_err()
+        );
+        for evt in acc
+            .handle_transaction(4, deposit(bhd), amount("1.001"))
+            .unwrap()
+        {
+            acc.apply(&evt);
+        }
+
+        let exchange = CurrencyTransactionKind::Exchange {
+            from_currency: bhd,
+            to_currency: eur,
+            rate: amount("2.4567"),
+        };
+        let events = acc
+            .handle_transaction(5, exchange, amount("1.001"))
+            .unwrap();
+        assert_eq!(events[1].amount, amount("2.45"));
+
+        for evt in acc
+            .handle_transaction(6, deposit(eur), amount("0.01"))
+            .unwrap()
+        {
+            acc.apply(&evt);
+        }
+        let exchange = CurrencyTransactionKind::Exchange {
+            from_currency: eur,
+            to_currency: currency("USD"),
+            rate: amount("0.1"),
+        };
+        let err = acc
+            .handle_transaction(7, exchange, amount("0.01"))
+            .unwrap_err();
+        assert!(matches!(err, CurrencyError::AmountTooSmall(c) if c.code() == "USD"));
+    }
+}
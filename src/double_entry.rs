@@ -0,0 +1,227 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    account::{AccountEvent, AccountEventKind},
+    event_bus::{AppliedEvent, EventSubscriber},
+};
+
+/// Internal accounts of the ledger, customer balances are aggregated over all clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LedgerAccount {
+    /// Funds held by us, asset
+    Cash,
+    /// Incoming funds that are not settled yet, asset
+    Suspense,
+    /// Liabilities to customers
+    CustomerAvailable,
+    CustomerHeld,
+    CustomerPending,
+    /// Funds paid back through card network
+    ChargebackExpense,
+    /// Operator corrections of customer balances
+    Adjustments,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posting {
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    pub amount: Decimal,
+}
+
+/// Postings for the event, each one moves the same amount, so debits always equal credits
+pub fn postings(event: &AccountEvent) -> Vec<Posting> {
+    use LedgerAccount::*;
+
+    let posting = |debit, credit| Posting {
+        debit,
+        credit,
+        amount: event.amount(),
+    };
+    match event.kind() {
+        AccountEventKind::Deposited => vec![posting(Cash, CustomerAvailable)],
+        AccountEventKind::Withdrawn => vec![posting(CustomerAvailable, Cash)],
+        AccountEventKind::Disputed | AccountEventKind::Authorized => {
+            vec![posting(CustomerAvailable, CustomerHeld)]
+        }
+        AccountEventKind::Resolved | AccountEventKind::Voided => {
+            vec![posting(CustomerHeld, CustomerAvailable)]
+        }
+        // paid out to card network, and recovered from customer held funds
+        AccountEventKind::Chargedback => vec![
+            posting(ChargebackExpense, Cash),
+            posting(CustomerHeld, ChargebackExpense),
+        ],
+        // recovered from card network, and returned to customer
+        AccountEventKind::Reinstated => vec![
+            posting(Cash, ChargebackExpense),
+            posting(ChargebackExpense, CustomerAvailable),
+        ],
+        AccountEventKind::DepositPending => vec![posting(Suspense, CustomerPending)],
+        AccountEventKind::Settled => vec![
+            posting(Cash, Suspense),
+            posting(CustomerPending, CustomerAvailable),
+        ],
+        AccountEventKind::Captured => vec![posting(CustomerHeld, Cash)],
+        AccountEventKind::OpeningBalance => vec![posting(Cash, CustomerAvailable)],
+        AccountEventKind::Locked | AccountEventKind::Unlocked | AccountEventKind::Erased => {
+            Vec::new()
+        }
+        // signed, while postings move positive amounts
+        AccountEventKind::Adjusted if event.amount() < Decimal::ZERO => vec![Posting {
+            debit: CustomerAvailable,
+            credit: Adjustments,
+            amount: -event.amount(),
+        }],
+        AccountEventKind::Adjusted => vec![posting(Adjustments, CustomerAvailable)],
+        // no funds move until the dispute is decided
+        AccountEventKind::Represented | AccountEventKind::PreArbitrated => Vec::new(),
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Trial balance doesn't match: debits {debit}, credits {credit}")]
+pub struct UnbalancedLedger {
+    pub debit: Decimal,
+    pub credit: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LedgerTotals {
+    pub debit: Decimal,
+    pub credit: Decimal,
+}
+
+impl LedgerTotals {
+    /// Debit minus credit
+    pub fn balance(&self) -> Decimal {
+        self.debit - self.credit
+    }
+}
+
+/// Double-entry book, where every applied event is posted
+#[derive(Debug, Default)]
+pub struct Ledger {
+    accounts: BTreeMap<LedgerAccount, LedgerTotals>,
+}
+
+impl EventSubscriber for Ledger {
+    fn on_event(&mut self, event: &AppliedEvent) {
+        self.post(event.event);
+    }
+}
+
+impl Ledger {
+    pub fn post(&mut self, event: &AccountEvent) {
+        for posting in postings(event) {
+            self.accounts.entry(posting.debit).or_default().debit += posting.amount;
+            self.accounts.entry(posting.credit).or_default().credit += posting.amount;
+        }
+    }
+
+    pub fn totals(&self, account: LedgerAccount) -> LedgerTotals {
+        self.accounts.get(&account).copied().unwrap_or_default()
+    }
+
+    /// Totals of every account, ensuring that debits equal credits
+    pub fn trial_balance(&self) -> Result<TrialBalance, UnbalancedLedger> {
+        let total = self
+            .accounts
+            .values()
+            .fold(LedgerTotals::default(), |acc, totals| LedgerTotals {
+                debit: acc.debit + totals.debit,
+                credit: acc.credit + totals.credit,
+            });
+        if total.debit != total.credit {
+            return Err(UnbalancedLedger {
+                debit: total.debit,
+                credit: total.credit,
+            });
+        }
+        Ok(TrialBalance {
+            accounts: self.accounts.clone(),
+            total,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrialBalance {
+    pub accounts: BTreeMap<LedgerAccount, LedgerTotals>,
+    pub total: LedgerTotals,
+}
+
+impl Display for TrialBalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<20} {:>16} {:>16}", "account", "debit", "credit")?;
+        for (account, totals) in &self.accounts {
+            writeln!(
+                f,
+                "{:<20} {:>16} {:>16}",
+                format!("{account:?}"),
+                totals.debit,
+                totals.credit
+            )?;
+        }
+        writeln!(
+            f,
+            "{:<20} {:>16} {:>16}",
+            "Total", self.total.debit, self.total.credit
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use crate::command::TransactionKind;
+    use crate::processor::{
+        TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor,
+    };
+
+    use super::*;
+
+    #[test]
+    fn trial_balance_matches_accounts() {
+        let mut processor = InMemoryTransactionProcessor::default().with_double_entry();
+        let amount = |n| Some(Decimal::from_u32(n).unwrap());
+        let rows = [
+            (1, 1, amount(10), TransactionKind::Deposit),
+            (2, 1, amount(3), TransactionKind::Withdrawal),
+            (3, 2, amount(5), TransactionKind::PendingDeposit),
+            (3, 2, None, TransactionKind::Settle),
+            (4, 2, amount(2), TransactionKind::Authorize),
+            (1, 1, None, TransactionKind::Dispute),
+            (1, 1, None, TransactionKind::Chargeback),
+        ];
+        for (tx, client, amount, kind) in rows {
+            processor
+                .process_transaction(tx, client, amount, kind)
+                .unwrap();
+        }
+        let ledger = processor.ledger().unwrap();
+        let trial_balance = ledger.trial_balance().unwrap();
+        assert_eq!(trial_balance.total.debit, trial_balance.total.credit);
+
+        // liabilities match balances of client accounts
+        let available: Decimal = processor.accounts().map(|(_, acc)| acc.available()).sum();
+        let held: Decimal = processor.accounts().map(|(_, acc)| acc.held()).sum();
+        assert_eq!(
+            -ledger.totals(LedgerAccount::CustomerAvailable).balance(),
+            available
+        );
+        assert_eq!(-ledger.totals(LedgerAccount::CustomerHeld).balance(), held);
+        assert_eq!(
+            ledger.totals(LedgerAccount::Suspense).balance(),
+            Decimal::ZERO
+        );
+        assert_eq!(
+            ledger.totals(LedgerAccount::Cash).balance(),
+            Decimal::from_u32(2).unwrap()
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{
+    account::{AccountError, ApplyError},
+    command::AccountCommandError,
+    money::MAX_SCALE,
+    processor::{TransactionProcessError, kyc::KycError},
+};
+
+/// Error kind with its parameters, independent of the English message:
+/// `code` is the same as returned by `code()` of the error, and `fields`
+/// hold values in machine-readable form (names of actions, plain decimals).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorData {
+    pub code: &'static str,
+    pub fields: BTreeMap<&'static str, String>,
+}
+
+impl ErrorData {
+    pub fn new(code: &'static str) -> Self {
+        Self {
+            code,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.fields.insert(name, value.to_string());
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+/// Errors, that expose their details as [`ErrorData`]
+pub trait ErrorDetails: Display {
+    fn error_data(&self) -> ErrorData;
+}
+
+/// Renders messages in other languages, `None` falls back to the English one
+pub trait Localize {
+    fn localize(&self, data: &ErrorData) -> Option<String>;
+}
+
+impl<F: Fn(&ErrorData) -> Option<String>> Localize for F {
+    fn localize(&self, data: &ErrorData) -> Option<String> {
+        self(data)
+    }
+}
+
+/// Message of the error, rendered by `localizer` when it knows the error
+pub fn localized(err: &impl ErrorDetails, localizer: &impl Localize) -> String {
+    localizer
+        .localize(&err.error_data())
+        .unwrap_or_else(|| err.to_string())
+}
+
+impl ErrorDetails for AccountError {
+    fn error_data(&self) -> ErrorData {
+        let data = ErrorData::new(self.code());
+        match self {
+            AccountError::AccountFrozen
+            | AccountError::InsufficientFunds
+            | AccountError::DisputeNotSupported
+            | AccountError::TransactionNotPending
+            | AccountError::TransactionNotChargedBack => data,
+            AccountError::TransactionDisputeStateMismatch {
+                action,
+                dispute_state_str,
+            } => data
+                .with("action", action.name())
+                // the only states reported by the account
+                .with(
+                    "under_dispute",
+                    dispute_state_str == "already under dispute",
+                ),
+            AccountError::TransactionNotSettled { action }
+            | AccountError::AuthorizationNotOpen { action } => data.with("action", action.name()),
+            AccountError::VersionMismatch { expected, actual } => {
+                data.with("expected", expected).with("actual", actual)
+            }
+            AccountError::BalanceCapExceeded { cap } => data.with("cap", cap),
+            AccountError::WithdrawalBelowMinimum { minimum } => data.with("minimum", minimum),
+            AccountError::WithdrawalDenominationMismatch { denomination } => {
+                data.with("denomination", denomination)
+            }
+        }
+    }
+}
+
+impl ErrorDetails for AccountCommandError {
+    fn error_data(&self) -> ErrorData {
+        let data = ErrorData::new(self.code());
+        match self {
+            AccountCommandError::AmountRequired { action }
+            | AccountCommandError::NegativeAmount { action }
+            | AccountCommandError::DuplicateTransaction { action }
+            | AccountCommandError::ZeroAmount { action }
+            | AccountCommandError::ZeroAmountSkipped { action } => {
+                data.with("action", action.name())
+            }
+            AccountCommandError::AmountTooPrecise { action, scale } => data
+                .with("action", action.name())
+                .with("scale", scale)
+                .with("max_scale", MAX_SCALE),
+            AccountCommandError::ExistingTxRequired { action }
+            | AccountCommandError::RepeatedModifySkipped { action }
+            | AccountCommandError::TransactionRetired { action } => {
+                data.with("action", action.name())
+            }
+            AccountCommandError::UnknownKind { kind } => data.with("kind", kind),
+            AccountCommandError::TxIdNotIncreasing { highest } => data.with("highest", highest),
+            AccountCommandError::ReferencedTxRejected { action, line } => {
+                data.with("action", action.name()).with("line", line)
+            }
+            AccountCommandError::LateEvent { watermark } => data.with("watermark", watermark),
+        }
+    }
+}
+
+impl ErrorDetails for ApplyError {
+    fn error_data(&self) -> ErrorData {
+        let data = ErrorData::new(self.code());
+        match self {
+            ApplyError::Overflow => data,
+            ApplyError::NegativeBalance { balance } => data.with("balance", balance),
+            ApplyError::TransactionStateMismatch { kind, tx_id } => {
+                data.with("kind", kind.name()).with("tx", tx_id)
+            }
+        }
+    }
+}
+
+impl ErrorDetails for KycError {
+    fn error_data(&self) -> ErrorData {
+        let data = ErrorData::new(self.code());
+        match self {
+            KycError::DepositCeiling { ceiling } | KycError::WithdrawalCeiling { ceiling } => {
+                data.with("ceiling", ceiling)
+            }
+            KycError::DepositLimit { limit } | KycError::WithdrawalLimit { limit } => {
+                data.with("limit", limit)
+            }
+        }
+    }
+}
+
+impl ErrorDetails for TransactionProcessError {
+    fn error_data(&self) -> ErrorData {
+        match self {
+            TransactionProcessError::CommandErr(err) => err.error_data(),
+            TransactionProcessError::AccountErr(err) => err.error_data(),
+            TransactionProcessError::ApplyErr(err) => err.error_data(),
+            TransactionProcessError::KycErr(err) => err.error_data(),
+            TransactionProcessError::StorageErr(reason) => {
+                ErrorData::new(self.code()).with("reason", reason)
+            }
+            TransactionProcessError::SignatureErr(reason) => {
+                ErrorData::new(self.code()).with("reason", reason)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::command::CreateTransactionAction;
+
+    use super::*;
+
+    #[test]
+    fn localizes_known_errors_only() {
+        let err = TransactionProcessError::from(AccountError::WithdrawalBelowMinimum {
+            minimum: Decimal::new(2000, 2),
+        });
+        let data = err.error_data();
+        assert_eq!(data.code, "withdrawal_below_minimum");
+        assert_eq!(data.field("minimum"), Some("20.00"));
+
+        let german = |data: &ErrorData| match data.code {
+            "withdrawal_below_minimum" => Some(format!(
+                "Auszahlung unter dem Minimum von {}",
+                data.field("minimum")?
+            )),
+            _ => None,
+        };
+        assert_eq!(
+            localized(&err, &german),
+            "Auszahlung unter dem Minimum von 20.00"
+        );
+
+        let err = TransactionProcessError::from(AccountCommandError::AmountTooPrecise {
+            action: CreateTransactionAction::Deposit,
+            scale: 6,
+        });
+        assert_eq!(
+            serde_json::to_string(&err.error_data()).unwrap(),
+            r#"{"code":"amount_too_precise","fields":{"action":"deposit","max_scale":"4","scale":"6"}}"#
+        );
+        assert_eq!(localized(&err, &german), err.to_string());
+    }
+}
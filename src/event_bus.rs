@@ -0,0 +1,129 @@
+use std::{any::Any, io};
+
+use crate::{
+    account::{Account, AccountEvent},
+    processor::ClientId,
+    projection::Projection,
+};
+
+/// Event, that was just applied to the account
+#[derive(Debug, Clone, Copy)]
+pub struct AppliedEvent<'a> {
+    pub client_id: ClientId,
+    pub event: &'a AccountEvent,
+    /// Account state after the event
+    pub account: &'a Account,
+    /// Origin of the transaction, if known
+    pub source: Option<&'a str>,
+    /// Trace id of the input record, e.g. to put into webhook payloads
+    pub trace_id: Option<&'a str>,
+}
+
+/// Receives every applied event exactly once, in the order events were applied
+pub trait EventSubscriber: Any {
+    fn on_event(&mut self, event: &AppliedEvent);
+
+    /// Writes out buffered output, failing with the first error of writing
+    /// any event, e.g. so a run doesn't succeed with events missing downstream
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<P: Projection> EventSubscriber for P {
+    fn on_event(&mut self, event: &AppliedEvent) {
+        self.apply(event.client_id, event.event, event.account);
+    }
+}
+
+/// Subscribers, queryable by their type. Events are delivered to them
+/// in the order they were subscribed.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Box<dyn EventSubscriber>>,
+}
+
+impl EventBus {
+    pub fn subscribe<S: EventSubscriber>(&mut self, subscriber: S) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// First subscriber of type `S`
+    pub fn get<S: EventSubscriber>(&self) -> Option<&S> {
+        self.subscribers
+            .iter()
+            .find_map(|subscriber| (subscriber.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub fn get_mut<S: EventSubscriber>(&mut self) -> Option<&mut S> {
+        self.subscribers
+            .iter_mut()
+            .find_map(|subscriber| (subscriber.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    pub fn publish(&mut self, event: &AppliedEvent) {
+        for subscriber in &mut self.subscribers {
+            subscriber.on_event(event);
+        }
+    }
+
+    /// Flushes all subscribers, and returns the first error
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for subscriber in &mut self.subscribers {
+            let flushed = subscriber.flush();
+            if result.is_ok() {
+                result = flushed;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::{account::AccountEventKind, projection::TotalHeld};
+
+    use super::*;
+
+    /// Collects clients of received events
+    #[derive(Default)]
+    struct Recorder(Vec<ClientId>);
+
+    impl EventSubscriber for Recorder {
+        fn on_event(&mut self, event: &AppliedEvent) {
+            self.0.push(event.client_id);
+        }
+    }
+
+    #[test]
+    fn delivers_events_to_every_subscriber() {
+        let mut bus = EventBus::default();
+        bus.subscribe(Recorder::default());
+        bus.subscribe(TotalHeld::default());
+        let account = Account::default();
+        for client_id in [3, 1] {
+            let event = AccountEvent::new(1, Decimal::ONE, AccountEventKind::Disputed);
+            bus.publish(&AppliedEvent {
+                client_id,
+                event: &event,
+                account: &account,
+                source: None,
+                trace_id: None,
+            });
+        }
+        assert_eq!(bus.len(), 2);
+        assert_eq!(bus.get::<Recorder>().unwrap().0, vec![3, 1]);
+        assert_eq!(bus.get::<TotalHeld>().unwrap().0, Decimal::TWO);
+    }
+}
@@ -0,0 +1,739 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    ops::Range,
+    str::FromStr,
+    sync::Arc,
+};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    account::{Account, AccountEvent, AccountEventKind, TransactionId},
+    event_bus::{AppliedEvent, EventSubscriber},
+    processor::ClientId,
+};
+
+/// Sequence number of applied event, unique across all clients
+pub type EventSeq = u64;
+
+/// Accounting period (e.g. business day), see [`EventHistory::start_period`]
+pub type PeriodId = u32;
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub seq: EventSeq,
+    pub client: ClientId,
+    pub event: AccountEvent,
+    /// Who submitted the transaction: API key id, file name, partner id
+    pub source: Option<Arc<str>>,
+    /// Period, in which the event was applied
+    pub period: PeriodId,
+}
+
+/// Append-only log of events, in the order they were applied
+#[derive(Debug, Default)]
+pub struct EventHistory {
+    entries: Vec<HistoryEntry>,
+    next_seq: EventSeq,
+    /// Distinct sources, shared by entries
+    sources: HashSet<Arc<str>>,
+    /// Period of newly recorded events
+    period: PeriodId,
+}
+
+impl EventSubscriber for EventHistory {
+    fn on_event(&mut self, event: &AppliedEvent) {
+        if event.event.kind() == AccountEventKind::Erased {
+            self.anonymize(event.client_id);
+        }
+        self.push_from(event.client_id, event.event.clone(), event.source);
+    }
+}
+
+impl EventHistory {
+    pub fn push(&mut self, client: ClientId, event: AccountEvent) -> EventSeq {
+        self.push_from(client, event, None)
+    }
+
+    /// Records event, attributed to the `source` of its transaction
+    pub fn push_from(
+        &mut self,
+        client: ClientId,
+        event: AccountEvent,
+        source: Option<&str>,
+    ) -> EventSeq {
+        let source = source.map(|source| match self.sources.get(source) {
+            Some(source) => source.clone(),
+            None => {
+                let source: Arc<str> = source.into();
+                self.sources.insert(source.clone());
+                source
+            }
+        });
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.entries.push(HistoryEntry {
+            seq,
+            client,
+            event,
+            source,
+            period: self.period,
+        });
+        seq
+    }
+
+    pub fn period(&self) -> PeriodId {
+        self.period
+    }
+
+    /// Sequence number of the next recorded event
+    pub fn next_seq(&self) -> EventSeq {
+        self.next_seq
+    }
+
+    /// Events recorded from now on belong to the next period
+    pub fn start_period(&mut self) -> PeriodId {
+        self.period += 1;
+        self.period
+    }
+
+    pub fn for_period(&self, period: PeriodId) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.period == period)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn for_client(&self, client: ClientId) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.client == client)
+    }
+
+    /// Collapses events with sequence numbers below `cutoff` into a single
+    /// opening balance per account. Events of still open items (disputes,
+    /// pending deposits and authorizations) are kept, and opening balance
+    /// excludes their effect, so replaying compacted history gives the same state.
+    pub fn compact(&mut self, cutoff: EventSeq) -> CompactionReport {
+        let split = self.entries.partition_point(|entry| entry.seq < cutoff);
+        let mut clients: BTreeMap<ClientId, ClientHistory> = BTreeMap::new();
+        for entry in self.entries.drain(..split) {
+            clients
+                .entry(entry.client)
+                .or_insert_with(|| ClientHistory::new(entry.seq, entry.period))
+                .apply(entry);
+        }
+
+        let events_before = split;
+        let mut compacted = Vec::new();
+        for (client, history) in clients {
+            compacted.extend(history.into_entries(client));
+        }
+        let report = CompactionReport {
+            events_before,
+            events_after: compacted.len(),
+        };
+        compacted.sort_by_key(|entry| entry.seq);
+        compacted.append(&mut self.entries);
+        self.entries = compacted;
+        report
+    }
+
+    /// Anonymizes history of the `client`: drops attribution of its events,
+    /// so no record links client to who submitted its transactions. Events
+    /// themselves are kept with their sequence numbers, amounts and kinds,
+    /// so balances, statements and closed periods stay the same.
+    /// Erasure is recorded in the log as [`AccountEventKind::Erased`] event,
+    /// see [`EventHistory::tombstones`].
+    pub fn erase_client(&mut self, client: ClientId) -> ErasureTombstone {
+        let events_anonymized = self.anonymize(client);
+        let seq = self.push(client, AccountEvent::erased());
+        ErasureTombstone {
+            client,
+            seq,
+            events_anonymized,
+        }
+    }
+
+    /// Drops attribution of client events, returns how many had one
+    fn anonymize(&mut self, client: ClientId) -> usize {
+        let mut events_anonymized = 0;
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.client == client)
+        {
+            if entry.source.take().is_some() {
+                events_anonymized += 1;
+            }
+        }
+        // sources, referenced only by this set, belonged to erased entries
+        self.sources.retain(|source| Arc::strong_count(source) > 1);
+        events_anonymized
+    }
+
+    /// Erasure events, in the order they were recorded
+    pub fn tombstones(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.event.kind() == AccountEventKind::Erased)
+    }
+
+    /// Replays client events from the very beginning, so opening balance
+    /// accounts for everything before `range.start`.
+    pub fn statement(&self, client: ClientId, range: Range<EventSeq>) -> Statement {
+        let mut account = Account::default();
+        let mut opening = None;
+        let mut lines = Vec::new();
+        for entry in self
+            .for_client(client)
+            .take_while(|entry| entry.seq < range.end)
+        {
+            if entry.seq >= range.start && opening.is_none() {
+                opening = Some(Balance::of(&account));
+            }
+            account.apply(&entry.event);
+            if entry.seq >= range.start {
+                lines.push(StatementLine {
+                    seq: entry.seq,
+                    tx: entry.event.transaction_id(),
+                    kind: entry.event.kind(),
+                    amount: entry.event.amount(),
+                    balance: Balance::of(&account),
+                    source: entry.source.clone(),
+                });
+            }
+        }
+        let closing = Balance::of(&account);
+        Statement {
+            client,
+            opening: opening.unwrap_or(closing),
+            lines,
+            closing,
+        }
+    }
+
+    /// Entries matching all conditions of the `filter`, in the order they were applied
+    pub fn query(&self, filter: &EventFilter) -> Vec<&HistoryEntry> {
+        let in_status = filter.status.map(|status| {
+            let mut in_status = HashSet::new();
+            for entry in &self.entries {
+                let key = (entry.client, entry.event.transaction_id());
+                match (status, entry.event.kind()) {
+                    (TxStatus::Disputed, AccountEventKind::Disputed)
+                    | (TxStatus::Chargedback, AccountEventKind::Chargedback) => {
+                        in_status.insert(key);
+                    }
+                    (TxStatus::Disputed, AccountEventKind::Resolved)
+                    | (TxStatus::Disputed, AccountEventKind::Chargedback)
+                    | (TxStatus::Chargedback, AccountEventKind::Reinstated) => {
+                        in_status.remove(&key);
+                    }
+                    _ => {}
+                }
+            }
+            in_status
+        });
+        self.entries
+            .iter()
+            .filter(|entry| {
+                let event = &entry.event;
+                filter.client.is_none_or(|client| entry.client == client)
+                    && filter.tx.is_none_or(|tx| event.transaction_id() == tx)
+                    && filter.kind.is_none_or(|kind| event.kind() == kind)
+                    && filter.min_amount.is_none_or(|min| event.amount() >= min)
+                    && in_status.as_ref().is_none_or(|in_status| {
+                        in_status.contains(&(entry.client, event.transaction_id()))
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Current state of a transaction, see [`EventFilter::status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    /// Dispute is open: neither resolved nor charged back yet
+    Disputed,
+    /// Charged back, and not reinstated since
+    Chargedback,
+}
+
+impl FromStr for TxStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disputed" => Ok(Self::Disputed),
+            "chargedback" => Ok(Self::Chargedback),
+            other => Err(format!("unknown transaction status `{other}`")),
+        }
+    }
+}
+
+/// Conditions on history entries, unset ones match everything
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub client: Option<ClientId>,
+    pub tx: Option<TransactionId>,
+    pub kind: Option<AccountEventKind>,
+    pub min_amount: Option<Decimal>,
+    /// Only events of transactions currently in this status
+    pub status: Option<TxStatus>,
+}
+
+/// Outcome of client erasure, whose event stays in the log after the
+/// client's data is anonymized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErasureTombstone {
+    pub client: ClientId,
+    /// Sequence number of the erasure event, events with lower ones were
+    /// recorded before erasure
+    pub seq: EventSeq,
+    /// Number of events, whose attribution was removed
+    pub events_anonymized: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub events_before: usize,
+    pub events_after: usize,
+}
+
+/// Compacted part of single client history
+struct ClientHistory {
+    first_seq: EventSeq,
+    first_period: PeriodId,
+    /// Event which froze the account
+    locked: Option<(EventSeq, PeriodId)>,
+    /// Event which unlocked the account, while its chargebacks are kept
+    unlocked: Option<(EventSeq, PeriodId)>,
+    account: Account,
+    /// Events that are still in effect, by transaction
+    open: HashMap<TransactionId, HistoryEntry>,
+    /// Dispute and chargeback of transactions, that can still be reinstated
+    chargedback: HashMap<TransactionId, [HistoryEntry; 2]>,
+    /// Representment and pre-arbitration of open disputes
+    dispute_stages: HashMap<TransactionId, Vec<HistoryEntry>>,
+    /// Erasures of the client, kept as a record that they happened
+    erased: Vec<HistoryEntry>,
+}
+
+impl ClientHistory {
+    fn new(first_seq: EventSeq, first_period: PeriodId) -> Self {
+        Self {
+            first_seq,
+            first_period,
+            locked: None,
+            unlocked: None,
+            account: Account::default(),
+            open: HashMap::new(),
+            chargedback: HashMap::new(),
+            dispute_stages: HashMap::new(),
+            erased: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, entry: HistoryEntry) {
+        self.account.apply(&entry.event);
+        let tx_id = entry.event.transaction_id();
+        match entry.event.kind() {
+            AccountEventKind::Disputed
+            | AccountEventKind::DepositPending
+            | AccountEventKind::Authorized => {
+                self.open.insert(tx_id, entry);
+            }
+            AccountEventKind::Represented | AccountEventKind::PreArbitrated => {
+                self.dispute_stages.entry(tx_id).or_default().push(entry);
+            }
+            // replaying kept chargeback locks the account again
+            AccountEventKind::Chargedback => match self.open.remove(&tx_id) {
+                Some(disputed) => {
+                    self.dispute_stages.remove(&tx_id);
+                    self.chargedback.insert(tx_id, [disputed, entry]);
+                }
+                None => {
+                    self.locked.get_or_insert((entry.seq, entry.period));
+                }
+            },
+            AccountEventKind::Reinstated => {
+                self.chargedback.remove(&tx_id);
+                if !self.account.locked() {
+                    self.locked = None;
+                }
+            }
+            AccountEventKind::Locked => {
+                self.locked.get_or_insert((entry.seq, entry.period));
+            }
+            AccountEventKind::Unlocked => {
+                self.locked = None;
+                self.unlocked = Some((entry.seq, entry.period));
+            }
+            AccountEventKind::Erased => self.erased.push(entry),
+            AccountEventKind::Resolved
+            | AccountEventKind::Settled
+            | AccountEventKind::Captured
+            | AccountEventKind::Voided => {
+                self.open.remove(&tx_id);
+                self.dispute_stages.remove(&tx_id);
+            }
+            AccountEventKind::Deposited
+            | AccountEventKind::Withdrawn
+            | AccountEventKind::OpeningBalance
+            | AccountEventKind::Adjusted => {}
+        }
+        if self.account.locked() {
+            self.unlocked = None;
+        }
+    }
+
+    fn into_entries(self, client: ClientId) -> Vec<HistoryEntry> {
+        // replayed chargebacks lock the account again
+        let unlocked = self.unlocked.filter(|_| !self.chargedback.is_empty());
+        // held and pending funds consist only of open items, which are replayed
+        let mut available = self.account.available();
+        for entry in self.open.values() {
+            if let AccountEventKind::Disputed | AccountEventKind::Authorized = entry.event.kind() {
+                available += entry.event.amount();
+            }
+        }
+        for [disputed, _] in self.chargedback.values() {
+            available += disputed.event.amount();
+        }
+        let mut entries = vec![HistoryEntry {
+            seq: self.first_seq,
+            client,
+            event: AccountEvent::opening_balance(available),
+            source: None,
+            period: self.first_period,
+        }];
+        entries.extend(self.open.into_values());
+        entries.extend(self.chargedback.into_values().flatten());
+        entries.extend(self.dispute_stages.into_values().flatten());
+        entries.extend(self.erased);
+        if let Some((seq, period)) = self.locked {
+            entries.push(HistoryEntry {
+                seq,
+                client,
+                event: AccountEvent::locked(),
+                source: None,
+                period,
+            });
+        }
+        if let Some((seq, period)) = unlocked {
+            entries.push(HistoryEntry {
+                seq,
+                client,
+                event: AccountEvent::unlocked(),
+                source: None,
+                period,
+            });
+        }
+        entries
+    }
+}
+
+/// Balances of the account at some point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub pending: Decimal,
+    pub locked: bool,
+}
+
+impl Balance {
+    pub fn of(account: &Account) -> Self {
+        Self {
+            available: account.available(),
+            held: account.held(),
+            pending: account.pending(),
+            locked: account.locked(),
+        }
+    }
+
+    pub fn total(&self) -> Decimal {
+        self.available + self.held
+    }
+}
+
+impl Display for Balance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "available {}, held {}, pending {}, locked {}",
+            self.available, self.held, self.pending, self.locked
+        )
+    }
+}
+
+/// Finalized balances of a closed period, carried over to the next one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodClose {
+    pub period: PeriodId,
+    /// Events applied during the period
+    pub events: u64,
+    pub balances: BTreeMap<ClientId, Balance>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatementLine {
+    pub seq: EventSeq,
+    pub tx: TransactionId,
+    pub kind: AccountEventKind,
+    pub amount: Decimal,
+    /// Running balance, after the event was applied
+    pub balance: Balance,
+    pub source: Option<Arc<str>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub client: ClientId,
+    pub opening: Balance,
+    pub lines: Vec<StatementLine>,
+    pub closing: Balance,
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use crate::{
+        assert_account,
+        command::{CreateTransactionAction, CreateTransactionCommand, TransactionKind},
+        money::Money,
+        processor::{TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor},
+    };
+
+    use super::*;
+
+    fn deposit(history: &mut EventHistory, client: ClientId, tx_id: TransactionId, amount: u32) {
+        let event = Account::default()
+            .handle_create_transaction(CreateTransactionCommand {
+                tx_id,
+                action: CreateTransactionAction::Deposit,
+                amount: Money::from(amount),
+            })
+            .unwrap();
+        history.push(client, event);
+    }
+
+    #[test]
+    fn erased_client_keeps_balance_without_sources() {
+        let mut history = EventHistory::default();
+        let event = |tx_id, amount: u32| {
+            Account::default()
+                .handle_create_transaction(CreateTransactionCommand {
+                    tx_id,
+                    action: CreateTransactionAction::Deposit,
+                    amount: Money::from(amount),
+                })
+                .unwrap()
+        };
+        history.push_from(1, event(1, 10), Some("alice.csv"));
+        history.push_from(2, event(2, 100), Some("shared.csv"));
+        history.push_from(1, event(3, 5), Some("shared.csv"));
+        let before = history.statement(1, 0..EventSeq::MAX).closing;
+
+        let tombstone = history.erase_client(1);
+        assert_eq!(
+            tombstone,
+            ErasureTombstone {
+                client: 1,
+                seq: 3,
+                events_anonymized: 2,
+            }
+        );
+        let seqs: Vec<_> = history.tombstones().map(|entry| entry.seq).collect();
+        assert_eq!(seqs, [3]);
+        assert!(history.for_client(1).all(|entry| entry.source.is_none()));
+        assert_eq!(
+            history.for_client(2).next().unwrap().source.as_deref(),
+            Some("shared.csv")
+        );
+        assert_eq!(history.sources.len(), 1);
+
+        let after = history.statement(1, 0..EventSeq::MAX);
+        assert_eq!(after.closing, before);
+        assert_eq!(
+            after.lines.iter().map(|line| line.seq).collect::<Vec<_>>(),
+            [0, 2, 3]
+        );
+
+        // erasure stays recorded after compaction
+        history.compact(EventSeq::MAX);
+        let seqs: Vec<_> = history.tombstones().map(|entry| entry.seq).collect();
+        assert_eq!(seqs, [3]);
+        assert_eq!(history.statement(1, 0..EventSeq::MAX).closing, before);
+    }
+
+    #[test]
+    fn statement_with_running_balance() {
+        let mut history = EventHistory::default();
+        deposit(&mut history, 1, 1, 10);
+        deposit(&mut history, 2, 2, 100);
+        deposit(&mut history, 1, 3, 5);
+        deposit(&mut history, 1, 4, 1);
+
+        let statement = history.statement(1, 1..3);
+        assert_eq!(statement.opening.available, Decimal::from_u32(10).unwrap());
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].tx, 3);
+        assert_eq!(
+            statement.lines[0].balance.available,
+            Decimal::from_u32(15).unwrap()
+        );
+        assert_eq!(statement.closing.available, Decimal::from_u32(15).unwrap());
+
+        let statement = history.statement(1, 0..EventSeq::MAX);
+        assert_eq!(statement.opening, Balance::default());
+        assert_eq!(statement.lines.len(), 3);
+        assert_eq!(statement.closing.total(), Decimal::from_u32(16).unwrap());
+
+        // no events in range, opening and closing are the same
+        let statement = history.statement(1, 4..10);
+        assert!(statement.lines.is_empty());
+        assert_eq!(statement.opening, statement.closing);
+        assert_eq!(statement.closing.available, Decimal::from_u32(16).unwrap());
+    }
+    #[test]
+    fn query_by_status_and_amount() {
+        let mut processor = InMemoryTransactionProcessor::default().with_history();
+        let amount = |n| Some(Decimal::from_u32(n).unwrap());
+        let rows = [
+            (1, 1, amount(10), TransactionKind::Deposit),
+            (2, 1, amount(5), TransactionKind::Deposit),
+            (3, 2, amount(20), TransactionKind::Deposit),
+            (1, 1, None, TransactionKind::Dispute),
+            (2, 1, None, TransactionKind::Dispute),
+            (2, 1, None, TransactionKind::Resolve),
+        ];
+        for (tx, client, amount, kind) in rows {
+            processor
+                .process_transaction(tx, client, amount, kind)
+                .unwrap();
+        }
+        let history = processor.history().unwrap();
+        let query = |filter| {
+            history
+                .query(&filter)
+                .iter()
+                .map(|entry| (entry.event.transaction_id(), entry.event.kind()))
+                .collect::<Vec<_>>()
+        };
+        let disputed = EventFilter {
+            status: Some(TxStatus::Disputed),
+            ..Default::default()
+        };
+        assert_eq!(
+            query(disputed),
+            [
+                (1, AccountEventKind::Deposited),
+                (1, AccountEventKind::Disputed)
+            ]
+        );
+        let large_deposits = EventFilter {
+            kind: Some(AccountEventKind::Deposited),
+            min_amount: amount(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            query(large_deposits),
+            [
+                (1, AccountEventKind::Deposited),
+                (3, AccountEventKind::Deposited)
+            ]
+        );
+        let client = EventFilter {
+            client: Some(1),
+            tx: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(query(client).len(), 3);
+    }
+
+    #[test]
+    fn compaction_preserves_state() {
+        let mut processor = InMemoryTransactionProcessor::default().with_history();
+        let amount = |n| Some(Decimal::from_u32(n).unwrap());
+        let rows = [
+            (1, 1, amount(10), TransactionKind::Deposit),
+            (2, 1, amount(5), TransactionKind::Deposit),
+            (3, 1, amount(3), TransactionKind::Withdrawal),
+            (2, 1, None, TransactionKind::Dispute),
+            (4, 1, amount(2), TransactionKind::Authorize),
+            (5, 1, amount(7), TransactionKind::PendingDeposit),
+            (6, 2, amount(4), TransactionKind::Deposit),
+            (6, 2, None, TransactionKind::Dispute),
+            (6, 2, None, TransactionKind::Chargeback),
+            (7, 1, amount(1), TransactionKind::Deposit),
+        ];
+        for (tx, client, amount, kind) in rows {
+            processor
+                .process_transaction(tx, client, amount, kind)
+                .unwrap();
+        }
+        let report = processor.compact_history(9).unwrap();
+        assert_eq!(report.events_before, 9);
+        // opening balances of both clients, 3 open items, and dispute
+        // with chargeback, which can still be reinstated
+        assert_eq!(report.events_after, 7);
+        assert_eq!(processor.history().unwrap().len(), 8);
+
+        let replay = |history: &EventHistory| {
+            let mut replayed: HashMap<ClientId, Account> = HashMap::new();
+            for entry in history.iter() {
+                replayed
+                    .entry(entry.client)
+                    .or_default()
+                    .apply(&entry.event);
+            }
+            replayed
+        };
+        let replayed = replay(processor.history().unwrap());
+        for (client, acc) in processor.accounts() {
+            assert_eq!(Balance::of(&replayed[&client]), Balance::of(acc));
+        }
+
+        // compacted items can still be modified, and new events get fresh seq
+        processor
+            .process_transaction(2, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        processor.compact_history(EventSeq::MAX).unwrap();
+        processor
+            .process_transaction(8, 1, amount(1), TransactionKind::Deposit)
+            .unwrap();
+        let history = processor.history().unwrap();
+        assert_eq!(history.iter().last().unwrap().seq, 11);
+        let replayed = replay(history);
+        assert_eq!(
+            Balance::of(&replayed[&1]),
+            Balance::of(processor.account(1).unwrap())
+        );
+
+        // chargeback survives compactions, so it can be reinstated
+        processor
+            .process_transaction(6, 2, None, TransactionKind::Reinstate)
+            .unwrap();
+        processor.compact_history(EventSeq::MAX).unwrap();
+        let replayed = replay(processor.history().unwrap());
+        assert_eq!(
+            Balance::of(&replayed[&2]),
+            Balance::of(processor.account(2).unwrap())
+        );
+        assert_account!(replayed[&2], available: 4, locked: false);
+    }
+}
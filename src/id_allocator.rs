@@ -0,0 +1,139 @@
+use std::ops::RangeInclusive;
+
+use roaring::RoaringBitmap;
+use sha2::{Digest, Sha256};
+
+use crate::account::TransactionId;
+
+/// First tx id of the default reserved range.
+/// Upper range of ids is assumed to be never used by input files.
+pub const DEFAULT_FIRST_TX_ID: TransactionId = 0xF000_0000;
+
+/// Source of tx ids for synthetic transactions (expanded standing orders,
+/// fees, adjustments), which must not collide with ids of input transactions.
+pub trait IdAllocator {
+    /// Next unused id, `None` when ids are exhausted
+    fn allocate(&mut self) -> Option<TransactionId>;
+}
+
+/// Embedders can allocate ids with a closure, e.g. from their own sequence
+impl<F> IdAllocator for F
+where
+    F: FnMut() -> Option<TransactionId>,
+{
+    fn allocate(&mut self) -> Option<TransactionId> {
+        self()
+    }
+}
+
+/// Sequential ids from a reserved range
+#[derive(Debug, Clone)]
+pub struct RangeAllocator {
+    next: Option<TransactionId>,
+    last: TransactionId,
+}
+
+impl RangeAllocator {
+    pub fn new(range: RangeInclusive<TransactionId>) -> Self {
+        let (first, last) = range.into_inner();
+        Self {
+            next: (first <= last).then_some(first),
+            last,
+        }
+    }
+
+    /// Ids from `first` up to the largest one
+    pub fn starting_at(first: TransactionId) -> Self {
+        Self::new(first..=TransactionId::MAX)
+    }
+}
+
+impl Default for RangeAllocator {
+    fn default() -> Self {
+        Self::starting_at(DEFAULT_FIRST_TX_ID)
+    }
+}
+
+impl IdAllocator for RangeAllocator {
+    fn allocate(&mut self) -> Option<TransactionId> {
+        let id = self.next?;
+        self.next = (id < self.last).then(|| id + 1);
+        Some(id)
+    }
+}
+
+/// UUID-like ids: each one is derived from a hash of the namespace
+/// (e.g. instance or run id) and a counter, spread over a reserved range.
+/// Allocators with different namespaces rarely collide, though with 32-bit
+/// tx ids that is not guaranteed, while ids of a single allocator never repeat.
+#[derive(Debug, Clone)]
+pub struct HashedAllocator {
+    namespace: String,
+    counter: u64,
+    first: TransactionId,
+    len: u64,
+    issued: RoaringBitmap,
+}
+
+impl HashedAllocator {
+    pub fn new(namespace: impl Into<String>, range: RangeInclusive<TransactionId>) -> Self {
+        let (first, last) = range.into_inner();
+        Self {
+            namespace: namespace.into(),
+            counter: 0,
+            first,
+            len: (last as u64 + 1).saturating_sub(first as u64),
+            issued: RoaringBitmap::new(),
+        }
+    }
+}
+
+impl IdAllocator for HashedAllocator {
+    fn allocate(&mut self) -> Option<TransactionId> {
+        if self.issued.len() >= self.len {
+            return None;
+        }
+        loop {
+            let hash = Sha256::new()
+                .chain_update(self.namespace.as_bytes())
+                .chain_update(self.counter.to_le_bytes())
+                .finalize();
+            self.counter += 1;
+            let hash = u64::from_le_bytes(hash[..8].try_into().expect("8 bytes"));
+            let id = self.first + (hash % self.len) as TransactionId;
+            if self.issued.insert(id) {
+                return Some(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn allocators_stay_in_range_and_never_repeat() {
+        let mut range = RangeAllocator::new(10..=12);
+        let ids: Vec<_> = std::iter::from_fn(|| range.allocate()).collect();
+        assert_eq!(ids, [10, 11, 12]);
+        assert_eq!(
+            RangeAllocator::starting_at(u32::MAX).allocate(),
+            Some(u32::MAX)
+        );
+
+        let mut hashed = HashedAllocator::new("run-1", 100..=163);
+        let ids: HashSet<_> = std::iter::from_fn(|| hashed.allocate()).collect();
+        assert_eq!(ids, (100..=163).collect());
+
+        // the same namespace always produces the same ids
+        let ids = |namespace| {
+            let mut hashed = HashedAllocator::new(namespace, DEFAULT_FIRST_TX_ID..=u32::MAX);
+            [(); 3].map(|_| hashed.allocate().unwrap())
+        };
+        assert_eq!(ids("run-1"), ids("run-1"));
+        assert_ne!(ids("run-1"), ids("run-2"));
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+
+use crate::{
+    bin_utils::{
+        OutputFormat, TransactionSource,
+        csv_parser::{CsvTransactionParser, Transaction},
+        csv_printer::{self, Account},
+    },
+    processor::{
+        TransactionOutcome, TransactionProcessError, TransactionProcessor,
+        in_memory_processor::InMemoryTransactionProcessor,
+    },
+};
+
+/// Single entry point for embedders: feed transactions in, get the accounts
+/// report out. Processor can be configured beforehand and is available
+/// for anything more involved, see [`Ledger::processor`].
+///
+/// ```
+/// use cute_ledger::{Ledger, bin_utils::OutputFormat};
+///
+/// let mut ledger = Ledger::new();
+/// let rejected = ledger
+///     .ingest_csv("type,client,tx,amount\ndeposit,1,1,2.5\nwithdrawal,1,2,5\n".as_bytes())
+///     .unwrap();
+/// assert_eq!(rejected.len(), 1);
+///
+/// let mut report = Vec::new();
+/// ledger.report(&mut report, OutputFormat::Csv).unwrap();
+/// assert_eq!(
+///     String::from_utf8(report).unwrap(),
+///     "client,available,held,total,locked,pending\n1,2.5,0,2.5,false,0\n"
+/// );
+/// ```
+pub struct Ledger<P = InMemoryTransactionProcessor> {
+    processor: P,
+    /// Origin recorded for transactions without one, see [`Transaction::source`]
+    source: Option<String>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::with_processor(InMemoryTransactionProcessor::default())
+    }
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: TransactionProcessor> Ledger<P> {
+    pub fn with_processor(processor: P) -> Self {
+        Self {
+            processor,
+            source: None,
+        }
+    }
+
+    /// Records `source` as origin of transactions, which don't have their own
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Processes all rows of CSV with `type,client,tx,amount` columns,
+    /// and returns rejected rows with their line numbers. Fails at the
+    /// first row that can't be parsed, rows before it stay processed.
+    pub fn ingest_csv(&mut self, reader: impl Read) -> Result<Vec<(u64, TransactionProcessError)>> {
+        let mut rejected = Vec::new();
+        let mut parser = CsvTransactionParser::new(reader);
+        for (line, tx) in &mut parser {
+            if let Err(err) = self.submit(tx) {
+                rejected.push((line, err));
+            }
+        }
+        if let Some(invalid) = parser.error() {
+            return Err(invalid.clone().into());
+        }
+        Ok(rejected)
+    }
+
+    pub fn submit(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let source = tx.source.as_deref().or(self.source.as_deref());
+        self.processor.process_transaction_traced(
+            tx.tx,
+            tx.client,
+            tx.amount,
+            tx.kind,
+            source,
+            tx.trace_id.as_deref(),
+        )
+    }
+
+    /// Writes balances of all accounts, ordered by client id
+    pub fn report(&self, writer: &mut impl Write, format: OutputFormat) -> Result<()> {
+        let mut accounts: Vec<_> = self
+            .processor
+            .accounts()
+            .map(|(client, acc)| Account::of(client, acc))
+            .collect();
+        accounts.sort_by_key(|acc| acc.client);
+        match format {
+            OutputFormat::Csv => csv_printer::print_accounts(writer, accounts.into_iter()),
+            #[cfg(feature = "xlsx")]
+            OutputFormat::Xlsx => crate::bin_utils::xlsx_printer::print_accounts_xlsx(
+                writer,
+                accounts.into_iter(),
+                &Default::default(),
+                true,
+            ),
+        }
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.processor
+    }
+
+    pub fn into_processor(self) -> P {
+        self.processor
+    }
+}
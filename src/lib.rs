@@ -2,6 +2,12 @@
 /// State is modified using events, which are created by handling commands
 pub mod account;
 
+/// Non-negative, scale-checked amount used by commands.
+pub mod money;
+
+/// Allocation of tx ids for synthetic transactions, that don't collide with input ids.
+pub mod id_allocator;
+
 /// Create account commands that later is executed by [`account`].
 pub mod command;
 
@@ -13,6 +19,39 @@ pub mod command;
 /// something more sophisticated.
 pub mod processor;
 
+/// Multi-currency account model, where each currency has its own balance
+/// and funds can be exchanged between currencies.
+#[cfg(feature = "multi-currency")]
+pub mod currency;
+
+/// Ordered history of applied events, and per-client statements built from it.
+pub mod history;
+
+/// Optional double-entry bookkeeping of applied events, with trial balance.
+pub mod double_entry;
+
+/// Read models, incrementally updated from applied events, so they can be
+/// queried during processing without scanning all accounts.
+pub mod projection;
+
+/// Bus delivering applied events to subscribers: event history, ledger,
+/// projections, and whatever else wants to observe the processor.
+pub mod event_bus;
+
+/// Error details as structured data, so messages can be localized.
+pub mod error_data;
+
+/// Per-stage processing timings, to guide optimization.
+pub mod stats;
+
+/// Corpus of tricky inputs with expected outputs, to check equivalence
+/// of alternative processor implementations.
+pub mod conformance;
+
+/// High-level facade over processor and reporters, for casual embedders.
+pub mod ledger;
+pub use ledger::Ledger;
+
 /// Ideally, this module should exists on its own crate, as a way to
 /// bootstrap core logic. However, I want to use it for integration test
 /// so I put it here.
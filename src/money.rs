@@ -0,0 +1,133 @@
+use std::fmt::Display;
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+#[cfg(feature = "multi-currency")]
+use crate::currency::Currency;
+
+/// Amounts are kept with at most this many decimal places
+pub const MAX_SCALE: u32 = 4;
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum MoneyError {
+    #[error("Amount must not be negative")]
+    Negative,
+    #[error("Amount has {scale} decimal places, at most {MAX_SCALE} are allowed")]
+    TooPrecise { scale: u32 },
+}
+
+/// Non-negative amount with at most [`MAX_SCALE`] decimal places.
+/// With `multi-currency` feature it can be tagged with its currency,
+/// untagged amount is in the account's base currency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Money {
+    amount: Decimal,
+    #[cfg(feature = "multi-currency")]
+    currency: Option<Currency>,
+}
+
+impl Money {
+    pub const ZERO: Money = Money {
+        amount: Decimal::ZERO,
+        #[cfg(feature = "multi-currency")]
+        currency: None,
+    };
+
+    pub fn new(amount: Decimal) -> Result<Self, MoneyError> {
+        if amount < Decimal::ZERO {
+            return Err(MoneyError::Negative);
+        }
+        let scale = amount.normalize().scale();
+        if scale > MAX_SCALE {
+            return Err(MoneyError::TooPrecise { scale });
+        }
+        Ok(Self {
+            amount,
+            #[cfg(feature = "multi-currency")]
+            currency: None,
+        })
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    #[cfg(feature = "multi-currency")]
+    pub fn with_currency(self, currency: Currency) -> Self {
+        Self {
+            currency: Some(currency),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "multi-currency")]
+    pub fn currency(&self) -> Option<Currency> {
+        self.currency
+    }
+}
+
+impl From<u32> for Money {
+    fn from(amount: u32) -> Self {
+        Self {
+            amount: Decimal::from(amount),
+            #[cfg(feature = "multi-currency")]
+            currency: None,
+        }
+    }
+}
+
+impl TryFrom<Decimal> for Money {
+    type Error = MoneyError;
+
+    fn try_from(amount: Decimal) -> Result<Self, Self::Error> {
+        Self::new(amount)
+    }
+}
+
+impl From<Money> for Decimal {
+    fn from(money: Money) -> Self {
+        money.amount
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.amount)?;
+        #[cfg(feature = "multi-currency")]
+        if let Some(currency) = self.currency {
+            write!(f, " {currency}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_sign_and_scale() {
+        assert_eq!(
+            Money::new(Decimal::new(15, 1)).unwrap().amount(),
+            Decimal::new(15, 1)
+        );
+        // trailing zeros don't count
+        assert!(Money::new(Decimal::new(1_000_000, 6)).is_ok());
+        assert_eq!(Money::new(Decimal::NEGATIVE_ONE), Err(MoneyError::Negative));
+        assert_eq!(
+            Money::new(Decimal::new(1, 5)),
+            Err(MoneyError::TooPrecise { scale: 5 })
+        );
+    }
+
+    #[cfg(feature = "multi-currency")]
+    #[test]
+    fn displays_currency_tag() {
+        let money = Money::from(5);
+        assert_eq!(money.currency(), None);
+        assert_eq!(money.to_string(), "5");
+        let money = money.with_currency("eur".parse().unwrap());
+        assert_eq!(money.to_string(), "5 EUR");
+    }
+}
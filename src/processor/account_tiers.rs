@@ -0,0 +1,205 @@
+//! Cold/warm tiering of accounts. Long-tail client populations are mostly
+//! idle, so accounts not touched for a while are spilled from the hot map to
+//! a side table of compactly encoded accounts, and promoted back on their
+//! next transaction. Cold accounts read through `&self` are decoded into
+//! a cache, dropped on the next spill.
+
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, hash_map::Entry},
+};
+
+use crate::account::Account;
+
+use super::ClientId;
+
+/// Accounts idle for this many processed transactions are spilled to the
+/// cold tier, see [`super::in_memory_processor::InMemoryTransactionProcessor::with_tiering`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TieringPolicy {
+    pub idle_after: u64,
+}
+
+/// Sizes of account tiers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TierStats {
+    pub hot: usize,
+    pub cold: usize,
+    /// Encoded size of cold accounts
+    pub cold_bytes: usize,
+    /// Cold accounts decoded for reading since the last spill
+    pub thawed: usize,
+}
+
+#[derive(Debug)]
+struct ColdAccount {
+    bytes: Box<[u8]>,
+    thawed: OnceCell<Box<Account>>,
+}
+
+impl ColdAccount {
+    fn account(&self) -> &Account {
+        self.thawed.get_or_init(|| {
+            Box::new(Account::decode(&self.bytes).expect("cold accounts are encoded by us"))
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AccountTiers {
+    policy: TieringPolicy,
+    /// Processed transactions, the clock of idleness
+    ticks: u64,
+    /// Tick of the last transaction of each hot account
+    last_access: HashMap<ClientId, u64>,
+    cold: HashMap<ClientId, ColdAccount>,
+}
+
+impl AccountTiers {
+    pub fn new(policy: TieringPolicy) -> Self {
+        Self {
+            policy: TieringPolicy {
+                idle_after: policy.idle_after.max(1),
+            },
+            ticks: 0,
+            last_access: HashMap::new(),
+            cold: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, client_id: ClientId) -> Option<&Account> {
+        self.cold.get(&client_id).map(ColdAccount::account)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ClientId, &Account)> {
+        self.cold
+            .iter()
+            .map(|(client_id, cold)| (*client_id, cold.account()))
+    }
+
+    /// Moves cold account of the client to `hot` accounts, if it is cold
+    pub fn promote(&mut self, client_id: ClientId, hot: &mut HashMap<ClientId, Account>) {
+        if let Some(cold) = self.cold.remove(&client_id) {
+            let acc = match cold.thawed.into_inner() {
+                Some(acc) => *acc,
+                None => Account::decode(&cold.bytes).expect("cold accounts are encoded by us"),
+            };
+            hot.insert(client_id, acc);
+        }
+    }
+
+    /// Moves all cold accounts to `hot` accounts
+    pub fn promote_all(&mut self, hot: &mut HashMap<ClientId, Account>) {
+        let clients: Vec<_> = self.cold.keys().copied().collect();
+        for client_id in clients {
+            self.promote(client_id, hot);
+        }
+    }
+
+    /// Records a transaction of the client. Every `idle_after` transactions
+    /// hot accounts idle since the previous spill are spilled, unless `spill`
+    /// is false, e.g. while changes are journaled.
+    pub fn touch(
+        &mut self,
+        client_id: ClientId,
+        hot: &mut HashMap<ClientId, Account>,
+        spill: bool,
+    ) {
+        self.ticks += 1;
+        if hot.contains_key(&client_id) {
+            self.last_access.insert(client_id, self.ticks);
+        }
+        if spill && self.ticks.is_multiple_of(self.policy.idle_after) {
+            self.spill(hot);
+        }
+    }
+
+    fn spill(&mut self, hot: &mut HashMap<ClientId, Account>) {
+        let idle_since = self.ticks.saturating_sub(self.policy.idle_after);
+        hot.retain(|client_id, acc| {
+            let entry = self.last_access.entry(*client_id);
+            if let Entry::Occupied(entry) = &entry
+                && *entry.get() > idle_since
+            {
+                return true;
+            }
+            // accounts created past the tiers, e.g. by merge, start idle now
+            if let Entry::Vacant(entry) = entry {
+                entry.insert(self.ticks);
+                return true;
+            }
+            self.last_access.remove(client_id);
+            self.cold.insert(
+                *client_id,
+                ColdAccount {
+                    bytes: acc.encode().into_boxed_slice(),
+                    thawed: OnceCell::new(),
+                },
+            );
+            false
+        });
+        for cold in self.cold.values_mut() {
+            cold.thawed.take();
+        }
+    }
+
+    pub fn stats(&self, hot: &HashMap<ClientId, Account>) -> TierStats {
+        TierStats {
+            hot: hot.len(),
+            cold: self.cold.len(),
+            cold_bytes: self.cold.values().map(|cold| cold.bytes.len()).sum(),
+            thawed: self
+                .cold
+                .values()
+                .filter(|cold| cold.thawed.get().is_some())
+                .count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        account::{AccountEvent, AccountEventKind},
+        assert_account,
+    };
+
+    use super::*;
+
+    #[test]
+    fn idle_accounts_are_spilled_and_promoted() {
+        let mut acc = Account::default();
+        acc.apply(&AccountEvent::new(
+            1,
+            10.into(),
+            AccountEventKind::Deposited,
+        ));
+        acc.apply(&AccountEvent::new(1, 10.into(), AccountEventKind::Disputed));
+        acc.set_extension("tier", "gold".into());
+        let case = acc.dispute_case_mut(1).unwrap();
+        case.assignee = Some("alice".to_string());
+        case.notes.push("called cardholder".to_string());
+        let decoded = Account::decode(&acc.encode()).unwrap();
+        assert_eq!(decoded.snapshot(), acc.snapshot());
+        assert_eq!(decoded.version(), acc.version());
+        assert_eq!(decoded.dispute_case(1), acc.dispute_case(1));
+        assert!(Account::decode(&acc.encode()[..20]).is_none());
+
+        let mut tiers = AccountTiers::new(TieringPolicy { idle_after: 2 });
+        let mut hot = HashMap::from([(1, acc), (2, Account::default())]);
+        tiers.touch(1, &mut hot, true);
+        tiers.touch(2, &mut hot, true);
+        // both touched within the last two transactions
+        assert_eq!(tiers.stats(&hot).cold, 0);
+        tiers.touch(2, &mut hot, true);
+        tiers.touch(2, &mut hot, true);
+        assert!(!hot.contains_key(&1));
+        assert_account!(tiers.get(1).unwrap(), available: 0, held: 10, open_disputes: [1]);
+        assert_eq!(tiers.stats(&hot).thawed, 1);
+
+        tiers.promote(1, &mut hot);
+        assert!(tiers.get(1).is_none());
+        assert_account!(hot[&1], held: 10);
+        assert_eq!(hot[&1].extension("tier"), Some(&"gold".into()));
+    }
+}
@@ -0,0 +1,83 @@
+//! Operations of operators, kept apart from [`super::TransactionProcessor`],
+//! so embedders can put different authentication in front of each, and code
+//! holding a transaction processor can't invoke them by accident.
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    account::{ApplyError, TransactionId},
+    history::ErasureTombstone,
+};
+
+use super::{ClientId, TransactionOutcome, in_memory_processor::MergeConflict};
+
+/// Source of events applied by operators, as recorded in history
+pub const ADMIN_SOURCE: &str = "admin";
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AdminError {
+    #[error("Client {0} has no account")]
+    UnknownClient(ClientId),
+    #[error("Account of client {0} is not locked")]
+    NotLocked(ClientId),
+    #[error("Transaction {tx_id} of client {client_id} is not under dispute")]
+    NotDisputed {
+        client_id: ClientId,
+        tx_id: TransactionId,
+    },
+    #[error("Event history is not recorded, so there is nothing to erase")]
+    HistoryNotRecorded,
+    #[error(transparent)]
+    Apply(#[from] ApplyError),
+}
+
+impl AdminError {
+    /// Stable identifier of the error, that doesn't change with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            AdminError::UnknownClient(_) => "admin_unknown_client",
+            AdminError::NotLocked(_) => "admin_not_locked",
+            AdminError::NotDisputed { .. } => "admin_not_disputed",
+            AdminError::HistoryNotRecorded => "admin_history_not_recorded",
+            AdminError::Apply(err) => err.code(),
+        }
+    }
+}
+
+/// Dangerous operations on processor state. Events of unlocks and
+/// adjustments are published like any other, with [`ADMIN_SOURCE`].
+pub trait AdminOps {
+    /// Unlocks account frozen by chargebacks, e.g. once the customer repaid
+    /// them. Charged back transactions can still be reinstated.
+    fn unlock(&mut self, client_id: ClientId) -> Result<TransactionOutcome, AdminError>;
+
+    /// Corrects available funds of existing account by signed `amount`,
+    /// e.g. after reconciliation with the bank found a discrepancy
+    fn adjust(
+        &mut self,
+        client_id: ClientId,
+        amount: Decimal,
+    ) -> Result<TransactionOutcome, AdminError>;
+
+    /// Combines state of a processor, that handled another partition of the
+    /// input, e.g. when rows are partitioned by client and processed in parallel.
+    /// Accounts, created transactions and their indexes are moved in.
+    /// Nothing is merged when any conflict is found: subscribers or open
+    /// savepoints of either processor, different periods, suspense, a watchlist
+    /// or KYC rules of `other`, whose state can't be combined, or indexes
+    /// kept by only one of them.
+    fn merge(&mut self, other: Self) -> Result<(), MergeConflict>
+    where
+        Self: Sized;
+
+    /// Applies [`crate::account::AccountEventKind::Erased`] event, which
+    /// removes custom fields of the account, see [`crate::account::Account::extensions`],
+    /// anonymizes recorded history of the client, see
+    /// [`crate::history::EventHistory::erase_client`], and removes statement
+    /// account mappings of subscribed [`crate::bin_utils::account_clients::AccountClients`].
+    /// Account and its balance are kept. Only the in-memory history is
+    /// anonymized: what was already written elsewhere (change feed, rejects
+    /// log, errors, state stores) has to be purged there.
+    fn erase_client(&mut self, client_id: ClientId) -> Result<ErasureTombstone, AdminError>;
+}
@@ -0,0 +1,90 @@
+//! E-money license limits the balance a single account may hold.
+//! Deposits that would raise the balance above the cap are rejected,
+//! or held in suspense for manual review.
+
+use std::{collections::HashMap, io::Read, str::FromStr};
+
+use csv::Trim;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::ClientId;
+
+/// What to do with deposits exceeding the cap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverCapPolicy {
+    #[default]
+    Reject,
+    /// Hold the deposit in suspense, only when suspense is enabled
+    Suspend,
+}
+
+impl FromStr for OverCapPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "suspend" => Ok(Self::Suspend),
+            other => Err(format!("unknown over cap policy `{other}`")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientCapRow {
+    client: ClientId,
+    cap: Decimal,
+}
+
+/// Maximum balance (total plus pending) of accounts
+#[derive(Debug, Clone, Default)]
+pub struct BalanceCaps {
+    pub global: Option<Decimal>,
+    pub per_client: HashMap<ClientId, Decimal>,
+    pub policy: OverCapPolicy,
+}
+
+impl BalanceCaps {
+    /// Parses CSV with `client,cap` columns
+    pub fn parse_client_caps(&mut self, source: impl Read) -> Result<(), csv::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(source);
+        for row in reader.deserialize() {
+            let ClientCapRow { client, cap } = row?;
+            self.per_client.insert(client, cap);
+        }
+        Ok(())
+    }
+
+    /// The lower of global and client's own cap
+    pub fn cap(&self, client_id: ClientId) -> Option<Decimal> {
+        match (self.global, self.per_client.get(&client_id)) {
+            (Some(global), Some(client)) => Some(global.min(*client)),
+            (global, client) => global.or(client.copied()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_cap_wins() {
+        let mut caps = BalanceCaps {
+            global: Some(Decimal::from(100)),
+            ..Default::default()
+        };
+        caps.parse_client_caps("client,cap\n1,50\n2,500\n".as_bytes())
+            .unwrap();
+        assert_eq!(caps.cap(1), Some(Decimal::from(50)));
+        assert_eq!(caps.cap(2), Some(Decimal::from(100)));
+        assert_eq!(caps.cap(3), Some(Decimal::from(100)));
+        caps.global = None;
+        assert_eq!(caps.cap(2), Some(Decimal::from(500)));
+        assert_eq!(caps.cap(3), None);
+    }
+}
@@ -0,0 +1,103 @@
+//! Negative cache of transaction ids: answers "definitely not seen" without
+//! asking the backend, which is most of the lookups of modify-heavy streams
+//! referencing unknown transactions.
+
+use crate::account::TransactionId;
+
+/// Bloom filter of transaction ids. May report an id, that was never inserted
+/// (with about the configured probability), but never misses an inserted one.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    capacity: usize,
+    len: usize,
+}
+
+impl BloomFilter {
+    /// Filter holding `capacity` ids with `false_positive_rate`, e.g. 0.01
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let words = bits.div_ceil(64).max(1);
+        let hashes = ((words * 64) as f64 / capacity as f64 * ln2).round() as u32;
+        Self {
+            bits: vec![0; words],
+            hashes: hashes.max(1),
+            capacity,
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, tx_id: TransactionId) {
+        for bit in self.bit_indexes(tx_id) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    /// `false` if `tx_id` was never inserted
+    pub fn might_contain(&self, tx_id: TransactionId) -> bool {
+        self.bit_indexes(tx_id)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Number of inserted ids
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// More ids than the filter was sized for were inserted, so false
+    /// positives are more frequent than configured
+    pub fn is_saturated(&self) -> bool {
+        self.len > self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Double hashing: i-th index is `h1 + i * h2`
+    fn bit_indexes(&self, tx_id: TransactionId) -> impl Iterator<Item = usize> + use<> {
+        let h1 = mix(tx_id as u64);
+        let h2 = mix(tx_id as u64 ^ 0x9e37_79b9_7f4a_7c15) | 1;
+        let bits = (self.bits.len() * 64) as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+}
+
+/// SplitMix64 finalizer, spreads sequential ids over all bits
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives_and_few_false_positives() {
+        let mut filter = BloomFilter::new(10_000, 0.01);
+        for tx_id in (0..20_000).step_by(2) {
+            filter.insert(tx_id);
+        }
+        assert!(
+            (0..20_000)
+                .step_by(2)
+                .all(|tx_id| filter.might_contain(tx_id))
+        );
+        let false_positives = (1..20_000)
+            .step_by(2)
+            .filter(|tx_id| filter.might_contain(*tx_id))
+            .count();
+        assert!(false_positives < 200, "{false_positives} false positives");
+        assert!(!filter.is_saturated());
+    }
+}
@@ -0,0 +1,163 @@
+use aws_sdk_dynamodb::{
+    Client,
+    error::SdkError,
+    operation::transact_write_items::TransactWriteItemsError,
+    types::{AttributeValue, Put, TransactWriteItem},
+};
+use tokio::runtime::Runtime;
+
+use crate::{
+    account::{AccountEvent, TransactionId},
+    command::CreateTransactionCommand,
+};
+
+use super::{
+    ClientId,
+    state_store::{StateStore, StoreError, decode_event, decode_tx, encode_event, encode_tx},
+};
+
+/// Table with `pk` (string) partition key and `sk` (number) sort key.
+/// Account events are stored as `pk = account#{client}`, `sk = version`
+/// after the event, so writing the same version twice fails the
+/// `attribute_not_exists` condition. Created transactions are stored as
+/// `pk = tx#{tx_id}`, `sk = 0`, in the same write transaction.
+pub struct DynamoDbStateStore {
+    client: Client,
+    table: String,
+    runtime: Runtime,
+}
+
+impl DynamoDbStateStore {
+    /// Uses credentials and region from the environment
+    pub fn connect(table: &str) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let config = runtime.block_on(aws_config::load_from_env());
+        Ok(Self {
+            client: Client::new(&config),
+            table: table.to_string(),
+            runtime,
+        })
+    }
+
+    fn put(&self, pk: String, sk: u64, value: String) -> Result<TransactWriteItem, StoreError> {
+        let put = Put::builder()
+            .table_name(&self.table)
+            .item("pk", AttributeValue::S(pk))
+            .item("sk", AttributeValue::N(sk.to_string()))
+            .item("value", AttributeValue::S(value))
+            .condition_expression("attribute_not_exists(pk)")
+            .build()
+            .map_err(backend_err)?;
+        Ok(TransactWriteItem::builder().put(put).build())
+    }
+}
+
+impl StateStore for DynamoDbStateStore {
+    fn get_tx(
+        &mut self,
+        tx_id: TransactionId,
+    ) -> Result<Option<CreateTransactionCommand>, StoreError> {
+        let output = self
+            .runtime
+            .block_on(
+                self.client
+                    .get_item()
+                    .table_name(&self.table)
+                    .key("pk", AttributeValue::S(format!("tx#{tx_id}")))
+                    .key("sk", AttributeValue::N("0".to_string()))
+                    .consistent_read(true)
+                    .send(),
+            )
+            .map_err(backend_err)?;
+        output
+            .item
+            .as_ref()
+            .map(|item| decode_tx(tx_id, string_value(item.get("value"))?))
+            .transpose()
+    }
+
+    fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError> {
+        let items = self
+            .runtime
+            .block_on(
+                self.client
+                    .query()
+                    .table_name(&self.table)
+                    .key_condition_expression("pk = :pk")
+                    .expression_attribute_values(
+                        ":pk",
+                        AttributeValue::S(format!("account#{client_id}")),
+                    )
+                    .consistent_read(true)
+                    .into_paginator()
+                    .items()
+                    .send()
+                    .collect::<Result<Vec<_>, _>>(),
+            )
+            .map_err(backend_err)?;
+        // items are sorted by sk, i.e. by version
+        items
+            .iter()
+            .map(|item| decode_event(string_value(item.get("value"))?))
+            .collect()
+    }
+
+    fn append_event(
+        &mut self,
+        client_id: ClientId,
+        expected_version: u64,
+        event: &AccountEvent,
+        created: Option<&CreateTransactionCommand>,
+    ) -> Result<(), StoreError> {
+        let mut items = vec![self.put(
+            format!("account#{client_id}"),
+            expected_version + 1,
+            encode_event(event),
+        )?];
+        if let Some(command) = created {
+            items.push(self.put(format!("tx#{}", command.tx_id), 0, encode_tx(command))?);
+        }
+        let result = self.runtime.block_on(
+            self.client
+                .transact_write_items()
+                .set_transact_items(Some(items))
+                .send(),
+        );
+        match result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError(err)) => match err.err() {
+                TransactWriteItemsError::TransactionCanceledException(canceled) => {
+                    let failed = |idx: usize| {
+                        canceled
+                            .cancellation_reasons()
+                            .get(idx)
+                            .and_then(|reason| reason.code())
+                            == Some("ConditionalCheckFailed")
+                    };
+                    if failed(0) {
+                        Err(StoreError::VersionConflict)
+                    } else if let (true, Some(command)) = (failed(1), created) {
+                        Err(StoreError::TxConflict(command.tx_id))
+                    } else {
+                        Err(backend_err(canceled))
+                    }
+                }
+                other => Err(backend_err(other)),
+            },
+            Err(err) => Err(backend_err(err)),
+        }
+    }
+}
+
+fn string_value(value: Option<&AttributeValue>) -> Result<&str, StoreError> {
+    match value {
+        Some(AttributeValue::S(value)) => Ok(value),
+        _ => Err(StoreError::Backend("item has no `value`".to_string())),
+    }
+}
+
+fn backend_err(err: impl std::fmt::Display) -> StoreError {
+    StoreError::Backend(err.to_string())
+}
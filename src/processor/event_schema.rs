@@ -0,0 +1,171 @@
+//! Versioned encoding of events in state stores. Logs are never rewritten,
+//! so events written by older versions are decoded into their own envelope,
+//! and upcast step by step to the latest one. Adding a field to events means
+//! adding the next version and an upcaster from the previous one.
+
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rust_decimal::Decimal;
+
+use crate::account::{AccountEvent, AccountEventKind, TransactionId};
+
+use super::state_store::StoreError;
+
+/// Version of newly written events
+pub const EVENT_VERSION: u32 = 2;
+
+/// Events written before versioning, as `kind:tx_id:amount`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventV1 {
+    pub kind: AccountEventKind,
+    pub tx_id: TransactionId,
+    pub amount: Decimal,
+}
+
+/// `v2:kind:tx_id:amount:recorded_at`, with time of the append
+/// in seconds since unix epoch, empty when unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventV2 {
+    pub kind: AccountEventKind,
+    pub tx_id: TransactionId,
+    pub amount: Decimal,
+    pub recorded_at: Option<u64>,
+}
+
+impl From<EventV1> for EventV2 {
+    fn from(event: EventV1) -> Self {
+        Self {
+            kind: event.kind,
+            tx_id: event.tx_id,
+            amount: event.amount,
+            recorded_at: None,
+        }
+    }
+}
+
+impl EventV2 {
+    pub fn new(event: &AccountEvent, recorded_at: Option<u64>) -> Self {
+        Self {
+            kind: event.kind(),
+            tx_id: event.transaction_id(),
+            amount: event.amount(),
+            recorded_at,
+        }
+    }
+
+    /// Envelope of event appended now
+    pub fn recorded_now(event: &AccountEvent) -> Self {
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs());
+        Self::new(event, recorded_at)
+    }
+
+    pub fn encode(&self) -> String {
+        let recorded_at = self
+            .recorded_at
+            .map(|at| at.to_string())
+            .unwrap_or_default();
+        format!(
+            "v2:{}:{}:{}:{recorded_at}",
+            self.kind.name(),
+            self.tx_id,
+            self.amount
+        )
+    }
+
+    pub fn event(&self) -> AccountEvent {
+        AccountEvent::new(self.tx_id, self.amount, self.kind)
+    }
+}
+
+/// Event of any version, as found in the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventEnvelope {
+    V1(EventV1),
+    V2(EventV2),
+}
+
+impl EventEnvelope {
+    pub fn decode(value: &str) -> Result<Self, StoreError> {
+        let invalid = || StoreError::Backend(format!("invalid event `{value}`"));
+        let (version, fields) = match value.split_once(':') {
+            Some(("v2", fields)) => (2, fields),
+            // unversioned events start with their kind
+            _ => (1, value),
+        };
+        let mut parts = fields.split(':');
+        let (Some(kind), Some(tx_id), Some(amount)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let kind = AccountEventKind::from_name(kind).ok_or_else(invalid)?;
+        let tx_id = tx_id.parse().map_err(|_| invalid())?;
+        let amount = Decimal::from_str(amount).map_err(|_| invalid())?;
+        if version == 1 {
+            if parts.next().is_some() {
+                return Err(invalid());
+            }
+            return Ok(Self::V1(EventV1 {
+                kind,
+                tx_id,
+                amount,
+            }));
+        }
+        let recorded_at = match parts.next() {
+            Some("") => None,
+            Some(at) => Some(at.parse().map_err(|_| invalid())?),
+            None => return Err(invalid()),
+        };
+        Ok(Self::V2(EventV2 {
+            kind,
+            tx_id,
+            amount,
+            recorded_at,
+        }))
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            EventEnvelope::V1(_) => 1,
+            EventEnvelope::V2(_) => 2,
+        }
+    }
+
+    /// Upgrades to the latest version, through every version in between
+    pub fn upcast(self) -> EventV2 {
+        match self {
+            EventEnvelope::V1(event) => event.into(),
+            EventEnvelope::V2(event) => event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_events_are_upcast() {
+        let old = EventEnvelope::decode("disputed:7:1.5").unwrap();
+        assert_eq!(old.version(), 1);
+        let upcast = old.upcast();
+        assert_eq!(upcast.recorded_at, None);
+        assert_eq!(upcast.encode(), "v2:disputed:7:1.5:");
+
+        let event = AccountEvent::new(7, Decimal::new(15, 1), AccountEventKind::Disputed);
+        let recorded = EventV2::recorded_now(&event);
+        let decoded = EventEnvelope::decode(&recorded.encode()).unwrap();
+        assert_eq!(decoded.version(), EVENT_VERSION);
+        assert_eq!(decoded.upcast(), recorded);
+        assert_eq!(upcast.event().kind(), event.kind());
+
+        assert!(EventEnvelope::decode("v2:disputed:7:1.5").is_err());
+        assert!(EventEnvelope::decode("disputed:7:1.5:9").is_err());
+        assert!(EventEnvelope::decode("v3:disputed:7:1.5:").is_err());
+    }
+}
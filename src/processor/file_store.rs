@@ -0,0 +1,607 @@
+//! Events kept in local files, for single-node daemons that don't run a
+//! database. Events are appended to the active segment, which is sealed
+//! (and, with `zstd` feature, compressed) once it grows too big or old,
+//! so disk usage of long runs stays manageable.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    account::{AccountEvent, TransactionId},
+    command::CreateTransactionCommand,
+    history::EventHistory,
+};
+
+use super::{
+    ClientId,
+    event_schema::EventV2,
+    state_store::{StateStore, StoreError, decode_envelope, decode_tx, encode_tx},
+};
+
+const PLAIN_EXTENSION: &str = "log";
+const ZSTD_EXTENSION: &str = "zst";
+
+/// How sealed segments are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd with the given level, 1 (fastest) to 22 (smallest)
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+/// When active segment is sealed, and how sealed segments are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPolicy {
+    pub max_bytes: u64,
+    pub max_age: Option<Duration>,
+    pub compression: Compression,
+}
+
+impl Default for SegmentPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 << 20,
+            max_age: None,
+            compression: Compression::None,
+        }
+    }
+}
+
+/// Segment file with versions of account events it holds
+#[derive(Debug)]
+struct Segment {
+    id: u32,
+    compressed: bool,
+    /// First version and number of events of every account in the segment
+    accounts: HashMap<ClientId, (u64, u64)>,
+}
+
+impl Segment {
+    fn new(id: u32) -> Self {
+        Self {
+            id,
+            compressed: false,
+            accounts: HashMap::new(),
+        }
+    }
+
+    fn path(&self, dir: &Path) -> PathBuf {
+        let name = format!("{:06}.{PLAIN_EXTENSION}", self.id);
+        if self.compressed {
+            return dir.join(format!("{name}.{ZSTD_EXTENSION}"));
+        }
+        dir.join(name)
+    }
+
+    fn overlaps(&self, client_id: ClientId, versions: &Range<u64>) -> bool {
+        self.accounts
+            .get(&client_id)
+            .is_some_and(|(first, count)| *first < versions.end && first + count > versions.start)
+    }
+
+    fn record(&mut self, client_id: ClientId, version: u64) {
+        self.accounts
+            .entry(client_id)
+            .and_modify(|(_, count)| *count += 1)
+            .or_insert((version, 1));
+    }
+}
+
+/// Single line of a segment: `client event [created transaction]`
+struct Record {
+    client_id: ClientId,
+    event: EventV2,
+    created: Option<CreateTransactionCommand>,
+}
+
+impl Record {
+    fn encode(
+        client_id: ClientId,
+        event: &EventV2,
+        created: Option<&CreateTransactionCommand>,
+    ) -> String {
+        match created {
+            Some(command) => format!("{client_id} {} {}\n", event.encode(), encode_tx(command)),
+            None => format!("{client_id} {}\n", event.encode()),
+        }
+    }
+
+    fn decode(line: &str) -> Result<Self, StoreError> {
+        let invalid = || StoreError::Backend(format!("invalid record `{line}`"));
+        let mut parts = line.split(' ');
+        let client_id = parts
+            .next()
+            .and_then(|client| client.parse().ok())
+            .ok_or_else(invalid)?;
+        let event = decode_envelope(parts.next().ok_or_else(invalid)?)?;
+        let created = parts
+            .next()
+            .map(|tx| decode_tx(event.tx_id, tx))
+            .transpose()?;
+        Ok(Self {
+            client_id,
+            event,
+            created,
+        })
+    }
+}
+
+/// [`StateStore`] in a directory of segment files. Account versions, created
+/// transactions and per-segment account ranges are indexed in memory, so
+/// reads of an account only open segments holding its events.
+pub struct FileStateStore {
+    dir: PathBuf,
+    policy: SegmentPolicy,
+    sealed: Vec<Segment>,
+    active: Segment,
+    active_file: File,
+    active_bytes: u64,
+    active_since: Instant,
+    versions: HashMap<ClientId, u64>,
+    txs: HashMap<TransactionId, CreateTransactionCommand>,
+    timestamps: bool,
+}
+
+impl FileStateStore {
+    /// Opens the store in `dir`, created when missing, and indexes existing segments
+    pub fn open(dir: impl Into<PathBuf>, policy: SegmentPolicy) -> Result<Self, StoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(backend_err)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir).map_err(backend_err)? {
+            let name = entry.map_err(backend_err)?.file_name();
+            if let Some(name) = name.to_str()
+                && let Some(segment) = parse_segment_name(name)?
+            {
+                segments.push(segment);
+            }
+        }
+        // compressed segment comes first, a plain one with the same id is
+        // left over by compression interrupted before removing it
+        segments.sort_by_key(|segment| (segment.id, !segment.compressed));
+        let mut leftovers = Vec::new();
+        segments.dedup_by(|plain, compressed| {
+            let leftover = plain.id == compressed.id;
+            if leftover {
+                leftovers.push(plain.path(&dir));
+            }
+            leftover
+        });
+        for path in leftovers {
+            fs::remove_file(path).map_err(backend_err)?;
+        }
+
+        let mut versions = HashMap::new();
+        let mut txs = HashMap::new();
+        for segment in &mut segments {
+            for record in read_segment(&segment.path(&dir))? {
+                let version = versions.entry(record.client_id).or_insert(0);
+                segment.record(record.client_id, *version);
+                *version += 1;
+                if let Some(command) = record.created {
+                    txs.insert(command.tx_id, command);
+                }
+            }
+        }
+        let active = match segments.pop() {
+            Some(segment) if !segment.compressed => segment,
+            Some(segment) => {
+                let next = Segment::new(segment.id + 1);
+                segments.push(segment);
+                next
+            }
+            None => Segment::new(0),
+        };
+        let (active_file, active_bytes) = open_active(&active.path(&dir))?;
+        Ok(Self {
+            dir,
+            policy,
+            sealed: segments,
+            active,
+            active_file,
+            active_bytes,
+            active_since: Instant::now(),
+            versions,
+            txs,
+            timestamps: true,
+        })
+    }
+
+    /// Appends events without the time of the append, so the same input
+    /// produces identical segments
+    pub fn without_timestamps(mut self) -> Self {
+        self.timestamps = false;
+        self
+    }
+
+    /// Events of the account with versions (0-based positions) in `versions`,
+    /// with the time they were appended
+    pub fn load_range(
+        &self,
+        client_id: ClientId,
+        versions: Range<u64>,
+    ) -> Result<Vec<EventV2>, StoreError> {
+        let mut events = Vec::new();
+        for segment in self.sealed.iter().chain([&self.active]) {
+            if !segment.overlaps(client_id, &versions) {
+                continue;
+            }
+            let (first, _) = segment.accounts[&client_id];
+            let records = read_segment(&segment.path(&self.dir))?
+                .into_iter()
+                .filter(|record| record.client_id == client_id);
+            events.extend(
+                (first..)
+                    .zip(records)
+                    .filter(|(version, _)| versions.contains(version))
+                    .map(|(_, record)| record.event),
+            );
+        }
+        Ok(events)
+    }
+
+    /// Events of all accounts in the order they were appended, for searches
+    /// and exports of recorded data. Sources of transactions are not stored,
+    /// so entries have none.
+    pub fn history(&self) -> Result<EventHistory, StoreError> {
+        let mut history = EventHistory::default();
+        for segment in self.sealed.iter().chain([&self.active]) {
+            for record in read_segment(&segment.path(&self.dir))? {
+                history.push(record.client_id, record.event.event());
+            }
+        }
+        Ok(history)
+    }
+
+    /// Clients with stored events, in no particular order
+    pub fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.versions.keys().copied()
+    }
+
+    /// Number of segment files, including the active one
+    pub fn segments(&self) -> usize {
+        self.sealed.len() + 1
+    }
+
+    /// Seals the active segment and starts a new one. On failure the active
+    /// segment stays in place, so its events remain readable and appendable.
+    pub fn rotate(&mut self) -> Result<(), StoreError> {
+        self.active_file.flush().map_err(backend_err)?;
+        let next = Segment::new(self.active.id + 1);
+        let (file, bytes) = open_active(&next.path(&self.dir))?;
+        match self.policy.compression {
+            Compression::None => {}
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => compress(&self.dir, &mut self.active, level)?,
+        }
+        let sealed = std::mem::replace(&mut self.active, next);
+        self.sealed.push(sealed);
+        self.active_file = file;
+        self.active_bytes = bytes;
+        self.active_since = Instant::now();
+        Ok(())
+    }
+
+    fn rotation_due(&self) -> bool {
+        self.active_bytes >= self.policy.max_bytes
+            || self
+                .policy
+                .max_age
+                .is_some_and(|max_age| self.active_since.elapsed() >= max_age)
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn get_tx(
+        &mut self,
+        tx_id: TransactionId,
+    ) -> Result<Option<CreateTransactionCommand>, StoreError> {
+        Ok(self.txs.get(&tx_id).cloned())
+    }
+
+    fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError> {
+        let events = self.load_range(client_id, 0..u64::MAX)?;
+        Ok(events.iter().map(EventV2::event).collect())
+    }
+
+    fn append_event(
+        &mut self,
+        client_id: ClientId,
+        expected_version: u64,
+        event: &AccountEvent,
+        created: Option<&CreateTransactionCommand>,
+    ) -> Result<(), StoreError> {
+        let version = self.versions.get(&client_id).copied().unwrap_or_default();
+        if version != expected_version {
+            return Err(StoreError::VersionConflict);
+        }
+        if let Some(command) = created
+            && self.txs.contains_key(&command.tx_id)
+        {
+            return Err(StoreError::TxConflict(command.tx_id));
+        }
+        // rotating before the write, so a failed rotation leaves nothing written
+        if self.rotation_due() {
+            self.rotate()?;
+        }
+        let event = if self.timestamps {
+            EventV2::recorded_now(event)
+        } else {
+            EventV2::new(event, None)
+        };
+        let record = Record::encode(client_id, &event, created);
+        self.active_file
+            .write_all(record.as_bytes())
+            .map_err(backend_err)?;
+        self.active_bytes += record.len() as u64;
+        self.active.record(client_id, version);
+        self.versions.insert(client_id, version + 1);
+        if let Some(command) = created {
+            self.txs.insert(command.tx_id, command.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Segment of `{id}.log` or `{id}.log.zst` file name, `None` for other files.
+/// Compressed segments can't be read without `zstd` feature, and skipping
+/// them would lose their events.
+fn parse_segment_name(name: &str) -> Result<Option<Segment>, StoreError> {
+    let compressed_stem = name
+        .strip_suffix(ZSTD_EXTENSION)
+        .and_then(|stem| stem.strip_suffix('.'))
+        .and_then(|stem| stem.strip_suffix(PLAIN_EXTENSION));
+    let (stem, compressed) = match (name.strip_suffix(PLAIN_EXTENSION), compressed_stem) {
+        (Some(stem), _) => (stem, false),
+        (None, Some(stem)) => (stem, true),
+        (None, None) => return Ok(None),
+    };
+    let Some(id) = stem.strip_suffix('.').and_then(|id| id.parse().ok()) else {
+        return Ok(None);
+    };
+    if compressed && cfg!(not(feature = "zstd")) {
+        return Err(StoreError::Backend(format!(
+            "segment `{name}` is compressed, reading it requires `zstd` feature"
+        )));
+    }
+    Ok(Some(Segment {
+        compressed,
+        ..Segment::new(id)
+    }))
+}
+
+fn open_active(path: &Path) -> Result<(File, u64), StoreError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(backend_err)?;
+    let bytes = file.metadata().map_err(backend_err)?.len();
+    Ok((file, bytes))
+}
+
+/// Replaces plain segment file with compressed one, and marks the segment
+/// compressed once it is. The compressed file appears under its name only
+/// once complete, and a plain one left by a crash or a failed removal is
+/// removed by [`FileStateStore::open`].
+#[cfg(feature = "zstd")]
+fn compress(dir: &Path, segment: &mut Segment, level: i32) -> Result<(), StoreError> {
+    let plain = segment.path(dir);
+    let compressed = Segment {
+        compressed: true,
+        ..Segment::new(segment.id)
+    }
+    .path(dir);
+    let partial = compressed.with_extension(format!("{ZSTD_EXTENSION}.tmp"));
+    let source = File::open(&plain).map_err(backend_err)?;
+    let mut target = File::create(&partial).map_err(backend_err)?;
+    zstd::stream::copy_encode(source, &mut target, level).map_err(backend_err)?;
+    target.sync_all().map_err(backend_err)?;
+    fs::rename(partial, compressed).map_err(backend_err)?;
+    segment.compressed = true;
+    let _ = fs::remove_file(plain);
+    Ok(())
+}
+
+fn read_segment(path: &Path) -> Result<Vec<Record>, StoreError> {
+    let file = File::open(path).map_err(backend_err)?;
+    let reader: Box<dyn Read> = match path.extension().and_then(|ext| ext.to_str()) {
+        #[cfg(feature = "zstd")]
+        Some(ZSTD_EXTENSION) => Box::new(zstd::Decoder::new(file).map_err(backend_err)?),
+        _ => Box::new(file),
+    };
+    let mut records = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(backend_err)?;
+        if !line.is_empty() {
+            records.push(Record::decode(&line)?);
+        }
+    }
+    Ok(records)
+}
+
+fn backend_err(err: io::Error) -> StoreError {
+    StoreError::Backend(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::{
+        command::TransactionKind,
+        processor::{TransactionProcessor, store_processor::StoreTransactionProcessor},
+    };
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cute-ledger-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn rotated_segments_survive_reopen() {
+        let dir = temp_dir("segments");
+        let policy = SegmentPolicy {
+            max_bytes: 40,
+            #[cfg(feature = "zstd")]
+            compression: Compression::Zstd { level: 3 },
+            ..Default::default()
+        };
+        {
+            let store = FileStateStore::open(&dir, policy).unwrap();
+            let mut processor = StoreTransactionProcessor::new(store);
+            for tx in 1..=6 {
+                let client = (tx % 2) as ClientId;
+                processor
+                    .process_transaction(tx, client, Some(Decimal::TEN), TransactionKind::Deposit)
+                    .unwrap();
+            }
+            processor
+                .process_transaction(5, 1, None, TransactionKind::Dispute)
+                .unwrap();
+            assert!(processor.store().segments() > 2);
+        }
+
+        let mut store = FileStateStore::open(&dir, policy).unwrap();
+        assert_eq!(store.load_events(1).unwrap().len(), 4);
+        let events = store.load_range(1, 1..3).unwrap();
+        let txs: Vec<_> = events.iter().map(|event| event.tx_id).collect();
+        assert_eq!(txs, [3, 5]);
+        assert!(events.iter().all(|event| event.recorded_at.is_some()));
+        assert!(store.get_tx(6).unwrap().is_some());
+        let history = store.history().unwrap();
+        let txs: Vec<_> = history
+            .iter()
+            .map(|entry| (entry.client, entry.event.transaction_id()))
+            .collect();
+        assert_eq!(
+            txs,
+            [(1, 1), (0, 2), (1, 3), (0, 4), (1, 5), (0, 6), (1, 5)]
+        );
+
+        let mut processor = StoreTransactionProcessor::new(store);
+        processor
+            .process_transaction(5, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::from(30));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn segments_without_timestamps_are_reproducible() {
+        let write = |name| {
+            let dir = temp_dir(name);
+            let store = FileStateStore::open(&dir, SegmentPolicy::default())
+                .unwrap()
+                .without_timestamps();
+            let mut processor = StoreTransactionProcessor::new(store);
+            processor
+                .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+                .unwrap();
+            assert_eq!(
+                processor.store().load_range(1, 0..1).unwrap()[0].recorded_at,
+                None
+            );
+            let segment = fs::read(Segment::new(0).path(&dir)).unwrap();
+            fs::remove_dir_all(dir).unwrap();
+            segment
+        };
+        let segment = write("reproducible-a");
+        assert!(!segment.is_empty());
+        assert_eq!(segment, write("reproducible-b"));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn interrupted_compression_is_completed_on_open() {
+        let dir = temp_dir("interrupted");
+        {
+            let store = FileStateStore::open(&dir, SegmentPolicy::default()).unwrap();
+            let mut processor = StoreTransactionProcessor::new(store);
+            for tx in 1..=3 {
+                processor
+                    .process_transaction(tx, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+                    .unwrap();
+            }
+        }
+        // crash after the compressed file was complete, before removing the plain one
+        let plain = Segment::new(0).path(&dir);
+        let contents = fs::read(&plain).unwrap();
+        compress(&dir, &mut Segment::new(0), 3).unwrap();
+        fs::write(&plain, contents).unwrap();
+
+        let mut store = FileStateStore::open(&dir, SegmentPolicy::default()).unwrap();
+        assert_eq!(store.load_events(1).unwrap().len(), 3);
+        assert!(!plain.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_rotation_keeps_store_and_cache_in_sync() {
+        let dir = temp_dir("failed-rotation");
+        let policy = SegmentPolicy {
+            max_bytes: 1,
+            #[cfg(feature = "zstd")]
+            compression: Compression::Zstd { level: 3 },
+            ..Default::default()
+        };
+        let store = FileStateStore::open(&dir, policy).unwrap();
+        let mut processor = StoreTransactionProcessor::new(store);
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+
+        // directory in place of the file rotation creates makes it fail
+        #[cfg(feature = "zstd")]
+        let blocked = dir.join("000000.log.zst.tmp");
+        #[cfg(not(feature = "zstd"))]
+        let blocked = Segment::new(1).path(&dir);
+        fs::create_dir(&blocked).unwrap();
+        assert!(
+            processor
+                .process_transaction(2, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+                .is_err()
+        );
+        assert_eq!(processor.store().segments(), 1);
+        assert_eq!(
+            processor.store().load_range(1, 0..u64::MAX).unwrap().len(),
+            1
+        );
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::TEN);
+
+        fs::remove_dir(&blocked).unwrap();
+        processor
+            .process_transaction(2, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert_eq!(processor.store().segments(), 2);
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::from(20));
+        drop(processor);
+        let mut store = FileStateStore::open(&dir, policy).unwrap();
+        assert_eq!(store.load_events(1).unwrap().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn compressed_segments_require_zstd() {
+        let dir = temp_dir("compressed");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("000000.log.zst"), b"").unwrap();
+        assert!(matches!(
+            FileStateStore::open(&dir, SegmentPolicy::default()),
+            Err(StoreError::Backend(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,45 +1,1090 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io, ops::Range, time::Instant};
 
 use rust_decimal::Decimal;
+use serde_json::Value;
+use thiserror::Error;
 
 use crate::{
-    account::{Account, TransactionId},
-    command::{AccountCommand, CreateTransactionCommand, TransactionKind},
+    account::{
+        Account, AccountError, AccountEvent, CaseStatus, DisputeCase, DisputeFlow, TransactionId,
+    },
+    command::{
+        AccountCommand, AccountCommandError, CreateTransactionAction, CreateTransactionCommand,
+        ModifyTransactionAction, TransactionKind, ZeroAmountPolicy,
+    },
+    double_entry::Ledger,
+    event_bus::{AppliedEvent, EventBus, EventSubscriber},
+    history::{
+        Balance, CompactionReport, ErasureTombstone, EventHistory, EventSeq, PeriodClose, PeriodId,
+        Statement,
+    },
+    projection::{AccountExtension, FraudFlag, FraudHeuristics, Projection},
+    stats::{PipelineStats, Stage},
 };
 
-use super::{ClientId, TransactionProcessError, TransactionProcessor};
+use super::{
+    AccountsCursor, AccountsPage, ClientId, TransactionOutcome, TransactionProcessError,
+    TransactionProcessor,
+    account_tiers::{AccountTiers, TierStats, TieringPolicy},
+    admin::{ADMIN_SOURCE, AdminError, AdminOps},
+    balance_cap::{BalanceCaps, OverCapPolicy},
+    kyc::{ComplianceReport, KycRules},
+    metrics::{MetricsHook, ProcessorMetrics},
+    risk_config::{DisabledSections, RiskConfig},
+    savepoint::{Journal, Savepoint, Undo},
+    suspense::{SuspendedRow, Suspense, SuspenseReport},
+    tx_id_order::{TxIdOrder, TxIdWatermarks},
+    tx_store::{MemoryStats, TxStore},
+    watchlist::{PendingReview, ReviewId, ReviewReport, Watchlist},
+    withdrawal_rules::WithdrawalRules,
+};
+
+/// State of two processors can't be combined
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum MergeConflict {
+    #[error("Client {0} has an account in both processors")]
+    SameClient(ClientId),
+    #[error("Transaction {0} was created in both processors")]
+    DuplicateTransaction(TransactionId),
+    #[error("Processors with {0} can't be merged")]
+    UnmergeableState(&'static str),
+    #[error("Only one of the processors has {0}")]
+    MismatchedIndex(&'static str),
+}
+
+impl MergeConflict {
+    /// Stable identifier of the error, that doesn't change with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            MergeConflict::SameClient(_) => "merge_same_client",
+            MergeConflict::DuplicateTransaction(_) => "merge_duplicate_transaction",
+            MergeConflict::UnmergeableState(_) => "merge_unmergeable_state",
+            MergeConflict::MismatchedIndex(_) => "merge_mismatched_index",
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct InMemoryTransactionProcessor {
-    created_tx_list: HashMap<TransactionId, CreateTransactionCommand>,
-    pub accounts: HashMap<ClientId, Account>,
+    created_tx_list: TxStore,
+    /// Hot accounts, cold ones are in `tiers` when tiering is enabled
+    accounts: HashMap<ClientId, Account>,
+    tiers: Option<AccountTiers>,
+    pub stats: PipelineStats,
+    suspense: Option<Suspense>,
+    balance_caps: BalanceCaps,
+    withdrawal_rules: WithdrawalRules,
+    kyc: Option<KycRules>,
+    watchlist: Watchlist,
+    /// Retire records of resolved and charged back transactions
+    gc_settled_txs: bool,
+    /// Accounts are still locked by chargebacks, but locks are not enforced
+    defer_locks: bool,
+    zero_amounts: ZeroAmountPolicy,
+    dispute_flow: DisputeFlow,
+    tx_ids: TxIdWatermarks,
+    /// Last modify action applied to each transaction, when repeated ones are skipped
+    last_modifies: Option<HashMap<TransactionId, ModifyTransactionAction>>,
+    /// Created transactions of each client, in order of creation
+    client_index: Option<HashMap<ClientId, Vec<TransactionId>>>,
+    metrics: MetricsHook,
+    /// History, ledger, projections and other subscribers of applied events
+    bus: EventBus,
+    extensions: Vec<Box<dyn AccountExtension>>,
+    period: PeriodId,
+    period_events: u64,
+    /// Changes since the first open savepoint
+    journal: Journal,
 }
 
-impl TransactionProcessor for InMemoryTransactionProcessor {
-    fn process_transaction(
+impl InMemoryTransactionProcessor {
+    /// Pre-sizes internal maps, so huge runs avoid repeated rehashing
+    pub fn with_capacity(clients: usize, txs: usize) -> Self {
+        Self {
+            created_tx_list: TxStore::with_capacity(txs),
+            accounts: HashMap::with_capacity(clients),
+            ..Default::default()
+        }
+    }
+
+    /// Keeps only existence of withdrawals created from now on, see [`TxStore::compact`]
+    pub fn with_compact_txs(mut self) -> Self {
+        self.created_tx_list.compact_withdrawals();
+        self
+    }
+
+    /// Additionally records every applied event, so statements can be produced
+    pub fn with_history(mut self) -> Self {
+        self.bus.subscribe(EventHistory::default());
+        self
+    }
+
+    pub fn history(&self) -> Option<&EventHistory> {
+        self.bus.get()
+    }
+
+    /// Registers projection, that is updated after every applied event
+    pub fn with_projection<T: Projection>(mut self, projection: T) -> Self {
+        self.bus.subscribe(projection);
+        self
+    }
+
+    pub fn projection<T: Projection>(&self) -> Option<&T> {
+        self.bus.get()
+    }
+
+    pub fn projection_mut<T: Projection>(&mut self) -> Option<&mut T> {
+        self.bus.get_mut()
+    }
+
+    /// Updates custom field of accounts after every applied event, before
+    /// subscribers are notified
+    pub fn with_extension<T: AccountExtension + 'static>(mut self, extension: T) -> Self {
+        self.extensions.push(Box::new(extension));
+        self
+    }
+
+    /// Sets custom field of existing account, e.g. from middleware in front
+    /// of the processor. Returns `false` if the account doesn't exist.
+    pub fn set_extension(&mut self, client_id: ClientId, key: &str, value: Value) -> bool {
+        self.promote(client_id);
+        let Some(acc) = self.accounts.get_mut(&client_id) else {
+            return false;
+        };
+        self.journal.record(|| Undo::Account {
+            client_id,
+            previous: Some(Box::new(acc.clone())),
+        });
+        acc.set_extension(key, value);
+        true
+    }
+
+    /// Moves case of open dispute to `status`, see [`DisputeCase`]
+    pub fn set_case_status(
+        &mut self,
+        client_id: ClientId,
+        tx_id: TransactionId,
+        status: CaseStatus,
+    ) -> Result<(), AdminError> {
+        self.update_case(client_id, tx_id, |case| case.status = status)
+    }
+
+    /// Assigns case of open dispute to somebody, or nobody with `None`
+    pub fn assign_case(
+        &mut self,
+        client_id: ClientId,
+        tx_id: TransactionId,
+        assignee: Option<String>,
+    ) -> Result<(), AdminError> {
+        self.update_case(client_id, tx_id, |case| case.assignee = assignee)
+    }
+
+    /// Appends a note to the case of open dispute
+    pub fn add_case_note(
+        &mut self,
+        client_id: ClientId,
+        tx_id: TransactionId,
+        note: impl Into<String>,
+    ) -> Result<(), AdminError> {
+        self.update_case(client_id, tx_id, |case| case.notes.push(note.into()))
+    }
+
+    fn update_case(
+        &mut self,
+        client_id: ClientId,
+        tx_id: TransactionId,
+        update: impl FnOnce(&mut DisputeCase),
+    ) -> Result<(), AdminError> {
+        self.promote(client_id);
+        let acc = self
+            .accounts
+            .get_mut(&client_id)
+            .ok_or(AdminError::UnknownClient(client_id))?;
+        if !acc.is_under_dispute(tx_id) {
+            return Err(AdminError::NotDisputed { client_id, tx_id });
+        }
+        self.journal.record(|| Undo::Account {
+            client_id,
+            previous: Some(Box::new(acc.clone())),
+        });
+        if let Some(case) = acc.dispute_case_mut(tx_id) {
+            update(case);
+        }
+        Ok(())
+    }
+
+    /// Subscribes to applied events, e.g. to send webhooks or metrics
+    pub fn with_subscriber<S: EventSubscriber>(mut self, subscriber: S) -> Self {
+        self.bus.subscribe(subscriber);
+        self
+    }
+
+    pub fn subscriber<S: EventSubscriber>(&self) -> Option<&S> {
+        self.bus.get()
+    }
+
+    /// Additionally posts every applied event to double-entry ledger
+    pub fn with_double_entry(mut self) -> Self {
+        self.bus.subscribe(Ledger::default());
+        self
+    }
+
+    pub fn ledger(&self) -> Option<&Ledger> {
+        self.bus.get()
+    }
+
+    /// Modify rows referencing unknown transactions are parked instead of
+    /// rejected, and re-attempted once referenced transaction arrives
+    pub fn with_suspense(mut self) -> Self {
+        self.suspense = Some(Suspense::default());
+        self
+    }
+
+    /// Deposits exceeding the cap are rejected, or held in suspense
+    /// with [`OverCapPolicy::Suspend`], when suspense is enabled
+    pub fn with_balance_caps(mut self, caps: BalanceCaps) -> Self {
+        self.balance_caps = caps;
+        self
+    }
+
+    /// Rejects withdrawals below the minimum or not in multiples of the denomination
+    pub fn with_withdrawal_rules(mut self, rules: WithdrawalRules) -> Self {
+        self.withdrawal_rules = rules;
+        self
+    }
+
+    /// Transactions of unverified clients are limited by `rules`
+    pub fn with_kyc(mut self, rules: KycRules) -> Self {
+        self.kyc = Some(rules);
+        self
+    }
+
+    /// Transactions of `clients` are queued for review instead of being applied,
+    /// see [`Self::approve_review`] and [`Self::reject_review`]
+    pub fn with_watchlist(mut self, clients: impl IntoIterator<Item = ClientId>) -> Self {
+        self.watchlist.set_clients(clients);
+        self
+    }
+
+    pub fn watch(&mut self, client_id: ClientId) {
+        self.watchlist.watch(client_id);
+    }
+
+    /// Transactions of the client, that are already queued, still wait for review
+    pub fn unwatch(&mut self, client_id: ClientId) {
+        self.watchlist.unwatch(client_id);
+    }
+
+    /// Transactions of watched clients waiting for review, oldest first
+    pub fn pending_reviews(&self) -> impl Iterator<Item = &PendingReview> {
+        self.watchlist.pending()
+    }
+
+    /// Releases queued transaction, and processes it as if it just arrived,
+    /// except it's not diverted again. `None` if there is no such review.
+    pub fn approve_review(
+        &mut self,
+        id: ReviewId,
+    ) -> Option<Result<TransactionOutcome, TransactionProcessError>> {
+        let review = self.watchlist.approve(id)?;
+        let result = self.process_parking(
+            review.tx_id,
+            review.client_id,
+            review.amount,
+            review.kind.clone(),
+            review.source.as_deref(),
+            review.trace_id.as_deref(),
+        );
+        self.metrics
+            .on_result(review.client_id, &review.kind, &result);
+        Some(result)
+    }
+
+    /// Drops queued transaction, it is never applied
+    pub fn reject_review(&mut self, id: ReviewId) -> Option<PendingReview> {
+        self.watchlist.reject(id)
+    }
+
+    /// Drops records of resolved, charged back, captured and voided
+    /// transactions, see [`TxStore::retire`]. Resolved transactions
+    /// cannot be disputed again, nor charged back ones reinstated.
+    pub fn with_settled_tx_gc(mut self) -> Self {
+        self.gc_settled_txs = true;
+        self
+    }
+
+    /// Backfill mode for replays of historical archives into a fresh ledger:
+    /// chargebacks still lock accounts, but transactions of locked accounts
+    /// are accepted, as later history happened regardless of the lock.
+    /// Locks are enforced again after [`Self::enforce_locks`].
+    pub fn with_deferred_locks(mut self) -> Self {
+        self.defer_locks = true;
+        self
+    }
+
+    /// Skips or rejects transactions created with zero amount, see [`ZeroAmountPolicy`]
+    pub fn with_zero_amounts(mut self, policy: ZeroAmountPolicy) -> Self {
+        self.zero_amounts = policy;
+        self
+    }
+
+    /// Spills accounts idle for `policy.idle_after` transactions to a side
+    /// table of compactly encoded accounts, promoting them back on their next
+    /// transaction, so long-tail client populations take less memory.
+    /// [`Self::accounts`] holds only hot accounts, while trait methods see
+    /// both tiers.
+    pub fn with_tiering(mut self, policy: TieringPolicy) -> Self {
+        self.tiers = Some(AccountTiers::new(policy));
+        self
+    }
+
+    /// Sizes of account tiers, `None` unless enabled by [`Self::with_tiering`]
+    pub fn tier_stats(&self) -> Option<TierStats> {
+        self.tiers.as_ref().map(|tiers| tiers.stats(&self.accounts))
+    }
+
+    fn promote(&mut self, client_id: ClientId) {
+        if let Some(tiers) = &mut self.tiers {
+            tiers.promote(client_id, &mut self.accounts);
+        }
+    }
+
+    /// Requires representment before chargeback with [`DisputeFlow::Strict`]
+    pub fn with_dispute_flow(mut self, flow: DisputeFlow) -> Self {
+        self.dispute_flow = flow;
+        self
+    }
+
+    /// Rejects created transactions with ids lower than already accepted ones,
+    /// see [`TxIdOrder`]
+    pub fn with_tx_id_order(mut self, order: TxIdOrder) -> Self {
+        self.tx_ids = TxIdWatermarks::new(order);
+        self
+    }
+
+    /// Modify rows repeating the last action applied to their transaction,
+    /// e.g. upstream retries of a resolve, are skipped with
+    /// [`AccountCommandError::RepeatedModifySkipped`] instead of rejected
+    pub fn with_idempotent_modifies(mut self) -> Self {
+        self.last_modifies = Some(HashMap::new());
+        self
+    }
+
+    /// Maintains index of created transactions by client, see [`Self::client_transactions`]
+    pub fn with_client_index(mut self) -> Self {
+        self.client_index = Some(HashMap::new());
+        self
+    }
+
+    /// Reports outcomes, account locks and stage timings to `metrics`,
+    /// replacing previously registered ones
+    pub fn with_metrics(mut self, metrics: impl ProcessorMetrics) -> Self {
+        self.metrics.set(metrics);
+        self
+    }
+
+    pub fn metrics<M: ProcessorMetrics>(&self) -> Option<&M> {
+        self.metrics.get()
+    }
+
+    /// Ends backfill started with [`Self::with_deferred_locks`], so transactions
+    /// of accounts locked during the replay are rejected from now on
+    pub fn enforce_locks(&mut self) {
+        self.defer_locks = false;
+    }
+
+    /// Closes the current period: finalizes balances of all accounts,
+    /// and starts the next period, into which balances roll over. Recorded
+    /// history tags events with their period. Intake is frozen for the
+    /// duration of the close, as no transaction can be processed meanwhile.
+    pub fn close_period(&mut self) -> PeriodClose {
+        let close = PeriodClose {
+            period: self.period,
+            events: self.period_events,
+            balances: TransactionProcessor::accounts(self)
+                .map(|(client_id, acc)| (client_id, Balance::of(acc)))
+                .collect(),
+        };
+        self.period += 1;
+        self.period_events = 0;
+        if let Some(history) = self.bus.get_mut::<EventHistory>() {
+            history.start_period();
+        }
+        close
+    }
+
+    pub fn period(&self) -> PeriodId {
+        self.period
+    }
+
+    /// Collapses recorded history before `cutoff`, see [`EventHistory::compact`]
+    pub fn compact_history(&mut self, cutoff: EventSeq) -> Option<CompactionReport> {
+        self.bus
+            .get_mut::<EventHistory>()
+            .map(|history| history.compact(cutoff))
+    }
+
+    /// Statement of client events with sequence numbers in `range`,
+    /// `None` if history is not recorded
+    pub fn statement(&self, client_id: ClientId, range: Range<EventSeq>) -> Option<Statement> {
+        self.history()
+            .map(|history| history.statement(client_id, range))
+    }
+
+    /// Transactions created for the client, oldest first, without scanning
+    /// all transactions. `None` unless enabled by [`Self::with_client_index`].
+    pub fn client_transactions(&self, client_id: ClientId) -> Option<&[TransactionId]> {
+        let index = self.client_index.as_ref()?;
+        Some(index.get(&client_id).map_or(&[], Vec::as_slice))
+    }
+
+    /// Created transactions, with `None` for retired ones, so state can be
+    /// handed over to another process, see [`crate::bin_utils::handover`]
+    pub fn created_transactions(
+        &self,
+    ) -> impl Iterator<Item = (TransactionId, Option<CreateTransactionCommand>)> + '_ {
+        self.created_tx_list
+            .ids()
+            .map(|tx_id| (tx_id, self.created_tx_list.get(tx_id)))
+    }
+
+    /// Puts in account handed over by another process, replacing the existing one
+    pub fn restore_account(&mut self, client_id: ClientId, account: Account) {
+        if let Some(tiers) = &mut self.tiers {
+            tiers.promote(client_id, &mut self.accounts);
+        }
+        self.accounts.insert(client_id, account);
+    }
+
+    /// Puts in transaction handed over by another process, `None` for
+    /// a retired one. Its client is indexed by [`Self::restore_client_transactions`].
+    pub fn restore_transaction(
+        &mut self,
+        tx_id: TransactionId,
+        command: Option<CreateTransactionCommand>,
+    ) {
+        let retired = command.is_none();
+        self.created_tx_list.restore(tx_id, command, retired);
+    }
+
+    /// Clients with their created transactions, oldest first, `None` unless
+    /// enabled by [`Self::with_client_index`]
+    pub fn indexed_clients(
+        &self,
+    ) -> Option<impl Iterator<Item = (ClientId, &[TransactionId])> + '_> {
+        let index = self.client_index.as_ref()?;
+        Some(
+            index
+                .iter()
+                .map(|(client_id, txs)| (*client_id, txs.as_slice())),
+        )
+    }
+
+    /// Puts in transactions of the client handed over by another process,
+    /// when this processor keeps the client index
+    pub fn restore_client_transactions(&mut self, client_id: ClientId, txs: Vec<TransactionId>) {
+        if let Some(index) = &mut self.client_index {
+            index.insert(client_id, txs);
+        }
+    }
+
+    /// Highest ids of accepted transactions, see [`TxIdWatermarks::iter`]
+    pub fn tx_id_watermarks(&self) -> impl Iterator<Item = (Option<ClientId>, TransactionId)> + '_ {
+        self.tx_ids.iter()
+    }
+
+    /// Puts in the highest id accepted by another process, see [`TxIdWatermarks::restore`]
+    pub fn restore_tx_id_watermark(&mut self, client_id: Option<ClientId>, tx_id: TransactionId) {
+        self.tx_ids.restore(client_id, tx_id);
+    }
+
+    /// Rows waiting in a queue for a transaction or a decision, that would be
+    /// lost if state was handed over now: parked in suspense, held over the
+    /// balance cap or queued for review
+    pub fn pending_rows(&self) -> Option<&'static str> {
+        if let Some(suspense) = &self.suspense
+            && suspense.has_pending()
+        {
+            return Some("rows in suspense");
+        }
+        self.watchlist
+            .pending()
+            .next()
+            .map(|_| "transactions queued for review")
+    }
+
+    /// Memory used by created transactions storage
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.created_tx_list.memory_stats()
+    }
+
+    fn process_once(
         &mut self,
         tx_id: TransactionId,
         client_id: ClientId,
         amount: Option<Decimal>,
-        kind: TransactionKind,
-    ) -> Result<(), TransactionProcessError> {
-        let tx_entry = self.created_tx_list.entry(tx_id);
-        let cmd = AccountCommand::parse_command(&tx_entry, kind, amount)?;
+        kind: &TransactionKind,
+        source: Option<&str>,
+        trace_id: Option<&str>,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let started = Instant::now();
+        self.promote(client_id);
+        // checked first, as transaction may be retired after its last action
+        if let Some(last_modifies) = &self.last_modifies
+            && let Some(action) = kind.modify_action()
+            && last_modifies.get(&tx_id) == Some(&action)
+        {
+            return Err(AccountCommandError::RepeatedModifySkipped { action }.into());
+        }
+        if let Some(action) = kind.modify_action()
+            && self.created_tx_list.is_retired(tx_id)
+        {
+            return Err(AccountCommandError::TransactionRetired { action }.into());
+        }
+        let existing_tx = self.created_tx_list.get(tx_id);
+        let cmd = AccountCommand::parse_command(
+            tx_id,
+            existing_tx.as_ref(),
+            kind,
+            amount,
+            self.zero_amounts,
+        )?;
+        if let AccountCommand::CreateTx(command) = &cmd
+            && self.created_tx_list.is_retired(tx_id)
+        {
+            return Err(AccountCommandError::DuplicateTransaction {
+                action: command.action,
+            }
+            .into());
+        }
+        if let AccountCommand::CreateTx(_) = &cmd {
+            self.tx_ids.check(client_id, tx_id)?;
+        }
+        if let AccountCommand::CreateTx(command) = &cmd
+            && command.action == CreateTransactionAction::Withdraw
+        {
+            self.withdrawal_rules.check(command.amount.amount())?;
+        }
+        self.journal.record(|| Undo::Account {
+            client_id,
+            previous: self.accounts.get(&client_id).cloned().map(Box::new),
+        });
         let acc = self.accounts.entry(client_id).or_default();
-        match cmd {
-            AccountCommand::CreateTx(command) => {
-                let evt = acc.handle_create_transaction(command.clone())?;
-                acc.apply(&evt);
-                // insert only when command succeeded
-                tx_entry.insert_entry(command);
+        let validated = Instant::now();
+        self.stats
+            .record(kind, Stage::Validation, validated - started);
+        self.metrics
+            .on_stage(kind, Stage::Validation, validated - started);
+        let evt = match &cmd {
+            AccountCommand::CreateTx(command) if self.defer_locks => {
+                acc.handle_create_transaction_ignoring_lock(command.clone())?
             }
+            AccountCommand::ModifyTx(command) if self.defer_locks => {
+                acc.handle_modify_transaction_ignoring_lock(command.clone())?
+            }
+            AccountCommand::CreateTx(command) => acc.handle_create_transaction(command.clone())?,
             AccountCommand::ModifyTx(command) => {
-                let evt = acc.handle_modify_transaction(command)?;
-                acc.apply(&evt);
+                acc.handle_modify_transaction_in(command.clone(), self.dispute_flow)?
             }
         };
+        if let Some(cap) = self.balance_caps.cap(client_id) {
+            acc.check_balance_cap(&evt, cap)?;
+        }
+        if let Some(kyc) = &mut self.kyc {
+            kyc.check(client_id, &evt)?;
+        }
+        let handled = Instant::now();
+        self.stats
+            .record(kind, Stage::AccountHandling, handled - validated);
+        self.metrics
+            .on_stage(kind, Stage::AccountHandling, handled - validated);
+        let was_locked = acc.locked();
+        acc.try_apply(&evt)?;
+        let outcome = TransactionOutcome::applied(evt.kind(), acc, was_locked);
+        if acc.locked() && !was_locked {
+            self.metrics.on_account_locked(client_id);
+        }
+        for extension in &self.extensions {
+            if let Some(value) = extension.update(client_id, &evt, acc) {
+                acc.set_extension(extension.key(), value);
+            }
+        }
+        if let Some(kyc) = &mut self.kyc {
+            kyc.record(client_id, &evt);
+        }
+        self.period_events += 1;
+        self.bus.publish(&AppliedEvent {
+            client_id,
+            event: &evt,
+            account: acc,
+            source,
+            trace_id,
+        });
+        if let (AccountCommand::ModifyTx(command), Some(last_modifies)) =
+            (&cmd, &mut self.last_modifies)
+        {
+            let previous = last_modifies.insert(tx_id, command.action);
+            self.journal.record(|| Undo::LastModify { tx_id, previous });
+        }
+        if let AccountCommand::CreateTx(_) = &cmd {
+            self.tx_ids.record(client_id, tx_id);
+            if let Some(client_index) = &mut self.client_index {
+                client_index.entry(client_id).or_default().push(tx_id);
+                self.journal.record(|| Undo::ClientIndex { client_id });
+            }
+        }
+        self.journal.record(|| Undo::CreatedTx {
+            tx_id,
+            previous: self.created_tx_list.get(tx_id),
+            retired: self.created_tx_list.is_retired(tx_id),
+        });
+        match cmd {
+            // insert only when command succeeded
+            AccountCommand::CreateTx(command) => self.created_tx_list.insert(command),
+            AccountCommand::ModifyTx(command)
+                if self.gc_settled_txs
+                    && matches!(
+                        command.action,
+                        ModifyTransactionAction::Resolve
+                            | ModifyTransactionAction::Chargeback
+                            | ModifyTransactionAction::Capture
+                            | ModifyTransactionAction::Void
+                    ) =>
+            {
+                self.created_tx_list.retire(tx_id)
+            }
+            AccountCommand::ModifyTx(_) => {}
+        }
+        let applied = handled.elapsed();
+        self.stats.record(kind, Stage::Apply, applied);
+        self.metrics.on_stage(kind, Stage::Apply, applied);
+        Ok(outcome)
+    }
+
+    /// Applies event of an operator to existing account, see [`AdminOps`]
+    fn apply_admin(
+        &mut self,
+        client_id: ClientId,
+        event: AccountEvent,
+    ) -> Result<TransactionOutcome, AdminError> {
+        self.promote(client_id);
+        let Some(acc) = self.accounts.get_mut(&client_id) else {
+            return Err(AdminError::UnknownClient(client_id));
+        };
+        acc.check_apply(&event)?;
+        self.journal.record(|| Undo::Account {
+            client_id,
+            previous: Some(Box::new(acc.clone())),
+        });
+        let was_locked = acc.locked();
+        acc.apply(&event);
+        let outcome = TransactionOutcome::applied(event.kind(), acc, was_locked);
+        for extension in &self.extensions {
+            if let Some(value) = extension.update(client_id, &event, acc) {
+                acc.set_extension(extension.key(), value);
+            }
+        }
+        self.period_events += 1;
+        self.bus.publish(&AppliedEvent {
+            client_id,
+            event: &event,
+            account: acc,
+            source: Some(ADMIN_SOURCE),
+            trace_id: None,
+        });
+        Ok(outcome)
+    }
+
+    /// Processes transaction, parking it in suspense if enabled and it cannot be applied yet
+    fn process_parking(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+        source: Option<&str>,
+        trace_id: Option<&str>,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let result = self.process_once(tx_id, client_id, amount, &kind, source, trace_id);
+        let Some(suspense) = &mut self.suspense else {
+            return result;
+        };
+        match result {
+            // transactions, that were created already, won't arrive again
+            Err(TransactionProcessError::CommandErr(AccountCommandError::ExistingTxRequired {
+                ..
+            })) if !self.created_tx_list.contains(tx_id) => {
+                suspense.park(SuspendedRow {
+                    tx_id,
+                    client_id,
+                    kind,
+                    source: source.map(str::to_string),
+                    trace_id: trace_id.map(str::to_string),
+                });
+                Ok(TransactionOutcome::deferred(self.accounts.get(&client_id)))
+            }
+            Err(TransactionProcessError::AccountErr(AccountError::BalanceCapExceeded {
+                ..
+            })) if self.balance_caps.policy == OverCapPolicy::Suspend => {
+                let row = SuspendedRow {
+                    tx_id,
+                    client_id,
+                    kind,
+                    source: source.map(str::to_string),
+                    trace_id: trace_id.map(str::to_string),
+                };
+                suspense.hold_over_cap(row, amount.unwrap_or_default());
+                Ok(TransactionOutcome::deferred(self.accounts.get(&client_id)))
+            }
+            Ok(outcome) => {
+                for row in suspense.take(tx_id) {
+                    let result = self.process_once(
+                        row.tx_id,
+                        row.client_id,
+                        None,
+                        &row.kind,
+                        row.source.as_deref(),
+                        row.trace_id.as_deref(),
+                    );
+                    if let Err(err) = result
+                        && let Some(suspense) = &mut self.suspense
+                    {
+                        suspense.failed(row, err.code());
+                    }
+                }
+                Ok(outcome)
+            }
+            err => err,
+        }
+    }
+}
+
+impl AdminOps for InMemoryTransactionProcessor {
+    fn unlock(&mut self, client_id: ClientId) -> Result<TransactionOutcome, AdminError> {
+        match TransactionProcessor::account(self, client_id) {
+            Some(acc) if acc.locked() => self.apply_admin(client_id, AccountEvent::unlocked()),
+            Some(_) => Err(AdminError::NotLocked(client_id)),
+            None => Err(AdminError::UnknownClient(client_id)),
+        }
+    }
+
+    fn adjust(
+        &mut self,
+        client_id: ClientId,
+        amount: Decimal,
+    ) -> Result<TransactionOutcome, AdminError> {
+        self.apply_admin(client_id, AccountEvent::adjusted(amount))
+    }
+
+    fn merge(&mut self, mut other: Self) -> Result<(), MergeConflict> {
+        if let Some((client_id, _)) = TransactionProcessor::accounts(&other)
+            .find(|(client_id, _)| TransactionProcessor::account(self, *client_id).is_some())
+        {
+            return Err(MergeConflict::SameClient(client_id));
+        }
+        // clients are disjoint, so a shared tx id belongs to two clients
+        if let Some(tx_id) = other
+            .created_tx_list
+            .ids()
+            .find(|tx_id| self.created_tx_list.contains(*tx_id))
+        {
+            return Err(MergeConflict::DuplicateTransaction(tx_id));
+        }
+        // subscribers of either side would miss events of the other one
+        let unmergeable = [
+            (!self.bus.is_empty() || !other.bus.is_empty(), "subscribers"),
+            (
+                self.journal.is_active() || other.journal.is_active(),
+                "an open savepoint",
+            ),
+            (self.period != other.period, "different periods"),
+            (other.suspense.is_some(), "suspense"),
+            (!other.watchlist.is_empty(), "a watchlist"),
+            (other.kyc.is_some(), "KYC rules"),
+        ];
+        if let Some((_, state)) = unmergeable.into_iter().find(|(present, _)| *present) {
+            return Err(MergeConflict::UnmergeableState(state));
+        }
+        let mismatched = [
+            (
+                self.client_index.is_some() != other.client_index.is_some(),
+                "a client index",
+            ),
+            (
+                self.last_modifies.is_some() != other.last_modifies.is_some(),
+                "last modifies of transactions",
+            ),
+        ];
+        if let Some((_, index)) = mismatched.into_iter().find(|(differs, _)| *differs) {
+            return Err(MergeConflict::MismatchedIndex(index));
+        }
+        if let Some(tiers) = &mut other.tiers {
+            tiers.promote_all(&mut other.accounts);
+        }
+
+        self.accounts.extend(other.accounts);
+        self.created_tx_list.merge(other.created_tx_list);
+        self.tx_ids.merge(other.tx_ids);
+        if let (Some(last_modifies), Some(other)) = (&mut self.last_modifies, other.last_modifies) {
+            last_modifies.extend(other);
+        }
+        if let (Some(client_index), Some(other)) = (&mut self.client_index, other.client_index) {
+            client_index.extend(other);
+        }
+        self.period_events += other.period_events;
+        Ok(())
+    }
+
+    fn erase_client(&mut self, client_id: ClientId) -> Result<ErasureTombstone, AdminError> {
+        let history = self
+            .bus
+            .get::<EventHistory>()
+            .ok_or(AdminError::HistoryNotRecorded)?;
+        let seq = history.next_seq();
+        let events_anonymized = history
+            .for_client(client_id)
+            .filter(|entry| entry.source.is_some())
+            .count();
+        // history anonymizes the client, once it receives the erasure event
+        self.apply_admin(client_id, AccountEvent::erased())?;
+        Ok(ErasureTombstone {
+            client: client_id,
+            seq,
+            events_anonymized,
+        })
+    }
+}
+
+impl TransactionProcessor for InMemoryTransactionProcessor {
+    fn process_transaction(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        self.process_transaction_from(tx_id, client_id, amount, kind, None)
+    }
+
+    fn process_transaction_from(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+        source: Option<&str>,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        self.process_transaction_traced(tx_id, client_id, amount, kind, source, None)
+    }
+
+    fn process_transaction_traced(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+        source: Option<&str>,
+        trace_id: Option<&str>,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        self.promote(client_id);
+        // reported to metrics once reviewed
+        if self.watchlist.is_watched(client_id) {
+            self.watchlist
+                .divert(tx_id, client_id, amount, kind, source, trace_id);
+            return Ok(TransactionOutcome::deferred(self.accounts.get(&client_id)));
+        }
+        let result = if self.metrics.is_set() {
+            let metered = kind.clone();
+            let result = self.process_parking(tx_id, client_id, amount, kind, source, trace_id);
+            self.metrics.on_result(client_id, &metered, &result);
+            result
+        } else {
+            self.process_parking(tx_id, client_id, amount, kind, source, trace_id)
+        };
+        if let Some(tiers) = &mut self.tiers {
+            // spilled accounts can't be restored by rollback
+            tiers.touch(client_id, &mut self.accounts, !self.journal.is_active());
+        }
+        result
+    }
+
+    fn accounts(&self) -> impl Iterator<Item = (ClientId, &Account)> {
+        self.accounts
+            .iter()
+            .map(|(client_id, acc)| (*client_id, acc))
+            .chain(self.tiers.iter().flat_map(AccountTiers::iter))
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts
+            .get(&client_id)
+            .or_else(|| self.tiers.as_ref()?.get(client_id))
+    }
+
+    fn accounts_page(&self, cursor: Option<AccountsCursor>, limit: usize) -> AccountsPage<'_> {
+        AccountsPage::probe(cursor, limit, |client_id| {
+            TransactionProcessor::account(self, client_id)
+        })
+    }
+
+    fn stats(&self) -> Option<&PipelineStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut PipelineStats> {
+        Some(&mut self.stats)
+    }
+
+    fn flags(&self) -> Vec<FraudFlag> {
+        self.projection::<FraudHeuristics>()
+            .map(FraudHeuristics::flags)
+            .unwrap_or_default()
+    }
+
+    fn suspense(&self) -> Option<SuspenseReport> {
+        self.suspense.as_ref().map(Suspense::report)
+    }
+
+    fn compliance(&self) -> Option<ComplianceReport> {
+        self.kyc.as_ref().map(KycRules::report)
+    }
+
+    fn reviews(&self) -> Option<ReviewReport> {
+        (!self.watchlist.is_empty()).then(|| self.watchlist.report())
+    }
+
+    /// Accounts, transactions and accumulated totals are kept. Sections for
+    /// components that are not enabled can't be applied: `fraud` without fraud
+    /// heuristics, `unverified` without KYC rules, or `over_cap` of `suspend`
+    /// without suspense.
+    fn reload_config(&mut self, config: &RiskConfig) -> Result<(), DisabledSections> {
+        let disabled = [
+            (
+                config.fraud.is_some() && self.projection::<FraudHeuristics>().is_none(),
+                "fraud thresholds",
+            ),
+            (
+                config.unverified.is_some() && self.kyc.is_none(),
+                "limits of unverified clients",
+            ),
+            (
+                config.over_cap == Some(OverCapPolicy::Suspend) && self.suspense.is_none(),
+                "suspense of deposits over the cap",
+            ),
+        ];
+        let disabled: Vec<_> = disabled
+            .into_iter()
+            .filter_map(|(disabled, section)| disabled.then_some(section))
+            .collect();
+        if !disabled.is_empty() {
+            return Err(DisabledSections(disabled));
+        }
+        if let Some(thresholds) = config.fraud
+            && let Some(heuristics) = self.projection_mut::<FraudHeuristics>()
+        {
+            heuristics.thresholds = thresholds;
+        }
+        if config.balance_cap.is_some() {
+            self.balance_caps.global = config.balance_cap;
+        }
+        if let Some(policy) = config.over_cap {
+            self.balance_caps.policy = policy;
+        }
+        if let Some(rules) = &config.withdrawals {
+            self.withdrawal_rules = rules.clone();
+        }
+        if let Some(clients) = &config.watchlist {
+            self.watchlist.set_clients(clients.iter().copied());
+        }
+        if let (Some(limits), Some(kyc)) = (&config.unverified, &mut self.kyc) {
+            kyc.set_limits(limits.clone());
+        }
+        if let Some(flow) = config.dispute_flow {
+            self.dispute_flow = flow;
+        }
         Ok(())
     }
+
+    /// Available when no state outside of accounts and transactions is kept:
+    /// history and other subscribers, suspense, KYC rules and watchlist
+    fn savepoint(&mut self) -> Option<Savepoint> {
+        if !self.bus.is_empty()
+            || self.suspense.is_some()
+            || self.kyc.is_some()
+            || !self.watchlist.is_empty()
+        {
+            return None;
+        }
+        Some(self.journal.open(self.tx_ids.clone(), self.period_events))
+    }
+
+    fn rollback_to(&mut self, savepoint: Savepoint) {
+        let Some((frame, undo)) = self.journal.rollback(savepoint) else {
+            return;
+        };
+        for undo in undo {
+            match undo {
+                Undo::Account {
+                    client_id,
+                    previous: Some(account),
+                } => {
+                    self.accounts.insert(client_id, *account);
+                }
+                Undo::Account {
+                    client_id,
+                    previous: None,
+                } => {
+                    self.accounts.remove(&client_id);
+                }
+                Undo::CreatedTx {
+                    tx_id,
+                    previous,
+                    retired,
+                } => self.created_tx_list.restore(tx_id, previous, retired),
+                Undo::LastModify { tx_id, previous } => {
+                    if let Some(last_modifies) = &mut self.last_modifies {
+                        match previous {
+                            Some(action) => last_modifies.insert(tx_id, action),
+                            None => last_modifies.remove(&tx_id),
+                        };
+                    }
+                }
+                Undo::ClientIndex { client_id } => {
+                    if let Some(txs) = self
+                        .client_index
+                        .as_mut()
+                        .and_then(|index| index.get_mut(&client_id))
+                    {
+                        txs.pop();
+                    }
+                }
+            }
+        }
+        self.tx_ids = frame.tx_ids;
+        self.period_events = frame.period_events;
+    }
+
+    fn release(&mut self, savepoint: Savepoint) {
+        self.journal.release(savepoint);
+    }
+
+    fn map_sizes(&self) -> Vec<(&'static str, usize)> {
+        let mut sizes = vec![
+            ("accounts", self.accounts.len()),
+            ("transactions", self.created_tx_list.memory_stats().records),
+        ];
+        if let Some(stats) = self.tier_stats() {
+            sizes.push(("cold accounts", stats.cold));
+        }
+        if let Some(history) = self.history() {
+            sizes.push(("history events", history.len()));
+        }
+        sizes
+    }
+
+    fn flush_subscribers(&mut self) -> io::Result<()> {
+        self.bus.flush()
+    }
 }
 
 #[cfg(test)]
@@ -47,7 +1092,12 @@ mod tests {
 
     use rust_decimal::prelude::FromPrimitive;
 
-    use crate::command::{AccountCommandError, ModifyTransactionAction};
+    use crate::{
+        account::AccountEventKind,
+        assert_account,
+        command::{AccountCommandError, ModifyTransactionAction},
+        processor::RejectKind,
+    };
 
     use super::*;
 
@@ -70,7 +1120,7 @@ mod tests {
                 TransactionKind::Deposit,
             )
             .unwrap();
-        assert_eq!(processor.accounts.len(), 2);
+        assert_eq!(processor.accounts().count(), 2);
         assert_eq!(processor.created_tx_list.len(), 2);
 
         processor
@@ -81,16 +1131,11 @@ mod tests {
                 TransactionKind::Dispute,
             )
             .unwrap();
-        assert_eq!(processor.accounts.len(), 2);
+        assert_eq!(processor.accounts().count(), 2);
         assert_eq!(processor.created_tx_list.len(), 2);
 
-        let a1 = processor.accounts.get(&1).unwrap();
-        assert_eq!(a1.available(), Decimal::from_u32(10).unwrap());
-        assert_eq!(a1.held(), Decimal::from_u32(0).unwrap());
-
-        let a2 = processor.accounts.get(&2).unwrap();
-        assert_eq!(a2.available(), Decimal::from_u32(0).unwrap());
-        assert_eq!(a2.held(), Decimal::from_u32(10).unwrap());
+        assert_account!(processor.account(1).unwrap(), available: 10, held: 0);
+        assert_account!(processor.account(2).unwrap(), available: 0, held: 10, open_disputes: [2]);
 
         let err = processor
             .process_transaction(
@@ -108,4 +1153,752 @@ mod tests {
             })
         ))
     }
+    #[test]
+    fn records_history_when_enabled() {
+        let mut processor = InMemoryTransactionProcessor::default();
+        processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        assert!(processor.history().is_none());
+
+        let mut processor = InMemoryTransactionProcessor::default().with_history();
+        processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        // rejected transactions are not recorded
+        processor
+            .process_transaction(2, 1, Some(Decimal::TWO), TransactionKind::Withdrawal)
+            .unwrap_err();
+        assert_eq!(processor.history().unwrap().len(), 1);
+        let statement = processor.statement(1, 0..EventSeq::MAX).unwrap();
+        assert_eq!(statement.closing.available, Decimal::ONE);
+    }
+    #[test]
+    fn suspense_reattempts_parked_rows() {
+        let mut processor = InMemoryTransactionProcessor::default().with_suspense();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(2, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TWO), TransactionKind::Deposit)
+            .unwrap();
+
+        assert_account!(processor.account(1).unwrap(), available: 0, held: 2);
+
+        let report = processor.suspense().unwrap();
+        assert_eq!(report.parked, 2);
+        assert_eq!(report.matched, 1);
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            report.unmatched,
+            vec![SuspendedRow {
+                tx_id: 2,
+                client_id: 1,
+                kind: TransactionKind::Dispute,
+                source: None,
+                trace_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn rows_of_retired_txs_are_not_parked() {
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_suspense()
+            .with_settled_tx_gc();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TWO), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Resolve)
+            .unwrap();
+
+        let err = processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap_err();
+        assert_eq!(err.code(), "transaction_retired");
+        let report = processor.suspense().unwrap();
+        assert_eq!(report.parked, 0);
+        assert!(report.unmatched.is_empty());
+    }
+
+    #[test]
+    fn returns_outcome_of_transaction() {
+        let mut processor = InMemoryTransactionProcessor::default().with_suspense();
+        // parked until the deposit arrives
+        let outcome = processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert_eq!(outcome, TransactionOutcome::deferred(None));
+
+        let outcome = processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert_eq!(outcome.applied, Some(AccountEventKind::Deposited));
+        assert_eq!(
+            (outcome.available, outcome.held),
+            (Decimal::TEN, Decimal::ZERO)
+        );
+
+        processor
+            .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        let outcome = processor
+            .process_transaction(2, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert_eq!(outcome.applied, Some(AccountEventKind::Disputed));
+        assert_eq!(
+            (outcome.available, outcome.held),
+            (Decimal::ZERO, Decimal::new(11, 0))
+        );
+        assert!(!outcome.locked);
+
+        let outcome = processor
+            .process_transaction(2, 1, None, TransactionKind::Chargeback)
+            .unwrap();
+        assert_eq!(outcome.applied, Some(AccountEventKind::Chargedback));
+        assert!(outcome.locked);
+    }
+
+    #[test]
+    fn balance_cap_rejects_or_holds_deposits() {
+        let caps = BalanceCaps {
+            global: Some(Decimal::TEN),
+            per_client: HashMap::from([(2, Decimal::ONE)]),
+            policy: OverCapPolicy::Reject,
+        };
+        let mut processor = InMemoryTransactionProcessor::default().with_balance_caps(caps.clone());
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        let err = processor
+            .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "balance_cap_exceeded");
+        let err = processor
+            .process_transaction(3, 2, Some(Decimal::TWO), TransactionKind::PendingDeposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "balance_cap_exceeded");
+
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_suspense()
+            .with_balance_caps(BalanceCaps {
+                policy: OverCapPolicy::Suspend,
+                ..caps
+            });
+        processor
+            .process_transaction(1, 2, Some(Decimal::TWO), TransactionKind::Deposit)
+            .unwrap();
+        assert_eq!(processor.account(2).unwrap().available(), Decimal::ZERO);
+        let report = processor.suspense().unwrap();
+        assert_eq!(report.over_cap.len(), 1);
+        assert_eq!(report.over_cap[0].1, Decimal::TWO);
+    }
+
+    #[test]
+    fn deferred_locks_accept_later_history() {
+        let mut processor = InMemoryTransactionProcessor::default().with_deferred_locks();
+        let rows = [
+            (1, Some(Decimal::TEN), TransactionKind::Deposit),
+            (2, Some(Decimal::TWO), TransactionKind::Deposit),
+            (2, None, TransactionKind::Dispute),
+            (2, None, TransactionKind::Chargeback),
+            (3, Some(Decimal::ONE), TransactionKind::Withdrawal),
+        ];
+        for (tx, amount, kind) in rows {
+            processor.process_transaction(tx, 1, amount, kind).unwrap();
+        }
+        assert_account!(processor.account(1).unwrap(), available: 9, locked: true);
+
+        processor.enforce_locks();
+        let err = processor
+            .process_transaction(4, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "account_frozen");
+    }
+
+    #[test]
+    fn zero_amounts_are_accepted_skipped_or_rejected() {
+        for (policy, code) in [
+            (ZeroAmountPolicy::Accept, None),
+            (ZeroAmountPolicy::Skip, Some("zero_amount_skipped")),
+            (ZeroAmountPolicy::Reject, Some("zero_amount")),
+        ] {
+            let mut processor = InMemoryTransactionProcessor::default().with_zero_amounts(policy);
+            let result =
+                processor.process_transaction(1, 1, Some(Decimal::ZERO), TransactionKind::Deposit);
+            assert_eq!(result.err().map(|err| err.code()), code);
+            // skipped and rejected ones don't take up the tx id
+            let reused =
+                processor.process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit);
+            assert_eq!(reused.is_err(), code.is_none());
+        }
+    }
+
+    #[test]
+    fn compact_txs_keep_withdrawal_ids_only() {
+        let mut processor = InMemoryTransactionProcessor::with_capacity(1, 2).with_compact_txs();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Withdrawal)
+            .unwrap();
+        let stats = processor.created_tx_list.memory_stats();
+        assert_eq!((stats.records, stats.bitmap_entries), (1, 1));
+        let err = processor
+            .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Withdrawal)
+            .unwrap_err();
+        assert_eq!(err.code(), "duplicate_transaction");
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert_account!(processor.account(1).unwrap(), available: -1, held: 10);
+    }
+
+    #[test]
+    fn repeated_modifies_are_skipped() {
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_idempotent_modifies()
+            .with_settled_tx_gc();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        for kind in [TransactionKind::Dispute, TransactionKind::Resolve] {
+            processor
+                .process_transaction(1, 1, None, kind.clone())
+                .unwrap();
+            // retried row matches the current state, even of retired transaction
+            let err = processor.process_transaction(1, 1, None, kind).unwrap_err();
+            assert_eq!(err.code(), "repeated_modify_skipped");
+        }
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::TEN);
+        assert_eq!(processor.account(1).unwrap().held(), Decimal::ZERO);
+
+        // without the switch, repeated dispute is rejected by the account
+        let mut processor = InMemoryTransactionProcessor::default();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        let err = processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap_err();
+        assert_ne!(err.code(), "repeated_modify_skipped");
+    }
+
+    #[test]
+    fn tx_ids_going_back_are_rejected() {
+        let mut processor =
+            InMemoryTransactionProcessor::default().with_tx_id_order(TxIdOrder::Global);
+        processor
+            .process_transaction(5, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        // rejected transaction doesn't raise the watermark
+        processor
+            .process_transaction(
+                9,
+                1,
+                Some(Decimal::ONE_HUNDRED),
+                TransactionKind::Withdrawal,
+            )
+            .unwrap_err();
+        processor
+            .process_transaction(7, 1, Some(Decimal::ONE), TransactionKind::Withdrawal)
+            .unwrap();
+        let err = processor
+            .process_transaction(6, 2, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "tx_id_not_increasing");
+        // modify rows reference earlier transactions
+        processor
+            .process_transaction(5, 1, None, TransactionKind::Dispute)
+            .unwrap();
+    }
+
+    #[test]
+    fn accounts_are_paged_by_client() {
+        let mut processor = InMemoryTransactionProcessor::default();
+        for client_id in [9, 1, 5, 3] {
+            processor
+                .process_transaction(
+                    client_id as u32,
+                    client_id,
+                    Some(Decimal::ONE),
+                    TransactionKind::Deposit,
+                )
+                .unwrap();
+        }
+        let page = processor.accounts_page(None, 2);
+        let clients: Vec<_> = page.accounts.iter().map(|(client, _)| *client).collect();
+        assert_eq!(clients, [1, 3]);
+        let cursor: AccountsCursor = page.next.unwrap().to_string().parse().unwrap();
+        // cursor stays valid when accounts are created before it
+        processor
+            .process_transaction(2, 2, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        let page = processor.accounts_page(Some(cursor), 2);
+        let clients: Vec<_> = page.accounts.iter().map(|(client, _)| *client).collect();
+        assert_eq!(clients, [5, 9]);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn merge_combines_partitions() {
+        let partition = |rows: &[(TransactionId, ClientId, i64)]| {
+            let mut processor = InMemoryTransactionProcessor::default().with_client_index();
+            for (tx_id, client_id, amount) in rows {
+                processor
+                    .process_transaction(
+                        *tx_id,
+                        *client_id,
+                        Some(Decimal::from(*amount)),
+                        TransactionKind::Deposit,
+                    )
+                    .unwrap();
+            }
+            processor
+        };
+        let mut merged = partition(&[(1, 1, 10), (2, 1, 5)]);
+        merged.merge(partition(&[(3, 2, 7)])).unwrap();
+        assert_account!(merged.account(1).unwrap(), available: 15);
+        assert_account!(merged.account(2).unwrap(), available: 7);
+        assert_eq!(merged.client_transactions(2), Some([3].as_slice()));
+        // transactions of the other partition can be disputed after the merge
+        merged
+            .process_transaction(3, 2, None, TransactionKind::Dispute)
+            .unwrap();
+        assert_account!(merged.account(2).unwrap(), available: 0, held: 7);
+
+        assert_eq!(
+            merged.merge(partition(&[(4, 1, 1)])).unwrap_err(),
+            MergeConflict::SameClient(1)
+        );
+        assert_eq!(
+            merged.merge(partition(&[(1, 3, 11)])).unwrap_err(),
+            MergeConflict::DuplicateTransaction(1)
+        );
+        // the same amount doesn't make it the same transaction of another client
+        assert_eq!(
+            merged.merge(partition(&[(1, 3, 10)])).unwrap_err(),
+            MergeConflict::DuplicateTransaction(1)
+        );
+        assert!(merged.account(3).is_none());
+        assert_eq!(
+            merged
+                .merge(InMemoryTransactionProcessor::default().with_history())
+                .unwrap_err(),
+            MergeConflict::UnmergeableState("subscribers")
+        );
+        let mut with_history = InMemoryTransactionProcessor::default()
+            .with_client_index()
+            .with_history();
+        assert_eq!(
+            with_history.merge(partition(&[(5, 4, 1)])).unwrap_err(),
+            MergeConflict::UnmergeableState("subscribers")
+        );
+        assert_eq!(
+            merged
+                .merge(InMemoryTransactionProcessor::default())
+                .unwrap_err(),
+            MergeConflict::MismatchedIndex("a client index")
+        );
+        assert_eq!(merged.client_transactions(2), Some([3].as_slice()));
+    }
+
+    #[test]
+    fn rollback_to_savepoint_reverts_sub_batch() {
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_client_index()
+            .with_settled_tx_gc();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+
+        let outer = processor.savepoint().unwrap();
+        processor
+            .process_transaction(3, 2, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        let inner = processor.savepoint().unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Chargeback)
+            .unwrap();
+        processor.rollback_to(inner);
+        assert_account!(processor.account(1).unwrap(), available: 1, held: 10, locked: false);
+
+        processor
+            .process_transaction(2, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor.rollback_to(outer);
+        assert_account!(processor.account(1).unwrap(), available: 11, held: 0);
+        assert!(processor.account(2).is_none());
+        assert_eq!(processor.client_transactions(1), Some([1, 2].as_slice()));
+        // rolled back transaction can be created again
+        processor
+            .process_transaction(3, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+
+        let savepoint = processor.savepoint().unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor.release(savepoint);
+        assert_account!(processor.account(1).unwrap(), available: 2, held: 10);
+
+        // subscribers can't be rolled back
+        assert!(
+            InMemoryTransactionProcessor::default()
+                .with_history()
+                .savepoint()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn client_index_lists_created_transactions() {
+        let processor = InMemoryTransactionProcessor::default();
+        assert_eq!(processor.client_transactions(1), None);
+
+        let mut processor = InMemoryTransactionProcessor::default().with_client_index();
+        for (tx_id, client_id) in [(3, 1), (1, 2), (2, 1)] {
+            processor
+                .process_transaction(
+                    tx_id,
+                    client_id,
+                    Some(Decimal::TEN),
+                    TransactionKind::Deposit,
+                )
+                .unwrap();
+        }
+        processor
+            .process_transaction(3, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        // rejected ones are not indexed
+        processor
+            .process_transaction(
+                4,
+                1,
+                Some(Decimal::ONE_HUNDRED),
+                TransactionKind::Withdrawal,
+            )
+            .unwrap_err();
+        assert_eq!(processor.client_transactions(1), Some([3, 2].as_slice()));
+        assert_eq!(processor.client_transactions(2), Some([1].as_slice()));
+        assert_eq!(processor.client_transactions(5), Some([].as_slice()));
+    }
+
+    #[test]
+    fn settled_tx_gc_retires_records() {
+        let mut processor = InMemoryTransactionProcessor::default().with_settled_tx_gc();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        assert_eq!(processor.memory_stats().records, 0);
+        assert_eq!(processor.memory_stats().retired, 1);
+
+        // resolved transaction cannot be disputed again, nor created twice
+        let err = processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap_err();
+        assert_eq!(err.code(), "transaction_retired");
+        assert_eq!(err.reject_kind(), RejectKind::Business);
+        let err = processor
+            .process_transaction(1, 1, None, TransactionKind::Resolve)
+            .unwrap_err();
+        assert_eq!(err.code(), "transaction_retired");
+        let err = processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "duplicate_transaction");
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::TEN);
+    }
+
+    #[test]
+    fn reload_config_keeps_state() {
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_projection(FraudHeuristics::default())
+            .with_balance_caps(BalanceCaps {
+                global: Some(Decimal::TEN),
+                ..Default::default()
+            });
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert!(
+            processor
+                .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+                .is_err()
+        );
+
+        let config = RiskConfig::from_json(
+            r#"{"balance_cap": "20", "fraud": {"max_chargebacks": 5}}"#.as_bytes(),
+        )
+        .unwrap();
+        processor.reload_config(&config).unwrap();
+        processor
+            .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::from(11));
+        let heuristics = processor.projection::<FraudHeuristics>().unwrap();
+        assert_eq!(heuristics.thresholds.max_chargebacks, 5);
+
+        let config = RiskConfig::from_json(
+            r#"{"balance_cap": "30", "over_cap": "suspend",
+                "unverified": {"deposit_ceiling": "5"}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            processor.reload_config(&config),
+            Err(DisabledSections(vec![
+                "limits of unverified clients",
+                "suspense of deposits over the cap"
+            ]))
+        );
+        // nothing was replaced
+        assert!(
+            processor
+                .process_transaction(3, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+                .is_err()
+        );
+
+        let config = RiskConfig::from_json(r#"{"dispute_flow": "strict"}"#.as_bytes()).unwrap();
+        processor.reload_config(&config).unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert!(
+            processor
+                .process_transaction(1, 1, None, TransactionKind::Chargeback)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn amounts_are_validated() {
+        let mut processor = InMemoryTransactionProcessor::default();
+        let err = processor
+            .process_transaction(1, 1, Some(Decimal::new(-1, 0)), TransactionKind::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "negative_amount");
+        let err = processor
+            .process_transaction(2, 1, Some(Decimal::new(1, 5)), TransactionKind::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code(), "amount_too_precise");
+        processor
+            .process_transaction(
+                3,
+                1,
+                Some(Decimal::new(10_000, 5)),
+                TransactionKind::Deposit,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn close_period_rolls_balances_over() {
+        let mut processor = InMemoryTransactionProcessor::default().with_history();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        let close = processor.close_period();
+        assert_eq!(close.period, 0);
+        assert_eq!(close.events, 2);
+        assert_eq!(close.balances[&1].held, Decimal::TEN);
+
+        // open dispute is carried over into the next period
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        let close = processor.close_period();
+        assert_eq!(close.period, 1);
+        assert_eq!(close.events, 1);
+        assert_eq!(close.balances[&1].available, Decimal::TEN);
+        assert_eq!(processor.period(), 2);
+
+        let history = processor.history().unwrap();
+        assert_eq!(history.for_period(0).count(), 2);
+        assert_eq!(history.for_period(1).count(), 1);
+    }
+
+    #[test]
+    fn watched_client_transactions_wait_for_review() {
+        #[derive(Default)]
+        struct Outcomes {
+            accepted: u32,
+            rejected: u32,
+        }
+
+        impl ProcessorMetrics for Outcomes {
+            fn on_accepted(&mut self, _: ClientId, _: &TransactionKind) {
+                self.accepted += 1;
+            }
+
+            fn on_rejected(
+                &mut self,
+                _: ClientId,
+                _: &TransactionKind,
+                _: &TransactionProcessError,
+            ) {
+                self.rejected += 1;
+            }
+        }
+
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_watchlist([1])
+            .with_metrics(Outcomes::default());
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(2, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(3, 2, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert!(processor.account(1).is_none());
+        let pending: Vec<_> = processor
+            .pending_reviews()
+            .map(|review| review.id)
+            .collect();
+        assert_eq!(pending, [0, 1]);
+
+        processor.approve_review(0).unwrap().unwrap();
+        assert_eq!(processor.reject_review(1).unwrap().tx_id, 2);
+        assert!(processor.approve_review(1).is_none());
+        assert_account!(processor.account(1).unwrap(), available: 10);
+        processor
+            .process_transaction(
+                5,
+                1,
+                Some(Decimal::ONE_HUNDRED),
+                TransactionKind::Withdrawal,
+            )
+            .unwrap();
+        assert!(processor.approve_review(2).unwrap().is_err());
+        let metrics = processor.metrics::<Outcomes>().unwrap();
+        // queued transactions are counted once reviewed, rejected reviews never
+        assert_eq!((metrics.accepted, metrics.rejected), (2, 1));
+
+        processor
+            .reload_config(&RiskConfig {
+                watchlist: Some(Vec::new()),
+                ..Default::default()
+            })
+            .unwrap();
+        processor
+            .process_transaction(4, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        assert_account!(processor.account(1).unwrap(), available: 20);
+        assert!(processor.reviews().is_none());
+    }
+
+    #[test]
+    fn idle_accounts_are_tiered_transparently() {
+        let mut processor =
+            InMemoryTransactionProcessor::default().with_tiering(TieringPolicy { idle_after: 2 });
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        for tx_id in 2..6 {
+            processor
+                .process_transaction(tx_id, 2, Some(Decimal::ONE), TransactionKind::Deposit)
+                .unwrap();
+        }
+        let stats = processor.tier_stats().unwrap();
+        assert_eq!((stats.hot, stats.cold), (1, 1));
+        assert!(!processor.accounts.contains_key(&1));
+        // read without promotion
+        assert_account!(processor.account(1).unwrap(), held: 10, open_disputes: [1]);
+        assert_eq!(processor.accounts().count(), 2);
+        assert_eq!(processor.close_period().balances.len(), 2);
+
+        // promoted by its next transaction
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        assert_account!(processor.account(1).unwrap(), available: 10, held: 0);
+        assert_eq!(processor.tier_stats().unwrap().cold, 0);
+    }
+
+    #[test]
+    fn admin_ops_unlock_and_adjust_accounts() {
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_history()
+            .with_double_entry();
+        processor
+            .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(2, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Chargeback)
+            .unwrap();
+        assert_eq!(processor.unlock(2), Err(AdminError::UnknownClient(2)));
+        assert_eq!(
+            InMemoryTransactionProcessor::default().erase_client(1),
+            Err(AdminError::HistoryNotRecorded)
+        );
+        assert!(processor.set_extension(1, "email", "client@example.com".into()));
+        assert_eq!(processor.erase_client(1).unwrap().client, 1);
+        assert!(processor.account(1).unwrap().extensions().is_empty());
+
+        let outcome = processor.unlock(1).unwrap();
+        assert_eq!(outcome.applied, Some(AccountEventKind::Unlocked));
+        assert_eq!(processor.unlock(1), Err(AdminError::NotLocked(1)));
+        processor
+            .process_transaction(3, 1, Some(Decimal::ONE), TransactionKind::Withdrawal)
+            .unwrap();
+        processor.adjust(1, Decimal::from(-4)).unwrap();
+        processor.adjust(1, Decimal::ONE).unwrap();
+        assert_account!(processor.account(1).unwrap(), available: 6, locked: false);
+        assert!(processor.ledger().unwrap().trial_balance().is_ok());
+        let history = processor.history().unwrap();
+        let last = history.iter().last().unwrap();
+        assert_eq!(last.event.kind(), AccountEventKind::Adjusted);
+        assert_eq!(last.source.as_deref(), Some(ADMIN_SOURCE));
+
+        // unlock survives compaction, which replays the chargeback
+        processor.compact_history(EventSeq::MAX).unwrap();
+        let mut replayed = Account::default();
+        for entry in processor.history().unwrap().iter() {
+            replayed.apply(&entry.event);
+        }
+        assert_account!(replayed, available: 6, locked: false);
+        // and the chargeback can still be reinstated
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Reinstate)
+            .unwrap();
+        assert_account!(processor.account(1).unwrap(), available: 16, locked: false);
+    }
 }
@@ -0,0 +1,261 @@
+//! Clients that haven't passed KYC verification may only move limited
+//! amounts: every deposit and withdrawal is capped by a ceiling, and their
+//! sum over the run by a cumulative limit. Breaches are rejected and
+//! collected into [`ComplianceReport`].
+
+use std::{collections::HashMap, fmt::Display, io::Read};
+
+use csv::Trim;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::account::{AccountEvent, AccountEventKind, TransactionId};
+
+use super::ClientId;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KycStatus {
+    Unverified,
+    #[default]
+    Verified,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum KycError {
+    #[error("Deposit exceeds ceiling of {ceiling} for unverified client")]
+    DepositCeiling { ceiling: Decimal },
+    #[error("Withdrawal exceeds ceiling of {ceiling} for unverified client")]
+    WithdrawalCeiling { ceiling: Decimal },
+    #[error("Deposits exceed cumulative limit of {limit} for unverified client")]
+    DepositLimit { limit: Decimal },
+    #[error("Withdrawals exceed cumulative limit of {limit} for unverified client")]
+    WithdrawalLimit { limit: Decimal },
+}
+
+impl KycError {
+    /// Stable identifier of the error, that doesn't change with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            KycError::DepositCeiling { .. } => "kyc_deposit_ceiling",
+            KycError::WithdrawalCeiling { .. } => "kyc_withdrawal_ceiling",
+            KycError::DepositLimit { .. } => "kyc_deposit_limit",
+            KycError::WithdrawalLimit { .. } => "kyc_withdrawal_limit",
+        }
+    }
+}
+
+/// Limits applied to unverified clients, `None` means unlimited
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KycLimits {
+    pub deposit_ceiling: Option<Decimal>,
+    pub withdrawal_ceiling: Option<Decimal>,
+    pub deposit_limit: Option<Decimal>,
+    pub withdrawal_limit: Option<Decimal>,
+}
+
+#[derive(Deserialize)]
+struct KycStatusRow {
+    client: ClientId,
+    status: KycStatus,
+}
+
+/// Rejected transaction of unverified client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KycBreach {
+    pub tx_id: TransactionId,
+    pub client_id: ClientId,
+    pub amount: Decimal,
+    pub error: KycError,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ComplianceReport {
+    pub breaches: Vec<KycBreach>,
+}
+
+impl Display for ComplianceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "kyc breaches:     {}", self.breaches.len())?;
+        for breach in &self.breaches {
+            writeln!(
+                f,
+                "  tx {} client {} amount {}: {}",
+                breach.tx_id,
+                breach.client_id,
+                breach.amount,
+                breach.error.code()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Verification status of clients with limits, and amounts they moved so far
+#[derive(Debug, Default)]
+pub struct KycRules {
+    statuses: HashMap<ClientId, KycStatus>,
+    /// Status of clients missing from metadata
+    default_status: KycStatus,
+    limits: KycLimits,
+    /// Deposited and withdrawn amounts of unverified clients
+    totals: HashMap<ClientId, (Decimal, Decimal)>,
+    breaches: Vec<KycBreach>,
+}
+
+impl KycRules {
+    pub fn new(limits: KycLimits, default_status: KycStatus) -> Self {
+        Self {
+            limits,
+            default_status,
+            ..Default::default()
+        }
+    }
+
+    /// Parses CSV with `client,status` columns, status is `verified` or `unverified`
+    pub fn parse_statuses(&mut self, source: impl Read) -> Result<(), csv::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_reader(source);
+        for row in reader.deserialize() {
+            let KycStatusRow { client, status } = row?;
+            self.set_status(client, status);
+        }
+        Ok(())
+    }
+
+    /// Replaces limits, amounts moved so far are kept
+    pub fn set_limits(&mut self, limits: KycLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_status(&mut self, client_id: ClientId, status: KycStatus) {
+        self.statuses.insert(client_id, status);
+    }
+
+    pub fn status(&self, client_id: ClientId) -> KycStatus {
+        self.statuses
+            .get(&client_id)
+            .copied()
+            .unwrap_or(self.default_status)
+    }
+
+    /// Checks that `event` of the client stays within limits,
+    /// breaches are recorded for the compliance report
+    pub fn check(&mut self, client_id: ClientId, event: &AccountEvent) -> Result<(), KycError> {
+        if self.status(client_id) == KycStatus::Verified {
+            return Ok(());
+        }
+        let (deposited, withdrawn) = self.totals.get(&client_id).copied().unwrap_or_default();
+        let amount = event.amount();
+        let exceeds = |limit: Option<Decimal>, value| limit.filter(|limit| value > *limit);
+        let result = match event.kind() {
+            AccountEventKind::Deposited | AccountEventKind::DepositPending => {
+                if let Some(ceiling) = exceeds(self.limits.deposit_ceiling, amount) {
+                    Err(KycError::DepositCeiling { ceiling })
+                } else if let Some(limit) = exceeds(self.limits.deposit_limit, deposited + amount) {
+                    Err(KycError::DepositLimit { limit })
+                } else {
+                    Ok(())
+                }
+            }
+            AccountEventKind::Withdrawn => {
+                if let Some(ceiling) = exceeds(self.limits.withdrawal_ceiling, amount) {
+                    Err(KycError::WithdrawalCeiling { ceiling })
+                } else if let Some(limit) =
+                    exceeds(self.limits.withdrawal_limit, withdrawn + amount)
+                {
+                    Err(KycError::WithdrawalLimit { limit })
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        };
+        if let Err(error) = &result {
+            self.breaches.push(KycBreach {
+                tx_id: event.transaction_id(),
+                client_id,
+                amount,
+                error: error.clone(),
+            });
+        }
+        result
+    }
+
+    /// Adds applied `event` to cumulative totals of unverified client
+    pub fn record(&mut self, client_id: ClientId, event: &AccountEvent) {
+        if self.status(client_id) == KycStatus::Verified {
+            return;
+        }
+        let (deposited, withdrawn) = self.totals.entry(client_id).or_default();
+        match event.kind() {
+            AccountEventKind::Deposited | AccountEventKind::DepositPending => {
+                *deposited += event.amount()
+            }
+            AccountEventKind::Withdrawn => *withdrawn += event.amount(),
+            _ => {}
+        }
+    }
+
+    pub fn report(&self) -> ComplianceReport {
+        ComplianceReport {
+            breaches: self.breaches.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_unverified_clients() {
+        let limits = KycLimits {
+            deposit_ceiling: Some(Decimal::from(100)),
+            deposit_limit: Some(Decimal::from(150)),
+            withdrawal_ceiling: Some(Decimal::from(20)),
+            ..Default::default()
+        };
+        let mut rules = KycRules::new(limits, KycStatus::Verified);
+        rules
+            .parse_statuses("client,status\n1,unverified\n2,verified\n".as_bytes())
+            .unwrap();
+        let deposit =
+            |tx, amount| AccountEvent::new(tx, Decimal::from(amount), AccountEventKind::Deposited);
+        let mut apply = |client, event: AccountEvent| {
+            let result = rules.check(client, &event);
+            if result.is_ok() {
+                rules.record(client, &event);
+            }
+            result.map_err(|err| err.code())
+        };
+
+        assert_eq!(apply(1, deposit(1, 101)), Err("kyc_deposit_ceiling"));
+        assert_eq!(apply(1, deposit(2, 100)), Ok(()));
+        assert_eq!(apply(1, deposit(3, 51)), Err("kyc_deposit_limit"));
+        assert_eq!(apply(1, deposit(4, 50)), Ok(()));
+        let withdrawal = AccountEvent::new(5, Decimal::from(21), AccountEventKind::Withdrawn);
+        assert_eq!(apply(1, withdrawal), Err("kyc_withdrawal_ceiling"));
+        // verified and unknown clients are not limited
+        assert_eq!(apply(2, deposit(6, 1000)), Ok(()));
+        assert_eq!(apply(3, deposit(7, 1000)), Ok(()));
+
+        let report = rules.report();
+        let codes: Vec<_> = report
+            .breaches
+            .iter()
+            .map(|breach| (breach.tx_id, breach.error.code()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (1, "kyc_deposit_ceiling"),
+                (3, "kyc_deposit_limit"),
+                (5, "kyc_withdrawal_ceiling")
+            ]
+        );
+    }
+}
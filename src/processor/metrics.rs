@@ -0,0 +1,173 @@
+//! Hooks for embedders wiring processors into their own metrics systems
+//! (StatsD, OpenTelemetry, in-house counters).
+
+use std::{any::Any, time::Duration};
+
+use crate::{command::TransactionKind, stats::Stage};
+
+use super::{ClientId, TransactionOutcome, TransactionProcessError};
+
+/// Called by processors as transactions go through them. Every hook does
+/// nothing by default, so implementations override only what they export.
+pub trait ProcessorMetrics: Any {
+    /// Transaction was applied, or accepted for later (e.g. parked in suspense)
+    fn on_accepted(&mut self, client_id: ClientId, kind: &TransactionKind) {
+        let _ = (client_id, kind);
+    }
+
+    /// Transaction was rejected, `error.code()` is a stable label for it
+    fn on_rejected(
+        &mut self,
+        client_id: ClientId,
+        kind: &TransactionKind,
+        error: &TransactionProcessError,
+    ) {
+        let _ = (client_id, kind, error);
+    }
+
+    /// Account became locked by a chargeback
+    fn on_account_locked(&mut self, client_id: ClientId) {
+        let _ = client_id;
+    }
+
+    /// Time spent by a transaction in a stage, for processors timing them
+    fn on_stage(&mut self, kind: &TransactionKind, stage: Stage, elapsed: Duration) {
+        let _ = (kind, stage, elapsed);
+    }
+}
+
+/// Metrics registered with a processor, calls are no-op when there are none
+#[derive(Default)]
+pub(crate) struct MetricsHook(Option<Box<dyn ProcessorMetrics>>);
+
+impl MetricsHook {
+    pub fn set(&mut self, metrics: impl ProcessorMetrics) {
+        self.0 = Some(Box::new(metrics));
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn get<M: ProcessorMetrics>(&self) -> Option<&M> {
+        self.0
+            .as_ref()
+            .and_then(|metrics| (metrics.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    /// Reports outcome of processing a transaction
+    pub fn on_result(
+        &mut self,
+        client_id: ClientId,
+        kind: &TransactionKind,
+        result: &Result<TransactionOutcome, TransactionProcessError>,
+    ) {
+        match result {
+            Ok(_) => self.on_accepted(client_id, kind),
+            Err(err) => self.on_rejected(client_id, kind, err),
+        }
+    }
+}
+
+impl ProcessorMetrics for MetricsHook {
+    fn on_accepted(&mut self, client_id: ClientId, kind: &TransactionKind) {
+        if let Some(metrics) = &mut self.0 {
+            metrics.on_accepted(client_id, kind);
+        }
+    }
+
+    fn on_rejected(
+        &mut self,
+        client_id: ClientId,
+        kind: &TransactionKind,
+        error: &TransactionProcessError,
+    ) {
+        if let Some(metrics) = &mut self.0 {
+            metrics.on_rejected(client_id, kind, error);
+        }
+    }
+
+    fn on_account_locked(&mut self, client_id: ClientId) {
+        if let Some(metrics) = &mut self.0 {
+            metrics.on_account_locked(client_id);
+        }
+    }
+
+    fn on_stage(&mut self, kind: &TransactionKind, stage: Stage, elapsed: Duration) {
+        if let Some(metrics) = &mut self.0 {
+            metrics.on_stage(kind, stage, elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rust_decimal::Decimal;
+
+    use crate::processor::{
+        TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor,
+    };
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counters {
+        accepted: u32,
+        rejected: HashMap<&'static str, u32>,
+        locked: Vec<ClientId>,
+        timed: u32,
+    }
+
+    impl ProcessorMetrics for Counters {
+        fn on_accepted(&mut self, _: ClientId, _: &TransactionKind) {
+            self.accepted += 1;
+        }
+
+        fn on_rejected(
+            &mut self,
+            _: ClientId,
+            _: &TransactionKind,
+            error: &TransactionProcessError,
+        ) {
+            *self.rejected.entry(error.code()).or_default() += 1;
+        }
+
+        fn on_account_locked(&mut self, client_id: ClientId) {
+            self.locked.push(client_id);
+        }
+
+        fn on_stage(&mut self, _: &TransactionKind, _: Stage, _: Duration) {
+            self.timed += 1;
+        }
+    }
+
+    #[test]
+    fn in_memory_processor_reports_to_metrics() {
+        let mut processor =
+            InMemoryTransactionProcessor::default().with_metrics(Counters::default());
+        let amount = Some(Decimal::from(10));
+        processor
+            .process_transaction(1, 1, amount, TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(2, 1, amount, TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(3, 1, Some(Decimal::from(30)), TransactionKind::Withdrawal)
+            .unwrap_err();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Chargeback)
+            .unwrap();
+
+        let metrics = processor.metrics::<Counters>().unwrap();
+        assert_eq!(metrics.accepted, 4);
+        assert_eq!(metrics.rejected, HashMap::from([("insufficient_funds", 1)]));
+        assert_eq!(metrics.locked, [1]);
+        assert!(metrics.timed >= 4 * 3);
+    }
+}
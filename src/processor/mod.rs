@@ -1,12 +1,44 @@
+use std::{fmt::Display, io, str::FromStr};
+
 use rust_decimal::Decimal;
 use thiserror::Error;
 
 use crate::{
-    account::{AccountError, TransactionId},
+    account::{Account, AccountError, AccountEventKind, ApplyError, TransactionId},
     command::{AccountCommandError, TransactionKind},
+    projection::FraudFlag,
+    stats::PipelineStats,
 };
+use kyc::{ComplianceReport, KycError};
+use risk_config::{DisabledSections, RiskConfig};
+use savepoint::Savepoint;
+use suspense::SuspenseReport;
+use watchlist::ReviewReport;
 
+pub mod account_tiers;
+pub mod admin;
+pub mod balance_cap;
+pub mod bloom;
+#[cfg(feature = "aws")]
+pub mod dynamodb_store;
+pub mod event_schema;
+pub mod file_store;
 pub mod in_memory_processor;
+pub mod kyc;
+pub mod metrics;
+#[cfg(feature = "redis")]
+pub mod redis_store;
+pub mod risk_config;
+pub mod savepoint;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_processor;
+pub mod state_store;
+pub mod store_processor;
+pub mod suspense;
+pub mod tx_id_order;
+pub mod tx_store;
+pub mod watchlist;
+pub mod withdrawal_rules;
 
 #[derive(Debug, Error)]
 pub enum TransactionProcessError {
@@ -14,10 +46,176 @@ pub enum TransactionProcessError {
     CommandErr(#[from] AccountCommandError),
     #[error(transparent)]
     AccountErr(#[from] AccountError),
+    /// Persistent backend failed, transaction was not applied
+    #[error("Storage error: {0}")]
+    StorageErr(String),
+    /// Row is not signed by the client's key, see [`crate::bin_utils::signature`]
+    #[error("Invalid signature: {0}")]
+    SignatureErr(&'static str),
+    /// Event is inconsistent with account state, nothing was applied
+    #[error("Invalid event: {0}")]
+    ApplyErr(#[from] ApplyError),
+    /// Unverified client exceeded its limits, see [`kyc`]
+    #[error(transparent)]
+    KycErr(#[from] KycError),
+}
+
+impl TransactionProcessError {
+    /// Stable identifier of the underlying error
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionProcessError::CommandErr(err) => err.code(),
+            TransactionProcessError::AccountErr(err) => err.code(),
+            TransactionProcessError::StorageErr(_) => "storage_error",
+            TransactionProcessError::SignatureErr(_) => "invalid_signature",
+            TransactionProcessError::KycErr(err) => err.code(),
+            TransactionProcessError::ApplyErr(err) => err.code(),
+        }
+    }
+}
+
+/// How serious a rejection is, see [`TransactionProcessError::reject_kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RejectKind {
+    /// Valid row, refused by business rules: insufficient funds, locked account, limits
+    Business,
+    /// Row can't be turned into a valid command: missing or malformed amount,
+    /// unknown type, bad signature
+    InvalidInput,
+    /// Ledger failed to process a valid row, e.g. storage is unavailable
+    Technical,
+}
+
+impl TransactionProcessError {
+    pub fn reject_kind(&self) -> RejectKind {
+        match self {
+            TransactionProcessError::CommandErr(
+                AccountCommandError::ExistingTxRequired { .. }
+                | AccountCommandError::ReferencedTxRejected { .. }
+                | AccountCommandError::LateEvent { .. }
+                | AccountCommandError::DuplicateTransaction { .. }
+                | AccountCommandError::TransactionRetired { .. },
+            ) => RejectKind::Business,
+            TransactionProcessError::CommandErr(_) | TransactionProcessError::SignatureErr(_) => {
+                RejectKind::InvalidInput
+            }
+            TransactionProcessError::AccountErr(_) | TransactionProcessError::KycErr(_) => {
+                RejectKind::Business
+            }
+            TransactionProcessError::StorageErr(_) | TransactionProcessError::ApplyErr(_) => {
+                RejectKind::Technical
+            }
+        }
+    }
 }
 
 pub type ClientId = u16;
 
+/// What processing a transaction did, so callers don't have to query
+/// the account after every call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionOutcome {
+    /// Kind of the applied event, `None` when the transaction was deferred:
+    /// parked in suspense, held over balance cap, or queued for review
+    pub applied: Option<AccountEventKind>,
+    /// Balances of the account afterwards, zero if it doesn't exist
+    pub available: Decimal,
+    pub held: Decimal,
+    /// Account got locked by this transaction
+    pub locked: bool,
+}
+
+impl TransactionOutcome {
+    /// Event of `kind` was applied to `account`
+    pub fn applied(kind: AccountEventKind, account: &Account, was_locked: bool) -> Self {
+        Self {
+            applied: Some(kind),
+            available: account.available(),
+            held: account.held(),
+            locked: account.locked() && !was_locked,
+        }
+    }
+
+    /// Transaction is not applied yet
+    pub fn deferred(account: Option<&Account>) -> Self {
+        Self {
+            applied: None,
+            available: account.map(Account::available).unwrap_or_default(),
+            held: account.map(Account::held).unwrap_or_default(),
+            locked: false,
+        }
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.applied.is_some()
+    }
+}
+
+/// Position in accounts ordered by client id, i.e. the last client of the
+/// previous page. Unlike offsets, it stays valid while accounts are created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountsCursor {
+    after: ClientId,
+}
+
+impl Display for AccountsCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.after)
+    }
+}
+
+impl FromStr for AccountsCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let after = s
+            .parse()
+            .map_err(|_| format!("invalid accounts cursor `{s}`"))?;
+        Ok(Self { after })
+    }
+}
+
+/// Accounts in ascending client order, see [`TransactionProcessor::accounts_page`]
+#[derive(Debug)]
+pub struct AccountsPage<'a> {
+    pub accounts: Vec<(ClientId, &'a Account)>,
+    /// Cursor of the next page, `None` on the last one
+    pub next: Option<AccountsCursor>,
+}
+
+impl<'a> AccountsPage<'a> {
+    /// Page of at most `limit` (at least one) of `accounts` ordered by client id
+    pub fn from_ordered(
+        accounts: impl Iterator<Item = (ClientId, &'a Account)>,
+        limit: usize,
+    ) -> Self {
+        let limit = limit.max(1);
+        let mut accounts: Vec<_> = accounts.take(limit + 1).collect();
+        let more = accounts.len() > limit;
+        accounts.truncate(limit);
+        let next = accounts
+            .last()
+            .filter(|_| more)
+            .map(|(client, _)| AccountsCursor { after: *client });
+        Self { accounts, next }
+    }
+
+    /// Page of processors looking up accounts by client id, probing ids
+    /// after the cursor, so no accounts are collected nor sorted
+    pub fn probe(
+        cursor: Option<AccountsCursor>,
+        limit: usize,
+        account: impl Fn(ClientId) -> Option<&'a Account>,
+    ) -> Self {
+        let first = cursor.map_or(Some(0), |cursor| cursor.after.checked_add(1));
+        let accounts = first
+            .into_iter()
+            .flat_map(|first| first..=ClientId::MAX)
+            .filter_map(|client| Some((client, account(client)?)));
+        Self::from_ordered(accounts, limit)
+    }
+}
+
 pub trait TransactionProcessor {
     fn process_transaction(
         &mut self,
@@ -25,5 +223,129 @@ pub trait TransactionProcessor {
         client_id: ClientId,
         amount: Option<Decimal>,
         kind: TransactionKind,
-    ) -> Result<(), TransactionProcessError>;
+    ) -> Result<TransactionOutcome, TransactionProcessError>;
+
+    /// Same as [`TransactionProcessor::process_transaction`], with the origin
+    /// of the transaction (API key id, file name, partner id), for processors
+    /// keeping audit trail. Other processors ignore the source.
+    fn process_transaction_from(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+        source: Option<&str>,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let _ = source;
+        self.process_transaction(tx_id, client_id, amount, kind)
+    }
+
+    /// Same as [`TransactionProcessor::process_transaction_from`], with trace id
+    /// of the input record, which processors pass to subscribers of applied
+    /// events, so a payment can be correlated across systems. Other
+    /// processors ignore the trace id.
+    fn process_transaction_traced(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+        source: Option<&str>,
+        trace_id: Option<&str>,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let _ = trace_id;
+        self.process_transaction_from(tx_id, client_id, amount, kind, source)
+    }
+
+    /// Iterates over all client accounts, in no particular order
+    fn accounts(&self) -> impl Iterator<Item = (ClientId, &Account)>;
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts()
+            .find(|(id, _)| *id == client_id)
+            .map(|(_, acc)| acc)
+    }
+
+    /// Up to `limit` accounts after `cursor`, or from the first one, ordered
+    /// by client id, so reports and APIs can stream accounts page by page
+    fn accounts_page(&self, cursor: Option<AccountsCursor>, limit: usize) -> AccountsPage<'_> {
+        let after = cursor.map(|cursor| cursor.after);
+        let mut accounts: Vec<_> = self
+            .accounts()
+            .filter(|(client, _)| after.is_none_or(|after| *client > after))
+            .collect();
+        accounts.sort_unstable_by_key(|(client, _)| *client);
+        AccountsPage::from_ordered(accounts.into_iter(), limit)
+    }
+
+    /// Per-stage timings, for processors that collect them
+    fn stats(&self) -> Option<&PipelineStats> {
+        None
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut PipelineStats> {
+        None
+    }
+
+    /// Suspicious clients, for processors running fraud heuristics
+    fn flags(&self) -> Vec<FraudFlag> {
+        Vec::new()
+    }
+
+    /// Parked and unmatched modify rows, for processors that park them
+    fn suspense(&self) -> Option<SuspenseReport> {
+        None
+    }
+
+    /// Rejected transactions of unverified clients, for processors applying KYC rules
+    fn compliance(&self) -> Option<ComplianceReport> {
+        None
+    }
+
+    /// Transactions of watched clients, for processors diverting them to review
+    fn reviews(&self) -> Option<ReviewReport> {
+        None
+    }
+
+    /// Replaces risk thresholds, limits and dispute policy present in `config`,
+    /// keeping accumulated state, for processors applying them. Nothing is
+    /// replaced, when some sections can't be applied.
+    fn reload_config(&mut self, config: &RiskConfig) -> Result<(), DisabledSections> {
+        let sections = config.sections();
+        if sections.is_empty() {
+            return Ok(());
+        }
+        Err(DisabledSections(sections))
+    }
+
+    /// Number of entries of in-memory maps by name, for processors keeping them
+    fn map_sizes(&self) -> Vec<(&'static str, usize)> {
+        Vec::new()
+    }
+
+    /// Flushes subscribers of applied events once the input is processed,
+    /// for processors publishing them, see [`crate::event_bus::EventSubscriber::flush`]
+    fn flush_subscribers(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Makes every transaction processed so far durable once the input is
+    /// processed, for processors buffering writes, e.g. in a group commit
+    fn commit_pending(&mut self) -> Result<(), TransactionProcessError> {
+        Ok(())
+    }
+
+    /// Opens a savepoint, for processors able to roll back to it, so
+    /// a sub-batch can be applied tentatively
+    fn savepoint(&mut self) -> Option<Savepoint> {
+        None
+    }
+
+    /// Reverts everything applied since `savepoint` was opened, and closes it
+    /// together with savepoints opened after it
+    fn rollback_to(&mut self, _savepoint: Savepoint) {}
+
+    /// Keeps everything applied since `savepoint` was opened, and closes it
+    /// together with savepoints opened after it
+    fn release(&mut self, _savepoint: Savepoint) {}
 }
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use redis::{Commands, Connection, Script};
+
+use crate::{
+    account::{AccountEvent, TransactionId},
+    command::CreateTransactionCommand,
+};
+
+use super::{
+    ClientId,
+    state_store::{StateStore, StoreError, decode_event, decode_tx, encode_event, encode_tx},
+};
+
+/// Checks account version and stores event with created transaction in one step.
+/// Returns 1 on success, 0 on version conflict, -1 when transaction already exists.
+const APPEND_SCRIPT: &str = r"
+local version = tonumber(redis.call('HGET', KEYS[1], 'version') or '0')
+if version ~= tonumber(ARGV[1]) then
+    return 0
+end
+if ARGV[3] ~= '' then
+    if redis.call('HSETNX', KEYS[2], ARGV[3], ARGV[4]) == 0 then
+        return -1
+    end
+end
+redis.call('HSET', KEYS[1], 'version', version + 1, tostring(version + 1), ARGV[2])
+return 1
+";
+
+/// Each account is a hash `{prefix}:account:{client}` with `version` field and
+/// events stored under their 1-based position, while created transactions
+/// are kept in `{prefix}:txs` hash.
+pub struct RedisStateStore {
+    conn: Connection,
+    prefix: String,
+    append: Script,
+}
+
+impl RedisStateStore {
+    pub fn connect(url: &str, prefix: &str) -> redis::RedisResult<Self> {
+        let conn = redis::Client::open(url)?.get_connection()?;
+        Ok(Self {
+            conn,
+            prefix: prefix.to_string(),
+            append: Script::new(APPEND_SCRIPT),
+        })
+    }
+
+    fn account_key(&self, client_id: ClientId) -> String {
+        format!("{}:account:{client_id}", self.prefix)
+    }
+
+    fn txs_key(&self) -> String {
+        format!("{}:txs", self.prefix)
+    }
+}
+
+impl StateStore for RedisStateStore {
+    fn get_tx(
+        &mut self,
+        tx_id: TransactionId,
+    ) -> Result<Option<CreateTransactionCommand>, StoreError> {
+        let key = self.txs_key();
+        let value: Option<String> = self.conn.hget(key, tx_id).map_err(backend_err)?;
+        value.map(|value| decode_tx(tx_id, &value)).transpose()
+    }
+
+    fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError> {
+        let key = self.account_key(client_id);
+        let mut fields: HashMap<String, String> = self.conn.hgetall(key).map_err(backend_err)?;
+        let version: u64 = match fields.get("version") {
+            Some(version) => version
+                .parse()
+                .map_err(|_| StoreError::Backend(format!("invalid version `{version}`")))?,
+            None => 0,
+        };
+        (1..=version)
+            .map(|pos| {
+                let value = fields
+                    .remove(&pos.to_string())
+                    .ok_or_else(|| StoreError::Backend(format!("event {pos} is missing")))?;
+                decode_event(&value)
+            })
+            .collect()
+    }
+
+    fn append_event(
+        &mut self,
+        client_id: ClientId,
+        expected_version: u64,
+        event: &AccountEvent,
+        created: Option<&CreateTransactionCommand>,
+    ) -> Result<(), StoreError> {
+        let (tx_id, tx) = match created {
+            Some(command) => (command.tx_id.to_string(), encode_tx(command)),
+            None => (String::new(), String::new()),
+        };
+        let result: i64 = self
+            .append
+            .key(self.account_key(client_id))
+            .key(self.txs_key())
+            .arg(expected_version)
+            .arg(encode_event(event))
+            .arg(tx_id)
+            .arg(tx)
+            .invoke(&mut self.conn)
+            .map_err(backend_err)?;
+        match result {
+            1 => Ok(()),
+            0 => Err(StoreError::VersionConflict),
+            _ => Err(StoreError::TxConflict(
+                created.map(|command| command.tx_id).unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+fn backend_err(err: redis::RedisError) -> StoreError {
+    StoreError::Backend(err.to_string())
+}
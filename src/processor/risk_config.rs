@@ -0,0 +1,108 @@
+//! Risk rule thresholds, limits and dispute policy, that can be replaced
+//! while the processor is running, without losing its in-memory state.
+
+use std::io::Read;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{account::DisputeFlow, projection::FraudThresholds};
+
+use super::{
+    ClientId, balance_cap::OverCapPolicy, kyc::KycLimits, withdrawal_rules::WithdrawalRules,
+};
+
+/// JSON configuration, only present fields are changed on reload:
+///
+/// ```json
+/// {
+///     "fraud": { "max_chargebacks": 2, "max_dispute_ratio": "0.3" },
+///     "balance_cap": "10000",
+///     "over_cap": "suspend",
+///     "withdrawals": { "minimum": "20", "denomination": "0.25" },
+///     "unverified": { "deposit_ceiling": "500", "withdrawal_limit": "1000" },
+///     "watchlist": [7, 42],
+///     "dispute_flow": "strict",
+///     "breaker_threshold": 0.5
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    pub fraud: Option<FraudThresholds>,
+    pub balance_cap: Option<Decimal>,
+    pub over_cap: Option<OverCapPolicy>,
+    pub withdrawals: Option<WithdrawalRules>,
+    pub unverified: Option<KycLimits>,
+    /// Clients, whose transactions are queued for review
+    pub watchlist: Option<Vec<ClientId>>,
+    pub dispute_flow: Option<DisputeFlow>,
+    /// Share of rejected rows, above which the circuit breaker trips,
+    /// applied by the service running the processor rather than by it
+    pub breaker_threshold: Option<f64>,
+}
+
+/// Sections of [`RiskConfig`] for components the processor was built without,
+/// e.g. `fraud` thresholds without fraud heuristics, which would do nothing
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("Risk config sets {}, but the processor was built without them", .0.join(", "))]
+pub struct DisabledSections(pub Vec<&'static str>);
+
+impl RiskConfig {
+    pub fn from_json(source: impl Read) -> serde_json::Result<Self> {
+        serde_json::from_reader(source)
+    }
+
+    /// Sections applied by the processor, that are present in the config
+    pub fn sections(&self) -> Vec<&'static str> {
+        [
+            (self.fraud.is_some(), "fraud thresholds"),
+            (self.balance_cap.is_some(), "balance cap"),
+            (self.over_cap.is_some(), "policy of deposits over the cap"),
+            (self.withdrawals.is_some(), "withdrawal rules"),
+            (self.unverified.is_some(), "limits of unverified clients"),
+            (self.watchlist.is_some(), "watchlist"),
+            (self.dispute_flow.is_some(), "dispute flow"),
+        ]
+        .into_iter()
+        .filter_map(|(present, section)| present.then_some(section))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_partial_config() {
+        let config = RiskConfig::from_json(
+            r#"{"fraud": {"max_chargebacks": 2}, "over_cap": "suspend",
+                "unverified": {"deposit_ceiling": "500"}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            config.sections(),
+            [
+                "fraud thresholds",
+                "policy of deposits over the cap",
+                "limits of unverified clients"
+            ]
+        );
+        let fraud = config.fraud.unwrap();
+        assert_eq!(fraud.max_chargebacks, 2);
+        assert_eq!(
+            fraud.max_dispute_ratio,
+            FraudThresholds::default().max_dispute_ratio
+        );
+        assert_eq!(config.balance_cap, None);
+        assert_eq!(config.over_cap, Some(OverCapPolicy::Suspend));
+        let unverified = config.unverified.unwrap();
+        assert_eq!(unverified.deposit_ceiling, Some(Decimal::from(500)));
+        assert_eq!(unverified.withdrawal_limit, None);
+
+        assert!(RiskConfig::from_json(r#"{"balance_limit": "1"}"#.as_bytes()).is_err());
+    }
+}
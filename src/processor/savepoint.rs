@@ -0,0 +1,95 @@
+//! Savepoints within a single run. While any savepoint is open, state
+//! changed by every transaction is journaled before the change, so
+//! a tentatively applied sub-batch can be rolled back wholesale.
+
+use crate::{
+    account::{Account, TransactionId},
+    command::{CreateTransactionCommand, ModifyTransactionAction},
+};
+
+use super::{ClientId, tx_id_order::TxIdWatermarks};
+
+/// Open savepoint, see [`super::TransactionProcessor::savepoint`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Savepoint {
+    depth: usize,
+}
+
+/// Previous state of something changed by a transaction
+#[derive(Debug)]
+pub(crate) enum Undo {
+    /// `None` when the account didn't exist
+    Account {
+        client_id: ClientId,
+        previous: Option<Box<Account>>,
+    },
+    CreatedTx {
+        tx_id: TransactionId,
+        previous: Option<CreateTransactionCommand>,
+        retired: bool,
+    },
+    LastModify {
+        tx_id: TransactionId,
+        previous: Option<ModifyTransactionAction>,
+    },
+    /// Transaction was appended to the client index
+    ClientIndex { client_id: ClientId },
+}
+
+/// State restored as a whole, instead of being journaled
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub undo_len: usize,
+    pub tx_ids: TxIdWatermarks,
+    pub period_events: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    frames: Vec<Frame>,
+    undo: Vec<Undo>,
+}
+
+impl Journal {
+    pub fn is_active(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    pub fn record(&mut self, undo: impl FnOnce() -> Undo) {
+        if self.is_active() {
+            self.undo.push(undo());
+        }
+    }
+
+    pub fn open(&mut self, tx_ids: TxIdWatermarks, period_events: u64) -> Savepoint {
+        self.frames.push(Frame {
+            undo_len: self.undo.len(),
+            tx_ids,
+            period_events,
+        });
+        Savepoint {
+            depth: self.frames.len() - 1,
+        }
+    }
+
+    /// Closes `savepoint` and the ones opened after it, returning its frame
+    /// and changes to undo, latest first. `None` if it is already closed.
+    pub fn rollback(&mut self, savepoint: Savepoint) -> Option<(Frame, Vec<Undo>)> {
+        if savepoint.depth >= self.frames.len() {
+            return None;
+        }
+        let frame = self.frames.drain(savepoint.depth..).next()?;
+        let mut undo = self.undo.split_off(frame.undo_len);
+        undo.reverse();
+        Some((frame, undo))
+    }
+
+    /// Closes `savepoint` and the ones opened after it, keeping the changes;
+    /// they are forgotten once no savepoint is open
+    pub fn release(&mut self, savepoint: Savepoint) {
+        self.frames.truncate(savepoint.depth);
+        if self.frames.is_empty() {
+            self.undo.clear();
+        }
+    }
+}
@@ -0,0 +1,689 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rusqlite::{Connection, OptionalExtension, Row, params, types::Type};
+use rust_decimal::Decimal;
+
+use crate::{
+    account::{Account, AccountEvent, AccountEventKind, TransactionId},
+    command::{
+        AccountCommand, CreateTransactionAction, CreateTransactionCommand, TransactionKind,
+        ZeroAmountPolicy,
+    },
+    history::{Balance, EventHistory},
+    money::Money,
+};
+
+use super::{
+    AccountsCursor, AccountsPage, ClientId, TransactionOutcome, TransactionProcessError,
+    TransactionProcessor,
+    bloom::BloomFilter,
+    metrics::{MetricsHook, ProcessorMetrics},
+};
+
+/// False positive rate of the transaction ids filter
+const TX_FILTER_FP_RATE: f64 = 0.01;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    tx_id INTEGER PRIMARY KEY,
+    action TEXT NOT NULL,
+    amount TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    client INTEGER NOT NULL,
+    tx_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    amount TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS accounts (
+    client INTEGER PRIMARY KEY,
+    available TEXT NOT NULL,
+    held TEXT NOT NULL,
+    pending TEXT NOT NULL,
+    total TEXT NOT NULL,
+    locked INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_by_client ON events (client, seq);
+";
+
+/// Several transactions are written in a single database transaction,
+/// committed once `max_batch` transactions are written, or the oldest of
+/// them waits for `max_delay`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupCommit {
+    pub max_batch: usize,
+    pub max_delay: Duration,
+}
+
+/// Database transaction, that is not committed yet
+struct OpenBatch {
+    started: Instant,
+    written: usize,
+}
+
+/// Processor keeping events, transactions index and account balances in a
+/// single SQLite file (WAL mode), so state survives restarts without running
+/// a database server. Accounts are also cached in memory, and restored by
+/// replaying stored events when database is opened.
+pub struct SqliteTransactionProcessor {
+    conn: Connection,
+    accounts: HashMap<ClientId, Account>,
+    metrics: MetricsHook,
+    /// Ids of stored transactions, so lookups of unknown ones skip the database
+    tx_filter: Option<BloomFilter>,
+    tx_lookups_skipped: u64,
+    group_commit: Option<GroupCommit>,
+    batch: Option<OpenBatch>,
+}
+
+impl SqliteTransactionProcessor {
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        let mut processor = Self {
+            conn,
+            accounts: HashMap::new(),
+            metrics: MetricsHook::default(),
+            tx_filter: None,
+            tx_lookups_skipped: 0,
+            group_commit: None,
+            batch: None,
+        };
+        processor.replay(None)?;
+        Ok(processor)
+    }
+
+    /// Reports outcomes and account locks to `metrics`
+    pub fn with_metrics(mut self, metrics: impl ProcessorMetrics) -> Self {
+        self.metrics.set(metrics);
+        self
+    }
+
+    pub fn metrics<M: ProcessorMetrics>(&self) -> Option<&M> {
+        self.metrics.get()
+    }
+
+    /// Keeps a bloom filter of stored transaction ids, sized for `capacity`
+    /// ids (or all already stored, if more), so that modify rows referencing
+    /// unknown transactions don't query the database. The filter is rebuilt
+    /// with double capacity when it fills up. Only valid while this processor
+    /// is the only writer of the database.
+    pub fn with_tx_filter(mut self, capacity: usize) -> rusqlite::Result<Self> {
+        self.rebuild_tx_filter(capacity)?;
+        Ok(self)
+    }
+
+    /// Coalesces writes of several transactions into a single commit. A
+    /// transaction is durable only once its batch is committed: if a commit
+    /// fails, the whole batch is lost, and cached accounts are restored from
+    /// the database. Pending batch is committed by [`Self::flush`] (also
+    /// called by [`TransactionProcessor::commit_pending`]), when
+    /// [`Self::commit_if_due`] finds it old enough, and on drop, where
+    /// a failed commit can't be reported.
+    pub fn with_group_commit(mut self, group_commit: GroupCommit) -> Self {
+        self.group_commit = Some(group_commit);
+        self
+    }
+
+    /// Commits pending batch, if any
+    pub fn flush(&mut self) -> rusqlite::Result<()> {
+        if self.batch.take().is_some()
+            && let Err(err) = self.conn.execute_batch("COMMIT")
+        {
+            self.abort_batch()?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Commits pending batch once it waited for `max_delay`, so idle daemons
+    /// don't hold transactions uncommitted until the next one arrives
+    pub fn commit_if_due(&mut self) -> rusqlite::Result<()> {
+        match (&self.batch, self.group_commit) {
+            (Some(batch), Some(group_commit))
+                if batch.started.elapsed() >= group_commit.max_delay =>
+            {
+                self.flush()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Rolls back pending writes, and restores cached accounts to match the database
+    fn abort_batch(&mut self) -> rusqlite::Result<()> {
+        self.batch = None;
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("ROLLBACK")?;
+        }
+        self.replay(None)
+    }
+
+    /// Stored events of the client, in the order they were applied
+    pub fn client_events(&self, client_id: ClientId) -> rusqlite::Result<Vec<AccountEvent>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT tx_id, kind, amount FROM events WHERE client = ?1 ORDER BY seq",
+        )?;
+        let events = stmt.query_map([client_id], |row| {
+            Ok(AccountEvent::new(
+                row.get(0)?,
+                decimal(row, 2)?,
+                event_kind(row, 1)?,
+            ))
+        })?;
+        events.collect()
+    }
+
+    /// Stored events of all clients, in the order they were applied, for
+    /// searches and exports of recorded data. Sources of transactions are
+    /// not stored, so entries have none.
+    pub fn history(&self) -> rusqlite::Result<EventHistory> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT client, tx_id, kind, amount FROM events ORDER BY seq")?;
+        let events = stmt.query_map([], |row| {
+            let event = AccountEvent::new(row.get(1)?, decimal(row, 3)?, event_kind(row, 2)?);
+            Ok((row.get::<_, ClientId>(0)?, event))
+        })?;
+        let mut history = EventHistory::default();
+        for event in events {
+            let (client_id, event) = event?;
+            history.push(client_id, event);
+        }
+        Ok(history)
+    }
+
+    /// Rows of the accounts table as `(client, available, held, locked)`,
+    /// written with the last event of each account
+    pub fn stored_balances(&self) -> rusqlite::Result<Vec<(ClientId, Balance)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT client, available, held, pending, locked FROM accounts ORDER BY client",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let balance = Balance {
+                available: decimal(row, 1)?,
+                held: decimal(row, 2)?,
+                pending: decimal(row, 3)?,
+                locked: row.get(4)?,
+            };
+            Ok((row.get(0)?, balance))
+        })?;
+        rows.collect()
+    }
+
+    /// Transactions created for the client, oldest first, looked up by index
+    pub fn client_transactions(&self, client_id: ClientId) -> rusqlite::Result<Vec<TransactionId>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT tx_id FROM events
+             WHERE client = ?1 AND kind IN (?2, ?3, ?4, ?5) ORDER BY seq",
+        )?;
+        let tx_ids = stmt.query_map(
+            params![
+                client_id,
+                AccountEventKind::Deposited.name(),
+                AccountEventKind::Withdrawn.name(),
+                AccountEventKind::DepositPending.name(),
+                AccountEventKind::Authorized.name()
+            ],
+            |row| row.get(0),
+        )?;
+        tx_ids.collect()
+    }
+
+    /// Transaction lookups answered by the filter, without querying the database
+    pub fn tx_lookups_skipped(&self) -> u64 {
+        self.tx_lookups_skipped
+    }
+
+    fn rebuild_tx_filter(&mut self, capacity: usize) -> rusqlite::Result<()> {
+        let stored: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))?;
+        let capacity = capacity.max(stored as usize * 2);
+        let mut filter = BloomFilter::new(capacity, TX_FILTER_FP_RATE);
+        let mut stmt = self.conn.prepare("SELECT tx_id FROM transactions")?;
+        for tx_id in stmt.query_map([], |row| row.get(0))? {
+            filter.insert(tx_id?);
+        }
+        self.tx_filter = Some(filter);
+        Ok(())
+    }
+
+    /// Rebuilds cached accounts from stored events, either all or of a single client
+    fn replay(&mut self, client_id: Option<ClientId>) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT client, tx_id, kind, amount FROM events
+             WHERE ?1 IS NULL OR client = ?1 ORDER BY seq",
+        )?;
+        match client_id {
+            Some(client_id) => _ = self.accounts.remove(&client_id),
+            None => self.accounts.clear(),
+        }
+        let events = stmt.query_map([client_id], |row| {
+            let event = AccountEvent::new(row.get(1)?, decimal(row, 3)?, event_kind(row, 2)?);
+            Ok((row.get::<_, ClientId>(0)?, event))
+        })?;
+        for event in events {
+            let (client_id, event) = event?;
+            self.accounts.entry(client_id).or_default().apply(&event);
+        }
+        Ok(())
+    }
+
+    fn get_tx(
+        &mut self,
+        tx_id: TransactionId,
+    ) -> rusqlite::Result<Option<CreateTransactionCommand>> {
+        if let Some(filter) = &self.tx_filter
+            && !filter.might_contain(tx_id)
+        {
+            self.tx_lookups_skipped += 1;
+            return Ok(None);
+        }
+        self.conn
+            .query_row(
+                "SELECT action, amount FROM transactions WHERE tx_id = ?1",
+                [tx_id],
+                |row| {
+                    Ok(CreateTransactionCommand {
+                        tx_id,
+                        action: create_action(row, 0)?,
+                        amount: money(row, 1)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    fn persist(
+        &mut self,
+        client_id: ClientId,
+        event: &AccountEvent,
+        created: Option<&CreateTransactionCommand>,
+    ) -> rusqlite::Result<()> {
+        let Some(group_commit) = self.group_commit else {
+            let tx = self.conn.transaction()?;
+            write(&tx, client_id, &self.accounts[&client_id], event, created)?;
+            return tx.commit();
+        };
+        if self.batch.is_none() {
+            self.conn.execute_batch("BEGIN")?;
+            self.batch = Some(OpenBatch {
+                started: Instant::now(),
+                written: 0,
+            });
+        }
+        if let Err(err) = write(
+            &self.conn,
+            client_id,
+            &self.accounts[&client_id],
+            event,
+            created,
+        ) {
+            // partially written transaction can't be committed with the rest of the batch
+            self.abort_batch()?;
+            return Err(err);
+        }
+        let batch = self.batch.as_mut().expect("started above");
+        batch.written += 1;
+        if batch.written >= group_commit.max_batch
+            || batch.started.elapsed() >= group_commit.max_delay
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn process_and_persist(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: &TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let existing_tx = self.get_tx(tx_id).map_err(storage_err)?;
+        let cmd = AccountCommand::parse_command(
+            tx_id,
+            existing_tx.as_ref(),
+            kind,
+            amount,
+            ZeroAmountPolicy::default(),
+        )?;
+        let acc = self.accounts.entry(client_id).or_default();
+        let evt = match &cmd {
+            AccountCommand::CreateTx(command) => acc.handle_create_transaction(command.clone())?,
+            AccountCommand::ModifyTx(command) => acc.handle_modify_transaction(command.clone())?,
+        };
+        let was_locked = acc.locked();
+        acc.try_apply(&evt)?;
+        let outcome = TransactionOutcome::applied(evt.kind(), acc, was_locked);
+        let created = match &cmd {
+            AccountCommand::CreateTx(command) => Some(command),
+            AccountCommand::ModifyTx(_) => None,
+        };
+        if let Err(err) = self.persist(client_id, &evt, created) {
+            // cached account is ahead of the database now
+            self.replay(Some(client_id)).map_err(storage_err)?;
+            return Err(storage_err(err));
+        }
+        if outcome.locked {
+            self.metrics.on_account_locked(client_id);
+        }
+        if let (Some(command), Some(filter)) = (created, &mut self.tx_filter) {
+            filter.insert(command.tx_id);
+            if filter.is_saturated() {
+                let capacity = filter.capacity() * 2;
+                self.rebuild_tx_filter(capacity).map_err(storage_err)?;
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+impl TransactionProcessor for SqliteTransactionProcessor {
+    fn process_transaction(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        self.commit_if_due().map_err(storage_err)?;
+        let result = self.process_and_persist(tx_id, client_id, amount, &kind);
+        self.metrics.on_result(client_id, &kind, &result);
+        result
+    }
+
+    fn accounts(&self) -> impl Iterator<Item = (ClientId, &Account)> {
+        self.accounts
+            .iter()
+            .map(|(client_id, acc)| (*client_id, acc))
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    fn accounts_page(&self, cursor: Option<AccountsCursor>, limit: usize) -> AccountsPage<'_> {
+        AccountsPage::probe(cursor, limit, |client_id| self.accounts.get(&client_id))
+    }
+
+    fn commit_pending(&mut self) -> Result<(), TransactionProcessError> {
+        self.flush().map_err(storage_err)
+    }
+}
+
+impl Drop for SqliteTransactionProcessor {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Writes event, created transaction and account balances
+fn write(
+    conn: &Connection,
+    client_id: ClientId,
+    acc: &Account,
+    event: &AccountEvent,
+    created: Option<&CreateTransactionCommand>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO events (client, tx_id, kind, amount) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            client_id,
+            event.transaction_id(),
+            event.kind().name(),
+            event.amount().to_string()
+        ],
+    )?;
+    if let Some(command) = created {
+        conn.execute(
+            "INSERT INTO transactions (tx_id, action, amount) VALUES (?1, ?2, ?3)",
+            params![
+                command.tx_id,
+                command.action.name(),
+                command.amount.amount().to_string()
+            ],
+        )?;
+    }
+    conn.execute(
+        "INSERT OR REPLACE INTO accounts (client, available, held, pending, total, locked)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            client_id,
+            acc.available().to_string(),
+            acc.held().to_string(),
+            acc.pending().to_string(),
+            acc.total_amount().to_string(),
+            acc.locked()
+        ],
+    )?;
+    Ok(())
+}
+
+fn storage_err(err: rusqlite::Error) -> TransactionProcessError {
+    TransactionProcessError::StorageErr(err.to_string())
+}
+
+fn conversion_err(idx: usize, msg: String) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, msg.into())
+}
+
+fn decimal(row: &Row, idx: usize) -> rusqlite::Result<Decimal> {
+    let text: String = row.get(idx)?;
+    Decimal::from_str(&text).map_err(|err| conversion_err(idx, err.to_string()))
+}
+
+fn money(row: &Row, idx: usize) -> rusqlite::Result<Money> {
+    Money::new(decimal(row, idx)?).map_err(|err| conversion_err(idx, err.to_string()))
+}
+
+fn event_kind(row: &Row, idx: usize) -> rusqlite::Result<AccountEventKind> {
+    let text: String = row.get(idx)?;
+    AccountEventKind::from_name(&text)
+        .ok_or_else(|| conversion_err(idx, format!("unknown event kind `{text}`")))
+}
+
+fn create_action(row: &Row, idx: usize) -> rusqlite::Result<CreateTransactionAction> {
+    let text: String = row.get(idx)?;
+    CreateTransactionAction::from_name(&text)
+        .ok_or_else(|| conversion_err(idx, format!("unknown action `{text}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use crate::{account::AccountError, command::AccountCommandError};
+
+    use super::*;
+
+    #[test]
+    fn state_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("cute-ledger-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut processor = SqliteTransactionProcessor::open(&path).unwrap();
+            processor
+                .process_transaction(
+                    1,
+                    1,
+                    Some(Decimal::from_u32(10).unwrap()),
+                    TransactionKind::Deposit,
+                )
+                .unwrap();
+            processor
+                .process_transaction(1, 1, None, TransactionKind::Dispute)
+                .unwrap();
+            let err = processor
+                .process_transaction(2, 1, Some(Decimal::ONE), TransactionKind::Withdrawal)
+                .unwrap_err();
+            assert!(matches!(
+                err,
+                TransactionProcessError::AccountErr(AccountError::InsufficientFunds)
+            ));
+        }
+
+        let mut processor = SqliteTransactionProcessor::open(&path).unwrap();
+        let (_, acc) = processor.accounts().next().unwrap();
+        assert_eq!(acc.held(), Decimal::from_u32(10).unwrap());
+        assert_eq!(acc.version(), 2);
+        // rejected withdrawal was not stored
+        assert!(processor.get_tx(2).unwrap().is_none());
+        assert_eq!(processor.client_transactions(1).unwrap(), [1]);
+        let history = processor.history().unwrap();
+        let kinds: Vec<_> = history.iter().map(|entry| entry.event.kind()).collect();
+        assert_eq!(
+            kinds,
+            [AccountEventKind::Deposited, AccountEventKind::Disputed]
+        );
+        let err = processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TransactionProcessError::CommandErr(AccountCommandError::DuplicateTransaction { .. })
+        ));
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        let available: String = processor
+            .conn
+            .query_row(
+                "SELECT available FROM accounts WHERE client = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(available, "10");
+
+        drop(processor);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[test]
+    fn tx_filter_skips_lookups_of_unknown_transactions() {
+        let path =
+            std::env::temp_dir().join(format!("cute-ledger-filter-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut processor = SqliteTransactionProcessor::open(&path).unwrap();
+            processor
+                .process_transaction(1, 1, Some(Decimal::TEN), TransactionKind::Deposit)
+                .unwrap();
+        }
+
+        // tiny capacity, so the filter is rebuilt on the way
+        let mut processor = SqliteTransactionProcessor::open(&path)
+            .unwrap()
+            .with_tx_filter(1)
+            .unwrap();
+        for tx_id in 2..10 {
+            processor
+                .process_transaction(tx_id, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+                .unwrap();
+        }
+        for tx_id in 100..200 {
+            processor
+                .process_transaction(tx_id, 1, None, TransactionKind::Dispute)
+                .unwrap_err();
+        }
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(9, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert!(processor.tx_lookups_skipped() > 100);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn group_commit_writes_batches() {
+        let path =
+            std::env::temp_dir().join(format!("cute-ledger-batch-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let group_commit = GroupCommit {
+            max_batch: 3,
+            max_delay: Duration::from_secs(60),
+        };
+        let mut processor = SqliteTransactionProcessor::open(&path)
+            .unwrap()
+            .with_group_commit(group_commit);
+        for tx_id in 1..=4 {
+            processor
+                .process_transaction(tx_id, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+                .unwrap();
+        }
+        // first three are committed, the fourth is still pending
+        let other = Connection::open(&path).unwrap();
+        let stored = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(stored(&other), 3);
+        processor.flush().unwrap();
+        assert_eq!(stored(&other), 4);
+
+        processor
+            .process_transaction(5, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        processor.commit_pending().unwrap();
+        assert_eq!(stored(&other), 5);
+
+        processor
+            .process_transaction(6, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        drop(processor);
+        assert_eq!(stored(&other), 6);
+        drop(other);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_batch_restores_cached_accounts() {
+        let path =
+            std::env::temp_dir().join(format!("cute-ledger-abort-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut processor = SqliteTransactionProcessor::open(&path)
+            .unwrap()
+            .with_group_commit(GroupCommit {
+                max_batch: 10,
+                max_delay: Duration::from_secs(60),
+            });
+        processor.conn.busy_timeout(Duration::ZERO).unwrap();
+        processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        processor.flush().unwrap();
+
+        // another writer holds the lock, so writing the next batch fails
+        let other = Connection::open(&path).unwrap();
+        other.execute_batch("BEGIN IMMEDIATE").unwrap();
+        assert!(
+            processor
+                .process_transaction(2, 2, Some(Decimal::ONE), TransactionKind::Deposit)
+                .is_err()
+        );
+        other.execute_batch("ROLLBACK").unwrap();
+
+        let cached: Vec<_> = processor
+            .accounts()
+            .map(|(client_id, acc)| (client_id, Balance::of(acc)))
+            .collect();
+        assert_eq!(cached, processor.stored_balances().unwrap());
+        assert_eq!(processor.account(1).unwrap().available(), Decimal::ONE);
+        drop(processor);
+        drop(other);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
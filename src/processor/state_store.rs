@@ -0,0 +1,153 @@
+use std::{collections::HashMap, str::FromStr};
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    account::{AccountEvent, TransactionId},
+    command::{CreateTransactionAction, CreateTransactionCommand},
+    money::Money,
+};
+
+use super::{
+    ClientId,
+    event_schema::{EventEnvelope, EventV2},
+};
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("Account was changed by another writer")]
+    VersionConflict,
+    #[error("Transaction {0} was already stored by another writer")]
+    TxConflict(TransactionId),
+    #[error("{0}")]
+    Backend(String),
+}
+
+/// Persistent state, that can be shared by several stateless ledger instances.
+/// Each account is stored as a list of its events, and the account version is
+/// the number of events, so writers detect concurrent changes on append.
+pub trait StateStore {
+    fn get_tx(
+        &mut self,
+        tx_id: TransactionId,
+    ) -> Result<Option<CreateTransactionCommand>, StoreError>;
+
+    /// Events of the account, in the order they were applied
+    fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError>;
+
+    /// Atomically appends event, together with created transaction, if any.
+    /// Fails with [`StoreError::VersionConflict`] when account has more than
+    /// `expected_version` events already.
+    fn append_event(
+        &mut self,
+        client_id: ClientId,
+        expected_version: u64,
+        event: &AccountEvent,
+        created: Option<&CreateTransactionCommand>,
+    ) -> Result<(), StoreError>;
+}
+
+/// Store used in tests, and as a reference implementation
+#[derive(Debug, Default)]
+pub struct InMemoryStateStore {
+    txs: HashMap<TransactionId, CreateTransactionCommand>,
+    events: HashMap<ClientId, Vec<AccountEvent>>,
+}
+
+impl StateStore for InMemoryStateStore {
+    fn get_tx(
+        &mut self,
+        tx_id: TransactionId,
+    ) -> Result<Option<CreateTransactionCommand>, StoreError> {
+        Ok(self.txs.get(&tx_id).cloned())
+    }
+
+    fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError> {
+        Ok(self.events.get(&client_id).cloned().unwrap_or_default())
+    }
+
+    fn append_event(
+        &mut self,
+        client_id: ClientId,
+        expected_version: u64,
+        event: &AccountEvent,
+        created: Option<&CreateTransactionCommand>,
+    ) -> Result<(), StoreError> {
+        let events = self.events.entry(client_id).or_default();
+        if events.len() as u64 != expected_version {
+            return Err(StoreError::VersionConflict);
+        }
+        if let Some(command) = created {
+            if self.txs.contains_key(&command.tx_id) {
+                return Err(StoreError::TxConflict(command.tx_id));
+            }
+            self.txs.insert(command.tx_id, command.clone());
+        }
+        events.push(event.clone());
+        Ok(())
+    }
+}
+
+/// Encodes event in the latest version of [`EventEnvelope`], for key-value stores
+pub fn encode_event(event: &AccountEvent) -> String {
+    EventV2::recorded_now(event).encode()
+}
+
+/// Decodes event of any version
+pub fn decode_event(value: &str) -> Result<AccountEvent, StoreError> {
+    Ok(decode_envelope(value)?.event())
+}
+
+/// Decodes event of any version, upcast to the latest envelope
+pub fn decode_envelope(value: &str) -> Result<EventV2, StoreError> {
+    Ok(EventEnvelope::decode(value)?.upcast())
+}
+
+/// Encodes created transaction as `action:amount`
+pub fn encode_tx(command: &CreateTransactionCommand) -> String {
+    format!("{}:{}", command.action.name(), command.amount.amount())
+}
+
+pub fn decode_tx(
+    tx_id: TransactionId,
+    value: &str,
+) -> Result<CreateTransactionCommand, StoreError> {
+    let invalid = || StoreError::Backend(format!("invalid transaction `{value}`"));
+    let (action, amount) = value.split_once(':').ok_or_else(invalid)?;
+    Ok(CreateTransactionCommand {
+        tx_id,
+        action: CreateTransactionAction::from_name(action).ok_or_else(invalid)?,
+        amount: Decimal::from_str(amount)
+            .ok()
+            .and_then(|amount| Money::new(amount).ok())
+            .ok_or_else(invalid)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::AccountEventKind;
+
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let event = AccountEvent::new(7, Decimal::new(15, 1), AccountEventKind::Disputed);
+        let encoded = encode_event(&event);
+        assert!(encoded.starts_with("v2:disputed:7:1.5:"));
+        let decoded = decode_event(&encoded).unwrap();
+        assert_eq!(decoded.kind(), AccountEventKind::Disputed);
+        assert_eq!(decoded.amount(), Decimal::new(15, 1));
+        assert!(decode_event("disputed:x:1").is_err());
+        // written before versioning
+        assert_eq!(
+            decode_event("disputed:7:1.5").unwrap().amount(),
+            Decimal::new(15, 1)
+        );
+
+        let tx = decode_tx(3, "pending_deposit:2").unwrap();
+        assert_eq!(tx.action, CreateTransactionAction::PendingDeposit);
+        assert_eq!(encode_tx(&tx), "pending_deposit:2");
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    account::{Account, TransactionId},
+    command::{AccountCommand, TransactionKind, ZeroAmountPolicy},
+};
+
+use super::{
+    AccountsCursor, AccountsPage, ClientId, TransactionOutcome, TransactionProcessError,
+    TransactionProcessor,
+    metrics::{MetricsHook, ProcessorMetrics},
+    state_store::{StateStore, StoreError},
+};
+
+/// How many times command is re-validated against fresh account state,
+/// when another writer changed the account in between
+const MAX_ATTEMPTS: usize = 5;
+
+/// Processor keeping its state in a [`StateStore`], so several instances
+/// can process transactions of the same clients. Accounts are cached
+/// locally, and reloaded whenever a concurrent change is detected, or
+/// the cached account rejects a command.
+pub struct StoreTransactionProcessor<S> {
+    store: S,
+    accounts: HashMap<ClientId, Account>,
+    metrics: MetricsHook,
+}
+
+impl<S: StateStore> StoreTransactionProcessor<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            accounts: HashMap::new(),
+            metrics: MetricsHook::default(),
+        }
+    }
+
+    /// Reports outcomes and account locks to `metrics`
+    pub fn with_metrics(mut self, metrics: impl ProcessorMetrics) -> Self {
+        self.metrics.set(metrics);
+        self
+    }
+
+    pub fn metrics<M: ProcessorMetrics>(&self) -> Option<&M> {
+        self.metrics.get()
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    fn reload(&mut self, client_id: ClientId) -> Result<(), StoreError> {
+        let mut acc = Account::default();
+        for event in self.store.load_events(client_id)? {
+            acc.apply(&event);
+        }
+        self.accounts.insert(client_id, acc);
+        Ok(())
+    }
+
+    fn process_with_retries(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: &TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        // account was loaded from the store for this transaction
+        let mut fresh = false;
+        for _ in 0..MAX_ATTEMPTS {
+            let existing_tx = self.store.get_tx(tx_id)?;
+            let cmd = AccountCommand::parse_command(
+                tx_id,
+                existing_tx.as_ref(),
+                kind,
+                amount,
+                ZeroAmountPolicy::default(),
+            )?;
+            // like in memory processor, account is known only after a valid command
+            if !self.accounts.contains_key(&client_id) {
+                self.reload(client_id)?;
+                fresh = true;
+            }
+            let acc = &self.accounts[&client_id];
+            let handled = match &cmd {
+                AccountCommand::CreateTx(command) => acc.handle_create_transaction(command.clone()),
+                AccountCommand::ModifyTx(command) => acc.handle_modify_transaction(command.clone()),
+            };
+            let evt = match handled {
+                Ok(evt) => evt,
+                // another instance may have made the command valid, since
+                // the account was cached, so it is rejected by the latest state only
+                Err(err) if !fresh => {
+                    let version = acc.version();
+                    self.reload(client_id)?;
+                    fresh = true;
+                    if self.accounts[&client_id].version() == version {
+                        return Err(err.into());
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            acc.check_apply(&evt)?;
+            let created = match &cmd {
+                AccountCommand::CreateTx(command) => Some(command),
+                AccountCommand::ModifyTx(_) => None,
+            };
+            match self
+                .store
+                .append_event(client_id, acc.version(), &evt, created)
+            {
+                Ok(()) => {
+                    let acc = self.accounts.get_mut(&client_id).expect("loaded above");
+                    // checked before it was stored
+                    let was_locked = acc.locked();
+                    acc.apply(&evt);
+                    let outcome = TransactionOutcome::applied(evt.kind(), acc, was_locked);
+                    if outcome.locked {
+                        self.metrics.on_account_locked(client_id);
+                    }
+                    return Ok(outcome);
+                }
+                Err(StoreError::VersionConflict | StoreError::TxConflict(_)) => {
+                    // validate again against the latest state
+                    self.reload(client_id)?;
+                    fresh = true;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(StoreError::VersionConflict.into())
+    }
+}
+
+impl<S: StateStore> TransactionProcessor for StoreTransactionProcessor<S> {
+    fn process_transaction(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        let result = self.process_with_retries(tx_id, client_id, amount, &kind);
+        self.metrics.on_result(client_id, &kind, &result);
+        result
+    }
+
+    fn accounts(&self) -> impl Iterator<Item = (ClientId, &Account)> {
+        self.accounts
+            .iter()
+            .map(|(client_id, acc)| (*client_id, acc))
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    fn accounts_page(&self, cursor: Option<AccountsCursor>, limit: usize) -> AccountsPage<'_> {
+        AccountsPage::probe(cursor, limit, |client_id| self.accounts.get(&client_id))
+    }
+}
+
+impl From<StoreError> for TransactionProcessError {
+    fn from(err: StoreError) -> Self {
+        TransactionProcessError::StorageErr(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use rust_decimal::prelude::FromPrimitive;
+
+    use crate::{
+        account::AccountEvent, command::CreateTransactionCommand,
+        processor::state_store::InMemoryStateStore,
+    };
+
+    use super::*;
+
+    /// Store where another writer sneaks in an event before the first append
+    struct RacingStore {
+        inner: InMemoryStateStore,
+        race: Option<AccountEvent>,
+    }
+
+    impl StateStore for RacingStore {
+        fn get_tx(
+            &mut self,
+            tx_id: TransactionId,
+        ) -> Result<Option<CreateTransactionCommand>, StoreError> {
+            self.inner.get_tx(tx_id)
+        }
+
+        fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError> {
+            self.inner.load_events(client_id)
+        }
+
+        fn append_event(
+            &mut self,
+            client_id: ClientId,
+            expected_version: u64,
+            event: &AccountEvent,
+            created: Option<&CreateTransactionCommand>,
+        ) -> Result<(), StoreError> {
+            if let Some(race) = self.race.take() {
+                self.inner.append_event(client_id, 0, &race, None)?;
+            }
+            self.inner
+                .append_event(client_id, expected_version, event, created)
+        }
+    }
+
+    #[test]
+    fn retries_on_concurrent_change() {
+        let mut processor = StoreTransactionProcessor::new(RacingStore {
+            inner: InMemoryStateStore::default(),
+            race: Some(AccountEvent::new(
+                100,
+                Decimal::from_u32(5).unwrap(),
+                crate::account::AccountEventKind::Deposited,
+            )),
+        });
+        processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        // withdrawal is only possible, because concurrent deposit was picked up
+        processor
+            .process_transaction(
+                2,
+                1,
+                Some(Decimal::from_u32(6).unwrap()),
+                TransactionKind::Withdrawal,
+            )
+            .unwrap();
+
+        let (_, acc) = processor.accounts().next().unwrap();
+        assert_eq!(acc.available(), Decimal::ZERO);
+        assert_eq!(acc.version(), 3);
+        let err = processor
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap_err();
+        assert!(matches!(err, TransactionProcessError::CommandErr(_)));
+    }
+
+    /// Store shared by several processors, like instances of a deployment
+    #[derive(Clone, Default)]
+    struct SharedStore(Rc<RefCell<InMemoryStateStore>>);
+
+    impl StateStore for SharedStore {
+        fn get_tx(
+            &mut self,
+            tx_id: TransactionId,
+        ) -> Result<Option<CreateTransactionCommand>, StoreError> {
+            self.0.borrow_mut().get_tx(tx_id)
+        }
+
+        fn load_events(&mut self, client_id: ClientId) -> Result<Vec<AccountEvent>, StoreError> {
+            self.0.borrow_mut().load_events(client_id)
+        }
+
+        fn append_event(
+            &mut self,
+            client_id: ClientId,
+            expected_version: u64,
+            event: &AccountEvent,
+            created: Option<&CreateTransactionCommand>,
+        ) -> Result<(), StoreError> {
+            self.0
+                .borrow_mut()
+                .append_event(client_id, expected_version, event, created)
+        }
+    }
+
+    #[test]
+    fn stale_account_is_reloaded_before_rejecting() {
+        let store = SharedStore::default();
+        let mut first = StoreTransactionProcessor::new(store.clone());
+        let mut second = StoreTransactionProcessor::new(store);
+        first
+            .process_transaction(1, 1, Some(Decimal::ONE), TransactionKind::Deposit)
+            .unwrap();
+        // cached account of the first instance goes stale
+        second
+            .process_transaction(2, 1, Some(Decimal::from(5)), TransactionKind::Deposit)
+            .unwrap();
+        second
+            .process_transaction(2, 1, None, TransactionKind::Dispute)
+            .unwrap();
+
+        first
+            .process_transaction(2, 1, None, TransactionKind::Resolve)
+            .unwrap();
+        first
+            .process_transaction(3, 1, Some(Decimal::from(6)), TransactionKind::Withdrawal)
+            .unwrap();
+        let err = first
+            .process_transaction(4, 1, Some(Decimal::ONE), TransactionKind::Withdrawal)
+            .unwrap_err();
+        assert_eq!(err.code(), "insufficient_funds");
+        assert_eq!(first.account(1).unwrap().version(), 5);
+    }
+
+    #[test]
+    fn conforms_to_in_memory_processor() {
+        let failures = crate::conformance::verify(|| {
+            StoreTransactionProcessor::new(InMemoryStateStore::default())
+        })
+        .unwrap();
+        assert_eq!(failures, Vec::new());
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fmt::Display};
+
+use rust_decimal::Decimal;
+
+use crate::{account::TransactionId, command::TransactionKind};
+
+use super::ClientId;
+
+/// Modify row, that arrived before the transaction it references
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuspendedRow {
+    pub tx_id: TransactionId,
+    pub client_id: ClientId,
+    pub kind: TransactionKind,
+    pub source: Option<String>,
+    pub trace_id: Option<String>,
+}
+
+/// Parked modify rows, waiting for referenced transaction to arrive
+#[derive(Debug, Default)]
+pub struct Suspense {
+    rows: HashMap<TransactionId, Vec<SuspendedRow>>,
+    parked: u64,
+    matched: u64,
+    failed: Vec<(SuspendedRow, &'static str)>,
+    over_cap: Vec<(SuspendedRow, Decimal)>,
+}
+
+impl Suspense {
+    pub fn park(&mut self, row: SuspendedRow) {
+        self.parked += 1;
+        self.rows.entry(row.tx_id).or_default().push(row);
+    }
+
+    /// Removes rows waiting for `tx_id`, in the order they were parked
+    pub fn take(&mut self, tx_id: TransactionId) -> Vec<SuspendedRow> {
+        let rows = self.rows.remove(&tx_id).unwrap_or_default();
+        self.matched += rows.len() as u64;
+        rows
+    }
+
+    /// Row was matched, but still rejected when re-attempted
+    pub fn failed(&mut self, row: SuspendedRow, code: &'static str) {
+        self.failed.push((row, code));
+    }
+
+    /// Deposit would exceed the balance cap, and is held for manual review
+    pub fn hold_over_cap(&mut self, row: SuspendedRow, amount: Decimal) {
+        self.over_cap.push((row, amount));
+    }
+
+    /// Rows still parked or held, waiting for a transaction or a decision
+    pub fn has_pending(&self) -> bool {
+        !self.rows.is_empty() || !self.over_cap.is_empty()
+    }
+
+    pub fn report(&self) -> SuspenseReport {
+        let mut unmatched: Vec<_> = self.rows.values().flatten().cloned().collect();
+        unmatched.sort_by_key(|row| row.tx_id);
+        SuspenseReport {
+            parked: self.parked,
+            matched: self.matched,
+            failed: self.failed.clone(),
+            unmatched,
+            over_cap: self.over_cap.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SuspenseReport {
+    pub parked: u64,
+    pub matched: u64,
+    /// Matched rows, that were rejected when re-attempted, with error code
+    pub failed: Vec<(SuspendedRow, &'static str)>,
+    /// Rows whose transaction never arrived
+    pub unmatched: Vec<SuspendedRow>,
+    /// Deposits held, because they would exceed the balance cap, with amount
+    pub over_cap: Vec<(SuspendedRow, Decimal)>,
+}
+
+impl Display for SuspenseReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "suspense parked:  {}", self.parked)?;
+        writeln!(f, "suspense matched: {}", self.matched)?;
+        for (row, code) in &self.failed {
+            writeln!(
+                f,
+                "  failed {:?} tx {} client {}: {code}",
+                row.kind, row.tx_id, row.client_id
+            )?;
+        }
+        writeln!(f, "unmatched:        {}", self.unmatched.len())?;
+        for row in &self.unmatched {
+            writeln!(
+                f,
+                "  {:?} tx {} client {}",
+                row.kind, row.tx_id, row.client_id
+            )?;
+        }
+        if !self.over_cap.is_empty() {
+            writeln!(f, "over balance cap: {}", self.over_cap.len())?;
+            for (row, amount) in &self.over_cap {
+                writeln!(
+                    f,
+                    "  {:?} tx {} client {}: {amount}",
+                    row.kind, row.tx_id, row.client_id
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! Validation of upstreams guaranteeing increasing tx ids, so corrupted or
+//! replayed files are caught by the first transaction going back.
+
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{account::TransactionId, command::AccountCommandError};
+
+use super::ClientId;
+
+/// Order of ids of created transactions, modify rows reference earlier ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TxIdOrder {
+    #[default]
+    Any,
+    /// Lower than the highest accepted one are rejected
+    Global,
+    /// Lower than the highest accepted one of the same client are rejected
+    PerClient,
+}
+
+impl FromStr for TxIdOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Self::Any),
+            "global" => Ok(Self::Global),
+            "per-client" => Ok(Self::PerClient),
+            other => Err(format!("unknown tx id order `{other}`")),
+        }
+    }
+}
+
+/// Highest ids of accepted transactions, globally or by client
+#[derive(Debug, Clone, Default)]
+pub struct TxIdWatermarks {
+    order: TxIdOrder,
+    highest: HashMap<Option<ClientId>, TransactionId>,
+}
+
+impl TxIdWatermarks {
+    pub fn new(order: TxIdOrder) -> Self {
+        Self {
+            order,
+            highest: HashMap::new(),
+        }
+    }
+
+    fn key(&self, client_id: ClientId) -> Option<Option<ClientId>> {
+        match self.order {
+            TxIdOrder::Any => None,
+            TxIdOrder::Global => Some(None),
+            TxIdOrder::PerClient => Some(Some(client_id)),
+        }
+    }
+
+    pub fn check(
+        &self,
+        client_id: ClientId,
+        tx_id: TransactionId,
+    ) -> Result<(), AccountCommandError> {
+        let highest = self
+            .key(client_id)
+            .and_then(|key| self.highest.get(&key).copied());
+        match highest {
+            Some(highest) if tx_id < highest => {
+                Err(AccountCommandError::TxIdNotIncreasing { highest })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Keeps the highest ids of both, for accepted transactions of another processor
+    pub fn merge(&mut self, other: TxIdWatermarks) {
+        for (key, tx_id) in other.highest {
+            let highest = self.highest.entry(key).or_default();
+            *highest = tx_id.max(*highest);
+        }
+    }
+
+    /// Highest ids by client, or `None` for the global one
+    pub fn iter(&self) -> impl Iterator<Item = (Option<ClientId>, TransactionId)> + '_ {
+        self.highest.iter().map(|(key, tx_id)| (*key, *tx_id))
+    }
+
+    /// Puts in the highest id of another processor, see [`Self::iter`].
+    /// Ids of clients also raise the global one, the global id is dropped
+    /// when ids are checked by client.
+    pub fn restore(&mut self, client_id: Option<ClientId>, tx_id: TransactionId) {
+        match client_id {
+            Some(client_id) => self.record(client_id, tx_id),
+            None if self.order == TxIdOrder::Global => {
+                let highest = self.highest.entry(None).or_default();
+                *highest = tx_id.max(*highest);
+            }
+            None => {}
+        }
+    }
+
+    /// Records id of accepted transaction
+    pub fn record(&mut self, client_id: ClientId, tx_id: TransactionId) {
+        if let Some(key) = self.key(client_id) {
+            let highest = self.highest.entry(key).or_default();
+            *highest = tx_id.max(*highest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_ids_going_back() {
+        let mut global = TxIdWatermarks::new(TxIdOrder::Global);
+        let mut per_client = TxIdWatermarks::new(TxIdOrder::PerClient);
+        for watermarks in [&mut global, &mut per_client] {
+            watermarks.record(1, 10);
+            watermarks.record(2, 5);
+            assert!(watermarks.check(1, 11).is_ok());
+            assert!(matches!(
+                watermarks.check(1, 9),
+                Err(AccountCommandError::TxIdNotIncreasing { highest: 10 })
+            ));
+        }
+        assert!(global.check(2, 7).is_err());
+        assert!(per_client.check(2, 7).is_ok());
+        assert!(per_client.check(3, 1).is_ok());
+
+        let mut restored = TxIdWatermarks::new(TxIdOrder::Global);
+        for (client_id, tx_id) in per_client.iter() {
+            restored.restore(client_id, tx_id);
+        }
+        assert!(restored.check(2, 9).is_err());
+
+        let mut any = TxIdWatermarks::default();
+        any.record(1, 10);
+        assert!(any.check(1, 1).is_ok());
+    }
+}
@@ -0,0 +1,338 @@
+use std::{collections::HashMap, mem::size_of};
+
+use roaring::RoaringBitmap;
+
+use crate::{
+    account::TransactionId,
+    command::{CreateTransactionAction, CreateTransactionCommand},
+    money::Money,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct TxRecord {
+    amount: Money,
+    action: CreateTransactionAction,
+}
+
+/// Storage of created transactions.
+/// Records are kept densely in a slab, while hash map only maps tx id to
+/// the slab index, so rehashing moves around 8 byte entries instead of
+/// full records, and records themselves are never reallocated one by one.
+///
+/// In compact mode withdrawals are only stored in a roaring bitmap.
+/// Withdrawals cannot be disputed, so only their existence matters, while
+/// deposits still need full records, because dispute arrives without amount.
+///
+/// Records of transactions that can't be modified anymore can be retired:
+/// their slab slots are reused, and only their ids are kept in a bitmap,
+/// so duplicates are still detected.
+#[derive(Debug, Default)]
+pub struct TxStore {
+    index: HashMap<TransactionId, u32>,
+    records: Vec<TxRecord>,
+    /// Slab slots of retired records
+    free: Vec<u32>,
+    withdrawals: Option<RoaringBitmap>,
+    retired: RoaringBitmap,
+}
+
+/// Approximate memory used by [`TxStore`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub records: usize,
+    pub index_capacity: usize,
+    pub slab_capacity: usize,
+    pub bitmap_entries: u64,
+    pub retired: u64,
+    pub approx_bytes: usize,
+}
+
+impl TxStore {
+    pub fn with_capacity(txs: usize) -> Self {
+        Self {
+            index: HashMap::with_capacity(txs),
+            records: Vec::with_capacity(txs),
+            ..Default::default()
+        }
+    }
+
+    /// Store that keeps withdrawals in a compressed bitmap
+    pub fn compact() -> Self {
+        Self {
+            withdrawals: Some(RoaringBitmap::new()),
+            ..Default::default()
+        }
+    }
+
+    /// Keeps withdrawals inserted from now on in a compressed bitmap,
+    /// see [`Self::compact`]
+    pub fn compact_withdrawals(&mut self) {
+        self.withdrawals.get_or_insert_with(RoaringBitmap::new);
+    }
+
+    /// In compact mode, withdrawal amount is not retained, so zero is returned
+    pub fn get(&self, tx_id: TransactionId) -> Option<CreateTransactionCommand> {
+        let Some(idx) = self.index.get(&tx_id) else {
+            return self
+                .withdrawals
+                .as_ref()
+                .filter(|withdrawals| withdrawals.contains(tx_id))
+                .map(|_| CreateTransactionCommand {
+                    tx_id,
+                    action: CreateTransactionAction::Withdraw,
+                    amount: Money::ZERO,
+                });
+        };
+        let record = self.records[*idx as usize];
+        Some(CreateTransactionCommand {
+            tx_id,
+            action: record.action,
+            amount: record.amount,
+        })
+    }
+
+    /// Whether transaction was created, including retired ones
+    pub fn contains(&self, tx_id: TransactionId) -> bool {
+        self.index.contains_key(&tx_id)
+            || self.retired.contains(tx_id)
+            || self
+                .withdrawals
+                .as_ref()
+                .is_some_and(|withdrawals| withdrawals.contains(tx_id))
+    }
+
+    /// Stores command, overwriting previous one with the same tx id
+    pub fn insert(&mut self, command: CreateTransactionCommand) {
+        if let (Some(withdrawals), CreateTransactionAction::Withdraw) =
+            (&mut self.withdrawals, command.action)
+        {
+            withdrawals.insert(command.tx_id);
+            return;
+        }
+        let record = TxRecord {
+            amount: command.amount,
+            action: command.action,
+        };
+        match self.index.get(&command.tx_id) {
+            Some(idx) => self.records[*idx as usize] = record,
+            None => {
+                let idx = match self.free.pop() {
+                    Some(idx) => {
+                        self.records[idx as usize] = record;
+                        idx
+                    }
+                    None => {
+                        let idx = u32::try_from(self.records.len()).expect("tx id space is u32");
+                        self.records.push(record);
+                        idx
+                    }
+                };
+                self.index.insert(command.tx_id, idx);
+            }
+        }
+    }
+
+    /// Drops record of transaction, that won't be modified anymore,
+    /// and keeps only its id
+    pub fn retire(&mut self, tx_id: TransactionId) {
+        if let Some(idx) = self.index.remove(&tx_id) {
+            self.free.push(idx);
+            self.retired.insert(tx_id);
+        }
+    }
+
+    /// Whether record of the transaction was dropped by [`TxStore::retire`]
+    pub fn is_retired(&self, tx_id: TransactionId) -> bool {
+        self.retired.contains(tx_id)
+    }
+
+    /// Ids of all created transactions, including retired ones
+    pub fn ids(&self) -> impl Iterator<Item = TransactionId> + '_ {
+        self.index
+            .keys()
+            .copied()
+            .chain(self.withdrawals.iter().flat_map(RoaringBitmap::iter))
+            .chain(self.retired.iter())
+    }
+
+    /// Moves all transactions of `other` in, overwriting records with the same tx id.
+    /// Withdrawals of compact store become zero amount records in a full one.
+    pub fn merge(&mut self, other: TxStore) {
+        for tx_id in other.index.keys().copied() {
+            if let Some(command) = other.get(tx_id) {
+                self.insert(command);
+            }
+        }
+        for tx_id in other.withdrawals.iter().flat_map(RoaringBitmap::iter) {
+            self.insert(CreateTransactionCommand {
+                tx_id,
+                action: CreateTransactionAction::Withdraw,
+                amount: Money::ZERO,
+            });
+        }
+        for tx_id in &other.retired {
+            self.retire(tx_id);
+            self.retired.insert(tx_id);
+        }
+    }
+
+    /// Puts back state of a transaction recorded before it was changed,
+    /// see [`super::savepoint`]
+    pub fn restore(
+        &mut self,
+        tx_id: TransactionId,
+        previous: Option<CreateTransactionCommand>,
+        retired: bool,
+    ) {
+        if let Some(idx) = self.index.remove(&tx_id) {
+            self.free.push(idx);
+        }
+        if let Some(withdrawals) = &mut self.withdrawals {
+            withdrawals.remove(tx_id);
+        }
+        if retired {
+            self.retired.insert(tx_id);
+        } else {
+            self.retired.remove(tx_id);
+        }
+        if let Some(command) = previous {
+            self.insert(command);
+        }
+    }
+
+    /// Number of stored records, not counting retired ones
+    pub fn len(&self) -> usize {
+        self.index.len() + self.bitmap_len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn bitmap_len(&self) -> u64 {
+        self.withdrawals.as_ref().map_or(0, RoaringBitmap::len)
+    }
+
+    /// Approximate memory needed for `records` transactions, when
+    /// `bitmap_entries` of them are withdrawals kept in compact mode
+    pub fn estimated_bytes(records: u64, bitmap_entries: u64) -> u64 {
+        let full = records.saturating_sub(bitmap_entries);
+        // maps grow at 7/8 load factor, and double
+        let buckets = (full * 8 / 7).next_power_of_two();
+        let index_bytes = buckets * (size_of::<TransactionId>() + size_of::<u32>() + 1) as u64;
+        // roaring bitmap of dense ids takes about 2 bytes per entry
+        index_bytes + full * size_of::<TxRecord>() as u64 + bitmap_entries * 2
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        // hashbrown keeps one control byte per bucket next to the entry
+        let index_bytes =
+            self.index.capacity() * (size_of::<TransactionId>() + size_of::<u32>() + 1);
+        let slab_bytes = self.records.capacity() * size_of::<TxRecord>()
+            + self.free.capacity() * size_of::<u32>();
+        let bitmap_bytes = self
+            .withdrawals
+            .as_ref()
+            .map_or(0, RoaringBitmap::serialized_size)
+            + self.retired.serialized_size();
+        MemoryStats {
+            records: self.index.len(),
+            index_capacity: self.index.capacity(),
+            slab_capacity: self.records.capacity(),
+            bitmap_entries: self.bitmap_len(),
+            retired: self.retired.len(),
+            approx_bytes: index_bytes + slab_bytes + bitmap_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_get() {
+        let mut store = TxStore::with_capacity(2);
+        assert!(store.is_empty());
+        store.insert(CreateTransactionCommand {
+            tx_id: 42,
+            action: CreateTransactionAction::Deposit,
+            amount: Money::from(10),
+        });
+        store.insert(CreateTransactionCommand {
+            tx_id: 7,
+            action: CreateTransactionAction::Withdraw,
+            amount: Money::from(3),
+        });
+        assert_eq!(store.len(), 2);
+        assert!(store.contains(42));
+        assert!(!store.contains(1));
+
+        let cmd = store.get(7).unwrap();
+        assert_eq!(cmd.tx_id, 7);
+        assert_eq!(cmd.amount, Money::from(3));
+        assert!(matches!(cmd.action, CreateTransactionAction::Withdraw));
+
+        let stats = store.memory_stats();
+        assert_eq!(stats.records, 2);
+        assert!(stats.approx_bytes >= 2 * size_of::<TxRecord>());
+    }
+
+    #[test]
+    fn compact_store_keeps_withdrawals_in_bitmap() {
+        let mut store = TxStore::compact();
+        store.insert(CreateTransactionCommand {
+            tx_id: 1,
+            action: CreateTransactionAction::Deposit,
+            amount: Money::from(10),
+        });
+        for tx_id in 2..1002 {
+            store.insert(CreateTransactionCommand {
+                tx_id,
+                action: CreateTransactionAction::Withdraw,
+                amount: Money::from(1),
+            });
+        }
+        assert_eq!(store.len(), 1001);
+        assert!(store.contains(500));
+        assert!(!store.contains(1002));
+
+        let deposit = store.get(1).unwrap();
+        assert_eq!(deposit.amount, Money::from(10));
+        let withdrawal = store.get(2).unwrap();
+        assert!(matches!(
+            withdrawal.action,
+            CreateTransactionAction::Withdraw
+        ));
+        assert_eq!(withdrawal.amount, Money::ZERO);
+
+        let stats = store.memory_stats();
+        assert_eq!(stats.records, 1);
+        assert_eq!(stats.bitmap_entries, 1000);
+        // dense run of ids takes far less than full records would
+        assert!(stats.approx_bytes < 1000 * size_of::<TxRecord>() / 4);
+    }
+
+    #[test]
+    fn retired_slots_are_reused() {
+        let mut store = TxStore::default();
+        let deposit = |tx_id, amount| CreateTransactionCommand {
+            tx_id,
+            action: CreateTransactionAction::Deposit,
+            amount: Money::from(amount),
+        };
+        store.insert(deposit(1, 10));
+        store.insert(deposit(2, 20));
+        store.retire(1);
+        assert!(store.get(1).is_none());
+        assert!(store.contains(1));
+        assert!(store.is_retired(1));
+        assert_eq!(store.len(), 1);
+
+        store.insert(deposit(3, 30));
+        assert_eq!(store.records.len(), 2);
+        assert_eq!(store.get(3).unwrap().amount, Money::from(30));
+        assert_eq!(store.get(2).unwrap().amount, Money::from(20));
+        assert_eq!(store.memory_stats().retired, 1);
+    }
+}
@@ -0,0 +1,151 @@
+//! Clients under review: their transactions are not applied right away,
+//! but queued until an operator approves or rejects them.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+};
+
+use rust_decimal::Decimal;
+
+use crate::{account::TransactionId, command::TransactionKind};
+
+use super::ClientId;
+
+/// Identifier of a queued transaction, assigned in the order of arrival
+pub type ReviewId = u64;
+
+/// Transaction of a watched client, waiting for a decision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingReview {
+    pub id: ReviewId,
+    pub tx_id: TransactionId,
+    pub client_id: ClientId,
+    pub amount: Option<Decimal>,
+    pub kind: TransactionKind,
+    pub source: Option<String>,
+    pub trace_id: Option<String>,
+}
+
+/// Watched clients and transactions diverted from them
+#[derive(Debug, Default)]
+pub struct Watchlist {
+    clients: HashSet<ClientId>,
+    queue: BTreeMap<ReviewId, PendingReview>,
+    next_id: ReviewId,
+    approved: u64,
+    rejected: u64,
+}
+
+impl Watchlist {
+    pub fn new(clients: impl IntoIterator<Item = ClientId>) -> Self {
+        Self {
+            clients: clients.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn watch(&mut self, client_id: ClientId) {
+        self.clients.insert(client_id);
+    }
+
+    /// New transactions of the client are applied again, already queued ones stay queued
+    pub fn unwatch(&mut self, client_id: ClientId) {
+        self.clients.remove(&client_id);
+    }
+
+    /// Replaces watched clients, queued transactions are kept
+    pub fn set_clients(&mut self, clients: impl IntoIterator<Item = ClientId>) {
+        self.clients = clients.into_iter().collect();
+    }
+
+    pub fn is_watched(&self, client_id: ClientId) -> bool {
+        self.clients.contains(&client_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty() && self.queue.is_empty()
+    }
+
+    /// Queues transaction for review
+    pub fn divert(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+        source: Option<&str>,
+        trace_id: Option<&str>,
+    ) -> ReviewId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.insert(
+            id,
+            PendingReview {
+                id,
+                tx_id,
+                client_id,
+                amount,
+                kind,
+                source: source.map(str::to_string),
+                trace_id: trace_id.map(str::to_string),
+            },
+        );
+        id
+    }
+
+    /// Queued transactions, oldest first
+    pub fn pending(&self) -> impl Iterator<Item = &PendingReview> {
+        self.queue.values()
+    }
+
+    /// Removes approved transaction from the queue, so it can be applied
+    pub fn approve(&mut self, id: ReviewId) -> Option<PendingReview> {
+        let review = self.queue.remove(&id)?;
+        self.approved += 1;
+        Some(review)
+    }
+
+    /// Removes rejected transaction from the queue, it is never applied
+    pub fn reject(&mut self, id: ReviewId) -> Option<PendingReview> {
+        let review = self.queue.remove(&id)?;
+        self.rejected += 1;
+        Some(review)
+    }
+
+    pub fn report(&self) -> ReviewReport {
+        ReviewReport {
+            diverted: self.next_id,
+            approved: self.approved,
+            rejected: self.rejected,
+            pending: self.queue.values().cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReviewReport {
+    /// Transactions of watched clients, queued for review
+    pub diverted: u64,
+    pub approved: u64,
+    pub rejected: u64,
+    /// Transactions still waiting for a decision
+    pub pending: Vec<PendingReview>,
+}
+
+impl Display for ReviewReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "review diverted:  {}", self.diverted)?;
+        writeln!(f, "review approved:  {}", self.approved)?;
+        writeln!(f, "review rejected:  {}", self.rejected)?;
+        writeln!(f, "review pending:   {}", self.pending.len())?;
+        for review in &self.pending {
+            writeln!(
+                f,
+                "  #{} {:?} tx {} client {}",
+                review.id, review.kind, review.tx_id, review.client_id
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! Cash-out constraints of withdrawals, e.g. ATM dispensing only amounts
+//! above some minimum and in multiples of the smallest note.
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::account::AccountError;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WithdrawalRules {
+    pub minimum: Option<Decimal>,
+    /// Withdrawn amount must be a multiple of it, e.g. 0.25 or 20
+    pub denomination: Option<Decimal>,
+}
+
+impl WithdrawalRules {
+    pub fn check(&self, amount: Decimal) -> Result<(), AccountError> {
+        if let Some(minimum) = self.minimum
+            && amount < minimum
+        {
+            return Err(AccountError::WithdrawalBelowMinimum { minimum });
+        }
+        if let Some(denomination) = self.denomination
+            && !denomination.is_zero()
+            && !(amount % denomination).is_zero()
+        {
+            return Err(AccountError::WithdrawalDenominationMismatch { denomination });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_minimum_and_denomination() {
+        let rules = WithdrawalRules {
+            minimum: Some(Decimal::from(20)),
+            denomination: Some(Decimal::new(25, 2)),
+        };
+        assert!(rules.check(Decimal::new(2050, 2)).is_ok());
+        assert!(matches!(
+            rules.check(Decimal::from(10)),
+            Err(AccountError::WithdrawalBelowMinimum { .. })
+        ));
+        assert!(matches!(
+            rules.check(Decimal::new(2010, 2)),
+            Err(AccountError::WithdrawalDenominationMismatch { .. })
+        ));
+        assert!(WithdrawalRules::default().check(Decimal::new(1, 2)).is_ok());
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    account::{Account, AccountEvent, AccountEventKind},
+    processor::ClientId,
+};
+
+/// Read model, that is incrementally updated from applied events.
+/// Every projection is an [`crate::event_bus::EventSubscriber`].
+pub trait Projection: Any {
+    /// Called after event was applied, `account` is already updated
+    fn apply(&mut self, client_id: ClientId, event: &AccountEvent, account: &Account);
+}
+
+/// Sum of held funds across all accounts
+#[derive(Debug, Default)]
+pub struct TotalHeld(pub Decimal);
+
+impl Projection for TotalHeld {
+    fn apply(&mut self, _client_id: ClientId, event: &AccountEvent, _account: &Account) {
+        match event.kind() {
+            AccountEventKind::Disputed | AccountEventKind::Authorized => self.0 += event.amount(),
+            AccountEventKind::Resolved
+            | AccountEventKind::Chargedback
+            | AccountEventKind::Captured
+            | AccountEventKind::Voided => self.0 -= event.amount(),
+            AccountEventKind::Deposited
+            | AccountEventKind::Withdrawn
+            | AccountEventKind::DepositPending
+            | AccountEventKind::Settled
+            | AccountEventKind::OpeningBalance
+            | AccountEventKind::Locked
+            | AccountEventKind::Reinstated
+            | AccountEventKind::Represented
+            | AccountEventKind::PreArbitrated
+            | AccountEventKind::Unlocked
+            | AccountEventKind::Adjusted
+            | AccountEventKind::Erased => {}
+        }
+    }
+}
+
+/// Derives custom field of accounts, see [`Account::extensions`]. Unlike
+/// projections, the value is stored on the account itself, so it is rolled
+/// back and merged together with the account.
+pub trait AccountExtension {
+    /// Name of the field
+    fn key(&self) -> &str;
+
+    /// New value of the field after `event` was applied, `account` is already
+    /// updated and holds the current value. `None` keeps the current value.
+    fn update(&self, client_id: ClientId, event: &AccountEvent, account: &Account)
+    -> Option<Value>;
+}
+
+/// Number of disputes the client ever opened, as `disputes` field
+#[derive(Debug, Default)]
+pub struct DisputeCount;
+
+impl AccountExtension for DisputeCount {
+    fn key(&self) -> &str {
+        "disputes"
+    }
+
+    fn update(
+        &self,
+        _client_id: ClientId,
+        event: &AccountEvent,
+        account: &Account,
+    ) -> Option<Value> {
+        if event.kind() != AccountEventKind::Disputed {
+            return None;
+        }
+        let disputes = account.extension(self.key()).and_then(Value::as_u64);
+        Some(Value::from(disputes.unwrap_or(0) + 1))
+    }
+}
+
+/// Clients whose accounts are frozen
+#[derive(Debug, Default)]
+pub struct LockedAccounts(pub BTreeSet<ClientId>);
+
+impl Projection for LockedAccounts {
+    fn apply(&mut self, client_id: ClientId, _event: &AccountEvent, account: &Account) {
+        if account.locked() {
+            self.0.insert(client_id);
+        }
+    }
+}
+
+/// Limits, exceeding which makes client suspicious
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FraudThresholds {
+    pub max_chargebacks: u32,
+    /// Disputed to deposited amount ratio
+    pub max_dispute_ratio: Decimal,
+}
+
+impl Default for FraudThresholds {
+    fn default() -> Self {
+        Self {
+            max_chargebacks: 1,
+            max_dispute_ratio: Decimal::new(5, 1),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClientActivity {
+    deposited: Decimal,
+    disputed: Decimal,
+    chargebacks: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FraudReason {
+    Chargebacks(u32),
+    DisputeRatio(Decimal),
+}
+
+impl Display for FraudReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FraudReason::Chargebacks(count) => write!(f, "chargebacks {count}"),
+            FraudReason::DisputeRatio(ratio) => write!(f, "dispute ratio {ratio}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FraudFlag {
+    pub client: ClientId,
+    pub reason: FraudReason,
+}
+
+/// Sample heuristics flagging clients with too many chargebacks, or
+/// disputing too large part of their deposits
+#[derive(Debug, Default)]
+pub struct FraudHeuristics {
+    pub thresholds: FraudThresholds,
+    clients: BTreeMap<ClientId, ClientActivity>,
+}
+
+impl FraudHeuristics {
+    pub fn new(thresholds: FraudThresholds) -> Self {
+        Self {
+            thresholds,
+            clients: BTreeMap::new(),
+        }
+    }
+
+    /// Flags ordered by client
+    pub fn flags(&self) -> Vec<FraudFlag> {
+        let mut flags = Vec::new();
+        for (client, activity) in &self.clients {
+            if activity.chargebacks > self.thresholds.max_chargebacks {
+                flags.push(FraudFlag {
+                    client: *client,
+                    reason: FraudReason::Chargebacks(activity.chargebacks),
+                });
+            }
+            if !activity.deposited.is_zero() {
+                let ratio = activity.disputed / activity.deposited;
+                if ratio > self.thresholds.max_dispute_ratio {
+                    flags.push(FraudFlag {
+                        client: *client,
+                        reason: FraudReason::DisputeRatio(ratio),
+                    });
+                }
+            }
+        }
+        flags
+    }
+}
+
+impl Projection for FraudHeuristics {
+    fn apply(&mut self, client_id: ClientId, event: &AccountEvent, _account: &Account) {
+        let activity = self.clients.entry(client_id).or_default();
+        match event.kind() {
+            AccountEventKind::Deposited | AccountEventKind::DepositPending => {
+                activity.deposited += event.amount()
+            }
+            AccountEventKind::Disputed => activity.disputed += event.amount(),
+            AccountEventKind::Chargedback => activity.chargebacks += 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use crate::{
+        command::TransactionKind,
+        processor::{TransactionProcessor, in_memory_processor::InMemoryTransactionProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn projections_follow_processing() {
+        let mut processor = InMemoryTransactionProcessor::default()
+            .with_projection(TotalHeld::default())
+            .with_projection(LockedAccounts::default());
+        let amount = |n| Some(Decimal::from_u32(n).unwrap());
+        processor
+            .process_transaction(1, 1, amount(10), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(2, 2, amount(5), TransactionKind::Deposit)
+            .unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        processor
+            .process_transaction(2, 2, None, TransactionKind::Dispute)
+            .unwrap();
+        assert_eq!(
+            processor.projection::<TotalHeld>().unwrap().0,
+            Decimal::from_u32(15).unwrap()
+        );
+        assert!(
+            processor
+                .projection::<LockedAccounts>()
+                .unwrap()
+                .0
+                .is_empty()
+        );
+
+        processor
+            .process_transaction(2, 2, None, TransactionKind::Chargeback)
+            .unwrap();
+        assert_eq!(
+            processor.projection::<TotalHeld>().unwrap().0,
+            Decimal::from_u32(10).unwrap()
+        );
+        assert_eq!(
+            processor.projection::<LockedAccounts>().unwrap().0,
+            BTreeSet::from([2])
+        );
+    }
+
+    #[test]
+    fn fraud_flags() {
+        let mut processor = InMemoryTransactionProcessor::default().with_projection(
+            FraudHeuristics::new(FraudThresholds {
+                max_chargebacks: 0,
+                max_dispute_ratio: Decimal::new(5, 1),
+            }),
+        );
+        let amount = |n| Some(Decimal::from_u32(n).unwrap());
+        let rows = [
+            (1, 1, amount(10), TransactionKind::Deposit),
+            (2, 1, amount(30), TransactionKind::Deposit),
+            (2, 1, None, TransactionKind::Dispute),
+            (3, 2, amount(10), TransactionKind::Deposit),
+            (4, 2, amount(1), TransactionKind::Deposit),
+            (4, 2, None, TransactionKind::Dispute),
+            (4, 2, None, TransactionKind::Chargeback),
+        ];
+        for (tx, client, amount, kind) in rows {
+            processor
+                .process_transaction(tx, client, amount, kind)
+                .unwrap();
+        }
+        assert_eq!(
+            processor.flags(),
+            vec![
+                FraudFlag {
+                    client: 1,
+                    reason: FraudReason::DisputeRatio(Decimal::new(75, 2))
+                },
+                FraudFlag {
+                    client: 2,
+                    reason: FraudReason::Chargebacks(1)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn extensions_are_stored_on_accounts() {
+        let mut processor = InMemoryTransactionProcessor::default().with_extension(DisputeCount);
+        let amount = |n| Some(Decimal::from_u32(n).unwrap());
+        processor
+            .process_transaction(1, 1, amount(10), TransactionKind::Deposit)
+            .unwrap();
+        assert_eq!(processor.account(1).unwrap().extension("disputes"), None);
+        for action in [TransactionKind::Dispute, TransactionKind::Resolve] {
+            processor.process_transaction(1, 1, None, action).unwrap();
+        }
+        let savepoint = processor.savepoint().unwrap();
+        processor
+            .process_transaction(1, 1, None, TransactionKind::Dispute)
+            .unwrap();
+        assert_eq!(
+            processor.account(1).unwrap().extension("disputes"),
+            Some(&Value::from(2))
+        );
+        // rolled back together with the account
+        processor.rollback_to(savepoint);
+        assert_eq!(
+            processor.account(1).unwrap().extension("disputes"),
+            Some(&Value::from(1))
+        );
+
+        assert!(processor.set_extension(1, "tier", Value::from("gold")));
+        assert!(!processor.set_extension(2, "tier", Value::from("gold")));
+        let snapshot = processor.account(1).unwrap().snapshot();
+        assert_eq!(
+            serde_json::to_value(&snapshot).unwrap()["extensions"],
+            serde_json::json!({"disputes": 1, "tier": "gold"})
+        );
+    }
+}
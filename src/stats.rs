@@ -0,0 +1,234 @@
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+use crate::command::TransactionKind;
+
+/// Stages every transaction goes through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Reading and deserializing the row
+    Parse,
+    /// Converting row into a command
+    Validation,
+    /// Handling command by the account
+    AccountHandling,
+    /// Applying resulting event to the account
+    Apply,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::Parse,
+        Stage::Validation,
+        Stage::AccountHandling,
+        Stage::Apply,
+    ];
+}
+
+// values below this are stored exactly, above - with 1/16 relative precision
+const LINEAR_BUCKETS: u64 = 16;
+const MANTISSA_BITS: u32 = 4;
+const BUCKETS: usize = (64 - MANTISSA_BITS as usize + 1) * LINEAR_BUCKETS as usize;
+
+/// Log-linear histogram of durations, keeps constant memory regardless of
+/// the number of samples, while percentiles are accurate to ~6%.
+#[derive(Debug, Clone)]
+pub struct StageTimings {
+    buckets: Box<[u64]>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for StageTimings {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS].into_boxed_slice(),
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl StageTimings {
+    pub fn record(&mut self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[bucket_index(nanos)] += 1;
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns lower bound of the bucket containing `p` quantile (0.0..=1.0)
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((p.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_lower_bound(idx));
+            }
+        }
+        self.max
+    }
+
+    pub fn p50(&self) -> Duration {
+        self.percentile(0.50)
+    }
+
+    pub fn p95(&self) -> Duration {
+        self.percentile(0.95)
+    }
+
+    pub fn p99(&self) -> Duration {
+        self.percentile(0.99)
+    }
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < LINEAR_BUCKETS {
+        return nanos as usize;
+    }
+    let exp = 63 - nanos.leading_zeros();
+    let mantissa = (nanos >> (exp - MANTISSA_BITS)) & (LINEAR_BUCKETS - 1);
+    ((exp - MANTISSA_BITS + 1) as u64 * LINEAR_BUCKETS + mantissa) as usize
+}
+
+fn bucket_lower_bound(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < LINEAR_BUCKETS {
+        return idx;
+    }
+    let exp = (idx / LINEAR_BUCKETS) as u32 + MANTISSA_BITS - 1;
+    let mantissa = idx % LINEAR_BUCKETS;
+    (LINEAR_BUCKETS + mantissa) << (exp - MANTISSA_BITS)
+}
+
+/// Timings of each processing stage, broken down by transaction kind.
+/// All unknown kinds share one entry, so untrusted input can't grow it.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineStats {
+    per_kind: HashMap<TransactionKind, HashMap<Stage, StageTimings>>,
+}
+
+fn stats_key(kind: &TransactionKind) -> TransactionKind {
+    match kind {
+        TransactionKind::Unknown(_) => TransactionKind::Unknown(String::new()),
+        known => known.clone(),
+    }
+}
+
+impl PipelineStats {
+    pub fn record(&mut self, kind: &TransactionKind, stage: Stage, elapsed: Duration) {
+        self.per_kind
+            .entry(stats_key(kind))
+            .or_default()
+            .entry(stage)
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn stage(&self, kind: &TransactionKind, stage: Stage) -> Option<&StageTimings> {
+        self.per_kind.get(&stats_key(kind))?.get(&stage)
+    }
+
+    /// Iterates over recorded (kind, stage) pairs in a stable order
+    pub fn iter(&self) -> impl Iterator<Item = (&TransactionKind, Stage, &StageTimings)> {
+        let mut kinds: Vec<_> = self.per_kind.keys().collect();
+        kinds.sort_by_key(|kind| format!("{kind:?}"));
+        kinds.into_iter().flat_map(move |kind| {
+            Stage::ALL
+                .into_iter()
+                .filter_map(move |stage| self.stage(kind, stage).map(|t| (kind, stage, t)))
+        })
+    }
+}
+
+impl Display for PipelineStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:<16} {:>10} {:>10} {:>10} {:>10}",
+            "kind", "stage", "count", "p50", "p95", "p99"
+        )?;
+        for (kind, stage, timings) in self.iter() {
+            writeln!(
+                f,
+                "{:<12} {:<16} {:>10} {:>10} {:>10} {:>10}",
+                format!("{kind:?}"),
+                format!("{stage:?}"),
+                timings.count(),
+                format!("{:?}", timings.p50()),
+                format!("{:?}", timings.p95()),
+                format!("{:?}", timings.p99()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_bounds_roundtrip() {
+        for nanos in [0, 1, 15, 16, 17, 31, 32, 1000, 123_456_789, u64::MAX] {
+            let idx = bucket_index(nanos);
+            assert!(idx < BUCKETS);
+            let lower = bucket_lower_bound(idx);
+            assert!(lower <= nanos);
+            // relative error is bounded by 1/16
+            assert!(nanos - lower <= nanos / LINEAR_BUCKETS);
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut timings = StageTimings::default();
+        for micros in 1..=100 {
+            timings.record(Duration::from_micros(micros));
+        }
+        assert_eq!(timings.count(), 100);
+        assert_eq!(timings.max(), Duration::from_micros(100));
+        let within = |actual: Duration, expected: u64| {
+            let expected = Duration::from_micros(expected);
+            actual <= expected && actual >= expected - expected / 16
+        };
+        assert!(within(timings.p50(), 50));
+        assert!(within(timings.p95(), 95));
+        assert!(within(timings.p99(), 99));
+    }
+
+    #[test]
+    fn unknown_kinds_share_one_entry() {
+        let mut stats = PipelineStats::default();
+        for idx in 0..100 {
+            let kind = TransactionKind::Unknown(format!("kind{idx}"));
+            stats.record(&kind, Stage::Parse, Duration::from_micros(1));
+        }
+        stats.record(
+            &TransactionKind::Deposit,
+            Stage::Parse,
+            Duration::from_micros(1),
+        );
+        assert_eq!(stats.per_kind.len(), 2);
+        let unknown = TransactionKind::Unknown("other".to_string());
+        assert_eq!(stats.stage(&unknown, Stage::Parse).unwrap().count(), 100);
+    }
+}
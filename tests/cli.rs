@@ -0,0 +1,195 @@
+use std::process::Command;
+
+fn cute_ledger(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_cute-ledger"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn in_memory_options_conflict_with_backends() {
+    let dir = std::env::temp_dir().join(format!("cute-ledger-cli-{}", std::process::id()));
+    let dir = dir.to_str().unwrap();
+    let output = cute_ledger(&[
+        "tests/transactions.csv",
+        "--event-store",
+        dir,
+        "--dispute-flow",
+        "strict",
+    ]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    assert!(!std::path::Path::new(dir).exists());
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("cute-ledger-cli-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn check_requires_a_store() {
+    let output = cute_ledger(&["check"]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn check_compares_accounts_report_with_event_store() {
+    let dir = temp_path("check-events");
+    let dir = dir.to_str().unwrap();
+    let accounts = temp_path("check-accounts.csv");
+    let run = cute_ledger(&["tests/transactions.csv", "--event-store", dir]);
+    std::fs::write(&accounts, &run.stdout).unwrap();
+    let accounts = accounts.to_str().unwrap();
+
+    let output = cute_ledger(&["check", "--event-store", dir, "--accounts", accounts]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let edited = String::from_utf8(run.stdout)
+        .unwrap()
+        .replace("1,1.5,", "1,2.5,");
+    std::fs::write(accounts, edited).unwrap();
+    let output = cute_ledger(&["check", "--event-store", dir, "--accounts", accounts]);
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "client 1: stored balance (available 2.5, held 0, pending 0, locked false) differs \
+         from events (available 1.5, held 0, pending 0, locked false)\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(accounts).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn check_detects_edited_sqlite_balance() {
+    let db = temp_path("check.db");
+    let db = db.to_str().unwrap();
+    cute_ledger(&["tests/transactions.csv", "--sqlite", db]);
+    assert_eq!(
+        cute_ledger(&["check", "--sqlite", db]).status.code(),
+        Some(0)
+    );
+
+    rusqlite::Connection::open(db)
+        .unwrap()
+        .execute("UPDATE accounts SET held = '1' WHERE client = 2", [])
+        .unwrap();
+    let output = cute_ledger(&["check", "--sqlite", db]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("client 2: stored balance"));
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{db}{suffix}"));
+    }
+}
+
+#[test]
+fn malformed_row_is_invalid_input() {
+    let input = temp_path("malformed.csv");
+    std::fs::write(
+        &input,
+        "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,x,2,1.0\n",
+    )
+    .unwrap();
+    let output = cute_ledger(&[input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid row at line 3"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    std::fs::remove_file(&input).unwrap();
+}
+
+#[test]
+fn deterministic_manifest_orders_repeated_options() {
+    let run = |watch: [&str; 2]| {
+        let manifest = temp_path("manifest.json");
+        let output = cute_ledger(&[
+            "tests/transactions.csv",
+            "--deterministic",
+            "--watch",
+            watch[0],
+            "--watch",
+            watch[1],
+            "--manifest",
+            manifest.to_str().unwrap(),
+        ]);
+        let json = std::fs::read_to_string(&manifest).unwrap();
+        std::fs::remove_file(&manifest).unwrap();
+        (output.stdout, json)
+    };
+    // clients not in the input, so accounts are printed
+    let (accounts, manifest) = run(["9", "7"]);
+    assert!(!accounts.is_empty());
+    assert!(manifest.contains("\"watch\""), "{manifest}");
+    assert_eq!((accounts, manifest), run(["7", "9"]));
+}
+
+#[test]
+fn export_reads_recorded_events() {
+    let dir = temp_path("export-events");
+    let dir = dir.to_str().unwrap();
+    cute_ledger(&["tests/transactions.csv", "--event-store", dir]);
+    let output = cute_ledger(&["export", "--event-store", dir, "--client", "2"]);
+    assert_eq!(output.status.code(), Some(0));
+    // the withdrawal of tx 5 was rejected, so it was never recorded
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "seq,client,tx,kind,amount,period,source\n\
+         1,2,2,deposited,2,0,\n"
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn query_searches_recorded_events() {
+    let db = temp_path("query.db");
+    let db = db.to_str().unwrap();
+    cute_ledger(&["tests/transactions.csv", "--sqlite", db]);
+    let output = cute_ledger(&["query", "--sqlite", db, "--kind", "withdrawn"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "seq,client,tx,kind,amount,period,source\n\
+         3,1,4,withdrawn,1.5,0,\n"
+    );
+}
+
+#[test]
+fn risk_config_for_disabled_components_fails() {
+    let config = temp_path("risk.json");
+    std::fs::write(&config, r#"{"fraud": {"max_chargebacks": 2}}"#).unwrap();
+    let config = config.to_str().unwrap();
+    let output = cute_ledger(&["tests/transactions.csv", "--risk-config", config]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("fraud thresholds"));
+
+    let fraud_flags = temp_path("risk-flags.csv");
+    let output = cute_ledger(&[
+        "tests/transactions.csv",
+        "--risk-config",
+        config,
+        "--fraud-flags",
+        fraud_flags.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn unmatched_suspended_rows_are_reported() {
+    let input = temp_path("unmatched.csv");
+    std::fs::write(
+        &input,
+        "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,2,\n",
+    )
+    .unwrap();
+    let output = cute_ledger(&[input.to_str().unwrap(), "--suspense", "--stats"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rows accepted:    1"), "{stderr}");
+    assert!(stderr.contains("rows deferred:    1"), "{stderr}");
+    assert!(stderr.contains("unmatched:        1"), "{stderr}");
+    assert_eq!(output.status.code(), Some(2));
+    std::fs::remove_file(input).unwrap();
+}
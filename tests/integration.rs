@@ -1,27 +1,70 @@
-use std::{collections::HashSet, str::from_utf8};
+use std::{
+    cell::RefCell, collections::HashSet, io::Read, path::PathBuf, rc::Rc, str::from_utf8,
+    time::Duration,
+};
 
-use cute_ledger::bin_utils::Service;
+use cute_ledger::{
+    Ledger,
+    account::{Account, AccountEventKind, CaseStatus, TransactionId},
+    bin_utils::{
+        AccountsOutput, Service, UnknownKindPolicy,
+        account_clients::AccountClients,
+        cdc::ChangeFeed,
+        circuit_breaker::{BreakerAction, CircuitBreaker},
+        config_reload::ConfigReload,
+        csv_parser::InvalidRow,
+        csv_printer,
+        error_sink::JsonLinesSink,
+        reject_log::RejectLog,
+        row_outcome::{RowOutcome, RowStatus},
+        run_report::ExitStatus,
+        watermark::LatePolicy,
+    },
+    command::TransactionKind,
+    event_bus::{AppliedEvent, EventSubscriber},
+    processor::{
+        ClientId, TransactionOutcome, TransactionProcessError, TransactionProcessor,
+        admin::{ADMIN_SOURCE, AdminOps},
+        in_memory_processor::InMemoryTransactionProcessor,
+    },
+    projection::DisputeCount,
+};
+use rust_decimal::Decimal;
 
 const TEST_FILE: &str = include_str!("transactions.csv");
 
 #[test]
 fn process_transactions() {
     let mut output = Vec::new();
-    let service = Service {
-        input: TEST_FILE.as_bytes(),
-        output: &mut output,
-        error_printer: Box::new(|line, err| {
+    let service = Service::builder()
+        .input(TEST_FILE.as_bytes())
+        .output(&mut output)
+        .on_error(|line, err| {
             match err {
                 cute_ledger::processor::TransactionProcessError::CommandErr(err) => {
                     eprintln!("Error at line {line}: {err}")
                 }
-                cute_ledger::processor::TransactionProcessError::AccountErr(_) => {
+                cute_ledger::processor::TransactionProcessError::AccountErr(_)
+                | cute_ledger::processor::TransactionProcessError::KycErr(_) => {
                     // these are not technical errors, so we don't need to print them
                 }
+                cute_ledger::processor::TransactionProcessError::StorageErr(err) => {
+                    panic!("Storage error at line {line}: {err}")
+                }
+                cute_ledger::processor::TransactionProcessError::SignatureErr(err) => {
+                    panic!("Invalid signature at line {line}: {err}")
+                }
+                cute_ledger::processor::TransactionProcessError::ApplyErr(err) => {
+                    panic!("Invalid event at line {line}: {err}")
+                }
             }
-        }),
-    };
-    service.run().unwrap();
+        })
+        .build();
+    let report = service.run().unwrap();
+    assert_eq!(report.rows_read, 5);
+    assert_eq!(report.rows_accepted, 4);
+    assert_eq!(report.rows_rejected.get("insufficient_funds"), Some(&1));
+    assert_eq!(report.accounts_touched, 2);
     // since underlying for client accounts container uses cryptographic hash function
     // results are randomized, so we collect lines into hashset
     let lines: HashSet<String> = from_utf8(&output)
@@ -30,7 +73,742 @@ fn process_transactions() {
         .map(ToOwned::to_owned)
         .collect();
     assert_eq!(lines.len(), 3);
-    assert!(lines.contains("client,available,held,total,locked"));
-    assert!(lines.contains("1,1.5,0,1.5,false"));
-    assert!(lines.contains("2,2,0,2,false"));
+    assert!(lines.contains("client,available,held,total,locked,pending"));
+    assert!(lines.contains("1,1.5,0,1.5,false,0"));
+    assert!(lines.contains("2,2,0,2,false,0"));
+}
+
+#[test]
+fn process_several_inputs_into_same_state() {
+    let mut output = Vec::new();
+    let mut processor = InMemoryTransactionProcessor::default();
+    for input in [TEST_FILE, "type,client,tx,amount\nwithdrawal,2,6,1.5\n"] {
+        let service = Service::builder()
+            .input(input.as_bytes())
+            .output(&mut output)
+            .processor(processor)
+            .build();
+        processor = service.run_into().unwrap();
+    }
+    // nothing is printed, when running into processor
+    assert!(output.is_empty());
+    assert_eq!(processor.accounts().count(), 2);
+    assert_eq!(
+        processor.account(2).unwrap().available(),
+        Decimal::from_str_exact("0.5").unwrap()
+    );
+}
+
+#[test]
+fn unknown_kinds_are_rejected_or_skipped() {
+    let input = "type,client,tx,amount\ndeposit,1,1,2.0\nrefund,1,2,1.0\n";
+    for (policy, rejected, skipped) in [
+        (UnknownKindPolicy::Reject, Some(&1), 0),
+        (UnknownKindPolicy::Skip, None, 1),
+    ] {
+        let mut errors = Vec::new();
+        let service = Service::builder()
+            .input(input.as_bytes())
+            .output(std::io::sink())
+            .unknown_kinds(policy)
+            .on_error(&mut errors)
+            .build();
+        let report = service.run().unwrap();
+        assert_eq!(report.rows_accepted, 1);
+        assert_eq!(report.rows_rejected.get("unknown_kind"), rejected);
+        assert_eq!(report.rows_skipped, skipped);
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|(line, err)| (*line, err.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![(3, "Unknown transaction type `refund`".to_string())]
+        );
+    }
+}
+
+#[test]
+fn deterministic_output_is_sorted() {
+    let input = "type,client,tx,amount\ndeposit,3,1,1.0\ndeposit,1,2,2.0\ndeposit,2,3,3.0\n";
+    let mut output = Vec::new();
+    let service = Service::builder()
+        .input(input.as_bytes())
+        .output(&mut output)
+        .deterministic(true)
+        .build();
+    service.run().unwrap();
+    assert_eq!(
+        from_utf8(&output).unwrap(),
+        "client,available,held,total,locked,pending\n\
+        1,2,0,2,false,0\n\
+        2,3,0,3,false,0\n\
+        3,1,0,1,false,0\n"
+    );
+}
+
+/// Panics on transactions of client 2, like a processor hitting a bug
+struct PoisonedProcessor(InMemoryTransactionProcessor);
+
+impl TransactionProcessor for PoisonedProcessor {
+    fn process_transaction(
+        &mut self,
+        tx_id: TransactionId,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        kind: TransactionKind,
+    ) -> Result<TransactionOutcome, TransactionProcessError> {
+        assert_ne!(client_id, 2, "poisoned client");
+        self.0.process_transaction(tx_id, client_id, amount, kind)
+    }
+
+    fn accounts(&self) -> impl Iterator<Item = (ClientId, &Account)> {
+        self.0.accounts()
+    }
+}
+
+#[test]
+fn poisoned_client_is_quarantined() {
+    let input = "type,client,tx,amount\n\
+        deposit,1,1,1.0\n\
+        deposit,2,2,1.0\n\
+        deposit,2,3,1.0\n\
+        deposit,1,4,1.0\n";
+    let service = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .processor(PoisonedProcessor(InMemoryTransactionProcessor::default()))
+        .isolate_clients(true)
+        .build();
+    let report = service.run().unwrap();
+    assert_eq!(report.rows_accepted, 2);
+    assert_eq!(report.rows_rejected.get("client_quarantined"), Some(&2));
+    assert_eq!(report.quarantined.len(), 1);
+    assert_eq!(report.quarantined[0].client, 2);
+    assert_eq!(report.quarantined[0].line, 3);
+    assert!(report.quarantined[0].reason.contains("poisoned client"));
+}
+
+#[test]
+fn invariant_violation_quarantines_client() {
+    let mut processor = InMemoryTransactionProcessor::default();
+    processor
+        .process_transaction(1, 2, Some(Decimal::MAX), TransactionKind::Deposit)
+        .unwrap();
+    let input = "type,client,tx,amount\n\
+        deposit,2,2,1.0\n\
+        deposit,1,3,1.0\n\
+        withdrawal,2,4,1.0\n";
+    let service = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .processor(processor)
+        .isolate_clients(true)
+        .build();
+    let report = service.run().unwrap();
+    assert_eq!(report.rows_accepted, 1);
+    assert_eq!(report.rows_rejected.get("client_quarantined"), Some(&2));
+    assert_eq!(report.quarantined.len(), 1);
+    assert_eq!(report.quarantined[0].client, 2);
+    assert_eq!(report.quarantined[0].line, 2);
+    assert_eq!(report.quarantined[0].reason, "Balance overflow");
+}
+
+#[test]
+fn delta_output_prints_only_changed_accounts() {
+    fn service<'w>(
+        input: &'static str,
+        output: &'w mut Vec<u8>,
+        processor: InMemoryTransactionProcessor,
+        accounts_output: AccountsOutput,
+    ) -> Service<&'static [u8], &'w mut Vec<u8>> {
+        Service::builder()
+            .input(input.as_bytes())
+            .output(output)
+            .processor(processor)
+            .deterministic(true)
+            .accounts_output(accounts_output)
+            .build()
+    }
+    let previous = "type,client,tx,amount\n\
+        deposit,1,1,1.0\n\
+        deposit,2,2,1.0\n\
+        deposit,3,3,1.0\n";
+    let today = "type,client,tx,amount\n\
+        withdrawal,2,4,0.5\n\
+        deposit,4,5,2.0\n";
+    let warm_start = || {
+        Service::builder()
+            .input(previous.as_bytes())
+            .build()
+            .run_into()
+            .unwrap()
+    };
+    let mut output = Vec::new();
+    service(today, &mut output, warm_start(), AccountsOutput::Changed)
+        .run()
+        .unwrap();
+    assert_eq!(
+        from_utf8(&output).unwrap(),
+        "client,available,held,total,locked,pending\n\
+        2,0.5,0,0.5,false,0\n\
+        4,2,0,2,false,0\n"
+    );
+
+    let mut output = Vec::new();
+    service(
+        today,
+        &mut output,
+        warm_start(),
+        AccountsOutput::ChangedWithTombstones,
+    )
+    .run()
+    .unwrap();
+    assert_eq!(
+        from_utf8(&output).unwrap(),
+        "client,available,held,total,locked,pending\n\
+        2,0.5,0,0.5,false,0\n\
+        4,2,0,2,false,0\n\
+        1,,,,,\n\
+        3,,,,,\n"
+    );
+}
+
+#[test]
+fn run_streaming_sends_row_outcomes() {
+    let input = "type,client,tx,amount\n\
+        deposit,1,1,2.0\n\
+        withdrawal,1,2,5.0\n";
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let service = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .build();
+    service.run_streaming(sender).unwrap();
+    let outcomes: Vec<RowOutcome> = receiver.iter().collect();
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0].status, RowStatus::Accepted);
+    assert_eq!(outcomes[1].line, 3);
+    assert_eq!(
+        outcomes[1].status,
+        RowStatus::Rejected {
+            code: "insufficient_funds",
+            message: "Insufficient funds".to_string()
+        }
+    );
+    let balance = outcomes[1].balance.unwrap();
+    assert_eq!(balance.available, Decimal::TWO);
+}
+
+#[test]
+fn rows_referencing_rejected_txs_point_to_their_line() {
+    let input = "type,client,tx,amount\n\
+        deposit,1,1,2.0\n\
+        withdrawal,1,2,5.0\n\
+        dispute,1,2,\n\
+        dispute,1,3,\n";
+    let mut errors = Vec::new();
+    let service = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .track_rejected_txs(true)
+        .on_error(&mut errors)
+        .build();
+    let report = service.run().unwrap();
+    assert_eq!(report.rows_rejected.get("referenced_tx_rejected"), Some(&1));
+    assert_eq!(report.rows_rejected.get("existing_tx_required"), Some(&1));
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|(line, err)| (*line, err.to_string()))
+        .collect();
+    assert_eq!(
+        errors[1],
+        (
+            4,
+            "Transaction referenced by Dispute was rejected at line 3".to_string()
+        )
+    );
+}
+
+#[test]
+fn rejected_duplicates_are_not_tracked_as_rejected_txs() {
+    let input = "type,client,tx,amount\n\
+        deposit,1,1,2.0\n\
+        deposit,1,1,2.0\n\
+        dispute,1,1,\n\
+        resolve,1,1,\n\
+        dispute,1,1,\n";
+    let mut errors = Vec::new();
+    // record of the resolved transaction is dropped, only its id is kept
+    let processor = InMemoryTransactionProcessor::default().with_settled_tx_gc();
+    let report = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .processor(processor)
+        .track_rejected_txs(true)
+        .on_error(&mut errors)
+        .build()
+        .run()
+        .unwrap();
+    assert_eq!(report.rows_rejected.get("duplicate_transaction"), Some(&1));
+    let codes: Vec<_> = errors
+        .iter()
+        .map(|(line, err)| (*line, err.code()))
+        .collect();
+    assert_eq!(
+        codes,
+        [(3, "duplicate_transaction"), (6, "transaction_retired")]
+    );
+}
+
+#[test]
+fn late_rows_follow_late_events_policy() {
+    let input = "type,client,tx,amount,timestamp\n\
+        deposit,1,1,2.0,100\n\
+        deposit,1,2,3.0,95\n\
+        deposit,1,3,4.0,89\n\
+        deposit,1,4,1.0,\n";
+    let run = |policy| {
+        let mut output = Vec::new();
+        let report = Service::builder()
+            .input(input.as_bytes())
+            .output(&mut output)
+            .late_events(10, policy)
+            .build()
+            .run()
+            .unwrap();
+        (report, String::from_utf8(output).unwrap())
+    };
+
+    let (report, output) = run(LatePolicy::Apply);
+    assert_eq!((report.rows_late, report.watermark), (1, Some(90)));
+    assert!(output.contains("1,10"), "{output}");
+
+    let (report, output) = run(LatePolicy::Divert);
+    assert_eq!(report.rows_skipped, 1);
+    assert_eq!(report.late.len(), 1);
+    assert_eq!((report.late[0].line, report.late[0].row.tx), (4, 3));
+    assert!(output.contains("1,6"), "{output}");
+
+    let (report, _) = run(LatePolicy::Reject);
+    assert_eq!(report.rows_rejected.get("late_event"), Some(&1));
+    assert!(report.late.is_empty());
+}
+
+#[test]
+fn tentative_sections_are_rolled_back_above_error_rate() {
+    let input = "type,client,tx,amount,source\n\
+        deposit,1,1,10.0,bank\n\
+        deposit,2,2,5.0,partner\n\
+        withdrawal,2,3,50.0,partner\n\
+        deposit,1,4,1.0,bank\n\
+        deposit,3,5,5.0,partner\n\
+        deposit,3,6,5.0,partner\n\
+        withdrawal,3,7,1.0,partner\n";
+    let mut output = Vec::new();
+    let report = Service::builder()
+        .input(input.as_bytes())
+        .output(&mut output)
+        .tentative_sources(["partner".to_string()], 0.25)
+        .deterministic(true)
+        .build()
+        .run()
+        .unwrap();
+    assert_eq!(report.rolled_back.len(), 1);
+    let batch = &report.rolled_back[0];
+    assert_eq!((batch.first_line, batch.last_line), (3, 4));
+    assert_eq!((batch.rows, batch.rejected), (2, 1));
+    assert_eq!(report.rows_accepted, 5);
+    assert_eq!(report.rows_rejected.get("batch_rolled_back"), Some(&1));
+    let output = from_utf8(&output).unwrap();
+    assert!(!output.contains("\n2,"), "{output}");
+    assert!(output.contains("\n3,9,"), "{output}");
+}
+
+#[test]
+fn rolled_back_sections_report_their_rows_once() {
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let input = "type,client,tx,amount,source\n\
+        deposit,1,1,10.0,bank\n\
+        deposit,2,2,5.0,partner\n\
+        withdrawal,2,3,50.0,partner\n\
+        dispute,2,2,,bank\n";
+    let rejects = Rc::new(RefCell::new(Vec::new()));
+    let (sender, receiver) = std::sync::mpsc::channel();
+    Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .tentative_sources(["partner".to_string()], 0.25)
+        .track_rejected_txs(true)
+        .rejects(RejectLog::json_lines(Shared(rejects.clone())))
+        .build()
+        .run_streaming(sender)
+        .unwrap();
+    let outcomes: Vec<RowOutcome> = receiver.iter().collect();
+    let statuses: Vec<_> = outcomes
+        .iter()
+        .map(|outcome| {
+            let code = match &outcome.status {
+                RowStatus::Rejected { code, .. } => *code,
+                _ => "",
+            };
+            (outcome.line, code)
+        })
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            (2, ""),
+            (3, "batch_rolled_back"),
+            (4, "insufficient_funds"),
+            (5, "referenced_tx_rejected"),
+        ]
+    );
+    // the account created by the section is rolled back too
+    assert_eq!(outcomes[1].balance, None);
+    let rejects = String::from_utf8(rejects.take()).unwrap();
+    let codes: Vec<_> = rejects
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["error_code"].clone())
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            "batch_rolled_back",
+            "insufficient_funds",
+            "referenced_tx_rejected"
+        ]
+    );
+}
+
+#[test]
+fn circuit_breaker_halts_or_quarantines() {
+    let mut input = "type,client,tx,amount,source\ndeposit,1,1,10.0,bank\n".to_string();
+    for tx in 2..10 {
+        input.push_str(&format!("withdrawal,2,{tx},5.0,partner\n"));
+    }
+    input.push_str("deposit,1,10,1.0,bank\n");
+    let run = |action| {
+        Service::builder()
+            .input(input.as_bytes())
+            .output(std::io::sink())
+            .circuit_breaker(CircuitBreaker::new(4, 0.5, action))
+            .build()
+            .run()
+            .unwrap()
+    };
+
+    let report = run(BreakerAction::Halt);
+    assert_eq!(report.halted.as_ref().unwrap().line, 5);
+    assert_eq!(report.rows_read, 4);
+    assert_eq!(report.exit_status(&[]), ExitStatus::Fatal);
+
+    let report = run(BreakerAction::QuarantineSource);
+    assert!(report.halted.is_none());
+    assert_eq!(report.quarantined_sources[0].source, "partner");
+    assert_eq!(report.rows_rejected.get("source_quarantined"), Some(&5));
+    assert_eq!(report.rows_accepted, 2);
+
+    let report = run(BreakerAction::QuarantineClient);
+    assert_eq!(report.quarantined[0].client, 2);
+}
+
+#[test]
+fn held_accrual_averages_disputed_funds_by_day() {
+    // disputed at noon of 2024-03-01, resolved at the end of the next day
+    let input = "type,client,tx,amount,timestamp\n\
+        deposit,1,1,10.0,1709251200\n\
+        dispute,1,1,,1709294400\n\
+        deposit,2,2,1.0,\n\
+        resolve,1,1,,1709424000\n";
+    let report = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .held_accrual(true)
+        .build()
+        .run()
+        .unwrap();
+    let daily: Vec<_> = report
+        .held_accrual
+        .iter()
+        .map(|held| (held.client, held.date(), held.average_held))
+        .collect();
+    assert_eq!(
+        daily,
+        [
+            (1, "2024-03-01".to_string(), Decimal::new(5, 0)),
+            (1, "2024-03-02".to_string(), Decimal::TEN),
+        ]
+    );
+}
+
+#[test]
+fn open_disputes_report_includes_cases() {
+    let mut processor = InMemoryTransactionProcessor::default();
+    for (tx, kind) in [
+        (1, TransactionKind::Deposit),
+        (2, TransactionKind::Deposit),
+        (1, TransactionKind::Dispute),
+    ] {
+        let amount = (kind == TransactionKind::Deposit).then_some(Decimal::ONE);
+        processor.process_transaction(tx, 1, amount, kind).unwrap();
+    }
+    processor
+        .set_case_status(1, 1, CaseStatus::AwaitingEvidence)
+        .unwrap();
+    processor
+        .assign_case(1, 1, Some("alice".to_string()))
+        .unwrap();
+    processor
+        .add_case_note(1, 1, "asked merchant for receipt")
+        .unwrap();
+    assert!(processor.add_case_note(1, 2, "not disputed").is_err());
+
+    let input = "type,client,tx,amount\n\
+        representment,1,1,\n\
+        dispute,1,2,\n";
+    let report = Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .processor(processor)
+        .open_disputes(true)
+        .build()
+        .run()
+        .unwrap();
+    let mut output = Vec::new();
+    csv_printer::print_open_disputes(&mut output, &report.open_disputes).unwrap();
+    assert_eq!(
+        from_utf8(&output).unwrap(),
+        "client,tx,stage,status,assignee,notes\n\
+        1,1,representment,awaiting-evidence,alice,\"[\"\"asked merchant for receipt\"\"]\"\n\
+        1,2,disputed,new,,[]\n"
+    );
+}
+
+#[test]
+fn extended_report_prints_account_extensions() {
+    let input = "type,client,tx,amount\n\
+        deposit,1,1,1.0\n\
+        dispute,1,1,\n\
+        deposit,2,2,1.0\n";
+    let mut output = Vec::new();
+    Service::builder()
+        .input(input.as_bytes())
+        .output(&mut output)
+        .processor(InMemoryTransactionProcessor::default().with_extension(DisputeCount))
+        .deterministic(true)
+        .extended_report(true)
+        .build()
+        .run()
+        .unwrap();
+    assert_eq!(
+        from_utf8(&output).unwrap(),
+        "client,available,held,total,locked,pending,extensions\n\
+        1,0,1,1,false,0,\"{\"\"disputes\"\":1}\"\n\
+        2,1,0,1,false,0,{}\n"
+    );
+}
+
+#[test]
+fn trace_ids_reach_events_and_errors() {
+    #[derive(Default)]
+    struct TraceRecorder(Rc<RefCell<Vec<Option<String>>>>);
+
+    impl EventSubscriber for TraceRecorder {
+        fn on_event(&mut self, event: &AppliedEvent) {
+            self.0.borrow_mut().push(event.trace_id.map(str::to_string));
+        }
+    }
+
+    let input = "type,client,tx,amount,trace_id\n\
+        deposit,1,1,1.0,pay-1\n\
+        withdrawal,1,2,5.0,pay-2\n\
+        dispute,1,1,,\n";
+    let traces = Rc::default();
+    let mut errors = Vec::new();
+    Service::builder()
+        .input(input.as_bytes())
+        .output(std::io::sink())
+        .processor(
+            InMemoryTransactionProcessor::default()
+                .with_subscriber(TraceRecorder(Rc::clone(&traces))),
+        )
+        .on_error(JsonLinesSink::new(&mut errors))
+        .build()
+        .run()
+        .unwrap();
+    assert_eq!(*traces.borrow(), [Some("pay-1".to_string()), None]);
+    let error: serde_json::Value = serde_json::from_slice(&errors).unwrap();
+    assert_eq!(error["line"], 3);
+    assert_eq!(error["trace_id"], "pay-2");
+}
+
+#[test]
+fn change_feed_write_error_fails_run() {
+    struct Full;
+
+    impl std::io::Write for Full {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::StorageFull.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut output = Vec::new();
+    let result = Service::builder()
+        .input(TEST_FILE.as_bytes())
+        .output(&mut output)
+        .processor(
+            InMemoryTransactionProcessor::default()
+                .with_subscriber(ChangeFeed::json_lines(Full, 0)),
+        )
+        .build()
+        .run();
+    assert!(result.is_err());
+    // accounts are not reported, as the feed misses their events
+    assert!(output.is_empty());
+}
+
+#[test]
+fn ledger_fails_on_malformed_row() {
+    let mut ledger = Ledger::new();
+    let err = ledger
+        .ingest_csv(
+            "type,client,tx,amount\ndeposit,1,1,2\ndeposit,x,2,1\ndeposit,1,3,1\n".as_bytes(),
+        )
+        .unwrap_err();
+    assert_eq!(err.downcast_ref::<InvalidRow>().unwrap().line, 3);
+    // rows before the malformed one stay processed
+    let account = ledger.processor().account(1).unwrap();
+    assert_eq!(account.available(), Decimal::TWO);
+}
+
+#[test]
+fn erased_client_is_recorded_in_history() {
+    let mut accounts = AccountClients::default();
+    accounts.insert("LT601010012345678901".to_string(), 1);
+    accounts.insert("LT601010098765432109".to_string(), 2);
+    let mut processor = InMemoryTransactionProcessor::default()
+        .with_history()
+        .with_subscriber(accounts);
+    processor
+        .process_transaction_from(
+            1,
+            1,
+            Some(Decimal::TEN),
+            TransactionKind::Deposit,
+            Some("alice.csv"),
+        )
+        .unwrap();
+    processor
+        .process_transaction_from(
+            2,
+            2,
+            Some(Decimal::ONE),
+            TransactionKind::Deposit,
+            Some("bob.csv"),
+        )
+        .unwrap();
+    assert!(processor.set_extension(1, "email", "alice@example.com".into()));
+
+    let tombstone = processor.erase_client(1).unwrap();
+    assert_eq!((tombstone.seq, tombstone.events_anonymized), (2, 1));
+    let account = processor.account(1).unwrap();
+    assert_eq!(account.available(), Decimal::TEN);
+    assert!(account.extensions().is_empty());
+
+    let history = processor.history().unwrap();
+    let erasures: Vec<_> = history.tombstones().collect();
+    assert_eq!(erasures.len(), 1);
+    assert_eq!(erasures[0].client, 1);
+    assert_eq!(erasures[0].event.kind(), AccountEventKind::Erased);
+    assert_eq!(erasures[0].source.as_deref(), Some(ADMIN_SOURCE));
+    let sources: Vec<_> = history
+        .iter()
+        .map(|entry| entry.source.as_deref())
+        .collect();
+    assert_eq!(sources, [None, Some("bob.csv"), Some(ADMIN_SOURCE)]);
+
+    let accounts = processor.subscriber::<AccountClients>().unwrap();
+    assert_eq!(accounts.client("LT601010012345678901"), None);
+    assert_eq!(accounts.client("LT601010098765432109"), Some(2));
+}
+
+#[test]
+fn risk_config_is_reloaded_between_rows() {
+    /// Hands out input a line at a time, rewriting the config before some lines
+    struct EditingInput {
+        lines: Vec<&'static str>,
+        next: usize,
+        config: PathBuf,
+        edits: Vec<(usize, &'static str)>,
+    }
+    impl Read for EditingInput {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some(line) = self.lines.get(self.next) else {
+                return Ok(0);
+            };
+            if let Some((_, config)) = self.edits.iter().find(|(at, _)| *at == self.next) {
+                std::fs::write(&self.config, config)?;
+            }
+            self.next += 1;
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+            Ok(line.len())
+        }
+    }
+
+    let config = std::env::temp_dir().join(format!("reloaded-risk-{}.json", std::process::id()));
+    std::fs::write(&config, r#"{"balance_cap": "10"}"#).unwrap();
+    let input = EditingInput {
+        lines: vec![
+            "type,client,tx,amount\n",
+            "deposit,1,1,5.0\n",
+            "deposit,2,2,1.0\n",
+            "deposit,2,3,1.0\n",
+            "deposit,1,4,20.0\n",
+            "deposit,2,5,1.0\n",
+            "deposit,2,6,1.0\n",
+            "deposit,1,7,200.0\n",
+        ],
+        next: 0,
+        config: config.clone(),
+        // filler rows follow each edit, in case the parser reads a row ahead
+        edits: vec![(2, r#"{"balance_cap": "100"}"#), (5, r#"{"fraud": {}}"#)],
+    };
+    let mut output = Vec::new();
+    let report = Service::builder()
+        .input(input)
+        .output(&mut output)
+        .risk_config(ConfigReload::new(&config, Duration::ZERO).unwrap())
+        .deterministic(true)
+        .build()
+        .run()
+        .unwrap();
+    std::fs::remove_file(&config).unwrap();
+
+    assert_eq!(report.config_reloads.len(), 2);
+    assert_eq!(report.config_reloads[0].error, None);
+    // processor without fraud heuristics keeps the previous config
+    let error = report.config_reloads[1].error.as_deref().unwrap();
+    assert!(error.contains("fraud thresholds"), "{error}");
+    assert_eq!(report.rows_accepted, 6);
+    assert_eq!(report.total_rejected(), 1);
+    assert_eq!(
+        from_utf8(&output).unwrap(),
+        "client,available,held,total,locked,pending\n1,25,0,25,false,0\n2,4,0,4,false,0\n"
+    );
 }